@@ -68,6 +68,15 @@ fn gen_conf() -> MemBrokerConfig {
         enable_ordered_proxy: s
             .get::<bool>("enable_ordered_proxy")
             .unwrap_or_else(|_| false),
+        memory_high_water_mark: s.get::<u64>("memory_high_water_mark").unwrap_or_else(|_| 0),
+        memory_low_water_mark: s.get::<u64>("memory_low_water_mark").unwrap_or_else(|_| 0),
+        memory_check_interval: NonZeroU64::new(
+            s.get::<u64>("memory_check_interval").unwrap_or_else(|_| 30),
+        ),
+        failover_max_repl_lag: s.get::<u64>("failover_max_repl_lag").unwrap_or_else(|_| 0),
+        failover_repl_lag_timeout: s
+            .get::<u64>("failover_repl_lag_timeout")
+            .unwrap_or_else(|_| 30),
         debug,
     }
 }
@@ -112,6 +121,14 @@ async fn sync_meta_to_replicas(service: Arc<MemBrokerService>, interval: Duratio
     }
 }
 
+async fn check_memory_usage(service: Arc<MemBrokerService>, interval: Duration) {
+    loop {
+        Delay::new(interval).await;
+        trace!("periodically check memory usage");
+        service.check_memory_usage().await;
+    }
+}
+
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
@@ -120,6 +137,7 @@ async fn main() -> std::io::Result<()> {
     let address = config.address.clone();
     let update_file_interval = config.update_meta_file_interval;
     let sync_meta_interval = config.sync_meta_interval;
+    let memory_check_interval = config.memory_check_interval;
 
     let meta_storage = Arc::new(JsonFileStorage::new(config.meta_filename.clone()));
     let meta_store = if config.recover_from_meta_file {
@@ -151,6 +169,12 @@ async fn main() -> std::io::Result<()> {
         actix_rt::spawn(sync_meta_to_replicas(service.clone(), interval));
     }
 
+    if let Some(interval) = memory_check_interval {
+        info!("start periodically checking memory usage");
+        let interval = Duration::from_secs(interval.get());
+        actix_rt::spawn(check_memory_usage(service.clone(), interval));
+    }
+
     HttpServer::new(move || {
         let service = service.clone();
         App::new()
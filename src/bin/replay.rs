@@ -0,0 +1,180 @@
+extern crate futures;
+extern crate tokio;
+extern crate undermoon;
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+
+use futures::future;
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use string_error::into_err;
+use undermoon::protocol::{resp_to_buf, RedisClient, RedisClientFactory, SimpleRedisClientFactory};
+use undermoon::proxy::capture::{read_capture_header, CaptureRecord};
+
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Default)]
+struct ReplayStats {
+    replayed: usize,
+    mismatched: usize,
+    failed: usize,
+}
+
+fn read_capture_file(path: &str) -> Result<Vec<CaptureRecord>, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read_capture_header(&mut reader)?;
+    let mut records = vec![];
+    while let Some(record) = CaptureRecord::decode(&mut reader)? {
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// The requests of every captured session are sent through their own connection
+// in the same order. With a positive `speed`, the requests are sent at
+// the captured time divided by `speed`. Otherwise they are sent as fast as possible.
+async fn replay_session(
+    client_factory: Arc<SimpleRedisClientFactory>,
+    address: String,
+    records: Vec<CaptureRecord>,
+    start: Instant,
+    first_timestamp: u64,
+    speed: f64,
+) -> ReplayStats {
+    let mut stats = ReplayStats::default();
+    let mut client = None;
+    for record in records.into_iter() {
+        if speed > 0.0 {
+            let offset = (record.timestamp.saturating_sub(first_timestamp) as f64 / speed) as u64;
+            let send_time = start + Duration::from_nanos(offset);
+            let now = Instant::now();
+            if send_time > now {
+                tokio::time::delay_for(send_time - now).await;
+            }
+        }
+
+        if client.is_none() {
+            match client_factory.create_client(address.clone()).await {
+                Ok(c) => client = Some(c),
+                Err(err) => {
+                    error!("failed to connect to {}: {:?}", address, err);
+                    stats.failed += 1;
+                    continue;
+                }
+            }
+        }
+        let c = match client.as_mut() {
+            Some(c) => c,
+            None => continue,
+        };
+
+        stats.replayed += 1;
+        let reply = match c.execute_single(record.request.clone()).await {
+            Ok(reply) => reply,
+            Err(err) => {
+                error!("failed to replay request {:?}: {:?}", record.request, err);
+                stats.failed += 1;
+                client = None;
+                continue;
+            }
+        };
+        let mut reply_buf = vec![];
+        if let Err(err) = resp_to_buf(&mut reply_buf, &reply) {
+            error!("failed to encode reply: {}", err);
+            stats.failed += 1;
+            continue;
+        }
+        if reply_buf != record.reply {
+            warn!(
+                "reply mismatched: session_id={} request={:?} captured={:?} replayed={:?}",
+                record.session_id,
+                record.request,
+                String::from_utf8_lossy(&record.reply),
+                String::from_utf8_lossy(&reply_buf),
+            );
+            stats.mismatched += 1;
+        }
+    }
+    stats
+}
+
+// Usage: replay <capture_file> <proxy_address> [speed]
+// `speed` defaults to 1 which keeps the captured timing. Use 0 to replay as fast as possible.
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+
+    let args: Vec<String> = env::args().collect();
+    let (path, address) = match (args.get(1), args.get(2)) {
+        (Some(path), Some(address)) => (path.clone(), address.clone()),
+        _ => {
+            return Err(into_err(
+                "usage: replay <capture_file> <proxy_address> [speed]".to_string(),
+            ))
+        }
+    };
+    let speed = match args.get(3) {
+        Some(speed) => speed
+            .parse::<f64>()
+            .map_err(|_| into_err(format!("invalid speed {}", speed)))?,
+        None => 1.0,
+    };
+
+    let records = read_capture_file(&path)?;
+    info!("loaded {} records from {}", records.len(), path);
+    let first_timestamp = records.iter().map(|r| r.timestamp).min().unwrap_or(0);
+
+    let mut sessions: BTreeMap<u64, Vec<CaptureRecord>> = BTreeMap::new();
+    for record in records.into_iter() {
+        sessions
+            .entry(record.session_id)
+            .or_insert_with(Vec::new)
+            .push(record);
+    }
+
+    let mut runtime = tokio::runtime::Builder::new()
+        .threaded_scheduler()
+        .enable_all()
+        .build()?;
+
+    let client_factory = Arc::new(SimpleRedisClientFactory::new(CLIENT_TIMEOUT));
+    let start = Instant::now();
+    let results = runtime.block_on(async {
+        let futs = sessions.into_iter().map(|(_, records)| {
+            tokio::spawn(replay_session(
+                client_factory.clone(),
+                address.clone(),
+                records,
+                start,
+                first_timestamp,
+                speed,
+            ))
+        });
+        future::join_all(futs).await
+    });
+
+    let mut total = ReplayStats::default();
+    for res in results.into_iter() {
+        match res {
+            Ok(stats) => {
+                total.replayed += stats.replayed;
+                total.mismatched += stats.mismatched;
+                total.failed += stats.failed;
+            }
+            Err(err) => error!("replay task failed: {}", err),
+        }
+    }
+    println!(
+        "replayed: {} mismatched: {} failed: {} time: {:?}",
+        total.replayed,
+        total.mismatched,
+        total.failed,
+        start.elapsed()
+    );
+    Ok(())
+}
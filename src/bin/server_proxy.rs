@@ -11,25 +11,47 @@ use std::cmp::min;
 use std::env;
 use std::error::Error;
 use std::num::NonZeroUsize;
-use std::sync::atomic::{AtomicI64, AtomicU64};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize};
 use std::sync::Arc;
 use std::time::Duration;
 use string_error::into_err;
+use tokio::signal::unix::{signal, SignalKind};
 use undermoon::common::config::ClusterConfig;
 use undermoon::common::track::TrackedFutureRegistry;
 use undermoon::protocol::SimpleRedisClientFactory;
 use undermoon::proxy::backend::DefaultConnFactory;
+use undermoon::proxy::capture::TrafficCapture;
 use undermoon::proxy::executor::SharedForwardHandler;
 use undermoon::proxy::manager::MetaMap;
+use undermoon::proxy::rate_limit::ThrottleStats;
 use undermoon::proxy::service::{ServerProxyConfig, ServerProxyService};
+use undermoon::proxy::shutdown::ShutdownSignal;
 use undermoon::proxy::slowlog::SlowRequestLogger;
 use undermoon::MAX_REDIRECTIONS;
 
+// Like `config::File::with_name`, the extension of the config file could be omitted.
+// The resolved path is used by `CONFIG REWRITE` to write the same file.
+fn resolve_conf_file_path(conf_file_path: String) -> String {
+    let mut path = PathBuf::from(&conf_file_path);
+    if path.is_file() {
+        return conf_file_path;
+    }
+    for ext in ["toml", "json", "yaml", "yml", "hjson", "ini"].iter() {
+        path.set_extension(ext);
+        if path.is_file() {
+            return path.to_string_lossy().to_string();
+        }
+    }
+    conf_file_path
+}
+
 fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
     let mut s = config::Config::new();
+    let conf_file_path = env::args().nth(1).map(resolve_conf_file_path);
     // If config file is specified, load it.
-    if let Some(conf_file_path) = env::args().nth(1) {
-        s.merge(config::File::with_name(&conf_file_path))
+    if let Some(conf_file_path) = conf_file_path.as_ref() {
+        s.merge(config::File::with_name(conf_file_path))
             .map(|_| ())
             .unwrap_or_else(|e| warn!("failed to read config file: {:?}", e));
     }
@@ -60,7 +82,6 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
     if max_redirections != 0 {
         max_redirections = min(MAX_REDIRECTIONS, max_redirections);
     }
-    let max_redirections = NonZeroUsize::new(max_redirections);
 
     let default_redirection_address = s
         .get::<String>("default_redirection_address")
@@ -71,14 +92,24 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
         Some(default_redirection_address)
     };
 
+    let capture_dir = s
+        .get::<String>("capture_dir")
+        .unwrap_or_else(|_| "".to_string());
+    let capture_dir = if capture_dir.is_empty() {
+        None
+    } else {
+        Some(capture_dir)
+    };
+
     let config = ServerProxyConfig {
         address: address.clone(),
         announce_address: s
             .get::<String>("announce_address")
             .unwrap_or_else(|_| address),
-        auto_select_cluster: s
-            .get::<bool>("auto_select_cluster")
-            .unwrap_or_else(|_| true),
+        auto_select_cluster: AtomicBool::new(
+            s.get::<bool>("auto_select_cluster")
+                .unwrap_or_else(|_| true),
+        ),
         slowlog_len,
         slowlog_log_slower_than: AtomicI64::new(
             s.get::<i64>("slowlog_log_slower_than")
@@ -87,6 +118,7 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
         slowlog_sample_rate: AtomicU64::new(
             s.get::<u64>("slowlog_sample_rate").unwrap_or_else(|_| 1000),
         ),
+        key_stats_top_k: s.get::<usize>("key_stats_top_k").unwrap_or_else(|_| 20),
         thread_number,
         session_channel_size: s
             .get::<usize>("session_channel_size")
@@ -94,26 +126,53 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
         backend_channel_size: s
             .get::<usize>("backend_channel_size")
             .unwrap_or_else(|_| 4096),
-        backend_conn_num,
-        backend_batch_min_time: s
-            .get::<usize>("backend_batch_min_time")
-            .unwrap_or_else(|_| 20000),
-        backend_batch_max_time: s
-            .get::<usize>("backend_batch_max_time")
-            .unwrap_or_else(|_| 400_000),
-        backend_batch_buf,
-        session_batch_min_time: s
-            .get::<usize>("session_batch_min_time")
-            .unwrap_or_else(|_| 20000),
-        session_batch_max_time: s
-            .get::<usize>("session_batch_max_time")
-            .unwrap_or_else(|_| 400_000),
-        session_batch_buf,
+        backend_conn_num: AtomicUsize::new(backend_conn_num.get()),
+        backend_batch_min_time: AtomicUsize::new(
+            s.get::<usize>("backend_batch_min_time")
+                .unwrap_or_else(|_| 20000),
+        ),
+        backend_batch_max_time: AtomicUsize::new(
+            s.get::<usize>("backend_batch_max_time")
+                .unwrap_or_else(|_| 400_000),
+        ),
+        backend_batch_buf: AtomicUsize::new(backend_batch_buf.get()),
+        session_batch_min_time: AtomicUsize::new(
+            s.get::<usize>("session_batch_min_time")
+                .unwrap_or_else(|_| 20000),
+        ),
+        session_batch_max_time: AtomicUsize::new(
+            s.get::<usize>("session_batch_max_time")
+                .unwrap_or_else(|_| 400_000),
+        ),
+        session_batch_buf: AtomicUsize::new(session_batch_buf.get()),
         active_redirection: s
             .get::<bool>("active_redirection")
             .unwrap_or_else(|_| false),
-        max_redirections,
+        max_redirections: AtomicUsize::new(max_redirections),
         default_redirection_address,
+        backend_request_timeout: AtomicU64::new(
+            s.get::<u64>("backend_request_timeout")
+                .unwrap_or_else(|_| 0),
+        ),
+        backend_circuit_breaker_threshold: s
+            .get::<u64>("backend_circuit_breaker_threshold")
+            .unwrap_or_else(|_| 3),
+        backend_circuit_breaker_open_time: s
+            .get::<u64>("backend_circuit_breaker_open_time")
+            .unwrap_or_else(|_| 1000),
+        shutdown_timeout: s.get::<u64>("shutdown_timeout").unwrap_or_else(|_| 10000),
+        session_rate_limit_ops_per_sec: AtomicU64::new(
+            s.get::<u64>("session_rate_limit_ops_per_sec")
+                .unwrap_or_else(|_| 0),
+        ),
+        session_rate_limit_bytes_per_sec: AtomicU64::new(
+            s.get::<u64>("session_rate_limit_bytes_per_sec")
+                .unwrap_or_else(|_| 0),
+        ),
+        timeout: AtomicU64::new(s.get::<u64>("timeout").unwrap_or_else(|_| 0)),
+        maxclients: AtomicUsize::new(s.get::<usize>("maxclients").unwrap_or_else(|_| 10000)),
+        capture_dir,
+        config_file: conf_file_path,
     };
 
     let mut cluster_config = ClusterConfig::default();
@@ -123,6 +182,21 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
         "migration_max_blocking_time",
         "migration_scan_interval",
         "migration_scan_count",
+        "migration_max_bytes_per_sec",
+        "migration_max_keys_per_sec",
+        "migration_big_key_threshold",
+        "migration_big_key_count_threshold",
+        "migration_scan_by_slot",
+        "migration_strategy",
+        "rate_limit_ops_per_sec",
+        "rate_limit_bytes_per_sec",
+        "denied_commands",
+        "command_aliases",
+        "key_prefixes",
+        "read_cache_max_keys",
+        "read_cache_ttl",
+        "read_cache_key_patterns",
+        "read_cache_hot_key_threshold",
     ];
     for field in cluster_fields.iter() {
         if let Ok(value) = s.get::<String>(*field) {
@@ -135,6 +209,25 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
     Ok((config, cluster_config))
 }
 
+// Only the fields which could be changed by `CONFIG SET` are reloaded.
+async fn reload_conf_on_sighup(config: Arc<ServerProxyConfig>) -> Result<(), std::io::Error> {
+    let mut sighup = signal(SignalKind::hangup())?;
+    while sighup.recv().await.is_some() {
+        let new_config = match gen_conf() {
+            Ok((new_config, _)) => new_config,
+            Err(field) => {
+                error!("failed to reload config: invalid field {}", field);
+                continue;
+            }
+        };
+        match config.update_from(&new_config) {
+            Ok(()) => info!("config reloaded: {:?}", config),
+            Err(err) => error!("failed to reload config: {:?}", err),
+        }
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
     let (config, cluster_config) = gen_conf().map_err(|field| {
@@ -153,6 +246,9 @@ fn main() -> Result<(), Box<dyn Error>> {
     let slow_request_logger = Arc::new(SlowRequestLogger::new(config.clone()));
     let meta_map = Arc::new(ArcSwap::new(Arc::new(MetaMap::empty())));
     let future_registry = Arc::new(TrackedFutureRegistry::default());
+    let shutdown = Arc::new(ShutdownSignal::default());
+    let throttle_stats = Arc::new(ThrottleStats::default());
+    let capture = Arc::new(TrafficCapture::default());
 
     let forward_handler = SharedForwardHandler::new(
         config.clone(),
@@ -162,12 +258,18 @@ fn main() -> Result<(), Box<dyn Error>> {
         meta_map,
         Arc::new(DefaultConnFactory::default()),
         future_registry.clone(),
+        shutdown.clone(),
+        throttle_stats.clone(),
+        capture.clone(),
     );
     let server = ServerProxyService::new(
         config.clone(),
         forward_handler,
         slow_request_logger,
         future_registry,
+        shutdown.clone(),
+        throttle_stats,
+        capture,
     );
 
     let mut runtime = tokio::runtime::Builder::new()
@@ -176,7 +278,20 @@ fn main() -> Result<(), Box<dyn Error>> {
         .enable_all()
         .build()?;
 
-    if let Err(err) = runtime.block_on(server.run()) {
+    let fut = async move {
+        tokio::spawn(async move {
+            if let Err(err) = shutdown.listen_to_signals().await {
+                error!("failed to listen to signals: {}", err);
+            }
+        });
+        tokio::spawn(async move {
+            if let Err(err) = reload_conf_on_sighup(config).await {
+                error!("failed to listen to SIGHUP: {}", err);
+            }
+        });
+        server.run().await
+    };
+    if let Err(err) = runtime.block_on(fut) {
         error!("tokio runtime failed: {}", err);
         return Err(err);
     }
@@ -0,0 +1,159 @@
+use crate::common::cluster::NodeMemoryStats;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// The coordinators report the memory stats every 10 seconds.
+// Stats older than this are considered outdated and will not be used for scaling.
+const MEMORY_STATS_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterMemoryUsage {
+    pub node_number: usize,
+    pub used_memory: u64,
+    pub maxmemory: u64,
+    // Only set when the usage is above the high-water mark
+    // or below the low-water mark.
+    pub suggested_node_number: Option<usize>,
+}
+
+#[derive(Debug)]
+struct NodeMemoryRecord {
+    stats: NodeMemoryStats,
+    update_time: Instant,
+}
+
+// The latest memory usage of the master nodes reported by the coordinators.
+// Like the slot stats, it's not persisted nor replicated.
+#[derive(Debug, Default)]
+pub struct MemoryStatsStore {
+    nodes: HashMap<String, NodeMemoryRecord>,
+}
+
+impl MemoryStatsStore {
+    pub fn add_stats(&mut self, memory_stats: Vec<NodeMemoryStats>) {
+        let now = Instant::now();
+        for stats in memory_stats.into_iter() {
+            let record = NodeMemoryRecord {
+                stats,
+                update_time: now,
+            };
+            self.nodes.insert(record.stats.node_address.clone(), record);
+        }
+    }
+
+    // Returns None if any of the master nodes has no fresh stats
+    // or has no `maxmemory` set, since the usage can't be decided.
+    pub fn get_usage<'a, It>(&self, master_addresses: It) -> Option<(u64, u64)>
+    where
+        It: Iterator<Item = &'a str>,
+    {
+        let now = Instant::now();
+        let mut used_memory: u64 = 0;
+        let mut maxmemory: u64 = 0;
+        for address in master_addresses {
+            let record = self.nodes.get(address)?;
+            if now.duration_since(record.update_time) > MEMORY_STATS_TTL {
+                return None;
+            }
+            if record.stats.maxmemory == 0 {
+                return None;
+            }
+            used_memory = used_memory.saturating_add(record.stats.used_memory);
+            maxmemory = maxmemory.saturating_add(record.stats.maxmemory);
+        }
+        if maxmemory == 0 {
+            return None;
+        }
+        Some((used_memory, maxmemory))
+    }
+
+    pub fn remove_expired(&mut self) {
+        let now = Instant::now();
+        self.nodes
+            .retain(|_, record| now.duration_since(record.update_time) <= MEMORY_STATS_TTL);
+    }
+}
+
+// The water marks are in percentage and zero disables the check.
+// The suggested node number is the smallest one
+// that could keep the usage under the high-water mark,
+// rounded up to whole chunks of `chunk_node_num` nodes.
+pub fn suggest_node_number(
+    node_number: usize,
+    chunk_node_num: usize,
+    used_memory: u64,
+    maxmemory: u64,
+    high_water_mark: u64,
+    low_water_mark: u64,
+) -> Option<usize> {
+    if node_number == 0 || chunk_node_num == 0 || maxmemory == 0 || high_water_mark == 0 {
+        return None;
+    }
+
+    let used = u128::from(used_memory) * 100;
+    let above_high = used > u128::from(maxmemory) * u128::from(high_water_mark);
+    let below_low = used < u128::from(maxmemory) * u128::from(low_water_mark);
+    if !above_high && !below_low {
+        return None;
+    }
+
+    // The maxmemory grows linearly with the node number.
+    let total = u128::from(maxmemory) * u128::from(high_water_mark);
+    let expected = (node_number as u128 * used).div_ceil(total);
+    let chunk_num = expected.div_ceil(chunk_node_num as u128);
+    let expected = (chunk_num.max(1) as usize).saturating_mul(chunk_node_num);
+
+    if (above_high && expected > node_number) || (below_low && expected < node_number) {
+        Some(expected)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_usage() {
+        let mut store = MemoryStatsStore::default();
+        store.add_stats(vec![
+            NodeMemoryStats {
+                node_address: "127.0.0.1:7000".to_string(),
+                used_memory: 30,
+                maxmemory: 100,
+            },
+            NodeMemoryStats {
+                node_address: "127.0.0.1:7001".to_string(),
+                used_memory: 50,
+                maxmemory: 100,
+            },
+            NodeMemoryStats {
+                node_address: "127.0.0.1:7002".to_string(),
+                used_memory: 50,
+                maxmemory: 0,
+            },
+        ]);
+
+        let usage = store.get_usage(vec!["127.0.0.1:7000", "127.0.0.1:7001"].into_iter());
+        assert_eq!(usage, Some((80, 200)));
+        let usage = store.get_usage(vec!["127.0.0.1:7000", "127.0.0.1:7002"].into_iter());
+        assert_eq!(usage, None);
+        let usage = store.get_usage(vec!["127.0.0.1:7000", "127.0.0.1:7003"].into_iter());
+        assert_eq!(usage, None);
+    }
+
+    #[test]
+    fn test_suggest_node_number() {
+        assert_eq!(suggest_node_number(8, 4, 70, 100, 80, 30), None);
+        assert_eq!(suggest_node_number(8, 4, 70, 100, 0, 30), None);
+        assert_eq!(suggest_node_number(8, 4, 90, 100, 80, 30), Some(12));
+        assert_eq!(suggest_node_number(8, 4, 170, 100, 80, 30), Some(20));
+        assert_eq!(suggest_node_number(16, 4, 20, 100, 80, 30), Some(4));
+        assert_eq!(suggest_node_number(16, 4, 25, 100, 80, 30), Some(8));
+        assert_eq!(suggest_node_number(4, 4, 10, 100, 80, 30), None);
+        assert_eq!(suggest_node_number(16, 4, 20, 100, 80, 0), None);
+        assert_eq!(suggest_node_number(6, 6, 90, 100, 80, 30), Some(12));
+        assert_eq!(suggest_node_number(4, 2, 20, 100, 80, 30), Some(2));
+    }
+}
@@ -1,13 +1,31 @@
 use super::store::{
-    ClusterStore, MetaStore, MetaStoreError, MigrationMetaStore, MigrationSlotRangeStore,
-    MigrationSlots, CHUNK_NODE_NUM,
+    get_weighted_slot_nums, ClusterStore, MetaStore, MetaStoreError, MigrationMetaStore,
+    MigrationSlotRangeStore, MigrationSlots,
 };
 use crate::common::cluster::ClusterName;
 use crate::common::cluster::{MigrationTaskMeta, Range, RangeList, SlotRange, SlotRangeTag};
-use crate::common::utils::SLOT_NUM;
-use std::cmp::min;
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
 
+// Stop moving hot slots once the busiest master is within this percentage over the average.
+const HOT_SLOT_LOAD_TOLERANCE_PERCENT: u64 = 20;
+const MAX_HOT_SLOT_MOVES: usize = 128;
+
+struct MasterSlotLoad {
+    chunk_index: usize,
+    chunk_part: usize,
+    slots: Vec<usize>,
+    load: u64,
+}
+
+// The masters are (chunk_index, chunk_part).
+struct HotSlotMove {
+    src: (usize, usize),
+    dst: (usize, usize),
+    slots: Vec<usize>,
+}
+
 pub struct MetaStoreMigrate<'a> {
     store: &'a mut MetaStore,
 }
@@ -22,6 +40,7 @@ impl<'a> MetaStoreMigrate<'a> {
             .map_err(|_| MetaStoreError::InvalidClusterName)?;
         let new_epoch = self.store.bump_global_epoch();
 
+        let all_proxies = &self.store.all_proxies;
         let cluster = match self.store.clusters.get_mut(&cluster_name) {
             None => return Err(MetaStoreError::ClusterNotFound),
             Some(cluster) => cluster,
@@ -39,7 +58,8 @@ impl<'a> MetaStoreMigrate<'a> {
             return Err(err);
         }
 
-        let migration_slots = Self::remove_slots_from_src(cluster, new_epoch);
+        let weights = cluster.get_master_weights(all_proxies);
+        let migration_slots = Self::remove_slots_from_src(cluster, new_epoch, &weights);
         Self::assign_dst_slots(cluster, migration_slots.clone());
         cluster.set_epoch(new_epoch);
 
@@ -47,18 +67,20 @@ impl<'a> MetaStoreMigrate<'a> {
         Ok(())
     }
 
-    fn remove_slots_from_src(cluster: &mut ClusterStore, epoch: u64) -> Vec<MigrationSlots> {
+    fn remove_slots_from_src(
+        cluster: &mut ClusterStore,
+        epoch: u64,
+        weights: &[usize],
+    ) -> Vec<MigrationSlots> {
         let dst_chunk_num = cluster
             .chunks
             .iter()
             .filter(|chunk| chunk.stable_slots[0].is_none() && chunk.stable_slots[1].is_none())
             .count();
         let dst_master_num = dst_chunk_num * 2;
-        let master_num = cluster.chunks.len() * 2;
         let src_chunk_num = cluster.chunks.len() - dst_chunk_num;
         let src_master_num = src_chunk_num * 2;
-        let average = SLOT_NUM / master_num;
-        let remainder = SLOT_NUM - average * master_num;
+        let final_slot_nums = get_weighted_slot_nums(weights);
 
         let mut curr_dst_master_index = 0;
         let mut migration_slots = vec![];
@@ -70,16 +92,20 @@ impl<'a> MetaStoreMigrate<'a> {
                 if let Some(slot_range) = slot_range {
                     while curr_dst_master_index != dst_master_num {
                         let src_master_index = src_chunk_index * 2 + src_chunk_part;
-                        let src_r = (src_master_index < remainder) as usize; // true will be 1, false will be 0
                         let dst_master_index = src_master_num + curr_dst_master_index;
-                        let dst_r = (dst_master_index < remainder) as usize; // true will be 1, false will be 0
-                        let src_final_num = average + src_r;
-                        let dst_final_num = average + dst_r;
+                        let src_final_num = final_slot_nums[src_master_index];
+                        let dst_final_num = final_slot_nums[dst_master_index];
 
                         if slot_range.get_range_list().get_slots_num() <= src_final_num {
                             break;
                         }
 
+                        // Only happens when the weight of the destination is too small.
+                        if dst_final_num == 0 {
+                            curr_dst_master_index += 1;
+                            continue;
+                        }
+
                         let need_num = dst_final_num - curr_slots_num;
                         let available_num =
                             slot_range.get_range_list().get_slots_num() - src_final_num;
@@ -143,6 +169,205 @@ impl<'a> MetaStoreMigrate<'a> {
         migration_slots
     }
 
+    // Moves the hot slots from the busiest masters to the idlest ones
+    // according to the slot loads reported by the server proxies.
+    pub fn migrate_hot_slots(
+        &mut self,
+        cluster_name: String,
+        slot_loads: &HashMap<usize, u64>,
+    ) -> Result<(), MetaStoreError> {
+        let cluster_name = ClusterName::try_from(cluster_name.as_str())
+            .map_err(|_| MetaStoreError::InvalidClusterName)?;
+
+        // Nothing is changed before all the checks pass.
+        let moves = {
+            let cluster = match self.store.clusters.get(&cluster_name) {
+                None => return Err(MetaStoreError::ClusterNotFound),
+                Some(cluster) => cluster,
+            };
+
+            let empty_exists = cluster
+                .chunks
+                .iter()
+                .any(|chunk| chunk.stable_slots.iter().any(|slots| slots.is_none()));
+            if empty_exists {
+                return Err(MetaStoreError::FreeNodeFound);
+            }
+
+            Self::check_running_tasks(cluster)?;
+
+            Self::get_hot_slot_moves(cluster, slot_loads)
+        };
+        if moves.is_empty() {
+            return Err(MetaStoreError::SlotLoadAlreadyBalanced);
+        }
+
+        let new_epoch = self.store.bump_global_epoch();
+        let cluster = match self.store.clusters.get_mut(&cluster_name) {
+            None => return Err(MetaStoreError::ClusterNotFound),
+            Some(cluster) => cluster,
+        };
+        let migration_slots = Self::remove_hot_slots_from_src(cluster, new_epoch, moves);
+        Self::assign_dst_slots(cluster, migration_slots.clone());
+        cluster.set_epoch(new_epoch);
+
+        Self::print_migration_slot(cluster, &migration_slots);
+        Ok(())
+    }
+
+    // Returns the slots moved from the src master to the dst master.
+    fn get_hot_slot_moves(
+        cluster: &ClusterStore,
+        slot_loads: &HashMap<usize, u64>,
+    ) -> Vec<HotSlotMove> {
+        let get_load = |slot: &usize| slot_loads.get(slot).cloned().unwrap_or(0);
+
+        let mut masters: Vec<MasterSlotLoad> =
+            cluster
+                .chunks
+                .iter()
+                .enumerate()
+                .flat_map(|(chunk_index, chunk)| {
+                    chunk.stable_slots.iter().enumerate().filter_map(
+                        move |(chunk_part, slot_range)| {
+                            slot_range
+                                .as_ref()
+                                .map(|slot_range| (chunk_index, chunk_part, slot_range))
+                        },
+                    )
+                })
+                .map(|(chunk_index, chunk_part, slot_range)| {
+                    let slots: Vec<usize> = slot_range
+                        .get_range_list()
+                        .get_ranges()
+                        .iter()
+                        .flat_map(|range| range.start()..=range.end())
+                        .collect();
+                    let load = slots.iter().map(get_load).sum();
+                    MasterSlotLoad {
+                        chunk_index,
+                        chunk_part,
+                        slots,
+                        load,
+                    }
+                })
+                .collect();
+
+        if masters.len() < 2 {
+            return vec![];
+        }
+        let total_load: u64 = masters.iter().map(|master| master.load).sum();
+        let average_load = total_load / masters.len() as u64;
+
+        // (src master index, dst master index) => slots
+        let mut moves: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+        let mut moved_slots = HashSet::new();
+        for _ in 0..MAX_HOT_SLOT_MOVES {
+            let (hot, cold) = match (
+                (0..masters.len()).max_by_key(|i| masters[*i].load),
+                (0..masters.len()).min_by_key(|i| masters[*i].load),
+            ) {
+                (Some(hot), Some(cold)) => (hot, cold),
+                _ => break,
+            };
+            let hot_load = masters[hot].load;
+            let cold_load = masters[cold].load;
+            if hot_load * 100 <= average_load * (100 + HOT_SLOT_LOAD_TOLERANCE_PERCENT) {
+                break;
+            }
+            // Always leave at least one slot so that the master won't become a free node.
+            if masters[hot].slots.len() <= 1 {
+                break;
+            }
+
+            // Moving a slot only helps when its load is smaller than the gap.
+            // Pick the one making the two masters closest.
+            let gap = hot_load - cold_load;
+            let candidate = masters[hot]
+                .slots
+                .iter()
+                .enumerate()
+                .filter(|(_, slot)| !moved_slots.contains(*slot))
+                .map(|(i, slot)| (i, *slot, get_load(slot)))
+                .filter(|(_, _, load)| *load > 0 && *load < gap)
+                .min_by_key(|(_, _, load)| max(hot_load - load, cold_load + load));
+            let (index, slot, load) = match candidate {
+                Some(candidate) => candidate,
+                None => break,
+            };
+
+            masters[hot].slots.remove(index);
+            masters[hot].load -= load;
+            masters[cold].slots.push(slot);
+            masters[cold].load += load;
+            moved_slots.insert(slot);
+            moves.entry((hot, cold)).or_default().push(slot);
+        }
+
+        moves
+            .into_iter()
+            .map(|((src, dst), slots)| HotSlotMove {
+                src: (masters[src].chunk_index, masters[src].chunk_part),
+                dst: (masters[dst].chunk_index, masters[dst].chunk_part),
+                slots,
+            })
+            .collect()
+    }
+
+    // Only the src masters are changed.
+    fn remove_hot_slots_from_src(
+        cluster: &mut ClusterStore,
+        epoch: u64,
+        moves: Vec<HotSlotMove>,
+    ) -> Vec<MigrationSlots> {
+        let mut migration_slots = vec![];
+        for HotSlotMove { src, dst, slots } in moves.into_iter() {
+            let (src_chunk_index, src_chunk_part) = src;
+            let (dst_chunk_index, dst_chunk_part) = dst;
+
+            let moved_slots: HashSet<usize> = slots.iter().cloned().collect();
+            let chunk = cluster
+                .chunks
+                .get_mut(src_chunk_index)
+                .expect("remove_hot_slots_from_src");
+            if let Some(slot_range) = chunk.stable_slots[src_chunk_part].as_mut() {
+                let range_list = slot_range.get_mut_range_list();
+                let mut remaining = vec![];
+                for range in range_list.get_ranges().iter() {
+                    let mut start = range.start();
+                    for slot in range.start()..=range.end() {
+                        if moved_slots.contains(&slot) {
+                            if slot > start {
+                                remaining.push(Range(start, slot - 1));
+                            }
+                            start = slot + 1;
+                        }
+                    }
+                    if start <= range.end() {
+                        remaining.push(Range(start, range.end()));
+                    }
+                }
+                *range_list.get_mut_ranges() = remaining;
+                range_list.compact();
+            }
+
+            let mut range_list =
+                RangeList::new(slots.into_iter().map(|slot| Range(slot, slot)).collect());
+            range_list.compact();
+            migration_slots.push(MigrationSlots {
+                meta: MigrationMetaStore {
+                    epoch,
+                    src_chunk_index,
+                    src_chunk_part,
+                    dst_chunk_index,
+                    dst_chunk_part,
+                },
+                ranges: range_list.get_ranges().to_vec(),
+            });
+        }
+        migration_slots
+    }
+
     fn print_migration_slot(cluster: &ClusterStore, mgr_slots: &[MigrationSlots]) {
         info!("cluster {} start migration", cluster.name);
         for slots in mgr_slots.iter() {
@@ -181,7 +406,7 @@ impl<'a> MetaStoreMigrate<'a> {
         }
     }
 
-    fn check_slots_balance(cluster: &ClusterStore) {
+    fn check_slots_balance(cluster: &ClusterStore, weights: &[usize]) {
         for chunk in cluster.chunks.iter() {
             if !chunk.migrating_slots[0].is_empty() || !chunk.migrating_slots[1].is_empty() {
                 return;
@@ -207,25 +432,34 @@ impl<'a> MetaStoreMigrate<'a> {
             }
         }
 
-        let slot_num_without_zeros: Vec<usize> =
-            slot_num.iter().filter(|n| **n > 0).cloned().collect();
-
-        let (max, min) = match (
-            slot_num_without_zeros.iter().max(),
-            slot_num_without_zeros.iter().min(),
-        ) {
-            (Some(max), Some(min)) => (max, min),
-            _ => {
-                error!(
-                    "Invalid metadata: cluster without any slot {}",
-                    cluster.name
-                );
-                return;
-            }
-        };
+        let (slot_num_without_zeros, weights_without_zeros): (Vec<usize>, Vec<usize>) = slot_num
+            .iter()
+            .zip(weights.iter())
+            .filter(|(n, _)| **n > 0)
+            .map(|(n, w)| (*n, *w))
+            .unzip();
+
+        if slot_num_without_zeros.is_empty() {
+            error!(
+                "Invalid metadata: cluster without any slot {}",
+                cluster.name
+            );
+            return;
+        }
 
-        if max - min > 1 {
-            error!("Unbalanced slots: {:?}", slot_num);
+        let target_slot_num = get_weighted_slot_nums(&weights_without_zeros);
+        let deviations: Vec<i64> = slot_num_without_zeros
+            .iter()
+            .zip(target_slot_num.iter())
+            .map(|(n, target)| *n as i64 - *target as i64)
+            .collect();
+
+        // This is expected after moving hot slots so it's only a warning.
+        if deviations.iter().any(|d| d.abs() > 1) {
+            warn!(
+                "Unbalanced slots: {:?} weighted target: {:?} deviation: {:?}",
+                slot_num_without_zeros, target_slot_num, deviations
+            );
         }
     }
 
@@ -294,6 +528,7 @@ impl<'a> MetaStoreMigrate<'a> {
             .map_err(|_| MetaStoreError::InvalidClusterName)?;
         let new_epoch = self.store.bump_global_epoch();
 
+        let all_proxies = &self.store.all_proxies;
         let cluster = match self.store.clusters.get_mut(&cluster_name) {
             None => return Err(MetaStoreError::ClusterNotFound),
             Some(cluster) => cluster,
@@ -312,15 +547,16 @@ impl<'a> MetaStoreMigrate<'a> {
         }
 
         if new_node_num == 0
-            || new_node_num % CHUNK_NODE_NUM != 0
-            || new_node_num >= cluster.chunks.len() * CHUNK_NODE_NUM
+            || new_node_num % cluster.get_chunk_node_num() != 0
+            || new_node_num >= cluster.get_node_number()
         {
             return Err(MetaStoreError::InvalidNodeNum);
         }
 
-        let new_chunk_num = new_node_num / 4;
+        let new_chunk_num = new_node_num / cluster.get_chunk_node_num();
+        let weights = cluster.get_master_weights(all_proxies);
         let migration_slots =
-            Self::remove_slots_from_src_to_scale_down(cluster, new_epoch, new_chunk_num);
+            Self::remove_slots_from_src_to_scale_down(cluster, new_epoch, new_chunk_num, &weights);
         Self::assign_dst_slots(cluster, migration_slots.clone());
         cluster.set_epoch(new_epoch);
 
@@ -332,12 +568,12 @@ impl<'a> MetaStoreMigrate<'a> {
         cluster: &mut ClusterStore,
         epoch: u64,
         new_chunk_num: usize,
+        weights: &[usize],
     ) -> Vec<MigrationSlots> {
         let dst_chunk_num = new_chunk_num;
 
         let dst_master_num = dst_chunk_num * 2;
-        let average = SLOT_NUM / dst_master_num;
-        let remainder = SLOT_NUM - average * dst_master_num;
+        let final_slot_nums = get_weighted_slot_nums(&weights[..dst_master_num]);
 
         let mut curr_dst_master_index = 0;
         let mut migration_slots = vec![];
@@ -362,21 +598,28 @@ impl<'a> MetaStoreMigrate<'a> {
             for (src_chunk_part, slot_range) in src_chunk.stable_slots.iter_mut().enumerate() {
                 if let Some(slot_range) = slot_range {
                     while curr_dst_master_index != dst_master_num {
-                        let dst_r = (curr_dst_master_index < remainder) as usize; // true will be 1, false will be 0
-                        let dst_final_num = average + dst_r;
+                        let dst_final_num = final_slot_nums[curr_dst_master_index];
 
                         let dst_existing = *dst_existing_slots_num
                             .get(curr_dst_master_index)
                             .expect(
                             "remove_slots_from_src_to_scale_down: get dst existing slots number",
                         );
-                        let need_num = dst_final_num - curr_slots_num - dst_existing;
                         let available_num = slot_range.get_range_list().get_slots_num();
 
                         if available_num == 0 {
                             break;
                         }
 
+                        // The destination could already have more slots than the weighted target.
+                        // The sum of `need_num` is still enough for all the source slots.
+                        let need_num = dst_final_num.saturating_sub(curr_slots_num + dst_existing);
+                        if need_num == 0 {
+                            curr_dst_master_index += 1;
+                            curr_slots_num = 0;
+                            continue;
+                        }
+
                         let remove_num = min(need_num, available_num);
                         let num = slot_range
                             .get_range_list()
@@ -440,6 +683,7 @@ impl<'a> MetaStoreMigrate<'a> {
         let new_epoch = self.store.bump_global_epoch();
 
         let cluster_name = task.cluster_name.clone();
+        let all_proxies = &self.store.all_proxies;
         let cluster = self
             .store
             .clusters
@@ -554,12 +798,73 @@ impl<'a> MetaStoreMigrate<'a> {
 
         Self::compact_slots(cluster);
         cluster.set_epoch(new_epoch);
+        if !cluster.is_migrating() {
+            cluster.migration_paused = false;
+        }
+
+        let weights = cluster.get_master_weights(all_proxies);
+        Self::check_slots_balance(cluster, &weights);
+        Ok(())
+    }
 
-        Self::check_slots_balance(cluster);
+    pub fn pause_migration(&mut self, cluster_name: String) -> Result<(), MetaStoreError> {
+        let cluster = self.get_migrating_cluster(cluster_name)?;
+        cluster.migration_paused = true;
         Ok(())
     }
 
-    fn check_running_tasks(cluster: &mut ClusterStore) -> Result<(), MetaStoreError> {
+    pub fn resume_migration(&mut self, cluster_name: String) -> Result<(), MetaStoreError> {
+        let cluster = self.get_migrating_cluster(cluster_name)?;
+        cluster.migration_paused = false;
+        Ok(())
+    }
+
+    // The migrated keys have been deleted from the source nodes,
+    // so the slots are reverted by migrating them back in a new epoch.
+    pub fn cancel_migration(&mut self, cluster_name: String) -> Result<(), MetaStoreError> {
+        self.get_migrating_cluster(cluster_name.clone())?;
+        let new_epoch = self.store.bump_global_epoch();
+        let cluster = self.get_migrating_cluster(cluster_name)?;
+
+        for chunk in cluster.chunks.iter_mut() {
+            for migrating_slots in chunk.migrating_slots.iter_mut() {
+                for slot_range_store in migrating_slots.iter_mut() {
+                    let meta = &slot_range_store.meta;
+                    slot_range_store.meta = MigrationMetaStore {
+                        epoch: new_epoch,
+                        src_chunk_index: meta.dst_chunk_index,
+                        src_chunk_part: meta.dst_chunk_part,
+                        dst_chunk_index: meta.src_chunk_index,
+                        dst_chunk_part: meta.src_chunk_part,
+                    };
+                    slot_range_store.is_migrating = !slot_range_store.is_migrating;
+                }
+            }
+        }
+
+        cluster.migration_paused = false;
+        cluster.set_epoch(new_epoch);
+        Ok(())
+    }
+
+    fn get_migrating_cluster(
+        &mut self,
+        cluster_name: String,
+    ) -> Result<&mut ClusterStore, MetaStoreError> {
+        let cluster_name = ClusterName::try_from(cluster_name.as_str())
+            .map_err(|_| MetaStoreError::InvalidClusterName)?;
+        let cluster = self
+            .store
+            .clusters
+            .get_mut(&cluster_name)
+            .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
+        if !cluster.is_migrating() {
+            return Err(MetaStoreError::MigrationNotRunning);
+        }
+        Ok(cluster)
+    }
+
+    fn check_running_tasks(cluster: &ClusterStore) -> Result<(), MetaStoreError> {
         let running_migration = cluster
             .chunks
             .iter()
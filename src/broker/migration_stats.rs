@@ -0,0 +1,158 @@
+use crate::common::cluster::{ClusterName, MigrationProgress};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// The coordinators report the progress every 5 seconds.
+// The finished tasks are no longer reported and will expire.
+const MIGRATION_STATS_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterMigrationProgress {
+    pub task_number: usize,
+    pub keys_scanned: u64,
+    pub keys_migrated: u64,
+    pub bytes_transferred: u64,
+    pub errors: u64,
+    // The slowest task decides the progress of the whole cluster.
+    pub scanned_ratio: f64,
+    pub eta_secs: Option<u64>,
+}
+
+#[derive(Debug)]
+struct MigrationProgressRecord {
+    progress: MigrationProgress,
+    update_time: Instant,
+}
+
+// The latest progress of the migrating tasks reported by the coordinators.
+// Like the memory stats, it's not persisted nor replicated.
+#[derive(Debug, Default)]
+pub struct MigrationStatsStore {
+    tasks: HashMap<(ClusterName, String, String), MigrationProgressRecord>,
+}
+
+impl MigrationStatsStore {
+    pub fn add_stats(&mut self, migration_stats: Vec<MigrationProgress>) {
+        let now = Instant::now();
+        for progress in migration_stats.into_iter() {
+            let key = (
+                progress.cluster_name.clone(),
+                progress.src_node_address.clone(),
+                progress.dst_node_address.clone(),
+            );
+            let record = MigrationProgressRecord {
+                progress,
+                update_time: now,
+            };
+            self.tasks.insert(key, record);
+        }
+    }
+
+    // The ETA is unknown until all the tasks have started scanning.
+    pub fn get_cluster_progress(
+        &self,
+        cluster_name: &ClusterName,
+    ) -> Option<ClusterMigrationProgress> {
+        let now = Instant::now();
+        let mut cluster_progress: Option<ClusterMigrationProgress> = None;
+        let records = self.tasks.iter().filter(|((name, _, _), record)| {
+            name == cluster_name && now.duration_since(record.update_time) <= MIGRATION_STATS_TTL
+        });
+        for (_, record) in records {
+            let progress = &record.progress;
+            let eta_secs = progress.get_eta_secs();
+            let p = match cluster_progress.as_mut() {
+                Some(p) => p,
+                None => {
+                    cluster_progress = Some(ClusterMigrationProgress {
+                        task_number: 1,
+                        keys_scanned: progress.keys_scanned,
+                        keys_migrated: progress.keys_migrated,
+                        bytes_transferred: progress.bytes_transferred,
+                        errors: progress.errors,
+                        scanned_ratio: progress.scanned_ratio,
+                        eta_secs,
+                    });
+                    continue;
+                }
+            };
+            p.task_number += 1;
+            p.keys_scanned = p.keys_scanned.saturating_add(progress.keys_scanned);
+            p.keys_migrated = p.keys_migrated.saturating_add(progress.keys_migrated);
+            p.bytes_transferred = p
+                .bytes_transferred
+                .saturating_add(progress.bytes_transferred);
+            p.errors = p.errors.saturating_add(progress.errors);
+            p.scanned_ratio = p.scanned_ratio.min(progress.scanned_ratio);
+            p.eta_secs = match (p.eta_secs, eta_secs) {
+                (Some(eta1), Some(eta2)) => Some(eta1.max(eta2)),
+                _ => None,
+            };
+        }
+        cluster_progress
+    }
+
+    pub fn remove_expired(&mut self) {
+        let now = Instant::now();
+        self.tasks
+            .retain(|_, record| now.duration_since(record.update_time) <= MIGRATION_STATS_TTL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn gen_progress(
+        cluster_name: &str,
+        dst_node_address: &str,
+        scanned_ratio: f64,
+    ) -> MigrationProgress {
+        MigrationProgress {
+            cluster_name: ClusterName::try_from(cluster_name).unwrap(),
+            src_node_address: "127.0.0.1:7000".to_string(),
+            dst_node_address: dst_node_address.to_string(),
+            keys_scanned: 100,
+            keys_migrated: 50,
+            bytes_transferred: 1000,
+            errors: 1,
+            scanned_ratio,
+            elapsed_secs: 10,
+        }
+    }
+
+    #[test]
+    fn test_cluster_migration_progress() {
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let mut store = MigrationStatsStore::default();
+        assert!(store.get_cluster_progress(&cluster_name).is_none());
+
+        store.add_stats(vec![
+            gen_progress("mycluster", "127.0.0.1:7001", 0.5),
+            gen_progress("mycluster", "127.0.0.1:7002", 0.25),
+            gen_progress("othercluster", "127.0.0.1:7003", 0.0),
+        ]);
+        let progress = store.get_cluster_progress(&cluster_name).unwrap();
+        assert_eq!(progress.task_number, 2);
+        assert_eq!(progress.keys_scanned, 200);
+        assert_eq!(progress.keys_migrated, 100);
+        assert_eq!(progress.bytes_transferred, 2000);
+        assert_eq!(progress.errors, 2);
+        assert_eq!(progress.scanned_ratio, 0.25);
+        assert_eq!(progress.eta_secs, Some(30));
+
+        let other = ClusterName::try_from("othercluster").unwrap();
+        let progress = store.get_cluster_progress(&other).unwrap();
+        assert_eq!(progress.eta_secs, None);
+
+        // The latest report replaces the old one.
+        store.add_stats(vec![gen_progress("mycluster", "127.0.0.1:7002", 0.5)]);
+        let progress = store.get_cluster_progress(&cluster_name).unwrap();
+        assert_eq!(progress.task_number, 2);
+        assert_eq!(progress.eta_secs, Some(10));
+
+        store.remove_expired();
+        assert!(store.get_cluster_progress(&cluster_name).is_some());
+    }
+}
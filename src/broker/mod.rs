@@ -1,10 +1,15 @@
 mod epoch;
+mod memory_stats;
 mod migrate;
+mod migration_stats;
 mod persistence;
+mod plan;
 mod query;
+mod repl_stats;
 mod replication;
 mod resource;
 mod service;
+mod slot_stats;
 mod store;
 mod update;
 
@@ -12,6 +17,7 @@ mod ordered_proxy;
 mod utils;
 
 pub use self::persistence::{JsonFileStorage, MetaStorage, MetaSyncError};
+pub use self::plan::{ScalePlan, SlotMovement};
 pub use self::replication::{JsonMetaReplicator, MetaReplicator};
 pub use self::service::{
     configure_app, MemBrokerConfig, MemBrokerService, ReplicaAddresses, MEM_BROKER_API_VERSION,
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use super::super::store::{MetaStore, MetaStoreError};
+    use super::super::store::{MetaStore, MetaStoreError, ProxyLocation};
     use super::super::utils::tests::{check_cluster_and_proxy, check_cluster_slots};
     use crate::common::cluster::{Cluster, ClusterName, Role};
     use std::collections::HashSet;
@@ -16,7 +16,14 @@ mod tests {
                 format!("127.0.0.{}:60{:02}", host_index, host_index * 2 + 1),
             ];
             store
-                .add_proxy(proxy_address, node_addresses, None, Some(host_index))
+                .add_proxy(
+                    proxy_address,
+                    node_addresses,
+                    None,
+                    Some(host_index),
+                    None,
+                    ProxyLocation::default(),
+                )
                 .unwrap();
         }
     }
@@ -42,12 +49,26 @@ mod tests {
         let nodes = ["127.0.0.1:6000".to_string(), "127.0.0.1:6001".to_string()];
 
         let err = store
-            .add_proxy(proxy_address.to_string(), nodes.clone(), None, None)
+            .add_proxy(
+                proxy_address.to_string(),
+                nodes.clone(),
+                None,
+                None,
+                None,
+                ProxyLocation::default(),
+            )
             .unwrap_err();
         assert_eq!(err, MetaStoreError::MissingIndex);
 
         store
-            .add_proxy(proxy_address.to_string(), nodes.clone(), None, Some(1))
+            .add_proxy(
+                proxy_address.to_string(),
+                nodes.clone(),
+                None,
+                Some(1),
+                None,
+                ProxyLocation::default(),
+            )
             .unwrap();
         assert_eq!(store.get_global_epoch(), 1);
         assert_eq!(store.all_proxies.len(), 1);
@@ -306,6 +327,8 @@ mod tests {
                 nodes,
                 None,
                 Some(failed_proxy_index),
+                None,
+                ProxyLocation::default(),
             )
             .unwrap_err();
         assert_eq!(err, MetaStoreError::AlreadyExisted);
@@ -0,0 +1,207 @@
+use super::store::{ChunkStore, MetaStore, MetaStoreError, ScaleOp};
+use crate::common::cluster::{ClusterName, MigrationMeta, RangeList, SlotRangeTag};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SlotMovement {
+    pub range_list: RangeList,
+    pub meta: MigrationMeta,
+}
+
+// The result of running a scaling operation against a copy of the metadata.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScalePlan {
+    pub chunks: Vec<ChunkStore>,
+    pub slot_movements: Vec<SlotMovement>,
+    pub consumed_proxy_num: usize,
+}
+
+pub struct MetaStorePlan<'a> {
+    store: &'a MetaStore,
+}
+
+impl<'a> MetaStorePlan<'a> {
+    pub fn new(store: &'a MetaStore) -> Self {
+        Self { store }
+    }
+
+    pub fn plan_auto_add_nodes(
+        &self,
+        cluster_name: String,
+        num: usize,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        self.dry_run(cluster_name.clone(), |store| {
+            store.auto_add_nodes(cluster_name, num).map(|_| ())
+        })
+    }
+
+    pub fn plan_migrate_slots(&self, cluster_name: String) -> Result<ScalePlan, MetaStoreError> {
+        self.dry_run(cluster_name.clone(), |store| {
+            store.migrate_slots(cluster_name)
+        })
+    }
+
+    pub fn plan_migrate_slots_to_scale_down(
+        &self,
+        cluster_name: String,
+        new_node_num: usize,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        self.dry_run(cluster_name.clone(), |store| {
+            store.migrate_slots_to_scale_down(cluster_name, new_node_num)
+        })
+    }
+
+    // The real API needs to wait for the new proxies to get synced between the two phases.
+    // There's nothing to wait for in the copied store so both phases are run at once.
+    pub fn plan_auto_scale_node_number(
+        &self,
+        cluster_name: String,
+        expected_num: usize,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        self.dry_run(cluster_name.clone(), |store| {
+            let (scale_op, _, _) =
+                store.auto_change_node_number(cluster_name.clone(), expected_num)?;
+            match scale_op {
+                ScaleOp::NoOp | ScaleOp::ScaleDown => Ok(()),
+                ScaleOp::ScaleOut => store.auto_scale_out_node_number(cluster_name, expected_num),
+            }
+        })
+    }
+
+    pub fn plan_migrate_hot_slots(
+        &self,
+        cluster_name: String,
+        slot_loads: &HashMap<usize, u64>,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        self.dry_run(cluster_name.clone(), |store| {
+            store.migrate_hot_slots(cluster_name, slot_loads)
+        })
+    }
+
+    fn dry_run<F>(&self, cluster_name: String, op: F) -> Result<ScalePlan, MetaStoreError>
+    where
+        F: FnOnce(&mut MetaStore) -> Result<(), MetaStoreError>,
+    {
+        let name = ClusterName::try_from(cluster_name.as_str())
+            .map_err(|_| MetaStoreError::InvalidClusterName)?;
+
+        let mut store = self.store.clone();
+        op(&mut store)?;
+
+        let cluster = store
+            .clusters
+            .get(&name)
+            .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
+
+        let slot_movements = cluster
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.migrating_slots.iter())
+            .flat_map(|slot_range_stores| slot_range_stores.iter())
+            .filter(|slot_range_store| slot_range_store.is_migrating)
+            .filter_map(|slot_range_store| {
+                match slot_range_store.to_slot_range(&cluster.chunks).tag {
+                    SlotRangeTag::Migrating(meta) => Some(SlotMovement {
+                        range_list: slot_range_store.range_list.clone(),
+                        meta,
+                    }),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        let original_proxies: HashSet<&String> = self
+            .store
+            .all_proxies
+            .values()
+            .filter(|proxy| proxy.cluster.as_ref() == Some(&name))
+            .map(|proxy| &proxy.proxy_address)
+            .collect();
+        let consumed_proxy_num = cluster
+            .get_proxy_addresses()
+            .iter()
+            .filter(|proxy_address| !original_proxies.contains(proxy_address))
+            .count();
+
+        Ok(ScalePlan {
+            chunks: cluster.chunks.clone(),
+            slot_movements,
+            consumed_proxy_num,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::utils::tests::add_testing_proxies;
+    use super::*;
+    use crate::common::utils::SLOT_NUM;
+
+    const CLUSTER_NAME: &str = "testcluster";
+
+    #[test]
+    fn test_plan_does_not_change_store() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+        store.add_cluster(CLUSTER_NAME.to_string(), 8).unwrap();
+        let epoch = store.get_global_epoch();
+        let free_proxy_num = store.get_free_proxies().len();
+
+        let plan = store
+            .plan_auto_scale_node_number(CLUSTER_NAME.to_string(), 16)
+            .unwrap();
+        assert_eq!(plan.chunks.len(), 4);
+        assert_eq!(plan.consumed_proxy_num, 4);
+        assert!(!plan.slot_movements.is_empty());
+
+        assert_eq!(store.get_global_epoch(), epoch);
+        assert_eq!(store.get_free_proxies().len(), free_proxy_num);
+        let cluster = store.get_cluster_by_name(CLUSTER_NAME, 0).unwrap();
+        assert_eq!(cluster.get_nodes().len(), 8);
+    }
+
+    #[test]
+    fn test_plan_scale_down() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+        store.add_cluster(CLUSTER_NAME.to_string(), 8).unwrap();
+
+        let plan = store
+            .plan_migrate_slots_to_scale_down(CLUSTER_NAME.to_string(), 4)
+            .unwrap();
+        assert_eq!(plan.consumed_proxy_num, 0);
+        let dst_proxies = &plan.chunks[0].proxy_addresses;
+        for movement in plan.slot_movements.iter() {
+            assert!(dst_proxies.contains(&movement.meta.dst_proxy_address));
+            assert!(!dst_proxies.contains(&movement.meta.src_proxy_address));
+        }
+        let moved: usize = plan
+            .slot_movements
+            .iter()
+            .map(|movement| movement.range_list.get_slots_num())
+            .sum();
+        assert_eq!(moved, SLOT_NUM / 2);
+
+        assert!(!store
+            .clusters
+            .values()
+            .any(|cluster| cluster.is_migrating()));
+    }
+
+    #[test]
+    fn test_plan_error() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+        store.add_cluster(CLUSTER_NAME.to_string(), 8).unwrap();
+
+        let err = store
+            .plan_migrate_slots(CLUSTER_NAME.to_string())
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::SlotsAlreadyEven);
+        let err = store
+            .plan_auto_add_nodes("notexists".to_string(), 4)
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::ClusterNotFound);
+    }
+}
@@ -1,9 +1,6 @@
-use super::store::{
-    ChunkRolePosition, ClusterInfo, ClusterStore, HostProxy, MetaStore, CHUNK_HALF_NODE_NUM,
-    CHUNK_NODE_NUM,
-};
+use super::store::{ChunkStore, ClusterInfo, ClusterStore, HostProxy, MetaStore};
 use crate::broker::store::ProxyResource;
-use crate::common::cluster::{Cluster, Node, PeerProxy, Proxy, ReplMeta, ReplPeer};
+use crate::common::cluster::{Cluster, Node, PeerProxy, Proxy};
 use crate::common::cluster::{ClusterName, Role};
 use itertools::Itertools;
 use std::collections::{HashMap, HashSet};
@@ -136,6 +133,15 @@ impl<'a> MetaStoreQuery<'a> {
         }
     }
 
+    pub fn get_paused_migrations(&self) -> Vec<ClusterName> {
+        self.store
+            .clusters
+            .values()
+            .filter(|cluster| cluster.migration_paused && cluster.is_migrating())
+            .map(|cluster| cluster.name.clone())
+            .collect()
+    }
+
     pub fn get_cluster_by_name(&self, cluster_name: &str, migration_limit: u64) -> Option<Cluster> {
         let cluster_name = ClusterName::try_from(cluster_name).ok()?;
 
@@ -157,106 +163,10 @@ impl<'a> MetaStoreQuery<'a> {
     }
 
     fn cluster_store_to_cluster(cluster_store: &ClusterStore) -> Cluster {
-        let cluster_name = cluster_store.name.clone();
-
-        let nodes = cluster_store
-            .chunks
-            .iter()
-            .map(|chunk| {
-                let mut nodes = vec![];
-                for i in 0..CHUNK_NODE_NUM {
-                    let address = chunk
-                        .node_addresses
-                        .get(i)
-                        .expect("MetaStore::get_cluster_by_name: failed to get node")
-                        .clone();
-                    let proxy_address = chunk
-                        .proxy_addresses
-                        .get(i / 2)
-                        .expect("MetaStore::get_cluster_by_name: failed to get proxy")
-                        .clone();
-
-                    // get slots
-                    let mut slots = vec![];
-                    let (first_slot_index, second_slot_index) = match chunk.role_position {
-                        ChunkRolePosition::Normal => (0, 2),
-                        ChunkRolePosition::FirstChunkMaster => (0, 1),
-                        ChunkRolePosition::SecondChunkMaster => (3, 2),
-                    };
-                    if i == first_slot_index {
-                        let mut first_slots = vec![];
-                        if let Some(stable_slots) = &chunk.stable_slots[0] {
-                            first_slots.push(stable_slots.clone());
-                        }
-                        slots.append(&mut first_slots);
-                        let slot_ranges: Vec<_> = chunk.migrating_slots[0]
-                            .iter()
-                            .map(|slot_range_store| {
-                                slot_range_store.to_slot_range(&cluster_store.chunks)
-                            })
-                            .collect();
-                        slots.extend(slot_ranges);
-                    }
-                    if i == second_slot_index {
-                        let mut second_slots = vec![];
-                        if let Some(stable_slots) = &chunk.stable_slots[1] {
-                            second_slots.push(stable_slots.clone());
-                        }
-                        slots.append(&mut second_slots);
-                        let slot_ranges: Vec<_> = chunk.migrating_slots[1]
-                            .iter()
-                            .map(|slot_range_store| {
-                                slot_range_store.to_slot_range(&cluster_store.chunks)
-                            })
-                            .collect();
-                        slots.extend(slot_ranges);
-                    }
-
-                    // get repl
-                    let mut role = Role::Master;
-                    match chunk.role_position {
-                        ChunkRolePosition::Normal if i % 2 == 1 => role = Role::Replica,
-                        ChunkRolePosition::FirstChunkMaster if i >= CHUNK_HALF_NODE_NUM => {
-                            role = Role::Replica
-                        }
-                        ChunkRolePosition::SecondChunkMaster if i < CHUNK_HALF_NODE_NUM => {
-                            role = Role::Replica
-                        }
-                        _ => (),
-                    }
-
-                    let peer_index = match i {
-                        0 => 3,
-                        1 => 2,
-                        2 => 1,
-                        _ => 0,
-                    };
-                    let peer = ReplPeer {
-                        node_address: chunk
-                            .node_addresses
-                            .get(peer_index)
-                            .expect("MetaStore::get_cluster_by_name: failed to get peer node")
-                            .clone(),
-                        proxy_address: chunk
-                            .proxy_addresses
-                            .get(peer_index / 2)
-                            .expect("MetaStore::get_cluster_by_name: failed to get peer proxy")
-                            .clone(),
-                    };
-                    let repl = ReplMeta::new(role, vec![peer]);
-
-                    let node = Node::new(address, proxy_address, cluster_name.clone(), slots, repl);
-                    nodes.push(node);
-                }
-                nodes
-            })
-            .flatten()
-            .collect();
-
         Cluster::new(
             cluster_store.name.clone(),
             cluster_store.epoch,
-            nodes,
+            cluster_store.to_nodes(),
             cluster_store.config.clone(),
         )
     }
@@ -327,23 +237,11 @@ impl<'a> MetaStoreQuery<'a> {
                                 data_correct = false;
                             }
                             proxy_address_set.insert(proxy_address.clone());
-                            let (host, node_addresses) = if i == 0 {
-                                (
-                                    chunk.hosts[0].clone(),
-                                    [
-                                        chunk.node_addresses[0].clone(),
-                                        chunk.node_addresses[1].clone(),
-                                    ],
-                                )
-                            } else {
-                                (
-                                    chunk.hosts[1].clone(),
-                                    [
-                                        chunk.node_addresses[2].clone(),
-                                        chunk.node_addresses[3].clone(),
-                                    ],
-                                )
-                            };
+                            let host = chunk.hosts[i].clone();
+                            let node_addresses = ChunkStore::get_proxy_node_indices(i)
+                                .iter()
+                                .map(|node_index| chunk.node_addresses[*node_index].clone())
+                                .collect::<Vec<_>>();
                             if host != proxy_resource.host {
                                 error!(
                                     "invalid host for {} {:?} != {:?}",
@@ -351,7 +249,7 @@ impl<'a> MetaStoreQuery<'a> {
                                 );
                                 data_correct = false;
                             }
-                            if node_addresses != proxy_resource.node_addresses {
+                            if node_addresses[..] != proxy_resource.node_addresses[..] {
                                 error!(
                                     "invalid node_addresses for {} {:?} != {:?}",
                                     proxy_address, node_addresses, proxy_resource.node_addresses
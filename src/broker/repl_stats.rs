@@ -0,0 +1,97 @@
+use crate::common::cluster::NodeReplStats;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// The offsets of the failed masters are kept for a while
+// so that the lost data could still be estimated in failover.
+const REPL_STATS_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+struct NodeReplRecord {
+    repl_offset: u64,
+    update_time: Instant,
+}
+
+// The latest replication offsets of the nodes reported by the coordinators.
+// Like the memory stats, it's not persisted nor replicated.
+#[derive(Debug, Default)]
+pub struct ReplStatsStore {
+    nodes: HashMap<String, NodeReplRecord>,
+}
+
+impl ReplStatsStore {
+    pub fn add_stats(&mut self, repl_stats: Vec<NodeReplStats>) {
+        let now = Instant::now();
+        for stats in repl_stats.into_iter() {
+            let record = NodeReplRecord {
+                repl_offset: stats.repl_offset,
+                update_time: now,
+            };
+            self.nodes.insert(stats.node_address, record);
+        }
+    }
+
+    pub fn get_offsets(&self) -> HashMap<String, u64> {
+        let now = Instant::now();
+        self.nodes
+            .iter()
+            .filter(|(_, record)| now.duration_since(record.update_time) <= REPL_STATS_TTL)
+            .map(|(address, record)| (address.clone(), record.repl_offset))
+            .collect()
+    }
+
+    // Returns the time since the latest report of any of the nodes.
+    pub fn get_last_update_elapsed<'a, It>(&self, node_addresses: It) -> Option<Duration>
+    where
+        It: Iterator<Item = &'a str>,
+    {
+        let now = Instant::now();
+        node_addresses
+            .filter_map(|address| self.nodes.get(address))
+            .map(|record| now.duration_since(record.update_time))
+            .min()
+    }
+
+    pub fn remove_expired(&mut self) {
+        let now = Instant::now();
+        self.nodes
+            .retain(|_, record| now.duration_since(record.update_time) <= REPL_STATS_TTL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repl_offsets() {
+        let mut store = ReplStatsStore::default();
+        store.add_stats(vec![
+            NodeReplStats {
+                node_address: "127.0.0.1:7000".to_string(),
+                repl_offset: 233,
+            },
+            NodeReplStats {
+                node_address: "127.0.0.1:7001".to_string(),
+                repl_offset: 200,
+            },
+        ]);
+        store.add_stats(vec![NodeReplStats {
+            node_address: "127.0.0.1:7001".to_string(),
+            repl_offset: 230,
+        }]);
+
+        let offsets = store.get_offsets();
+        assert_eq!(offsets.len(), 2);
+        assert_eq!(offsets.get("127.0.0.1:7000"), Some(&233));
+        assert_eq!(offsets.get("127.0.0.1:7001"), Some(&230));
+
+        let elapsed = store.get_last_update_elapsed(vec!["127.0.0.1:7000"].into_iter());
+        assert!(elapsed.unwrap() < REPL_STATS_TTL);
+        let elapsed = store.get_last_update_elapsed(vec!["127.0.0.1:7002"].into_iter());
+        assert!(elapsed.is_none());
+
+        store.remove_expired();
+        assert_eq!(store.get_offsets().len(), 2);
+    }
+}
@@ -1,6 +1,16 @@
-use super::store::{MetaStore, MetaStoreError};
+use super::store::{LocationLevel, MetaStore, MetaStoreError};
 use std::collections::HashMap;
 
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ZoneFailureTolerance {
+    pub zone: String,
+    // The chunks with all the proxies in this zone will lose
+    // both the masters and the replicas when the zone fails.
+    // The chunks without replicas are always counted.
+    pub lost_chunk_num: usize,
+    pub enough_resource: bool,
+}
+
 pub struct ResourceChecker {
     store: MetaStore,
 }
@@ -36,6 +46,49 @@ impl ResourceChecker {
         Ok(hosts)
     }
 
+    // Only the proxies with zone set are checked.
+    pub fn check_zone_failure_tolerance(
+        &self,
+        migration_limit: u64,
+    ) -> Result<Vec<ZoneFailureTolerance>, MetaStoreError> {
+        // zone => proxy addresses
+        let mut proxy_map = HashMap::new();
+        for (proxy_address, proxy_resource) in self.store.all_proxies.iter() {
+            if let Some(zone) = proxy_resource.get_location(LocationLevel::Zone) {
+                proxy_map
+                    .entry(zone)
+                    .or_insert_with(Vec::new)
+                    .push(proxy_address.clone());
+            }
+        }
+
+        let mut zones = vec![];
+        for (zone, proxy_addresses) in proxy_map.into_iter() {
+            let lost_chunk_num = self
+                .store
+                .clusters
+                .values()
+                .flat_map(|cluster| cluster.chunks.iter())
+                .filter(|chunk| {
+                    chunk
+                        .proxy_addresses
+                        .iter()
+                        .all(|address| proxy_addresses.contains(address))
+                })
+                .count();
+            let enough_resource =
+                self.check_failure_tolerance_for_one_host(&proxy_addresses, migration_limit)?;
+            zones.push(ZoneFailureTolerance {
+                zone,
+                lost_chunk_num,
+                enough_resource,
+            });
+        }
+        zones.sort_by(|a, b| a.zone.cmp(&b.zone));
+
+        Ok(zones)
+    }
+
     fn check_failure_tolerance_for_one_host(
         &self,
         proxy_addresses: &[String],
@@ -58,6 +111,7 @@ impl ResourceChecker {
 
 #[cfg(test)]
 mod tests {
+    use super::super::store::ProxyLocation;
     use super::super::utils::tests::add_testing_proxies;
     use super::*;
 
@@ -95,4 +149,34 @@ mod tests {
         let proxies = res.unwrap();
         assert!(!proxies.is_empty());
     }
+
+    #[test]
+    fn test_zone_failure_tolerance() {
+        let mut store = MetaStore::new(false);
+        for zone_index in 1..=3 {
+            for i in 1..=2 {
+                let proxy_address = format!("127.0.0.{}:70{:02}", zone_index, i);
+                let node_addresses = [
+                    format!("127.0.0.{}:60{:02}", zone_index, i * 2),
+                    format!("127.0.0.{}:60{:02}", zone_index, i * 2 + 1),
+                ];
+                let location = ProxyLocation {
+                    zone: Some(format!("zone{}", zone_index)),
+                    rack: None,
+                };
+                store
+                    .add_proxy(proxy_address, node_addresses, None, None, None, location)
+                    .unwrap();
+            }
+        }
+        store.add_cluster("test_cluster".to_string(), 8).unwrap();
+
+        let checker = ResourceChecker::new(store);
+        let zones = checker.check_zone_failure_tolerance(2).unwrap();
+        assert_eq!(zones.len(), 3);
+        for (i, zone) in zones.iter().enumerate() {
+            assert_eq!(zone.zone, format!("zone{}", i + 1));
+            assert_eq!(zone.lost_chunk_num, 0);
+        }
+    }
 }
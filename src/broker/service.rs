@@ -1,21 +1,36 @@
+use super::memory_stats::{suggest_node_number, ClusterMemoryUsage, MemoryStatsStore};
+use super::migration_stats::MigrationStatsStore;
 use super::persistence::{MetaStorage, MetaSyncError};
+use super::plan::ScalePlan;
+use super::repl_stats::ReplStatsStore;
 use super::replication::MetaReplicator;
-use super::resource::ResourceChecker;
-use super::store::{ClusterInfo, MetaStore, MetaStoreError, ScaleOp, CHUNK_HALF_NODE_NUM};
+use super::resource::{ResourceChecker, ZoneFailureTolerance};
+use super::slot_stats::{SlotLoadMetric, SlotStatsStore};
+use super::store::{
+    ClusterInfo, MetaStore, MetaStoreError, ProxyLocation, ScaleOp, CHUNK_HALF_NODE_NUM,
+    DEFAULT_REPLICA_NUM,
+};
 use crate::broker::epoch::{fetch_max_epoch, wait_for_proxy_epoch, EpochFetchResult};
 use crate::common::atomic_lock::AtomicLock;
-use crate::common::cluster::{Cluster, ClusterName, MigrationTaskMeta, Node, Proxy};
+use crate::common::cluster::{
+    Cluster, ClusterName, MigrationProgress, MigrationTaskMeta, Node, NodeMemoryStats,
+    NodeReplStats, Proxy, Role, SlotStats, SwitchoverTask,
+};
 use crate::common::version::UNDERMOON_VERSION;
-use crate::coordinator::http_mani_broker::ReplaceProxyResponse;
+use crate::coordinator::http_mani_broker::{
+    MemoryStatsPayload, MigrationStatsPayload, ReplStatsPayload, ReplaceProxyResponse,
+    SlotStatsPayload,
+};
 use crate::coordinator::http_meta_broker::{
     ClusterNamesPayload, ClusterPayload, FailedProxiesPayload, FailuresPayload,
-    ProxyAddressesPayload, ProxyPayload,
+    ProxyAddressesPayload, ProxyPayload, SwitchoverTasksPayload,
 };
 use actix_http::ResponseBuilder;
 use actix_web::dev::Service;
-use actix_web::{error, http, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{error, http, web, Either, HttpRequest, HttpResponse, Responder};
 use arc_swap::ArcSwap;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::num::NonZeroU64;
 use std::sync::{Arc, RwLock};
 
@@ -79,8 +94,30 @@ pub fn configure_app(cfg: &mut web::ServiceConfig, service: Arc<MemBrokerService
                 "/proxies/failover/{address}",
                 web::post().to(replace_failed_node),
             )
+            .route("/nodes/failures", web::get().to(get_node_failures))
+            .route(
+                "/nodes/failures/{node_address}/{reporter_id}",
+                web::post().to(add_node_failure),
+            )
+            .route(
+                "/nodes/failover/{node_address}",
+                web::post().to(failover_node),
+            )
             .route("/clusters/migrations", web::put().to(commit_migration))
+            .route("/clusters/migrations/paused", web::get().to(get_paused_migrations))
             .route("/proxies/failed/addresses", web::get().to(get_failed_proxies))
+            .route("/proxies/slot_stats/{proxy_address}", web::post().to(add_slot_stats))
+            .route("/proxies/memory_stats/{proxy_address}", web::post().to(add_memory_stats))
+            .route("/proxies/repl_stats/{proxy_address}", web::post().to(add_repl_stats))
+            .route(
+                "/proxies/migration_stats/{proxy_address}",
+                web::post().to(add_migration_stats),
+            )
+            .route("/proxies/switchover", web::get().to(get_switchover_tasks))
+            .route(
+                "/proxies/switchover/commit/{proxy_address}",
+                web::post().to(commit_switchover),
+            )
 
             // Additional api
             .route("/clusters/info/{cluster_name}", web::get().to(get_cluster_info_by_name))
@@ -101,8 +138,16 @@ pub fn configure_app(cfg: &mut web::ServiceConfig, service: Arc<MemBrokerService
             )
             .route("/clusters/migrations/expand/{cluster_name}", web::post().to(migrate_slots))
             .route("/clusters/migrations/auto/{cluster_name}/{node_number}", web::post().to(auto_scale_node_number))
+            .route("/clusters/migrations/hot_slots/{cluster_name}", web::post().to(migrate_hot_slots))
+            .route("/clusters/migrations/pause/{cluster_name}", web::post().to(pause_migration))
+            .route("/clusters/migrations/resume/{cluster_name}", web::post().to(resume_migration))
+            .route("/clusters/migrations/cancel/{cluster_name}", web::post().to(cancel_migration))
+            .route("/clusters/slot_stats/{cluster_name}", web::get().to(get_slot_stats))
+            .route("/clusters/memory/{cluster_name}", web::get().to(get_memory_usage))
             .route("/clusters/config/{cluster_name}", web::patch().to(change_config))
             .route("/clusters/balance/{cluster_name}", web::put().to(balance_masters))
+            .route("/proxies/switchover/{proxy_address}", web::post().to(add_switchover))
+            .route("/hosts/switchover/{host}", web::post().to(add_host_switchover))
 
             .route("/proxies/meta", web::post().to(add_proxy))
             .route(
@@ -110,6 +155,7 @@ pub fn configure_app(cfg: &mut web::ServiceConfig, service: Arc<MemBrokerService
                 web::delete().to(remove_proxy),
             )
             .route("/resources/failures/check", web::post().to(check_resource_for_failures))
+            .route("/resources/failures/check/zones", web::post().to(check_zone_failure_tolerance))
             .route("/config", web::put().to(change_broker_config))
             .route("/config", web::get().to(get_broker_config))
             .route("/epoch", web::get().to(get_epoch))
@@ -133,6 +179,16 @@ pub struct MemBrokerConfig {
     pub replica_addresses: ReplicaAddresses,
     pub sync_meta_interval: Option<NonZeroU64>,
     pub enable_ordered_proxy: bool,
+    // In percentage of the total maxmemory of the masters. Zero disables them.
+    pub memory_high_water_mark: u64,
+    pub memory_low_water_mark: u64,
+    pub memory_check_interval: Option<NonZeroU64>,
+    // Refuse to failover when the new master lags behind more than this in bytes.
+    // Zero disables it.
+    pub failover_max_repl_lag: u64,
+    // Stop waiting for the replicas to catch up
+    // after the failed proxy has not reported its offsets for this long in seconds.
+    pub failover_repl_lag_timeout: u64,
     pub debug: bool,
 }
 
@@ -155,6 +211,10 @@ pub struct MemBrokerService {
     meta_storage: Arc<dyn MetaStorage + Send + Sync + 'static>,
     meta_replicator: Arc<dyn MetaReplicator + Send + Sync + 'static>,
     scale_lock: AtomicLock,
+    slot_stats: RwLock<SlotStatsStore>,
+    memory_stats: RwLock<MemoryStatsStore>,
+    repl_stats: RwLock<ReplStatsStore>,
+    migration_stats: RwLock<MigrationStatsStore>,
 }
 
 impl MemBrokerService {
@@ -180,6 +240,10 @@ impl MemBrokerService {
             meta_storage,
             meta_replicator,
             scale_lock: AtomicLock::default(),
+            slot_stats: RwLock::new(SlotStatsStore::default()),
+            memory_stats: RwLock::new(MemoryStatsStore::default()),
+            repl_stats: RwLock::new(ReplStatsStore::default()),
+            migration_stats: RwLock::new(MigrationStatsStore::default()),
         };
         Ok(service)
     }
@@ -255,10 +319,19 @@ impl MemBrokerService {
 
     pub fn get_cluster_info_by_name(&self, name: &str) -> Option<ClusterInfo> {
         let migration_limit = self.config.migration_limit;
-        self.store
+        let mut cluster_info = self
+            .store
             .read()
             .expect("MemBrokerService::get_cluster_info_by_name")
-            .get_cluster_info_by_name(name, migration_limit)
+            .get_cluster_info_by_name(name, migration_limit)?;
+        if cluster_info.is_migrating {
+            cluster_info.migration_progress = self
+                .migration_stats
+                .read()
+                .expect("MemBrokerService::get_cluster_info_by_name")
+                .get_cluster_progress(&cluster_info.name);
+        }
+        Some(cluster_info)
     }
 
     pub fn add_proxy(&self, proxy_resource: ProxyResourcePayload) -> Result<(), MetaStoreError> {
@@ -267,25 +340,39 @@ impl MemBrokerService {
             nodes,
             host,
             index,
+            weight,
+            location,
         } = proxy_resource;
         self.store
             .write()
             .expect("MemBrokerService::add_proxy")
-            .add_proxy(proxy_address, nodes, host, index)
+            .add_proxy(proxy_address, nodes, host, index, weight, location)
     }
 
-    pub fn add_cluster(&self, cluster_name: String, node_num: usize) -> Result<(), MetaStoreError> {
+    pub fn add_cluster(
+        &self,
+        cluster_name: String,
+        node_num: usize,
+        replica_num: usize,
+    ) -> Result<(), MetaStoreError> {
         self.store
             .write()
             .expect("MemBrokerService::add_cluster")
-            .add_cluster(cluster_name, node_num)
+            .add_cluster_with_replica_num(cluster_name, node_num, replica_num)
     }
 
     pub fn remove_cluster(&self, cluster_name: String) -> Result<(), MetaStoreError> {
+        let name = ClusterName::try_from(cluster_name.as_str())
+            .map_err(|_| MetaStoreError::InvalidClusterName)?;
         self.store
             .write()
             .expect("MemBrokerService::remove_cluster")
-            .remove_cluster(cluster_name)
+            .remove_cluster(cluster_name)?;
+        self.slot_stats
+            .write()
+            .expect("MemBrokerService::remove_cluster")
+            .remove_cluster(&name);
+        Ok(())
     }
 
     pub fn auto_add_node(
@@ -368,6 +455,19 @@ impl MemBrokerService {
         checker.check_failure_tolerance(migration_limit)
     }
 
+    pub fn check_zone_failure_tolerance(
+        &self,
+    ) -> Result<Vec<ZoneFailureTolerance>, MetaStoreError> {
+        let migration_limit = self.config.migration_limit;
+        let store_copy = self
+            .store
+            .read()
+            .expect("MemBrokerService::check_zone_failure_tolerance")
+            .clone();
+        let checker = ResourceChecker::new(store_copy);
+        checker.check_zone_failure_tolerance(migration_limit)
+    }
+
     pub fn change_broker_config(
         &self,
         config_payload: MemBrokerConfigPayload,
@@ -449,6 +549,346 @@ impl MemBrokerService {
             .auto_scale_out_node_number(cluster_name, new_node_num)
     }
 
+    pub fn plan_auto_add_nodes(
+        &self,
+        cluster_name: String,
+        node_num: usize,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        self.store
+            .read()
+            .expect("MemBrokerService::plan_auto_add_nodes")
+            .plan_auto_add_nodes(cluster_name, node_num)
+    }
+
+    pub fn plan_migrate_slots(&self, cluster_name: String) -> Result<ScalePlan, MetaStoreError> {
+        self.store
+            .read()
+            .expect("MemBrokerService::plan_migrate_slots")
+            .plan_migrate_slots(cluster_name)
+    }
+
+    pub fn plan_migrate_slots_to_scale_down(
+        &self,
+        cluster_name: String,
+        new_node_num: usize,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        self.store
+            .read()
+            .expect("MemBrokerService::plan_migrate_slots_to_scale_down")
+            .plan_migrate_slots_to_scale_down(cluster_name, new_node_num)
+    }
+
+    pub fn plan_auto_scale_node_number(
+        &self,
+        cluster_name: String,
+        new_node_num: usize,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        self.store
+            .read()
+            .expect("MemBrokerService::plan_auto_scale_node_number")
+            .plan_auto_scale_node_number(cluster_name, new_node_num)
+    }
+
+    pub fn add_slot_stats(
+        &self,
+        proxy_address: String,
+        slot_stats: Vec<SlotStats>,
+    ) -> Result<(), MetaStoreError> {
+        let cluster_name = self
+            .store
+            .read()
+            .expect("MemBrokerService::add_slot_stats")
+            .all_proxies
+            .get(&proxy_address)
+            .ok_or_else(|| MetaStoreError::ProxyNotFound)?
+            .cluster
+            .clone();
+        // The free proxies could still have some stale traffic. Just ignore it.
+        if let Some(cluster_name) = cluster_name {
+            self.slot_stats
+                .write()
+                .expect("MemBrokerService::add_slot_stats")
+                .add_stats(cluster_name, slot_stats);
+        }
+        Ok(())
+    }
+
+    pub fn add_memory_stats(
+        &self,
+        proxy_address: String,
+        memory_stats: Vec<NodeMemoryStats>,
+    ) -> Result<(), MetaStoreError> {
+        if !self
+            .store
+            .read()
+            .expect("MemBrokerService::add_memory_stats")
+            .all_proxies
+            .contains_key(&proxy_address)
+        {
+            return Err(MetaStoreError::ProxyNotFound);
+        }
+        self.memory_stats
+            .write()
+            .expect("MemBrokerService::add_memory_stats")
+            .add_stats(memory_stats);
+        Ok(())
+    }
+
+    pub fn add_repl_stats(
+        &self,
+        proxy_address: String,
+        repl_stats: Vec<NodeReplStats>,
+    ) -> Result<(), MetaStoreError> {
+        if !self
+            .store
+            .read()
+            .expect("MemBrokerService::add_repl_stats")
+            .all_proxies
+            .contains_key(&proxy_address)
+        {
+            return Err(MetaStoreError::ProxyNotFound);
+        }
+        self.repl_stats
+            .write()
+            .expect("MemBrokerService::add_repl_stats")
+            .add_stats(repl_stats);
+        Ok(())
+    }
+
+    pub fn add_migration_stats(
+        &self,
+        proxy_address: String,
+        migration_stats: Vec<MigrationProgress>,
+    ) -> Result<(), MetaStoreError> {
+        if !self
+            .store
+            .read()
+            .expect("MemBrokerService::add_migration_stats")
+            .all_proxies
+            .contains_key(&proxy_address)
+        {
+            return Err(MetaStoreError::ProxyNotFound);
+        }
+        let mut stats = self
+            .migration_stats
+            .write()
+            .expect("MemBrokerService::add_migration_stats");
+        stats.remove_expired();
+        stats.add_stats(migration_stats);
+        Ok(())
+    }
+
+    pub fn get_memory_usage(
+        &self,
+        cluster_name: &str,
+    ) -> Result<ClusterMemoryUsage, MetaStoreError> {
+        let cluster = self
+            .get_cluster_by_name(cluster_name)
+            .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
+        let name =
+            ClusterName::try_from(cluster_name).map_err(|_| MetaStoreError::InvalidClusterName)?;
+        let chunk_node_num = self
+            .store
+            .read()
+            .expect("MemBrokerService::get_memory_usage")
+            .clusters
+            .get(&name)
+            .map(|cluster_store| cluster_store.get_chunk_node_num())
+            .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
+        let node_number = cluster.get_nodes().len();
+        let masters = cluster
+            .get_nodes()
+            .iter()
+            .filter(|node| node.get_role() == Role::Master)
+            .map(|node| node.get_address());
+        let (used_memory, maxmemory) = self
+            .memory_stats
+            .read()
+            .expect("MemBrokerService::get_memory_usage")
+            .get_usage(masters)
+            .ok_or_else(|| MetaStoreError::MemoryStatsNotFound)?;
+        let suggested_node_number = suggest_node_number(
+            node_number,
+            chunk_node_num,
+            used_memory,
+            maxmemory,
+            self.config.memory_high_water_mark,
+            self.config.memory_low_water_mark,
+        );
+        Ok(ClusterMemoryUsage {
+            node_number,
+            used_memory,
+            maxmemory,
+            suggested_node_number,
+        })
+    }
+
+    // Scale out the clusters whose memory usage is above the high-water mark,
+    // and only log the suggestion for the ones below the low-water mark
+    // since scaling down is more risky and should be decided by the operator.
+    pub async fn check_memory_usage(&self) {
+        self.memory_stats
+            .write()
+            .expect("MemBrokerService::check_memory_usage")
+            .remove_expired();
+
+        let cluster_names = self
+            .store
+            .read()
+            .expect("MemBrokerService::check_memory_usage")
+            .get_cluster_names();
+        for cluster_name in cluster_names.into_iter() {
+            let usage = match self.get_memory_usage(cluster_name.as_str()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    debug!("skip checking memory of {}: {}", cluster_name, err);
+                    continue;
+                }
+            };
+            let expected_num = match usage.suggested_node_number {
+                Some(expected_num) => expected_num,
+                None => continue,
+            };
+            if expected_num < usage.node_number {
+                info!(
+                    "cluster {} memory usage {}/{} is below the low-water mark. Suggest scaling down to {} nodes",
+                    cluster_name, usage.used_memory, usage.maxmemory, expected_num
+                );
+                continue;
+            }
+
+            let expected_num =
+                match self.limit_memory_scale_out(&cluster_name, usage.node_number, expected_num) {
+                    Some(expected_num) => expected_num,
+                    None => continue,
+                };
+            info!(
+                "cluster {} memory usage {}/{} is above the high-water mark. Scale out from {} to {} nodes",
+                cluster_name, usage.used_memory, usage.maxmemory, usage.node_number, expected_num
+            );
+            if let Err(err) = self
+                .auto_scale_node_number(cluster_name.to_string(), expected_num)
+                .await
+            {
+                error!("failed to scale out cluster {}: {}", cluster_name, err);
+                continue;
+            }
+            if let Err(err) = self.trigger_update().await {
+                error!("failed to update meta file: {}", err);
+            }
+        }
+    }
+
+    // Returns None if the scaling should be postponed.
+    fn limit_memory_scale_out(
+        &self,
+        cluster_name: &ClusterName,
+        node_number: usize,
+        expected_num: usize,
+    ) -> Option<usize> {
+        let migration_limit = self.config.migration_limit;
+        let store = self
+            .store
+            .read()
+            .expect("MemBrokerService::limit_memory_scale_out");
+        let cluster = store.clusters.get(cluster_name)?;
+
+        let migrating_num = store
+            .get_cluster_names()
+            .into_iter()
+            .filter_map(|name| store.get_cluster_info_by_name(name.as_str(), migration_limit))
+            .filter(|info| info.is_migrating)
+            .count();
+        if migration_limit != 0 && migrating_num as u64 >= migration_limit {
+            info!(
+                "{} clusters are migrating. Postpone scaling out for memory",
+                migrating_num
+            );
+            return None;
+        }
+
+        let free_chunk_num = store.get_free_proxies().len() / cluster.get_chunk_proxy_num();
+        let max_num = node_number + free_chunk_num * cluster.get_chunk_node_num();
+        if max_num <= node_number {
+            warn!(
+                "no free proxy for scaling out from {} to {} nodes",
+                node_number, expected_num
+            );
+            return None;
+        }
+        if max_num < expected_num {
+            warn!(
+                "not enough free proxies for scaling out to {} nodes. Only scale out to {} nodes",
+                expected_num, max_num
+            );
+        }
+        Some(expected_num.min(max_num))
+    }
+
+    pub fn get_slot_stats(&self, cluster_name: String) -> Result<Vec<SlotStats>, MetaStoreError> {
+        let name = ClusterName::try_from(cluster_name.as_str())
+            .map_err(|_| MetaStoreError::InvalidClusterName)?;
+        let slot_stats = self
+            .slot_stats
+            .read()
+            .expect("MemBrokerService::get_slot_stats")
+            .get_stats(&name);
+        Ok(slot_stats)
+    }
+
+    fn get_slot_loads(
+        &self,
+        cluster_name: &str,
+        metric: SlotLoadMetric,
+    ) -> Result<HashMap<usize, u64>, MetaStoreError> {
+        let name =
+            ClusterName::try_from(cluster_name).map_err(|_| MetaStoreError::InvalidClusterName)?;
+        let slot_loads = self
+            .slot_stats
+            .read()
+            .expect("MemBrokerService::get_slot_loads")
+            .get_slot_loads(&name, metric);
+        Ok(slot_loads)
+    }
+
+    pub fn migrate_hot_slots(
+        &self,
+        cluster_name: String,
+        metric: SlotLoadMetric,
+    ) -> Result<(), MetaStoreError> {
+        let _guard = self
+            .scale_lock
+            .lock()
+            .ok_or_else(|| MetaStoreError::NodeNumberChanging)?;
+
+        let slot_loads = self.get_slot_loads(&cluster_name, metric)?;
+        let name = ClusterName::try_from(cluster_name.as_str())
+            .map_err(|_| MetaStoreError::InvalidClusterName)?;
+        self.store
+            .write()
+            .expect("MemBrokerService::migrate_hot_slots")
+            .migrate_hot_slots(cluster_name, &slot_loads)?;
+
+        // The traffic will be different after the migration so we need to start over.
+        self.slot_stats
+            .write()
+            .expect("MemBrokerService::migrate_hot_slots")
+            .remove_cluster(&name);
+        Ok(())
+    }
+
+    pub fn plan_migrate_hot_slots(
+        &self,
+        cluster_name: String,
+        metric: SlotLoadMetric,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        let slot_loads = self.get_slot_loads(&cluster_name, metric)?;
+        self.store
+            .read()
+            .expect("MemBrokerService::plan_migrate_hot_slots")
+            .plan_migrate_hot_slots(cluster_name, &slot_loads)
+    }
+
     pub fn get_failures(&self) -> Vec<String> {
         let failure_ttl = chrono::Duration::seconds(self.config.failure_ttl as i64);
         let failure_quorum = self.config.failure_quorum;
@@ -465,6 +905,90 @@ impl MemBrokerService {
             .add_failure(address, reporter_id)
     }
 
+    pub fn get_node_failures(&self) -> Vec<String> {
+        let failure_ttl = chrono::Duration::seconds(self.config.failure_ttl as i64);
+        let failure_quorum = self.config.failure_quorum;
+        self.store
+            .write()
+            .expect("MemBrokerService::get_node_failures")
+            .get_node_failures(failure_ttl, failure_quorum)
+    }
+
+    pub fn add_node_failure(&self, node_address: String, reporter_id: String) {
+        self.store
+            .write()
+            .expect("MemBrokerService::add_node_failure")
+            .add_node_failure(node_address, reporter_id)
+    }
+
+    pub fn failover_node(&self, failed_node_address: String) -> Result<(), MetaStoreError> {
+        self.store
+            .write()
+            .expect("MemBrokerService::failover_node")
+            .failover_node(failed_node_address)
+    }
+
+    pub fn add_switchover(&self, proxy_address: String) -> Result<(), MetaStoreError> {
+        self.store
+            .write()
+            .expect("MemBrokerService::add_switchover")
+            .add_switchover(proxy_address)
+    }
+
+    pub fn add_host_switchover(&self, host: String) -> Result<(), MetaStoreError> {
+        self.store
+            .write()
+            .expect("MemBrokerService::add_host_switchover")
+            .add_host_switchover(host)
+    }
+
+    pub fn get_switchover_tasks(&self) -> Vec<SwitchoverTask> {
+        self.store
+            .write()
+            .expect("MemBrokerService::get_switchover_tasks")
+            .get_switchover_tasks()
+    }
+
+    pub fn commit_switchover(&self, proxy_address: String) -> Result<(), MetaStoreError> {
+        self.store
+            .write()
+            .expect("MemBrokerService::commit_switchover")
+            .commit_switchover(proxy_address)
+    }
+
+    pub fn pause_migration(&self, cluster_name: String) -> Result<(), MetaStoreError> {
+        self.store
+            .write()
+            .expect("MemBrokerService::pause_migration")
+            .pause_migration(cluster_name)
+    }
+
+    pub fn resume_migration(&self, cluster_name: String) -> Result<(), MetaStoreError> {
+        self.store
+            .write()
+            .expect("MemBrokerService::resume_migration")
+            .resume_migration(cluster_name)
+    }
+
+    pub fn cancel_migration(&self, cluster_name: String) -> Result<(), MetaStoreError> {
+        let _guard = self
+            .scale_lock
+            .lock()
+            .ok_or_else(|| MetaStoreError::NodeNumberChanging)?;
+
+        self.store
+            .write()
+            .expect("MemBrokerService::cancel_migration")
+            .cancel_migration(cluster_name)
+    }
+
+    pub fn get_paused_migrations(&self) -> Vec<ClusterName> {
+        self.store
+            .read()
+            .expect("MemBrokerService::get_paused_migrations")
+            .get_paused_migrations()
+    }
+
     pub fn commit_migration(&self, task: MigrationTaskMeta) -> Result<(), MetaStoreError> {
         // TODO: Maybe we need to make `clear_free_nodes` of `commit_migration` configurable.
         self.store
@@ -478,10 +1002,48 @@ impl MemBrokerService {
         failed_proxy_address: String,
     ) -> Result<Option<Proxy>, MetaStoreError> {
         let migration_limit = self.config.migration_limit;
+        let max_repl_lag = self.get_failover_max_repl_lag(&failed_proxy_address);
+        let repl_offsets = {
+            let mut repl_stats = self
+                .repl_stats
+                .write()
+                .expect("MemBrokerService::replace_failed_node");
+            repl_stats.remove_expired();
+            repl_stats.get_offsets()
+        };
         self.store
             .write()
             .expect("MemBrokerService::replace_failed_node")
-            .replace_failed_proxy(failed_proxy_address, migration_limit)
+            .replace_failed_proxy_with_repl_offsets(
+                failed_proxy_address,
+                migration_limit,
+                &repl_offsets,
+                max_repl_lag,
+            )
+    }
+
+    // The failed masters can't catch up so stop waiting for the replicas
+    // when the failed proxy has not reported the offsets for a while.
+    fn get_failover_max_repl_lag(&self, failed_proxy_address: &str) -> u64 {
+        let node_addresses = match self
+            .store
+            .read()
+            .expect("MemBrokerService::get_failover_max_repl_lag")
+            .all_proxies
+            .get(failed_proxy_address)
+        {
+            Some(proxy) => proxy.node_addresses.clone(),
+            None => return self.config.failover_max_repl_lag,
+        };
+        let elapsed = self
+            .repl_stats
+            .read()
+            .expect("MemBrokerService::get_failover_max_repl_lag")
+            .get_last_update_elapsed(node_addresses.iter().map(|address| address.as_str()));
+        match elapsed {
+            Some(elapsed) if elapsed.as_secs() >= self.config.failover_repl_lag_timeout => 0,
+            _ => self.config.failover_max_repl_lag,
+        }
     }
 
     pub fn get_failed_proxies(&self) -> Vec<String> {
@@ -612,6 +1174,9 @@ pub struct ProxyResourcePayload {
     nodes: [String; CHUNK_HALF_NODE_NUM],
     host: Option<String>,
     index: Option<usize>,
+    weight: Option<usize>,
+    #[serde(flatten)]
+    location: ProxyLocation,
 }
 
 async fn add_proxy(
@@ -625,6 +1190,7 @@ async fn add_proxy(
 #[derive(Deserialize, Serialize)]
 pub struct CreateClusterPayload {
     node_number: usize,
+    replica_num: Option<usize>,
 }
 
 async fn add_cluster(
@@ -635,8 +1201,14 @@ async fn add_cluster(
     ),
 ) -> Result<&'static str, MetaStoreError> {
     let cluster_name = path.into_inner().0;
-    let CreateClusterPayload { node_number } = payload.into_inner();
-    let res = state.add_cluster(cluster_name, node_number).map(|()| "")?;
+    let CreateClusterPayload {
+        node_number,
+        replica_num,
+    } = payload.into_inner();
+    let replica_num = replica_num.unwrap_or(DEFAULT_REPLICA_NUM);
+    let res = state
+        .add_cluster(cluster_name, node_number, replica_num)
+        .map(|()| "")?;
     state.trigger_update().await?;
     Ok(res)
 }
@@ -677,17 +1249,22 @@ pub struct AutoAddNodesPayload {
 }
 
 async fn auto_add_nodes(
-    (path, payload, state): (
+    (path, web::Query(dry_run), payload, state): (
         web::Path<(String,)>,
+        web::Query<DryRunQuery>,
         web::Json<AutoAddNodesPayload>,
         ServiceState,
     ),
-) -> Result<web::Json<Vec<Node>>, MetaStoreError> {
+) -> Result<Either<web::Json<Vec<Node>>, web::Json<ScalePlan>>, MetaStoreError> {
     let cluster_name = path.into_inner().0;
     let node_num = payload.into_inner().node_number;
+    if dry_run.is_enabled() {
+        let plan = state.plan_auto_add_nodes(cluster_name, node_num)?;
+        return Ok(Either::B(web::Json(plan)));
+    }
     let res = state.auto_add_node(cluster_name, node_num).map(web::Json)?;
     state.trigger_update().await?;
-    Ok(res)
+    Ok(Either::A(res))
 }
 
 async fn audo_delete_free_nodes(
@@ -714,6 +1291,38 @@ async fn change_config(
     Ok(res)
 }
 
+async fn pause_migration(
+    (path, state): (web::Path<(String,)>, ServiceState),
+) -> Result<&'static str, MetaStoreError> {
+    let cluster_name = path.into_inner().0;
+    state.pause_migration(cluster_name)?;
+    state.trigger_update().await?;
+    Ok("")
+}
+
+async fn resume_migration(
+    (path, state): (web::Path<(String,)>, ServiceState),
+) -> Result<&'static str, MetaStoreError> {
+    let cluster_name = path.into_inner().0;
+    state.resume_migration(cluster_name)?;
+    state.trigger_update().await?;
+    Ok("")
+}
+
+async fn cancel_migration(
+    (path, state): (web::Path<(String,)>, ServiceState),
+) -> Result<&'static str, MetaStoreError> {
+    let cluster_name = path.into_inner().0;
+    state.cancel_migration(cluster_name)?;
+    state.trigger_update().await?;
+    Ok("")
+}
+
+async fn get_paused_migrations(state: ServiceState) -> impl Responder {
+    let names = state.get_paused_migrations();
+    web::Json(ClusterNamesPayload { names })
+}
+
 async fn balance_masters(
     (path, state): (web::Path<(String,)>, ServiceState),
 ) -> Result<&'static str, MetaStoreError> {
@@ -755,6 +1364,18 @@ async fn check_resource_for_failures(
     Ok(web::Json(ResourceFailureCheckPayload { hosts_cannot_fail }))
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct ZoneFailureCheckPayload {
+    zones: Vec<ZoneFailureTolerance>,
+}
+
+async fn check_zone_failure_tolerance(
+    state: ServiceState,
+) -> Result<web::Json<ZoneFailureCheckPayload>, MetaStoreError> {
+    let zones = state.check_zone_failure_tolerance()?;
+    Ok(web::Json(ZoneFailureCheckPayload { zones }))
+}
+
 async fn change_broker_config(
     (state, config_payload): (ServiceState, web::Json<MemBrokerConfigPayload>),
 ) -> Result<&'static str, MetaStoreError> {
@@ -769,33 +1390,160 @@ async fn get_broker_config(
     Ok(web::Json(payload))
 }
 
+#[derive(Deserialize)]
+struct DryRunQuery {
+    dry_run: Option<bool>,
+}
+
+impl DryRunQuery {
+    fn is_enabled(&self) -> bool {
+        self.dry_run.unwrap_or(false)
+    }
+}
+
 async fn migrate_slots(
-    (path, state): (web::Path<(String,)>, ServiceState),
-) -> Result<&'static str, MetaStoreError> {
+    (path, web::Query(dry_run), state): (
+        web::Path<(String,)>,
+        web::Query<DryRunQuery>,
+        ServiceState,
+    ),
+) -> Result<Either<&'static str, web::Json<ScalePlan>>, MetaStoreError> {
     let (cluster_name,) = path.into_inner();
+    if dry_run.is_enabled() {
+        let plan = state.plan_migrate_slots(cluster_name)?;
+        return Ok(Either::B(web::Json(plan)));
+    }
     state.migrate_slots(cluster_name)?;
     state.trigger_update().await?;
-    Ok("")
+    Ok(Either::A(""))
 }
 
 async fn migrate_slots_to_scale_down(
-    (path, state): (web::Path<(String, usize)>, ServiceState),
-) -> Result<&'static str, MetaStoreError> {
+    (path, web::Query(dry_run), state): (
+        web::Path<(String, usize)>,
+        web::Query<DryRunQuery>,
+        ServiceState,
+    ),
+) -> Result<Either<&'static str, web::Json<ScalePlan>>, MetaStoreError> {
     let (cluster_name, new_node_num) = path.into_inner();
+    if dry_run.is_enabled() {
+        let plan = state.plan_migrate_slots_to_scale_down(cluster_name, new_node_num)?;
+        return Ok(Either::B(web::Json(plan)));
+    }
     state.migrate_slots_to_scale_down(cluster_name, new_node_num)?;
     state.trigger_update().await?;
-    Ok("")
+    Ok(Either::A(""))
 }
 
 async fn auto_scale_node_number(
-    (path, state): (web::Path<(String, usize)>, ServiceState),
-) -> Result<&'static str, MetaStoreError> {
+    (path, web::Query(dry_run), state): (
+        web::Path<(String, usize)>,
+        web::Query<DryRunQuery>,
+        ServiceState,
+    ),
+) -> Result<Either<&'static str, web::Json<ScalePlan>>, MetaStoreError> {
     let (cluster, new_node_num) = path.into_inner();
+    if dry_run.is_enabled() {
+        let plan = state.plan_auto_scale_node_number(cluster, new_node_num)?;
+        return Ok(Either::B(web::Json(plan)));
+    }
     state.auto_scale_node_number(cluster, new_node_num).await?;
     state.trigger_update().await?;
+    Ok(Either::A(""))
+}
+
+#[derive(Deserialize)]
+struct SlotLoadQuery {
+    metric: Option<SlotLoadMetric>,
+}
+
+async fn migrate_hot_slots(
+    (path, web::Query(dry_run), web::Query(load_query), state): (
+        web::Path<(String,)>,
+        web::Query<DryRunQuery>,
+        web::Query<SlotLoadQuery>,
+        ServiceState,
+    ),
+) -> Result<Either<&'static str, web::Json<ScalePlan>>, MetaStoreError> {
+    let (cluster_name,) = path.into_inner();
+    let metric = load_query.metric.unwrap_or_default();
+    if dry_run.is_enabled() {
+        let plan = state.plan_migrate_hot_slots(cluster_name, metric)?;
+        return Ok(Either::B(web::Json(plan)));
+    }
+    state.migrate_hot_slots(cluster_name, metric)?;
+    state.trigger_update().await?;
+    Ok(Either::A(""))
+}
+
+async fn add_slot_stats(
+    (path, payload, state): (
+        web::Path<(String,)>,
+        web::Json<SlotStatsPayload>,
+        ServiceState,
+    ),
+) -> Result<&'static str, MetaStoreError> {
+    let (proxy_address,) = path.into_inner();
+    let SlotStatsPayload { slot_stats } = payload.into_inner();
+    state.add_slot_stats(proxy_address, slot_stats)?;
+    Ok("")
+}
+
+async fn add_memory_stats(
+    (path, payload, state): (
+        web::Path<(String,)>,
+        web::Json<MemoryStatsPayload>,
+        ServiceState,
+    ),
+) -> Result<&'static str, MetaStoreError> {
+    let (proxy_address,) = path.into_inner();
+    let MemoryStatsPayload { memory_stats } = payload.into_inner();
+    state.add_memory_stats(proxy_address, memory_stats)?;
+    Ok("")
+}
+
+async fn add_repl_stats(
+    (path, payload, state): (
+        web::Path<(String,)>,
+        web::Json<ReplStatsPayload>,
+        ServiceState,
+    ),
+) -> Result<&'static str, MetaStoreError> {
+    let (proxy_address,) = path.into_inner();
+    let ReplStatsPayload { repl_stats } = payload.into_inner();
+    state.add_repl_stats(proxy_address, repl_stats)?;
     Ok("")
 }
 
+async fn add_migration_stats(
+    (path, payload, state): (
+        web::Path<(String,)>,
+        web::Json<MigrationStatsPayload>,
+        ServiceState,
+    ),
+) -> Result<&'static str, MetaStoreError> {
+    let (proxy_address,) = path.into_inner();
+    let MigrationStatsPayload { migration_stats } = payload.into_inner();
+    state.add_migration_stats(proxy_address, migration_stats)?;
+    Ok("")
+}
+
+async fn get_memory_usage(
+    (path, state): (web::Path<(String,)>, ServiceState),
+) -> Result<web::Json<ClusterMemoryUsage>, MetaStoreError> {
+    let (cluster_name,) = path.into_inner();
+    let usage = state.get_memory_usage(&cluster_name)?;
+    Ok(web::Json(usage))
+}
+
+async fn get_slot_stats(
+    (path, state): (web::Path<(String,)>, ServiceState),
+) -> Result<web::Json<SlotStatsPayload>, MetaStoreError> {
+    let (cluster_name,) = path.into_inner();
+    let slot_stats = state.get_slot_stats(cluster_name)?;
+    Ok(web::Json(SlotStatsPayload { slot_stats }))
+}
+
 async fn add_failure(
     (path, state): (web::Path<(String, String)>, ServiceState),
 ) -> Result<&'static str, MetaStoreError> {
@@ -805,6 +1553,65 @@ async fn add_failure(
     Ok("")
 }
 
+async fn get_node_failures(state: ServiceState) -> impl Responder {
+    let addresses = state.get_node_failures();
+    web::Json(FailuresPayload { addresses })
+}
+
+async fn add_node_failure(
+    (path, state): (web::Path<(String, String)>, ServiceState),
+) -> Result<&'static str, MetaStoreError> {
+    let (node_address, reporter_id) = path.into_inner();
+    state.add_node_failure(node_address, reporter_id);
+    state.trigger_update().await?;
+    Ok("")
+}
+
+async fn failover_node(
+    (path, state): (web::Path<(String,)>, ServiceState),
+) -> Result<&'static str, MetaStoreError> {
+    let (node_address,) = path.into_inner();
+    let res = state.failover_node(node_address).map(|()| "");
+    let sync_res = state.trigger_update().await;
+    let res = res?;
+    sync_res?;
+    Ok(res)
+}
+
+async fn add_switchover(
+    (path, state): (web::Path<(String,)>, ServiceState),
+) -> Result<&'static str, MetaStoreError> {
+    let (proxy_address,) = path.into_inner();
+    state.add_switchover(proxy_address)?;
+    state.trigger_update().await?;
+    Ok("")
+}
+
+async fn add_host_switchover(
+    (path, state): (web::Path<(String,)>, ServiceState),
+) -> Result<&'static str, MetaStoreError> {
+    let (host,) = path.into_inner();
+    state.add_host_switchover(host)?;
+    state.trigger_update().await?;
+    Ok("")
+}
+
+async fn get_switchover_tasks(state: ServiceState) -> impl Responder {
+    let tasks = state.get_switchover_tasks();
+    web::Json(SwitchoverTasksPayload { tasks })
+}
+
+async fn commit_switchover(
+    (path, state): (web::Path<(String,)>, ServiceState),
+) -> Result<&'static str, MetaStoreError> {
+    let (proxy_address,) = path.into_inner();
+    let res = state.commit_switchover(proxy_address).map(|()| "");
+    let sync_res = state.trigger_update().await;
+    let res = res?;
+    sync_res?;
+    Ok(res)
+}
+
 async fn commit_migration(
     (task, state): (web::Json<MigrationTaskMeta>, ServiceState),
 ) -> Result<&'static str, MetaStoreError> {
@@ -861,15 +1668,25 @@ impl error::ResponseError for MetaStoreError {
             MetaStoreError::FreeNodeNotFound => http::StatusCode::NOT_FOUND,
             MetaStoreError::FreeNodeFound => http::StatusCode::CONFLICT,
             MetaStoreError::ProxyNotFound => http::StatusCode::NOT_FOUND,
+            MetaStoreError::NodeNotFound => http::StatusCode::NOT_FOUND,
+            MetaStoreError::NodeFailoverNotAvailable => http::StatusCode::CONFLICT,
+            MetaStoreError::ReplicaLagTooLarge => http::StatusCode::CONFLICT,
+            MetaStoreError::SwitchoverNotAvailable => http::StatusCode::CONFLICT,
             MetaStoreError::InvalidNodeNum => http::StatusCode::BAD_REQUEST,
+            MetaStoreError::InvalidReplicaNum => http::StatusCode::BAD_REQUEST,
             MetaStoreError::NodeNumAlreadyEnough => http::StatusCode::CONFLICT,
             MetaStoreError::InvalidClusterName => http::StatusCode::BAD_REQUEST,
             MetaStoreError::InvalidMigrationTask => http::StatusCode::BAD_REQUEST,
             MetaStoreError::InvalidProxyAddress => http::StatusCode::BAD_REQUEST,
+            MetaStoreError::InvalidProxyWeight => http::StatusCode::BAD_REQUEST,
+            MetaStoreError::ProxyWeightTooLarge => http::StatusCode::BAD_REQUEST,
             MetaStoreError::MigrationTaskNotFound => http::StatusCode::NOT_FOUND,
             MetaStoreError::MigrationRunning => http::StatusCode::CONFLICT,
+            MetaStoreError::MigrationNotRunning => http::StatusCode::CONFLICT,
             MetaStoreError::InvalidConfig { .. } => http::StatusCode::BAD_REQUEST,
             MetaStoreError::SlotsAlreadyEven => http::StatusCode::BAD_REQUEST,
+            MetaStoreError::SlotLoadAlreadyBalanced => http::StatusCode::CONFLICT,
+            MetaStoreError::MemoryStatsNotFound => http::StatusCode::NOT_FOUND,
             MetaStoreError::SyncError(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
             MetaStoreError::InvalidMetaVersion => http::StatusCode::CONFLICT,
             MetaStoreError::SmallEpoch => http::StatusCode::CONFLICT,
@@ -0,0 +1,123 @@
+use crate::common::cluster::{ClusterName, SlotStats};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlotLoadMetric {
+    Requests,
+    Bytes,
+}
+
+impl Default for SlotLoadMetric {
+    fn default() -> Self {
+        SlotLoadMetric::Requests
+    }
+}
+
+// The slot traffic accumulated from the increments reported by the coordinators.
+// It's only used for finding hot slots so it's not persisted nor replicated.
+#[derive(Debug, Default)]
+pub struct SlotStatsStore {
+    clusters: HashMap<ClusterName, HashMap<usize, SlotStats>>,
+}
+
+impl SlotStatsStore {
+    pub fn add_stats(&mut self, cluster_name: ClusterName, slot_stats: Vec<SlotStats>) {
+        let cluster_stats = self.clusters.entry(cluster_name).or_default();
+        for stats in slot_stats.into_iter() {
+            let curr = cluster_stats
+                .entry(stats.slot)
+                .or_insert_with(|| SlotStats {
+                    slot: stats.slot,
+                    requests: 0,
+                    bytes: 0,
+                });
+            curr.requests = curr.requests.saturating_add(stats.requests);
+            curr.bytes = curr.bytes.saturating_add(stats.bytes);
+        }
+    }
+
+    pub fn get_stats(&self, cluster_name: &ClusterName) -> Vec<SlotStats> {
+        let mut slot_stats: Vec<SlotStats> = self
+            .clusters
+            .get(cluster_name)
+            .map(|cluster_stats| cluster_stats.values().cloned().collect())
+            .unwrap_or_default();
+        slot_stats.sort_unstable_by_key(|stats| stats.slot);
+        slot_stats
+    }
+
+    pub fn get_slot_loads(
+        &self,
+        cluster_name: &ClusterName,
+        metric: SlotLoadMetric,
+    ) -> HashMap<usize, u64> {
+        let cluster_stats = match self.clusters.get(cluster_name) {
+            Some(cluster_stats) => cluster_stats,
+            None => return HashMap::new(),
+        };
+        cluster_stats
+            .iter()
+            .map(|(slot, stats)| {
+                let load = match metric {
+                    SlotLoadMetric::Requests => stats.requests,
+                    SlotLoadMetric::Bytes => stats.bytes,
+                };
+                (*slot, load)
+            })
+            .collect()
+    }
+
+    pub fn remove_cluster(&mut self, cluster_name: &ClusterName) {
+        self.clusters.remove(cluster_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_accumulate_slot_stats() {
+        let cluster_name = ClusterName::try_from("testcluster").unwrap();
+        let mut store = SlotStatsStore::default();
+        store.add_stats(
+            cluster_name.clone(),
+            vec![
+                SlotStats {
+                    slot: 2,
+                    requests: 1,
+                    bytes: 10,
+                },
+                SlotStats {
+                    slot: 1,
+                    requests: 3,
+                    bytes: 5,
+                },
+            ],
+        );
+        store.add_stats(
+            cluster_name.clone(),
+            vec![SlotStats {
+                slot: 2,
+                requests: 4,
+                bytes: 20,
+            }],
+        );
+
+        let slot_stats = store.get_stats(&cluster_name);
+        assert_eq!(slot_stats.len(), 2);
+        assert_eq!(slot_stats[0].slot, 1);
+        assert_eq!(slot_stats[1].requests, 5);
+        assert_eq!(slot_stats[1].bytes, 30);
+
+        let loads = store.get_slot_loads(&cluster_name, SlotLoadMetric::Requests);
+        assert_eq!(loads.get(&2), Some(&5));
+        let loads = store.get_slot_loads(&cluster_name, SlotLoadMetric::Bytes);
+        assert_eq!(loads.get(&2), Some(&30));
+
+        store.remove_cluster(&cluster_name);
+        assert!(store.get_stats(&cluster_name).is_empty());
+    }
+}
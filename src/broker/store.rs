@@ -1,13 +1,16 @@
 use super::migrate::MetaStoreMigrate;
+use super::migration_stats::ClusterMigrationProgress;
 use super::persistence::MetaSyncError;
+use super::plan::{MetaStorePlan, ScalePlan};
 use super::query::MetaStoreQuery;
 use super::update::MetaStoreUpdate;
 use crate::common::cluster::ClusterName;
 use crate::common::cluster::{
-    Cluster, MigrationMeta, MigrationTaskMeta, Node, Proxy, Range, RangeList, SlotRange,
-    SlotRangeTag,
+    Cluster, MigrationMeta, MigrationTaskMeta, Node, Proxy, Range, RangeList, ReplMeta, ReplPeer,
+    Role, SlotRange, SlotRangeTag, SwitchoverTask,
 };
 use crate::common::config::ClusterConfig;
+use crate::common::utils::SLOT_NUM;
 use crate::common::version::UNDERMOON_MEM_BROKER_META_VERSION;
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 use std::cmp::{max, Ordering};
@@ -19,19 +22,109 @@ use std::fmt;
 pub const NODES_PER_PROXY: usize = 2;
 pub const CHUNK_PARTS: usize = 2;
 pub const CHUNK_HALF_NODE_NUM: usize = 2;
-pub const CHUNK_NODE_NUM: usize = 4;
+pub const DEFAULT_PROXY_WEIGHT: usize = 1;
+pub const MAX_PROXY_WEIGHT: usize = 10000;
+pub const DEFAULT_REPLICA_NUM: usize = 1;
+pub const MAX_REPLICA_NUM: usize = 2;
+
+fn default_proxy_weight() -> usize {
+    DEFAULT_PROXY_WEIGHT
+}
+
+fn default_replica_num() -> usize {
+    DEFAULT_REPLICA_NUM
+}
+
+// A chunk consists of `replica_num + 1` proxies holding two masters and their replicas.
+pub fn get_chunk_proxy_num(replica_num: usize) -> usize {
+    replica_num + 1
+}
+
+pub fn get_chunk_node_num(replica_num: usize) -> usize {
+    get_chunk_proxy_num(replica_num) * NODES_PER_PROXY
+}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ProxyResource {
     pub proxy_address: String,
     pub node_addresses: [String; NODES_PER_PROXY],
     pub host: String,
+    #[serde(flatten)]
+    pub location: ProxyLocation,
+    // The relative capacity of the Redis nodes behind this proxy.
+    // The masters will own slots in proportion to it.
+    #[serde(default = "default_proxy_weight")]
+    pub weight: usize,
     // `index` is only used as the index in StatefulSet of Kubernetes
     // when `enable_ordered_proxy` is true.
     pub index: usize,
     pub cluster: Option<ClusterName>,
 }
 
+// Returns the number of slots each master should own in proportion to the weights.
+// All the masters get `SLOT_NUM / master_num` or one more slot when the weights are the same.
+// The masters with positive weights get at least one slot.
+pub fn get_weighted_slot_nums(weights: &[usize]) -> Vec<usize> {
+    let sum: u128 = weights.iter().map(|w| *w as u128).sum();
+    if sum == 0 {
+        return vec![0; weights.len()];
+    }
+    let mut slot_nums: Vec<usize> = weights
+        .iter()
+        .map(|w| (SLOT_NUM as u128 * *w as u128 / sum) as usize)
+        .collect();
+    for (slot_num, weight) in slot_nums.iter_mut().zip(weights.iter()) {
+        if *weight > 0 && *slot_num == 0 {
+            *slot_num = 1;
+        }
+    }
+
+    let total: usize = slot_nums.iter().sum();
+    if total <= SLOT_NUM {
+        for slot_num in slot_nums.iter_mut().take(SLOT_NUM - total) {
+            *slot_num += 1;
+        }
+        return slot_nums;
+    }
+    // Take back the slots given to the small masters from the largest ones.
+    for _ in SLOT_NUM..total {
+        let largest = (0..slot_nums.len())
+            .max_by_key(|i| slot_nums[*i])
+            .expect("get_weighted_slot_nums");
+        slot_nums[largest] -= 1;
+    }
+    slot_nums
+}
+
+// The failure domains above the host, from the largest to the smallest.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ProxyLocation {
+    pub zone: Option<String>,
+    pub rack: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LocationLevel {
+    Zone,
+    Rack,
+    Host,
+}
+
+impl ProxyResource {
+    // The rack names are only required to be unique inside the zone.
+    pub fn get_location(&self, level: LocationLevel) -> Option<String> {
+        match level {
+            LocationLevel::Zone => self.location.zone.clone(),
+            LocationLevel::Rack => self
+                .location
+                .rack
+                .as_ref()
+                .map(|rack| format!("{}/{}", self.location.zone.as_deref().unwrap_or(""), rack)),
+            LocationLevel::Host => Some(self.host.clone()),
+        }
+    }
+}
+
 pub struct HostProxy {
     pub host: String,
     pub proxy_address: String,
@@ -54,15 +147,13 @@ pub struct MigrationSlotRangeStore {
 impl MigrationSlotRangeStore {
     pub fn to_slot_range(&self, chunks: &[ChunkStore]) -> SlotRange {
         let src_chunk = chunks.get(self.meta.src_chunk_index).expect("get_cluster");
-        let src_proxy_index =
-            Self::chunk_part_to_proxy_index(self.meta.src_chunk_part, src_chunk.role_position);
+        let src_proxy_index = src_chunk.get_master_proxy_index(self.meta.src_chunk_part);
         let src_proxy_address = src_chunk
             .proxy_addresses
             .get(src_proxy_index)
             .expect("get_cluster")
             .clone();
-        let src_node_index =
-            Self::chunk_part_to_node_index(self.meta.src_chunk_part, src_chunk.role_position);
+        let src_node_index = src_chunk.get_master_node_index(self.meta.src_chunk_part);
         let src_node_address = src_chunk
             .node_addresses
             .get(src_node_index)
@@ -70,15 +161,13 @@ impl MigrationSlotRangeStore {
             .clone();
 
         let dst_chunk = chunks.get(self.meta.dst_chunk_index).expect("get_cluster");
-        let dst_proxy_index =
-            Self::chunk_part_to_proxy_index(self.meta.dst_chunk_part, dst_chunk.role_position);
+        let dst_proxy_index = dst_chunk.get_master_proxy_index(self.meta.dst_chunk_part);
         let dst_proxy_address = dst_chunk
             .proxy_addresses
             .get(dst_proxy_index)
             .expect("get_cluster")
             .clone();
-        let dst_node_index =
-            Self::chunk_part_to_node_index(self.meta.dst_chunk_part, dst_chunk.role_position);
+        let dst_node_index = dst_chunk.get_master_node_index(self.meta.dst_chunk_part);
         let dst_node_address = dst_chunk
             .node_addresses
             .get(dst_node_index)
@@ -104,22 +193,6 @@ impl MigrationSlotRangeStore {
             }
         }
     }
-
-    fn chunk_part_to_proxy_index(chunk_part: usize, role_position: ChunkRolePosition) -> usize {
-        match (chunk_part, role_position) {
-            (0, ChunkRolePosition::SecondChunkMaster) => 1,
-            (1, ChunkRolePosition::FirstChunkMaster) => 0,
-            (i, _) => i,
-        }
-    }
-
-    fn chunk_part_to_node_index(chunk_part: usize, role_position: ChunkRolePosition) -> usize {
-        match (chunk_part, role_position) {
-            (0, ChunkRolePosition::SecondChunkMaster) => 3,
-            (1, ChunkRolePosition::FirstChunkMaster) => 1,
-            (i, _) => 2 * i,
-        }
-    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -136,9 +209,165 @@ pub struct ChunkStore {
     pub role_position: ChunkRolePosition,
     pub stable_slots: [Option<SlotRange>; CHUNK_PARTS],
     pub migrating_slots: [Vec<MigrationSlotRangeStore>; CHUNK_PARTS],
-    pub proxy_addresses: [String; CHUNK_PARTS],
-    pub hosts: [String; CHUNK_PARTS],
-    pub node_addresses: [String; CHUNK_NODE_NUM],
+    // The first two proxies hold the two masters in turn.
+    // The others only hold replicas.
+    pub proxy_addresses: Vec<String>,
+    pub hosts: Vec<String>,
+    // Every proxy has `NODES_PER_PROXY` nodes.
+    pub node_addresses: Vec<String>,
+}
+
+impl ChunkStore {
+    pub fn get_replica_num(&self) -> usize {
+        self.proxy_addresses.len().saturating_sub(1)
+    }
+
+    // Returns the indices of the nodes holding the data of the chunk part.
+    // The first one is the master in `ChunkRolePosition::Normal`.
+    // Without replicas, the only proxy holds both masters.
+    pub fn get_part_node_indices(&self, chunk_part: usize) -> Vec<usize> {
+        let proxy_num = self.proxy_addresses.len();
+        if proxy_num <= 1 {
+            return vec![chunk_part];
+        }
+        let mut indices = match chunk_part {
+            0 => vec![0, 3],
+            _ => vec![2, 1],
+        };
+        for proxy_index in CHUNK_PARTS..proxy_num {
+            indices.push(proxy_index * NODES_PER_PROXY + chunk_part);
+        }
+        indices
+    }
+
+    pub fn get_master_proxy_index(&self, chunk_part: usize) -> usize {
+        if self.proxy_addresses.len() <= 1 {
+            return 0;
+        }
+        match (chunk_part, self.role_position) {
+            (_, ChunkRolePosition::FirstChunkMaster) => 0,
+            (_, ChunkRolePosition::SecondChunkMaster) => 1,
+            (i, ChunkRolePosition::Normal) => i,
+        }
+    }
+
+    pub fn get_master_node_index(&self, chunk_part: usize) -> usize {
+        let master_proxy_index = self.get_master_proxy_index(chunk_part);
+        self.get_part_node_index(chunk_part, master_proxy_index)
+            .expect("get_master_node_index")
+    }
+
+    // The node of the proxy holding the data of the chunk part.
+    pub fn get_part_node_index(&self, chunk_part: usize, proxy_index: usize) -> Option<usize> {
+        self.get_part_node_indices(chunk_part)
+            .into_iter()
+            .find(|i| i / NODES_PER_PROXY == proxy_index)
+    }
+
+    // The chunk part that the node belongs to.
+    pub fn get_node_chunk_part(&self, node_index: usize) -> usize {
+        if self.proxy_addresses.len() <= 1 {
+            return node_index;
+        }
+        match node_index {
+            0 | 3 => 0,
+            1 | 2 => 1,
+            i => i % NODES_PER_PROXY,
+        }
+    }
+
+    pub fn get_proxy_node_indices(proxy_index: usize) -> [usize; NODES_PER_PROXY] {
+        [
+            proxy_index * NODES_PER_PROXY,
+            proxy_index * NODES_PER_PROXY + 1,
+        ]
+    }
+
+    pub fn get_proxy_index(&self, proxy_address: &str) -> Option<usize> {
+        self.proxy_addresses
+            .iter()
+            .position(|address| address == proxy_address)
+    }
+
+    pub fn replace_proxy(&mut self, proxy_index: usize, proxy_resource: &ProxyResource) {
+        self.hosts[proxy_index] = proxy_resource.host.clone();
+        self.proxy_addresses[proxy_index] = proxy_resource.proxy_address.clone();
+        for (i, node_index) in Self::get_proxy_node_indices(proxy_index).iter().enumerate() {
+            self.node_addresses[*node_index] = proxy_resource.node_addresses[i].clone();
+        }
+    }
+
+    // Only the proxies with the same node layout, which are
+    // the first proxy and the ones only holding replicas, could be swapped.
+    pub fn swap_proxies(&mut self, first_index: usize, second_index: usize) {
+        self.hosts.swap(first_index, second_index);
+        self.proxy_addresses.swap(first_index, second_index);
+        let first_nodes = Self::get_proxy_node_indices(first_index);
+        let second_nodes = Self::get_proxy_node_indices(second_index);
+        for (a, b) in first_nodes.iter().zip(second_nodes.iter()) {
+            self.node_addresses.swap(*a, *b);
+        }
+    }
+
+    pub fn to_nodes(&self, cluster_name: &ClusterName, chunks: &[ChunkStore]) -> Vec<Node> {
+        let mut nodes = vec![];
+        for (i, address) in self.node_addresses.iter().enumerate() {
+            let proxy_address = self
+                .proxy_addresses
+                .get(i / NODES_PER_PROXY)
+                .expect("ChunkStore::to_nodes: failed to get proxy")
+                .clone();
+
+            let chunk_part = self.get_node_chunk_part(i);
+            let master_index = self.get_master_node_index(chunk_part);
+
+            let mut slots = vec![];
+            if i == master_index {
+                if let Some(stable_slots) = &self.stable_slots[chunk_part] {
+                    slots.push(stable_slots.clone());
+                }
+                let slot_ranges = self.migrating_slots[chunk_part]
+                    .iter()
+                    .map(|slot_range_store| slot_range_store.to_slot_range(chunks));
+                slots.extend(slot_ranges);
+            }
+
+            let gen_peer = |peer_index: usize| ReplPeer {
+                node_address: self
+                    .node_addresses
+                    .get(peer_index)
+                    .expect("ChunkStore::to_nodes: failed to get peer node")
+                    .clone(),
+                proxy_address: self
+                    .proxy_addresses
+                    .get(peer_index / NODES_PER_PROXY)
+                    .expect("ChunkStore::to_nodes: failed to get peer proxy")
+                    .clone(),
+            };
+
+            let repl = if i == master_index {
+                let peers = self
+                    .get_part_node_indices(chunk_part)
+                    .into_iter()
+                    .filter(|peer_index| *peer_index != i)
+                    .map(gen_peer)
+                    .collect();
+                ReplMeta::new(Role::Master, peers)
+            } else {
+                ReplMeta::new(Role::Replica, vec![gen_peer(master_index)])
+            };
+
+            let node = Node::new(
+                address.clone(),
+                proxy_address,
+                cluster_name.clone(),
+                slots,
+                repl,
+            );
+            nodes.push(node);
+        }
+        nodes
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -146,7 +375,12 @@ pub struct ClusterInfo {
     pub name: ClusterName,
     pub node_number: usize,
     pub node_number_with_slots: usize,
+    pub replica_num: usize,
     pub is_migrating: bool,
+    pub migration_paused: bool,
+    pub failover_lost_bytes: u64,
+    // Only set by the service when the coordinators have reported the progress.
+    pub migration_progress: Option<ClusterMigrationProgress>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -155,6 +389,15 @@ pub struct ClusterStore {
     pub name: ClusterName,
     pub chunks: Vec<ChunkStore>,
     pub config: ClusterConfig,
+    #[serde(default = "default_replica_num")]
+    pub replica_num: usize,
+    // The estimated bytes lost in the failovers
+    // when the new masters had not caught up with the failed ones.
+    #[serde(default)]
+    pub failover_lost_bytes: u64,
+    // The scanning of the running migration is paused by the operators.
+    #[serde(default)]
+    pub migration_paused: bool,
 }
 
 impl ClusterStore {
@@ -163,7 +406,11 @@ impl ClusterStore {
             name: self.name.clone(),
             node_number: self.get_node_number(),
             node_number_with_slots: self.get_node_number_with_slots(),
+            replica_num: self.replica_num,
             is_migrating: self.is_migrating(),
+            migration_paused: self.migration_paused,
+            failover_lost_bytes: self.failover_lost_bytes,
+            migration_progress: None,
         }
     }
 
@@ -186,7 +433,45 @@ impl ClusterStore {
     }
 
     pub fn get_node_number(&self) -> usize {
-        self.chunks.len() * CHUNK_NODE_NUM
+        self.chunks.len() * self.get_chunk_node_num()
+    }
+
+    pub fn get_chunk_node_num(&self) -> usize {
+        get_chunk_node_num(self.replica_num)
+    }
+
+    pub fn get_chunk_proxy_num(&self) -> usize {
+        get_chunk_proxy_num(self.replica_num)
+    }
+
+    pub fn to_nodes(&self) -> Vec<Node> {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.to_nodes(&self.name, &self.chunks))
+            .collect()
+    }
+
+    // Both proxies of a chunk hold the data of the two chunk parts,
+    // so the chunk can only afford the capacity of the smaller one.
+    // The returned weights are indexed by `chunk_index * CHUNK_PARTS + chunk_part`.
+    pub fn get_master_weights(&self, all_proxies: &HashMap<String, ProxyResource>) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| {
+                let weight = chunk
+                    .proxy_addresses
+                    .iter()
+                    .map(|address| {
+                        all_proxies
+                            .get(address)
+                            .map(|proxy| proxy.weight)
+                            .unwrap_or(DEFAULT_PROXY_WEIGHT)
+                    })
+                    .min()
+                    .unwrap_or(DEFAULT_PROXY_WEIGHT);
+                vec![weight; CHUNK_PARTS]
+            })
+            .collect()
     }
 
     pub fn get_node_number_with_slots(&self) -> usize {
@@ -201,7 +486,7 @@ impl ClusterStore {
                     .count()
             })
             .sum();
-        masters_with_slots * CHUNK_HALF_NODE_NUM
+        masters_with_slots * get_chunk_proxy_num(self.replica_num)
     }
 
     // LimitMigration reduces the concurrent running migration.
@@ -290,6 +575,9 @@ impl ClusterStore {
             name: self.name.clone(),
             chunks,
             config: self.config.clone(),
+            replica_num: self.replica_num,
+            failover_lost_bytes: self.failover_lost_bytes,
+            migration_paused: self.migration_paused,
         }
     }
 }
@@ -317,6 +605,13 @@ pub struct MetaStore {
     pub failed_proxies: HashSet<String>,
     // failed_proxy_address => reporter_id => time,
     pub failures: HashMap<String, HashMap<String, i64>>,
+    // failed_node_address => reporter_id => time,
+    #[serde(default)]
+    pub node_failures: HashMap<String, HashMap<String, i64>>,
+    // proxy_address => cluster_name of the planned switchovers
+    // waiting for the proxies to pause the writes.
+    #[serde(default)]
+    pub switchovers: HashMap<String, ClusterName>,
     // Set it `true` for kubernetes StatefulSet
     // to disable the chunk allocation algorithm
     // and only use ProxyResource.index to allocate chunks.
@@ -332,6 +627,8 @@ impl MetaStore {
             all_proxies: HashMap::new(),
             failed_proxies: HashSet::new(),
             failures: HashMap::new(),
+            node_failures: HashMap::new(),
+            switchovers: HashMap::new(),
             enable_ordered_proxy,
         }
     }
@@ -408,14 +705,28 @@ impl MetaStore {
         MetaStoreUpdate::new(self).get_failures(falure_ttl, failure_quorum)
     }
 
+    pub fn add_node_failure(&mut self, node_address: String, reporter_id: String) {
+        MetaStoreUpdate::new(self).add_node_failure(node_address, reporter_id)
+    }
+
+    pub fn get_node_failures(
+        &mut self,
+        falure_ttl: chrono::Duration,
+        failure_quorum: u64,
+    ) -> Vec<String> {
+        MetaStoreUpdate::new(self).get_node_failures(falure_ttl, failure_quorum)
+    }
+
     pub fn add_proxy(
         &mut self,
         proxy_address: String,
         nodes: [String; NODES_PER_PROXY],
         host: Option<String>,
         index: Option<usize>,
+        weight: Option<usize>,
+        location: ProxyLocation,
     ) -> Result<(), MetaStoreError> {
-        MetaStoreUpdate::new(self).add_proxy(proxy_address, nodes, host, index)
+        MetaStoreUpdate::new(self).add_proxy(proxy_address, nodes, host, index, weight, location)
     }
 
     pub fn add_cluster(
@@ -426,6 +737,15 @@ impl MetaStore {
         MetaStoreUpdate::new(self).add_cluster(cluster_name, node_num)
     }
 
+    pub fn add_cluster_with_replica_num(
+        &mut self,
+        cluster_name: String,
+        node_num: usize,
+        replica_num: usize,
+    ) -> Result<(), MetaStoreError> {
+        MetaStoreUpdate::new(self).add_cluster_with_replica_num(cluster_name, node_num, replica_num)
+    }
+
     pub fn remove_cluster(&mut self, cluster_name: String) -> Result<(), MetaStoreError> {
         MetaStoreUpdate::new(self).remove_cluster(cluster_name)
     }
@@ -466,6 +786,30 @@ impl MetaStore {
         MetaStoreMigrate::new(self).migrate_slots_to_scale_down(cluster_name, new_node_num)
     }
 
+    pub fn migrate_hot_slots(
+        &mut self,
+        cluster_name: String,
+        slot_loads: &HashMap<usize, u64>,
+    ) -> Result<(), MetaStoreError> {
+        MetaStoreMigrate::new(self).migrate_hot_slots(cluster_name, slot_loads)
+    }
+
+    pub fn pause_migration(&mut self, cluster_name: String) -> Result<(), MetaStoreError> {
+        MetaStoreMigrate::new(self).pause_migration(cluster_name)
+    }
+
+    pub fn resume_migration(&mut self, cluster_name: String) -> Result<(), MetaStoreError> {
+        MetaStoreMigrate::new(self).resume_migration(cluster_name)
+    }
+
+    pub fn cancel_migration(&mut self, cluster_name: String) -> Result<(), MetaStoreError> {
+        MetaStoreMigrate::new(self).cancel_migration(cluster_name)
+    }
+
+    pub fn get_paused_migrations(&self) -> Vec<ClusterName> {
+        MetaStoreQuery::new(self).get_paused_migrations()
+    }
+
     pub fn commit_migration(
         &mut self,
         task: MigrationTaskMeta,
@@ -512,7 +856,7 @@ impl MetaStore {
 
         let existing_node_num = match self.clusters.get(&name) {
             None => return Err(MetaStoreError::ClusterNotFound),
-            Some(cluster) => cluster.chunks.len() * CHUNK_NODE_NUM,
+            Some(cluster) => cluster.get_node_number(),
         };
 
         let scale_op = match existing_node_num.cmp(&expected_num) {
@@ -561,6 +905,42 @@ impl MetaStore {
         Ok(())
     }
 
+    pub fn plan_auto_add_nodes(
+        &self,
+        cluster_name: String,
+        num: usize,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        MetaStorePlan::new(self).plan_auto_add_nodes(cluster_name, num)
+    }
+
+    pub fn plan_migrate_slots(&self, cluster_name: String) -> Result<ScalePlan, MetaStoreError> {
+        MetaStorePlan::new(self).plan_migrate_slots(cluster_name)
+    }
+
+    pub fn plan_migrate_slots_to_scale_down(
+        &self,
+        cluster_name: String,
+        new_node_num: usize,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        MetaStorePlan::new(self).plan_migrate_slots_to_scale_down(cluster_name, new_node_num)
+    }
+
+    pub fn plan_auto_scale_node_number(
+        &self,
+        cluster_name: String,
+        expected_num: usize,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        MetaStorePlan::new(self).plan_auto_scale_node_number(cluster_name, expected_num)
+    }
+
+    pub fn plan_migrate_hot_slots(
+        &self,
+        cluster_name: String,
+        slot_loads: &HashMap<usize, u64>,
+    ) -> Result<ScalePlan, MetaStoreError> {
+        MetaStorePlan::new(self).plan_migrate_hot_slots(cluster_name, slot_loads)
+    }
+
     pub fn get_free_proxies(&self) -> Vec<HostProxy> {
         MetaStoreQuery::new(&self).get_free_proxies()
     }
@@ -573,6 +953,41 @@ impl MetaStore {
         MetaStoreUpdate::new(self).replace_failed_proxy(failed_proxy_address, migration_limit)
     }
 
+    pub fn replace_failed_proxy_with_repl_offsets(
+        &mut self,
+        failed_proxy_address: String,
+        migration_limit: u64,
+        repl_offsets: &HashMap<String, u64>,
+        max_repl_lag: u64,
+    ) -> Result<Option<Proxy>, MetaStoreError> {
+        MetaStoreUpdate::new(self).replace_failed_proxy_with_repl_offsets(
+            failed_proxy_address,
+            migration_limit,
+            repl_offsets,
+            max_repl_lag,
+        )
+    }
+
+    pub fn failover_node(&mut self, failed_node_address: String) -> Result<(), MetaStoreError> {
+        MetaStoreUpdate::new(self).failover_node(failed_node_address)
+    }
+
+    pub fn add_switchover(&mut self, proxy_address: String) -> Result<(), MetaStoreError> {
+        MetaStoreUpdate::new(self).add_switchover(proxy_address)
+    }
+
+    pub fn add_host_switchover(&mut self, host: String) -> Result<(), MetaStoreError> {
+        MetaStoreUpdate::new(self).add_host_switchover(host)
+    }
+
+    pub fn get_switchover_tasks(&mut self) -> Vec<SwitchoverTask> {
+        MetaStoreUpdate::new(self).get_switchover_tasks()
+    }
+
+    pub fn commit_switchover(&mut self, proxy_address: String) -> Result<(), MetaStoreError> {
+        MetaStoreUpdate::new(self).commit_switchover(proxy_address)
+    }
+
     pub fn change_config(
         &mut self,
         cluster_name: String,
@@ -630,19 +1045,29 @@ pub enum MetaStoreError {
     FreeNodeNotFound,
     FreeNodeFound,
     ProxyNotFound,
+    NodeNotFound,
+    NodeFailoverNotAvailable,
+    ReplicaLagTooLarge,
+    SwitchoverNotAvailable,
     InvalidNodeNum,
+    InvalidReplicaNum,
     NodeNumAlreadyEnough,
     InvalidClusterName,
     InvalidMigrationTask,
     InvalidProxyAddress,
+    InvalidProxyWeight,
+    ProxyWeightTooLarge,
     MigrationTaskNotFound,
     MigrationRunning,
+    MigrationNotRunning,
     InvalidConfig {
         key: String,
         value: String,
         error: String,
     },
     SlotsAlreadyEven,
+    SlotLoadAlreadyBalanced,
+    MemoryStatsNotFound,
     SyncError(MetaSyncError),
     InvalidMetaVersion,
     SmallEpoch,
@@ -666,15 +1091,25 @@ impl MetaStoreError {
             Self::FreeNodeNotFound => "FREE_NODE_NOT_FOUND",
             Self::FreeNodeFound => "FREE_NODE_FOUND",
             Self::ProxyNotFound => "PROXY_NOT_FOUND",
+            Self::NodeNotFound => "NODE_NOT_FOUND",
+            Self::NodeFailoverNotAvailable => "NODE_FAILOVER_NOT_AVAILABLE",
+            Self::ReplicaLagTooLarge => "REPLICA_LAG_TOO_LARGE",
+            Self::SwitchoverNotAvailable => "SWITCHOVER_NOT_AVAILABLE",
             Self::InvalidNodeNum => "INVALID_NODE_NUMBER",
+            Self::InvalidReplicaNum => "INVALID_REPLICA_NUMBER",
             Self::NodeNumAlreadyEnough => "NODE_NUM_ALREADY_ENOUGH",
             Self::InvalidClusterName => "INVALID_CLUSTER_NAME",
             Self::InvalidMigrationTask => "INVALID_MIGRATION_TASK",
             Self::InvalidProxyAddress => "INVALID_PROXY_ADDRESS",
+            Self::InvalidProxyWeight => "INVALID_PROXY_WEIGHT",
+            Self::ProxyWeightTooLarge => "PROXY_WEIGHT_TOO_LARGE",
             Self::MigrationTaskNotFound => "MIGRATION_TASK_NOT_FOUND",
             Self::MigrationRunning => "MIGRATION_RUNNING",
+            Self::MigrationNotRunning => "MIGRATION_NOT_RUNNING",
             Self::InvalidConfig { .. } => "INVALID_CONFIG",
             Self::SlotsAlreadyEven => "SLOTS_ALREADY_EVEN",
+            Self::SlotLoadAlreadyBalanced => "SLOT_LOAD_ALREADY_BALANCED",
+            Self::MemoryStatsNotFound => "MEMORY_STATS_NOT_FOUND",
             Self::SyncError(err) => err.to_code(),
             Self::InvalidMetaVersion => "INVALID_META_VERSION",
             Self::SmallEpoch => "EPOCH_SMALLER_THAN_CURRENT",
@@ -736,7 +1171,6 @@ mod tests {
         add_testing_proxies, check_cluster_and_proxy, check_cluster_slots,
     };
     use super::*;
-    use crate::common::cluster::Role;
     use crate::common::config::CompressionStrategy;
     use std::convert::TryFrom;
 
@@ -749,11 +1183,25 @@ mod tests {
         let nodes = ["127.0.0.1:6000".to_string(), "127.0.0.1:6001".to_string()];
 
         assert!(store
-            .add_proxy("127.0.0.1".to_string(), nodes.clone(), None, None)
+            .add_proxy(
+                "127.0.0.1".to_string(),
+                nodes.clone(),
+                None,
+                None,
+                None,
+                ProxyLocation::default()
+            )
             .is_err());
 
         store
-            .add_proxy(proxy_address.to_string(), nodes.clone(), None, None)
+            .add_proxy(
+                proxy_address.to_string(),
+                nodes.clone(),
+                None,
+                None,
+                None,
+                ProxyLocation::default(),
+            )
             .unwrap();
         assert_eq!(store.get_global_epoch(), 1);
         assert_eq!(store.all_proxies.len(), 1);
@@ -783,7 +1231,14 @@ mod tests {
         {
             let mut store = MetaStore::new(false);
             store
-                .add_proxy(proxy_address.to_string(), nodes.clone(), None, None)
+                .add_proxy(
+                    proxy_address.to_string(),
+                    nodes.clone(),
+                    None,
+                    None,
+                    None,
+                    ProxyLocation::default(),
+                )
                 .unwrap();
             let proxies = store.get_free_proxies();
             let proxy = proxies.get(0).unwrap();
@@ -798,6 +1253,8 @@ mod tests {
                     nodes.clone(),
                     Some("localhost".to_string()),
                     Some(299),
+                    None,
+                    ProxyLocation::default(),
                 )
                 .unwrap();
             let proxies = store.get_free_proxies();
@@ -1110,7 +1567,14 @@ mod tests {
             .node_addresses
             .clone();
         let err = store
-            .add_proxy(failed_proxy_address.clone(), nodes, None, None)
+            .add_proxy(
+                failed_proxy_address.clone(),
+                nodes,
+                None,
+                None,
+                None,
+                ProxyLocation::default(),
+            )
             .unwrap_err();
         assert_eq!(err, MetaStoreError::AlreadyExisted);
         assert_eq!(
@@ -1122,77 +1586,553 @@ mod tests {
         check_cluster_and_proxy(&store);
     }
 
-    const CLUSTER_NAME: &'static str = "testcluster";
+    fn get_master_num(cluster: &Cluster) -> usize {
+        cluster
+            .get_nodes()
+            .iter()
+            .filter(|node| node.get_role() == Role::Master)
+            .count()
+    }
 
     #[test]
-    fn test_add_and_delete_free_nodes() {
-        let migration_limit = 0;
+    fn test_invalid_replica_num() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+        let err = store
+            .add_cluster_with_replica_num("testcluster".to_string(), 8, MAX_REPLICA_NUM + 1)
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::InvalidReplicaNum);
+        let err = store
+            .add_cluster_with_replica_num("testcluster".to_string(), 4, 2)
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::InvalidNodeNum);
+        let err = store
+            .add_cluster_with_replica_num("testcluster".to_string(), 3, 0)
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::InvalidNodeNum);
+    }
 
+    #[test]
+    fn test_cluster_without_replica() {
+        let migration_limit = 0;
         let mut store = MetaStore::new(false);
-        let host_num = 4;
-        let proxy_per_host = 3;
-        let all_proxy_num = host_num * proxy_per_host;
-        add_testing_proxies(&mut store, host_num, proxy_per_host);
-        assert_eq!(store.get_free_proxies().len(), all_proxy_num);
+        add_testing_proxies(&mut store, 4, 3);
 
-        let cluster_name = CLUSTER_NAME.to_string();
+        let cluster_name = "testcluster".to_string();
+        store
+            .add_cluster_with_replica_num(cluster_name.clone(), 4, 0)
+            .unwrap();
+        assert_eq!(store.get_free_proxies().len(), 10);
+        let info = store
+            .get_cluster_info_by_name(&cluster_name, migration_limit)
+            .unwrap();
+        assert_eq!(info.replica_num, 0);
+        assert_eq!(info.node_number, 4);
+        assert_eq!(info.node_number_with_slots, 4);
 
-        store.add_cluster(cluster_name.clone(), 4).unwrap();
-        let epoch1 = store.get_global_epoch();
-        assert_eq!(store.get_free_proxies().len(), all_proxy_num - 2);
-        assert_eq!(
-            store
-                .get_cluster_by_name(&cluster_name, migration_limit)
-                .unwrap()
-                .get_nodes()
-                .len(),
-            4
-        );
+        let cluster = store
+            .get_cluster_by_name(&cluster_name, migration_limit)
+            .unwrap();
+        assert_eq!(get_master_num(&cluster), 4);
+        for node in cluster.get_nodes().iter() {
+            assert!(node.get_repl_meta().get_peers().is_empty());
+            assert_eq!(node.get_slots().len(), 1);
+        }
         check_cluster_and_proxy(&store);
 
-        store.auto_add_nodes(cluster_name.clone(), 4).unwrap();
-        let epoch2 = store.get_global_epoch();
-        assert!(epoch1 < epoch2);
-        assert_eq!(store.get_free_proxies().len(), all_proxy_num - 4);
-        assert_eq!(
-            store
-                .get_cluster_by_name(&cluster_name, migration_limit)
-                .unwrap()
-                .get_nodes()
-                .len(),
-            8
-        );
-        check_cluster_and_proxy(&store);
+        store.auto_add_nodes(cluster_name.clone(), 2).unwrap();
+        assert_eq!(store.get_free_proxies().len(), 9);
 
-        store.audo_delete_free_nodes(cluster_name.clone()).unwrap();
-        let epoch3 = store.get_global_epoch();
-        assert!(epoch2 < epoch3);
-        assert_eq!(store.get_free_proxies().len(), all_proxy_num - 2);
-        assert_eq!(
-            store
-                .get_cluster_by_name(&cluster_name, migration_limit)
-                .unwrap()
-                .get_nodes()
-                .len(),
-            4
-        );
+        let failed_proxy_address = cluster.get_nodes()[0].get_proxy_address().to_string();
+        let new_proxy = store
+            .replace_failed_proxy(failed_proxy_address, migration_limit)
+            .unwrap()
+            .unwrap();
+        let cluster = store
+            .get_cluster_by_name(&cluster_name, migration_limit)
+            .unwrap();
+        let new_nodes: Vec<&Node> = cluster
+            .get_nodes()
+            .iter()
+            .filter(|node| node.get_proxy_address() == new_proxy.get_address())
+            .collect();
+        assert_eq!(new_nodes.len(), 2);
+        for node in new_nodes.into_iter() {
+            assert_eq!(node.get_role(), Role::Master);
+            assert_eq!(node.get_slots().len(), 1);
+        }
         check_cluster_and_proxy(&store);
     }
 
-    fn test_migration_helper(
-        host_num: usize,
-        proxy_per_host: usize,
-        start_node_num: usize,
-        added_node_num: usize,
-        migration_limit: u64,
-    ) {
-        let mut store =
-            init_migration_test_store(host_num, proxy_per_host, start_node_num, migration_limit);
-        test_scaling(
-            &mut store,
-            host_num * proxy_per_host,
-            added_node_num,
-            migration_limit,
+    #[test]
+    fn test_cluster_with_two_replicas() {
+        let migration_limit = 0;
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+
+        let cluster_name = "testcluster".to_string();
+        store
+            .add_cluster_with_replica_num(cluster_name.clone(), 12, 2)
+            .unwrap();
+        assert_eq!(store.get_free_proxies().len(), 6);
+        let info = store
+            .get_cluster_info_by_name(&cluster_name, migration_limit)
+            .unwrap();
+        assert_eq!(info.replica_num, 2);
+        assert_eq!(info.node_number, 12);
+        assert_eq!(info.node_number_with_slots, 12);
+
+        let name = ClusterName::try_from(cluster_name.as_str()).unwrap();
+        for chunk in store.clusters.get(&name).unwrap().chunks.iter() {
+            assert_eq!(chunk.proxy_addresses.len(), 3);
+            assert_eq!(chunk.node_addresses.len(), 6);
+            let hosts: HashSet<&String> = chunk.hosts.iter().collect();
+            assert_eq!(hosts.len(), 3);
+        }
+
+        let cluster = store
+            .get_cluster_by_name(&cluster_name, migration_limit)
+            .unwrap();
+        assert_eq!(get_master_num(&cluster), 4);
+        for node in cluster.get_nodes().iter() {
+            let peers = node.get_repl_meta().get_peers();
+            if node.get_role() == Role::Master {
+                assert_eq!(peers.len(), 2);
+            } else {
+                assert_eq!(peers.len(), 1);
+                let master = cluster
+                    .get_nodes()
+                    .iter()
+                    .find(|n| n.get_address() == peers[0].node_address)
+                    .unwrap();
+                assert_eq!(master.get_role(), Role::Master);
+            }
+        }
+        check_cluster_and_proxy(&store);
+
+        // The replicas in the third proxy don't affect the masters.
+        let chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        store
+            .replace_failed_proxy(chunk.proxy_addresses[2].clone(), migration_limit)
+            .unwrap()
+            .unwrap();
+        let new_chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(new_chunk.role_position, ChunkRolePosition::Normal);
+        assert_ne!(new_chunk.proxy_addresses[2], chunk.proxy_addresses[2]);
+
+        store
+            .replace_failed_proxy(chunk.proxy_addresses[0].clone(), migration_limit)
+            .unwrap()
+            .unwrap();
+        let new_chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(
+            new_chunk.role_position,
+            ChunkRolePosition::SecondChunkMaster
+        );
+        let cluster = store
+            .get_cluster_by_name(&cluster_name, migration_limit)
+            .unwrap();
+        assert_eq!(get_master_num(&cluster), 4);
+        for node in cluster.get_nodes().iter() {
+            if node.get_proxy_address() == new_chunk.proxy_addresses[1] {
+                assert_eq!(node.get_role(), Role::Master);
+            }
+        }
+        check_cluster_and_proxy(&store);
+    }
+
+    #[test]
+    fn test_cluster_with_two_replicas_losing_both_masters() {
+        let migration_limit = 0;
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+
+        let cluster_name = "testcluster".to_string();
+        store
+            .add_cluster_with_replica_num(cluster_name.clone(), 6, 2)
+            .unwrap();
+        let name = ClusterName::try_from(cluster_name.as_str()).unwrap();
+        let chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+
+        for address in chunk.proxy_addresses[..2].iter() {
+            store.add_failure(address.clone(), "reporter_id".to_string());
+        }
+        store
+            .replace_failed_proxy(chunk.proxy_addresses[1].clone(), migration_limit)
+            .unwrap()
+            .unwrap();
+        let new_chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(new_chunk.role_position, ChunkRolePosition::FirstChunkMaster);
+        assert_eq!(new_chunk.proxy_addresses[0], chunk.proxy_addresses[2]);
+        assert_eq!(new_chunk.proxy_addresses[2], chunk.proxy_addresses[0]);
+
+        let cluster = store
+            .get_cluster_by_name(&cluster_name, migration_limit)
+            .unwrap();
+        for node in cluster.get_nodes().iter() {
+            if node.get_role() == Role::Master {
+                assert_eq!(node.get_proxy_address(), &chunk.proxy_addresses[2]);
+            }
+        }
+
+        store
+            .replace_failed_proxy(chunk.proxy_addresses[0].clone(), migration_limit)
+            .unwrap()
+            .unwrap();
+        let new_chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(new_chunk.role_position, ChunkRolePosition::FirstChunkMaster);
+        assert_eq!(new_chunk.proxy_addresses[0], chunk.proxy_addresses[2]);
+        assert!(!new_chunk
+            .proxy_addresses
+            .contains(&chunk.proxy_addresses[0]));
+        assert!(!new_chunk
+            .proxy_addresses
+            .contains(&chunk.proxy_addresses[1]));
+        check_cluster_and_proxy(&store);
+    }
+
+    #[test]
+    fn test_failover_to_most_up_to_date_replica() {
+        let migration_limit = 0;
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+
+        let cluster_name = "testcluster".to_string();
+        store
+            .add_cluster_with_replica_num(cluster_name.clone(), 6, 2)
+            .unwrap();
+        let name = ClusterName::try_from(cluster_name.as_str()).unwrap();
+        let chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+
+        let master = chunk.node_addresses[chunk.get_master_node_index(0)].clone();
+        let replica1 = chunk.node_addresses[chunk.get_part_node_index(0, 1).unwrap()].clone();
+        let replica2 = chunk.node_addresses[chunk.get_part_node_index(0, 2).unwrap()].clone();
+        let mut repl_offsets = HashMap::new();
+        repl_offsets.insert(master, 1000);
+        repl_offsets.insert(replica1, 900);
+        repl_offsets.insert(replica2, 990);
+
+        let failed_proxy_address = chunk.proxy_addresses[0].clone();
+        let err = store
+            .replace_failed_proxy_with_repl_offsets(
+                failed_proxy_address.clone(),
+                migration_limit,
+                &repl_offsets,
+                5,
+            )
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::ReplicaLagTooLarge);
+        let unchanged_chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(unchanged_chunk.proxy_addresses, chunk.proxy_addresses);
+        assert_eq!(unchanged_chunk.role_position, ChunkRolePosition::Normal);
+
+        store
+            .replace_failed_proxy_with_repl_offsets(
+                failed_proxy_address.clone(),
+                migration_limit,
+                &repl_offsets,
+                100,
+            )
+            .unwrap()
+            .unwrap();
+        let new_chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(new_chunk.role_position, ChunkRolePosition::Normal);
+        assert_eq!(new_chunk.proxy_addresses[0], chunk.proxy_addresses[2]);
+        assert!(!new_chunk.proxy_addresses.contains(&failed_proxy_address));
+
+        let info = store
+            .get_cluster_info_by_name(&cluster_name, migration_limit)
+            .unwrap();
+        assert_eq!(info.failover_lost_bytes, 10);
+        check_cluster_and_proxy(&store);
+    }
+
+    #[test]
+    fn test_switchover() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+        let cluster_name = "testcluster".to_string();
+        store.add_cluster(cluster_name.clone(), 4).unwrap();
+        let name = ClusterName::try_from(cluster_name.as_str()).unwrap();
+        let chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        let master_proxy = chunk.proxy_addresses[0].clone();
+
+        assert_eq!(
+            store.add_switchover("127.0.0.1:9999".to_string()),
+            Err(MetaStoreError::ProxyNotFound)
+        );
+
+        store.add_switchover(master_proxy.clone()).unwrap();
+        let tasks = store.get_switchover_tasks();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].proxy_address, master_proxy);
+        assert_eq!(tasks[0].nodes.len(), 1);
+        assert_eq!(
+            tasks[0].nodes[0].replica_proxy_address,
+            chunk.proxy_addresses[1]
+        );
+
+        let epoch = store.get_global_epoch();
+        store.commit_switchover(master_proxy.clone()).unwrap();
+        assert!(store.get_global_epoch() > epoch);
+        assert!(store.get_switchover_tasks().is_empty());
+        let new_chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(new_chunk.proxy_addresses, chunk.proxy_addresses);
+        assert_eq!(
+            new_chunk.role_position,
+            ChunkRolePosition::SecondChunkMaster
+        );
+
+        // The proxy does not own any master now.
+        store.add_switchover(master_proxy.clone()).unwrap();
+        assert!(store.get_switchover_tasks().is_empty());
+        let epoch = store.get_global_epoch();
+        store.commit_switchover(master_proxy).unwrap();
+        assert_eq!(store.get_global_epoch(), epoch);
+        check_cluster_and_proxy(&store);
+    }
+
+    #[test]
+    fn test_switchover_without_replica() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+        let cluster_name = "testcluster".to_string();
+        store
+            .add_cluster_with_replica_num(cluster_name.clone(), 4, 0)
+            .unwrap();
+        let name = ClusterName::try_from(cluster_name.as_str()).unwrap();
+        let chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+
+        assert_eq!(
+            store.add_switchover(chunk.proxy_addresses[0].clone()),
+            Err(MetaStoreError::SwitchoverNotAvailable)
+        );
+        assert!(store.get_switchover_tasks().is_empty());
+    }
+
+    #[test]
+    fn test_host_switchover() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+        let cluster_name = "testcluster".to_string();
+        store.add_cluster(cluster_name.clone(), 8).unwrap();
+
+        assert_eq!(
+            store.add_host_switchover("127.0.0.9".to_string()),
+            Err(MetaStoreError::ProxyNotFound)
+        );
+
+        let chunk_master_proxy = {
+            let name = ClusterName::try_from(cluster_name.as_str()).unwrap();
+            store.clusters.get(&name).unwrap().chunks[0].proxy_addresses[0].clone()
+        };
+        let host = chunk_master_proxy.split(':').next().unwrap().to_string();
+        store.add_host_switchover(host.clone()).unwrap();
+
+        let tasks = store.get_switchover_tasks();
+        assert!(!tasks.is_empty());
+        for task in tasks.iter() {
+            assert!(task.proxy_address.starts_with(&host));
+            for node in task.nodes.iter() {
+                assert!(!node.replica_proxy_address.starts_with(&host));
+            }
+        }
+        for task in tasks {
+            store.commit_switchover(task.proxy_address).unwrap();
+        }
+        check_cluster_and_proxy(&store);
+    }
+
+    #[test]
+    fn test_node_failures() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+        let cluster_name = "testcluster".to_string();
+        store.add_cluster(cluster_name.clone(), 4).unwrap();
+        let name = ClusterName::try_from(cluster_name.as_str()).unwrap();
+        let chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        let node_address = chunk.node_addresses[0].clone();
+
+        let epoch1 = store.get_global_epoch();
+        store.add_node_failure(node_address.clone(), "reporter_id1".to_string());
+        store.add_node_failure("127.0.0.1:9999".to_string(), "reporter_id1".to_string());
+        let epoch2 = store.get_global_epoch();
+        assert!(epoch1 < epoch2);
+
+        assert_eq!(
+            store.get_node_failures(chrono::Duration::max_value(), 1),
+            vec![node_address.clone()]
+        );
+        assert!(store
+            .get_node_failures(chrono::Duration::max_value(), 2)
+            .is_empty());
+        store.add_node_failure(node_address.clone(), "reporter_id2".to_string());
+        assert_eq!(
+            store.get_node_failures(chrono::Duration::max_value(), 2),
+            vec![node_address]
+        );
+        assert!(store
+            .get_node_failures(chrono::Duration::zero(), 1)
+            .is_empty());
+        assert!(store.node_failures.is_empty());
+    }
+
+    #[test]
+    fn test_failover_node() {
+        let migration_limit = 0;
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+        let cluster_name = "testcluster".to_string();
+        store.add_cluster(cluster_name.clone(), 4).unwrap();
+        let name = ClusterName::try_from(cluster_name.as_str()).unwrap();
+        let chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(chunk.role_position, ChunkRolePosition::Normal);
+
+        let err = store
+            .failover_node("127.0.0.1:9999".to_string())
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::NodeNotFound);
+
+        // Failed replicas don't change the masters.
+        let replica_address = chunk.node_addresses[3].clone();
+        store.failover_node(replica_address).unwrap();
+        let new_chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(new_chunk.role_position, ChunkRolePosition::Normal);
+
+        let epoch1 = store.get_global_epoch();
+        let failed_master = chunk.node_addresses[0].clone();
+        store.add_node_failure(failed_master.clone(), "reporter_id".to_string());
+        store.failover_node(failed_master.clone()).unwrap();
+        let epoch2 = store.get_global_epoch();
+        assert!(epoch1 < epoch2);
+        let new_chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(
+            new_chunk.role_position,
+            ChunkRolePosition::SecondChunkMaster
+        );
+        assert_eq!(new_chunk.proxy_addresses, chunk.proxy_addresses);
+
+        let cluster = store
+            .get_cluster_by_name(&cluster_name, migration_limit)
+            .unwrap();
+        assert_eq!(get_master_num(&cluster), 2);
+        for node in cluster.get_nodes().iter() {
+            if node.get_address() == failed_master {
+                assert_eq!(node.get_role(), Role::Replica);
+            }
+            if node.get_proxy_address() == chunk.proxy_addresses[1] {
+                assert_eq!(node.get_role(), Role::Master);
+            }
+        }
+
+        // Both masters would be moved back to the failed node.
+        let err = store
+            .failover_node(chunk.node_addresses[2].clone())
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::NodeFailoverNotAvailable);
+
+        // The masters should not be moved back to the failed node.
+        store.balance_masters(cluster_name.clone()).unwrap();
+        let new_chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(
+            new_chunk.role_position,
+            ChunkRolePosition::SecondChunkMaster
+        );
+
+        store.node_failures.clear();
+        store.balance_masters(cluster_name).unwrap();
+        let new_chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        assert_eq!(new_chunk.role_position, ChunkRolePosition::Normal);
+        check_cluster_and_proxy(&store);
+    }
+
+    #[test]
+    fn test_failover_node_without_replica() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+        let cluster_name = "testcluster".to_string();
+        store
+            .add_cluster_with_replica_num(cluster_name.clone(), 4, 0)
+            .unwrap();
+        let name = ClusterName::try_from(cluster_name.as_str()).unwrap();
+        let chunk = store.clusters.get(&name).unwrap().chunks[0].clone();
+        let err = store
+            .failover_node(chunk.node_addresses[0].clone())
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::NodeFailoverNotAvailable);
+    }
+
+    const CLUSTER_NAME: &'static str = "testcluster";
+
+    #[test]
+    fn test_add_and_delete_free_nodes() {
+        let migration_limit = 0;
+
+        let mut store = MetaStore::new(false);
+        let host_num = 4;
+        let proxy_per_host = 3;
+        let all_proxy_num = host_num * proxy_per_host;
+        add_testing_proxies(&mut store, host_num, proxy_per_host);
+        assert_eq!(store.get_free_proxies().len(), all_proxy_num);
+
+        let cluster_name = CLUSTER_NAME.to_string();
+
+        store.add_cluster(cluster_name.clone(), 4).unwrap();
+        let epoch1 = store.get_global_epoch();
+        assert_eq!(store.get_free_proxies().len(), all_proxy_num - 2);
+        assert_eq!(
+            store
+                .get_cluster_by_name(&cluster_name, migration_limit)
+                .unwrap()
+                .get_nodes()
+                .len(),
+            4
+        );
+        check_cluster_and_proxy(&store);
+
+        store.auto_add_nodes(cluster_name.clone(), 4).unwrap();
+        let epoch2 = store.get_global_epoch();
+        assert!(epoch1 < epoch2);
+        assert_eq!(store.get_free_proxies().len(), all_proxy_num - 4);
+        assert_eq!(
+            store
+                .get_cluster_by_name(&cluster_name, migration_limit)
+                .unwrap()
+                .get_nodes()
+                .len(),
+            8
+        );
+        check_cluster_and_proxy(&store);
+
+        store.audo_delete_free_nodes(cluster_name.clone()).unwrap();
+        let epoch3 = store.get_global_epoch();
+        assert!(epoch2 < epoch3);
+        assert_eq!(store.get_free_proxies().len(), all_proxy_num - 2);
+        assert_eq!(
+            store
+                .get_cluster_by_name(&cluster_name, migration_limit)
+                .unwrap()
+                .get_nodes()
+                .len(),
+            4
+        );
+        check_cluster_and_proxy(&store);
+    }
+
+    fn test_migration_helper(
+        host_num: usize,
+        proxy_per_host: usize,
+        start_node_num: usize,
+        added_node_num: usize,
+        migration_limit: u64,
+    ) {
+        let mut store =
+            init_migration_test_store(host_num, proxy_per_host, start_node_num, migration_limit);
+        test_scaling(
+            &mut store,
+            host_num * proxy_per_host,
+            added_node_num,
+            migration_limit,
         );
     }
 
@@ -1844,6 +2784,99 @@ mod tests {
         assert_eq!(migrating_masters, 4);
     }
 
+    #[test]
+    fn test_pause_and_resume_migration() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+
+        let cluster_name = CLUSTER_NAME.to_string();
+        store.add_cluster(cluster_name.clone(), 4).unwrap();
+        let err = store.pause_migration(cluster_name.clone()).unwrap_err();
+        assert_eq!(err, MetaStoreError::MigrationNotRunning);
+        let err = store.pause_migration("another".to_string()).unwrap_err();
+        assert_eq!(err, MetaStoreError::ClusterNotFound);
+
+        store.auto_add_nodes(cluster_name.clone(), 4).unwrap();
+        store.migrate_slots(cluster_name.clone()).unwrap();
+        assert!(store.get_paused_migrations().is_empty());
+
+        let epoch = store.get_global_epoch();
+        store.pause_migration(cluster_name.clone()).unwrap();
+        assert_eq!(store.get_global_epoch(), epoch);
+        let info = store.get_cluster_info_by_name(CLUSTER_NAME, 0).unwrap();
+        assert!(info.migration_paused);
+        assert_eq!(
+            store.get_paused_migrations(),
+            vec![ClusterName::try_from(CLUSTER_NAME).unwrap()]
+        );
+
+        store.resume_migration(cluster_name).unwrap();
+        let info = store.get_cluster_info_by_name(CLUSTER_NAME, 0).unwrap();
+        assert!(!info.migration_paused);
+        assert!(store.get_paused_migrations().is_empty());
+    }
+
+    #[test]
+    fn test_cancel_migration() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+
+        let cluster_name = CLUSTER_NAME.to_string();
+        store.add_cluster(cluster_name.clone(), 4).unwrap();
+        let err = store.cancel_migration(cluster_name.clone()).unwrap_err();
+        assert_eq!(err, MetaStoreError::MigrationNotRunning);
+
+        store.auto_add_nodes(cluster_name.clone(), 4).unwrap();
+        store.migrate_slots(cluster_name.clone()).unwrap();
+        store.pause_migration(cluster_name.clone()).unwrap();
+
+        let epoch = store.get_global_epoch();
+        store.cancel_migration(cluster_name.clone()).unwrap();
+        assert!(store.get_global_epoch() > epoch);
+        let info = store.get_cluster_info_by_name(CLUSTER_NAME, 0).unwrap();
+        assert!(info.is_migrating);
+        assert!(!info.migration_paused);
+
+        let cluster = store.get_cluster_by_name(CLUSTER_NAME, 0).unwrap();
+        let added_nodes: HashSet<String> = cluster
+            .get_nodes()
+            .iter()
+            .skip(4)
+            .map(|node| node.get_address().to_string())
+            .collect();
+        let slot_ranges: Vec<_> = cluster
+            .get_nodes()
+            .iter()
+            .filter(|node| node.get_role() == Role::Master)
+            .flat_map(|node| node.get_slots().iter())
+            .filter(|slot_range| slot_range.tag.is_migrating())
+            .cloned()
+            .collect();
+        assert!(!slot_ranges.is_empty());
+        for slot_range in slot_ranges.into_iter() {
+            let meta = slot_range.tag.get_migration_meta().unwrap();
+            assert!(added_nodes.contains(&meta.src_node_address));
+            assert!(!added_nodes.contains(&meta.dst_node_address));
+            assert!(meta.epoch > epoch);
+            let task_meta = MigrationTaskMeta {
+                cluster_name: ClusterName::try_from(CLUSTER_NAME).unwrap(),
+                slot_range,
+            };
+            store.commit_migration(task_meta, false).unwrap();
+        }
+
+        let cluster = store.get_cluster_by_name(CLUSTER_NAME, 0).unwrap();
+        for node in cluster.get_nodes().iter() {
+            assert!(node.get_slots().iter().all(|s| s.tag.is_stable()));
+            if added_nodes.contains(node.get_address()) {
+                assert!(node.get_slots().is_empty());
+            }
+        }
+        let info = store.get_cluster_info_by_name(CLUSTER_NAME, 0).unwrap();
+        assert!(!info.is_migrating);
+        check_cluster_and_proxy(&store);
+    }
+
     // Docs examples:
     #[test]
     fn test_one_proxy_per_host() {
@@ -2173,4 +3206,344 @@ mod tests {
             assert_eq!(second_slots_str, "1 8192-16383");
         }
     }
+
+    #[test]
+    fn test_weighted_slot_nums() {
+        assert_eq!(get_weighted_slot_nums(&[1, 1]), vec![8192, 8192]);
+        assert_eq!(get_weighted_slot_nums(&[1, 1, 1]), vec![5462, 5461, 5461]);
+        assert_eq!(
+            get_weighted_slot_nums(&[1, 1, 3, 3]),
+            vec![2048, 2048, 6144, 6144]
+        );
+        assert_eq!(get_weighted_slot_nums(&[2, 1]), vec![10923, 5461]);
+    }
+
+    #[test]
+    fn test_skewed_weighted_slot_nums() {
+        assert_eq!(get_weighted_slot_nums(&[1, 100_000]), vec![1, 16383]);
+        assert_eq!(get_weighted_slot_nums(&[100_000, 1]), vec![16383, 1]);
+        let slot_nums = get_weighted_slot_nums(&[1, 1, 100_000, 100_000]);
+        assert_eq!(slot_nums, vec![1, 1, 8191, 8191]);
+
+        let mut weights = vec![1; 100];
+        weights.push(usize::MAX);
+        let slot_nums = get_weighted_slot_nums(&weights);
+        assert!(slot_nums.iter().all(|slot_num| *slot_num >= 1));
+        assert_eq!(slot_nums.iter().sum::<usize>(), SLOT_NUM);
+        assert_eq!(slot_nums[100], SLOT_NUM - 100);
+    }
+
+    fn commit_all_migration(store: &mut MetaStore, cluster_name: &str) {
+        let cluster = store.get_cluster_by_name(cluster_name, 0).unwrap();
+        for node in cluster.get_nodes() {
+            for slot_range in node.get_slots() {
+                if !slot_range.tag.is_migrating() {
+                    continue;
+                }
+                let task_meta = MigrationTaskMeta {
+                    cluster_name: ClusterName::try_from(cluster_name).unwrap(),
+                    slot_range: slot_range.clone(),
+                };
+                store.commit_migration(task_meta, false).unwrap();
+            }
+        }
+    }
+
+    fn get_master_slot_nums(store: &MetaStore, cluster_name: &str) -> Vec<usize> {
+        let cluster_name = ClusterName::try_from(cluster_name).unwrap();
+        store
+            .clusters
+            .get(&cluster_name)
+            .unwrap()
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.stable_slots.iter())
+            .map(|slots| {
+                slots
+                    .as_ref()
+                    .map(|slots| slots.get_range_list().get_slots_num())
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    fn get_master_range_nums(store: &MetaStore, cluster_name: &str) -> Vec<usize> {
+        let cluster_name = ClusterName::try_from(cluster_name).unwrap();
+        store
+            .clusters
+            .get(&cluster_name)
+            .unwrap()
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.stable_slots.iter())
+            .map(|slots| {
+                slots
+                    .as_ref()
+                    .map(|slots| slots.get_range_list().get_ranges().len())
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_weighted_slots() {
+        let mut store = MetaStore::new(false);
+        let add_proxy = |store: &mut MetaStore, host_index: usize, weight: usize| {
+            let proxy_address = format!("127.0.0.{}:7000", host_index);
+            let nodes = [
+                format!("127.0.0.{}:6000", host_index),
+                format!("127.0.0.{}:6001", host_index),
+            ];
+            store
+                .add_proxy(
+                    proxy_address,
+                    nodes,
+                    None,
+                    None,
+                    Some(weight),
+                    ProxyLocation::default(),
+                )
+                .unwrap();
+        };
+
+        let err = store
+            .add_proxy(
+                "127.0.0.1:7000".to_string(),
+                ["127.0.0.1:6000".to_string(), "127.0.0.1:6001".to_string()],
+                None,
+                None,
+                Some(0),
+                ProxyLocation::default(),
+            )
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::InvalidProxyWeight);
+        let err = store
+            .add_proxy(
+                "127.0.0.1:7000".to_string(),
+                ["127.0.0.1:6000".to_string(), "127.0.0.1:6001".to_string()],
+                None,
+                None,
+                Some(MAX_PROXY_WEIGHT + 1),
+                ProxyLocation::default(),
+            )
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::ProxyWeightTooLarge);
+
+        add_proxy(&mut store, 1, 1);
+        add_proxy(&mut store, 2, 1);
+        store.add_cluster(CLUSTER_NAME.to_string(), 4).unwrap();
+        assert_eq!(get_master_slot_nums(&store, CLUSTER_NAME), vec![8192, 8192]);
+
+        add_proxy(&mut store, 3, 3);
+        add_proxy(&mut store, 4, 3);
+        store.auto_add_nodes(CLUSTER_NAME.to_string(), 4).unwrap();
+        store.migrate_slots(CLUSTER_NAME.to_string()).unwrap();
+        commit_all_migration(&mut store, CLUSTER_NAME);
+        assert_eq!(
+            get_master_slot_nums(&store, CLUSTER_NAME),
+            vec![2048, 2048, 6144, 6144]
+        );
+        check_cluster_and_proxy(&store);
+
+        store
+            .migrate_slots_to_scale_down(CLUSTER_NAME.to_string(), 4)
+            .unwrap();
+        commit_all_migration(&mut store, CLUSTER_NAME);
+        assert_eq!(
+            get_master_slot_nums(&store, CLUSTER_NAME),
+            vec![8192, 8192, 0, 0]
+        );
+        check_cluster_and_proxy(&store);
+    }
+
+    #[test]
+    fn test_migrate_hot_slots() {
+        let mut store = MetaStore::new(false);
+        add_testing_proxies(&mut store, 4, 3);
+        store.add_cluster(CLUSTER_NAME.to_string(), 8).unwrap();
+
+        // The store is not changed on error.
+        let epoch = store.get_global_epoch();
+        let err = store
+            .migrate_hot_slots(CLUSTER_NAME.to_string(), &HashMap::new())
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::SlotLoadAlreadyBalanced);
+        assert_eq!(store.get_global_epoch(), epoch);
+        assert_eq!(
+            get_master_range_nums(&store, CLUSTER_NAME),
+            vec![1, 1, 1, 1]
+        );
+
+        let mut slot_loads = HashMap::new();
+        slot_loads.insert(0, 100);
+        slot_loads.insert(1, 100);
+        slot_loads.insert(2, 100);
+        slot_loads.insert(5000, 10);
+        store
+            .migrate_hot_slots(CLUSTER_NAME.to_string(), &slot_loads)
+            .unwrap();
+        // The remaining slots of the src master are still compacted
+        // and the other masters are not changed.
+        assert_eq!(
+            get_master_range_nums(&store, CLUSTER_NAME),
+            vec![1, 1, 1, 1]
+        );
+        let err = store
+            .migrate_hot_slots(CLUSTER_NAME.to_string(), &slot_loads)
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::MigrationRunning);
+
+        commit_all_migration(&mut store, CLUSTER_NAME);
+        assert_eq!(
+            get_master_slot_nums(&store, CLUSTER_NAME),
+            vec![4094, 4096, 4097, 4097]
+        );
+        check_cluster_and_proxy(&store);
+
+        let epoch = store.get_global_epoch();
+        let err = store
+            .migrate_hot_slots(CLUSTER_NAME.to_string(), &slot_loads)
+            .unwrap_err();
+        assert_eq!(err, MetaStoreError::SlotLoadAlreadyBalanced);
+        assert_eq!(store.get_global_epoch(), epoch);
+    }
+
+    fn add_zone_proxies(store: &mut MetaStore, zone: &str, host_num: usize, proxy_per_host: usize) {
+        for host_index in 1..=host_num {
+            for i in 1..=proxy_per_host {
+                let host = format!("{}-host{}", zone, host_index);
+                let proxy_address = format!("{}:70{:02}", host, i);
+                let node_addresses = [
+                    format!("{}:60{:02}", host, i * 2),
+                    format!("{}:60{:02}", host, i * 2 + 1),
+                ];
+                let location = ProxyLocation {
+                    zone: Some(zone.to_string()),
+                    rack: None,
+                };
+                store
+                    .add_proxy(
+                        proxy_address,
+                        node_addresses,
+                        Some(host),
+                        None,
+                        None,
+                        location,
+                    )
+                    .unwrap();
+            }
+        }
+    }
+
+    fn get_zone(store: &MetaStore, proxy_address: &str) -> String {
+        store
+            .all_proxies
+            .get(proxy_address)
+            .unwrap()
+            .location
+            .zone
+            .clone()
+            .unwrap()
+    }
+
+    fn check_chunk_zones(store: &MetaStore) {
+        for cluster in store.clusters.values() {
+            for chunk in cluster.chunks.iter() {
+                assert_ne!(
+                    get_zone(store, &chunk.proxy_addresses[0]),
+                    get_zone(store, &chunk.proxy_addresses[1])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_zone_aware_allocation() {
+        let mut store = MetaStore::new(false);
+        add_zone_proxies(&mut store, "zone1", 2, 2);
+        add_zone_proxies(&mut store, "zone2", 2, 2);
+        add_zone_proxies(&mut store, "zone3", 2, 2);
+
+        store.add_cluster(CLUSTER_NAME.to_string(), 8).unwrap();
+        check_chunk_zones(&store);
+        store.auto_add_nodes(CLUSTER_NAME.to_string(), 8).unwrap();
+        check_chunk_zones(&store);
+        check_cluster_and_proxy(&store);
+    }
+
+    #[test]
+    fn test_zone_aware_allocation_fallback() {
+        let mut store = MetaStore::new(false);
+        add_zone_proxies(&mut store, "zone1", 2, 3);
+        add_zone_proxies(&mut store, "zone2", 1, 1);
+
+        // Only one chunk could span two zones.
+        store.add_cluster(CLUSTER_NAME.to_string(), 8).unwrap();
+        let cluster = store
+            .clusters
+            .get(&ClusterName::try_from(CLUSTER_NAME).unwrap());
+        for chunk in cluster.unwrap().chunks.iter() {
+            let hosts = &chunk.hosts;
+            assert_ne!(hosts[0], hosts[1]);
+        }
+        check_cluster_and_proxy(&store);
+    }
+
+    #[test]
+    fn test_zone_aware_replacement() {
+        let mut store = MetaStore::new(false);
+        add_zone_proxies(&mut store, "zone1", 2, 1);
+        add_zone_proxies(&mut store, "zone2", 2, 1);
+        add_zone_proxies(&mut store, "zone3", 2, 1);
+        store.add_cluster(CLUSTER_NAME.to_string(), 8).unwrap();
+        check_chunk_zones(&store);
+
+        let failed_proxy_address = store
+            .clusters
+            .get(&ClusterName::try_from(CLUSTER_NAME).unwrap())
+            .unwrap()
+            .chunks[0]
+            .proxy_addresses[0]
+            .clone();
+        let proxy = store
+            .replace_failed_proxy(failed_proxy_address, 1)
+            .unwrap()
+            .unwrap();
+        assert!(store
+            .all_proxies
+            .get(proxy.get_address())
+            .unwrap()
+            .cluster
+            .is_some());
+        check_chunk_zones(&store);
+        check_cluster_and_proxy(&store);
+    }
+
+    #[test]
+    fn test_balance_masters_relocate_colocated_chunk() {
+        let mut store = MetaStore::new(false);
+        add_zone_proxies(&mut store, "zone1", 2, 1);
+        store.add_cluster(CLUSTER_NAME.to_string(), 4).unwrap();
+        add_zone_proxies(&mut store, "zone2", 2, 1);
+
+        let cluster_name = ClusterName::try_from(CLUSTER_NAME).unwrap();
+        let old_proxy_address =
+            store.clusters.get(&cluster_name).unwrap().chunks[0].proxy_addresses[1].clone();
+
+        store.balance_masters(CLUSTER_NAME.to_string()).unwrap();
+        check_chunk_zones(&store);
+        check_cluster_and_proxy(&store);
+        let chunk = &store.clusters.get(&cluster_name).unwrap().chunks[0];
+        assert_eq!(chunk.role_position, ChunkRolePosition::FirstChunkMaster);
+        assert!(store
+            .all_proxies
+            .get(&old_proxy_address)
+            .unwrap()
+            .cluster
+            .is_none());
+
+        store.balance_masters(CLUSTER_NAME.to_string()).unwrap();
+        let chunk = &store.clusters.get(&cluster_name).unwrap().chunks[0];
+        assert_eq!(chunk.role_position, ChunkRolePosition::Normal);
+    }
 }
@@ -1,17 +1,18 @@
 use super::query::MetaStoreQuery;
 use super::store::{
-    ChunkRolePosition, ChunkStore, ClusterStore, HostProxy, MetaStore, MetaStoreError,
-    ProxyResource, CHUNK_HALF_NODE_NUM, CHUNK_NODE_NUM, CHUNK_PARTS, NODES_PER_PROXY,
+    get_chunk_node_num, get_chunk_proxy_num, get_weighted_slot_nums, ChunkRolePosition, ChunkStore,
+    ClusterStore, LocationLevel, MetaStore, MetaStoreError, ProxyLocation, ProxyResource,
+    CHUNK_PARTS, DEFAULT_PROXY_WEIGHT, DEFAULT_REPLICA_NUM, MAX_PROXY_WEIGHT, MAX_REPLICA_NUM,
+    NODES_PER_PROXY,
 };
+use crate::common::cluster::ClusterName;
 use crate::common::cluster::{
-    Cluster, Node, Proxy, Range, RangeList, ReplMeta, ReplPeer, SlotRange, SlotRangeTag,
+    Cluster, Node, Proxy, Range, RangeList, SlotRange, SlotRangeTag, SwitchoverNode, SwitchoverTask,
 };
-use crate::common::cluster::{ClusterName, Role};
 use crate::common::config::ClusterConfig;
-use crate::common::utils::SLOT_NUM;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use itertools::Itertools;
-use std::cmp::Ordering;
+use std::cmp::{self, Ordering};
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::num::NonZeroUsize;
@@ -26,106 +27,10 @@ impl<'a> MetaStoreUpdate<'a> {
     }
 
     fn cluster_store_to_cluster(cluster_store: &ClusterStore) -> Cluster {
-        let cluster_name = cluster_store.name.clone();
-
-        let nodes = cluster_store
-            .chunks
-            .iter()
-            .map(|chunk| {
-                let mut nodes = vec![];
-                for i in 0..CHUNK_NODE_NUM {
-                    let address = chunk
-                        .node_addresses
-                        .get(i)
-                        .expect("MetaStore::get_cluster_by_name: failed to get node")
-                        .clone();
-                    let proxy_address = chunk
-                        .proxy_addresses
-                        .get(i / 2)
-                        .expect("MetaStore::get_cluster_by_name: failed to get proxy")
-                        .clone();
-
-                    // get slots
-                    let mut slots = vec![];
-                    let (first_slot_index, second_slot_index) = match chunk.role_position {
-                        ChunkRolePosition::Normal => (0, 2),
-                        ChunkRolePosition::FirstChunkMaster => (0, 1),
-                        ChunkRolePosition::SecondChunkMaster => (2, 3),
-                    };
-                    if i == first_slot_index {
-                        let mut first_slots = vec![];
-                        if let Some(stable_slots) = &chunk.stable_slots[0] {
-                            first_slots.push(stable_slots.clone());
-                        }
-                        slots.append(&mut first_slots);
-                        let slot_ranges: Vec<_> = chunk.migrating_slots[0]
-                            .iter()
-                            .map(|slot_range_store| {
-                                slot_range_store.to_slot_range(&cluster_store.chunks)
-                            })
-                            .collect();
-                        slots.extend(slot_ranges);
-                    }
-                    if i == second_slot_index {
-                        let mut second_slots = vec![];
-                        if let Some(stable_slots) = &chunk.stable_slots[1] {
-                            second_slots.push(stable_slots.clone());
-                        }
-                        slots.append(&mut second_slots);
-                        let slot_ranges: Vec<_> = chunk.migrating_slots[1]
-                            .iter()
-                            .map(|slot_range_store| {
-                                slot_range_store.to_slot_range(&cluster_store.chunks)
-                            })
-                            .collect();
-                        slots.extend(slot_ranges);
-                    }
-
-                    // get repl
-                    let mut role = Role::Master;
-                    match chunk.role_position {
-                        ChunkRolePosition::Normal if i % 2 == 1 => role = Role::Replica,
-                        ChunkRolePosition::FirstChunkMaster if i >= CHUNK_HALF_NODE_NUM => {
-                            role = Role::Replica
-                        }
-                        ChunkRolePosition::SecondChunkMaster if i < CHUNK_HALF_NODE_NUM => {
-                            role = Role::Replica
-                        }
-                        _ => (),
-                    }
-
-                    let peer_index = match i {
-                        0 => 3,
-                        1 => 2,
-                        2 => 1,
-                        _ => 0,
-                    };
-                    let peer = ReplPeer {
-                        node_address: chunk
-                            .node_addresses
-                            .get(peer_index)
-                            .expect("MetaStore::get_cluster_by_name: failed to get peer node")
-                            .clone(),
-                        proxy_address: chunk
-                            .proxy_addresses
-                            .get(peer_index / 2)
-                            .expect("MetaStore::get_cluster_by_name: failed to get peer proxy")
-                            .clone(),
-                    };
-                    let repl = ReplMeta::new(role, vec![peer]);
-
-                    let node = Node::new(address, proxy_address, cluster_name.clone(), slots, repl);
-                    nodes.push(node);
-                }
-                nodes
-            })
-            .flatten()
-            .collect();
-
         Cluster::new(
             cluster_store.name.clone(),
             cluster_store.epoch,
-            nodes,
+            cluster_store.to_nodes(),
             cluster_store.config.clone(),
         )
     }
@@ -172,17 +77,76 @@ impl<'a> MetaStoreUpdate<'a> {
             .collect()
     }
 
+    pub fn add_node_failure(&mut self, node_address: String, reporter_id: String) {
+        let now = Utc::now();
+        self.store.bump_global_epoch();
+        self.store
+            .node_failures
+            .entry(node_address)
+            .or_insert_with(HashMap::new)
+            .insert(reporter_id, now.timestamp());
+    }
+
+    pub fn get_node_failures(
+        &mut self,
+        falure_ttl: chrono::Duration,
+        failure_quorum: u64,
+    ) -> Vec<String> {
+        let now = Utc::now();
+        for reporter_map in self.store.node_failures.values_mut() {
+            reporter_map.retain(|_, report_time| {
+                let report_datetime =
+                    DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(*report_time, 0), Utc);
+                now - report_datetime < falure_ttl
+            });
+        }
+        self.store
+            .node_failures
+            .retain(|_, node_failure_map| !node_failure_map.is_empty());
+
+        let cluster_nodes: HashSet<&String> = self
+            .store
+            .clusters
+            .values()
+            .flat_map(|cluster| cluster.chunks.iter())
+            .flat_map(|chunk| chunk.node_addresses.iter())
+            .collect();
+        self.store
+            .node_failures
+            .iter()
+            .filter(|(_, v)| v.len() >= failure_quorum as usize)
+            .filter_map(|(address, _)| {
+                if cluster_nodes.contains(address) {
+                    Some(address.clone())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
     pub fn add_proxy(
         &mut self,
         proxy_address: String,
         nodes: [String; NODES_PER_PROXY],
         host: Option<String>,
         proxy_index: Option<usize>,
+        weight: Option<usize>,
+        location: ProxyLocation,
     ) -> Result<(), MetaStoreError> {
         if proxy_address.split(':').count() != 2 {
             return Err(MetaStoreError::InvalidProxyAddress);
         }
 
+        let weight = match weight {
+            None => DEFAULT_PROXY_WEIGHT,
+            Some(0) => return Err(MetaStoreError::InvalidProxyWeight),
+            Some(weight) if weight > MAX_PROXY_WEIGHT => {
+                return Err(MetaStoreError::ProxyWeightTooLarge)
+            }
+            Some(weight) => weight,
+        };
+
         let host = match (host, proxy_address.split(':').next()) {
             (Some(h), _) => h,
             (None, Some(h)) => h.to_string(),
@@ -206,6 +170,8 @@ impl<'a> MetaStoreUpdate<'a> {
                 proxy_address: proxy_address.clone(),
                 node_addresses: nodes,
                 host,
+                location,
+                weight,
                 index,
                 cluster: None,
             });
@@ -225,6 +191,18 @@ impl<'a> MetaStoreUpdate<'a> {
         cluster_name: String,
         node_num: usize,
     ) -> Result<(), MetaStoreError> {
+        self.add_cluster_with_replica_num(cluster_name, node_num, DEFAULT_REPLICA_NUM)
+    }
+
+    pub fn add_cluster_with_replica_num(
+        &mut self,
+        cluster_name: String,
+        node_num: usize,
+        replica_num: usize,
+    ) -> Result<(), MetaStoreError> {
+        if replica_num > MAX_REPLICA_NUM {
+            return Err(MetaStoreError::InvalidReplicaNum);
+        }
         if self.store.enable_ordered_proxy && !self.store.clusters.is_empty() {
             return Err(MetaStoreError::OneClusterAlreadyExisted);
         }
@@ -235,16 +213,17 @@ impl<'a> MetaStoreUpdate<'a> {
             return Err(MetaStoreError::AlreadyExisted);
         }
 
-        if node_num % 4 != 0 {
+        if node_num % get_chunk_node_num(replica_num) != 0 {
             return Err(MetaStoreError::InvalidNodeNum);
         }
-        let proxy_num =
-            NonZeroUsize::new(node_num / 2).ok_or_else(|| MetaStoreError::InvalidNodeNum)?;
+        let proxy_num = NonZeroUsize::new(node_num / NODES_PER_PROXY)
+            .ok_or_else(|| MetaStoreError::InvalidNodeNum)?;
+        let chunk_proxy_num = get_chunk_proxy_num(replica_num);
 
         let proxy_resource_arr = if self.store.enable_ordered_proxy {
-            self.generate_free_chunks_for_ordered_proxy_index(proxy_num, 0)?
+            self.generate_free_chunks_for_ordered_proxy_index(proxy_num, 0, chunk_proxy_num)?
         } else {
-            self.generate_free_chunks(proxy_num)?
+            self.generate_free_chunks(proxy_num, chunk_proxy_num)?
         };
         let chunk_stores = Self::proxy_resource_to_chunk_store(proxy_resource_arr, true);
 
@@ -255,6 +234,9 @@ impl<'a> MetaStoreUpdate<'a> {
             name: cluster_name.clone(),
             chunks: chunk_stores,
             config: ClusterConfig::default(),
+            replica_num,
+            failover_lost_bytes: 0,
+            migration_paused: false,
         };
 
         // Tag the proxies as occupied
@@ -275,22 +257,30 @@ impl<'a> MetaStoreUpdate<'a> {
 
     // This function should preserve the order of the chunks in `proxy_resource_arr`.
     fn proxy_resource_to_chunk_store(
-        proxy_resource_arr: Vec<[ProxyResource; CHUNK_HALF_NODE_NUM]>,
+        proxy_resource_arr: Vec<Vec<ProxyResource>>,
         with_slots: bool,
     ) -> Vec<ChunkStore> {
-        let master_num = proxy_resource_arr.len() * 2;
-        let average = SLOT_NUM / master_num;
-        let remainder = SLOT_NUM - average * master_num;
+        let weights: Vec<usize> = proxy_resource_arr
+            .iter()
+            .flat_map(|proxies| {
+                let weight = proxies
+                    .iter()
+                    .map(|proxy| proxy.weight)
+                    .min()
+                    .unwrap_or(DEFAULT_PROXY_WEIGHT);
+                vec![weight; CHUNK_PARTS]
+            })
+            .collect();
+        let slot_nums = get_weighted_slot_nums(&weights);
         let mut chunk_stores = vec![];
         let mut curr_slot = 0;
         for (i, chunk) in proxy_resource_arr.into_iter().enumerate() {
             let a = 2 * i;
             let b = a + 1;
 
-            let mut create_slots = |index| {
-                let r = (index < remainder) as usize;
+            let mut create_slots = |index: usize| {
                 let start = curr_slot;
-                let end = curr_slot + average + r;
+                let end = curr_slot + slot_nums[index];
                 curr_slot = end;
                 SlotRange {
                     range_list: RangeList::from_single_range(Range(start, end - 1)),
@@ -304,23 +294,19 @@ impl<'a> MetaStoreUpdate<'a> {
                 [None, None]
             };
 
-            let first_proxy = chunk[0].clone();
-            let second_proxy = chunk[1].clone();
             let chunk_store = ChunkStore {
                 role_position: ChunkRolePosition::Normal,
                 stable_slots,
                 migrating_slots: [vec![], vec![]],
-                proxy_addresses: [
-                    first_proxy.proxy_address.clone(),
-                    second_proxy.proxy_address.clone(),
-                ],
-                hosts: [first_proxy.host.clone(), second_proxy.host.clone()],
-                node_addresses: [
-                    first_proxy.node_addresses[0].clone(),
-                    first_proxy.node_addresses[1].clone(),
-                    second_proxy.node_addresses[0].clone(),
-                    second_proxy.node_addresses[1].clone(),
-                ],
+                proxy_addresses: chunk
+                    .iter()
+                    .map(|proxy| proxy.proxy_address.clone())
+                    .collect(),
+                hosts: chunk.iter().map(|proxy| proxy.host.clone()).collect(),
+                node_addresses: chunk
+                    .iter()
+                    .flat_map(|proxy| proxy.node_addresses.iter().cloned())
+                    .collect(),
             };
             chunk_stores.push(chunk_store);
         }
@@ -359,7 +345,7 @@ impl<'a> MetaStoreUpdate<'a> {
 
         let existing_node_num = match self.store.clusters.get(&name) {
             None => return Err(MetaStoreError::ClusterNotFound),
-            Some(cluster) => cluster.chunks.len() * 4,
+            Some(cluster) => cluster.get_node_number(),
         };
 
         let added_num = match expected_num.checked_sub(existing_node_num) {
@@ -378,7 +364,7 @@ impl<'a> MetaStoreUpdate<'a> {
         let cluster_name = ClusterName::try_from(cluster_name.as_str())
             .map_err(|_| MetaStoreError::InvalidClusterName)?;
 
-        let existing_proxy_num = match self.store.clusters.get(&cluster_name) {
+        let (existing_proxy_num, replica_num) = match self.store.clusters.get(&cluster_name) {
             None => return Err(MetaStoreError::ClusterNotFound),
             Some(cluster) => {
                 if cluster
@@ -388,19 +374,28 @@ impl<'a> MetaStoreUpdate<'a> {
                 {
                     return Err(MetaStoreError::MigrationRunning);
                 }
-                cluster.chunks.len() * CHUNK_PARTS
+                (
+                    cluster.chunks.len() * cluster.get_chunk_proxy_num(),
+                    cluster.replica_num,
+                )
             }
         };
 
-        if num % 4 != 0 {
+        if num % get_chunk_node_num(replica_num) != 0 {
             return Err(MetaStoreError::InvalidNodeNum);
         }
-        let proxy_num = NonZeroUsize::new(num / 2).ok_or_else(|| MetaStoreError::InvalidNodeNum)?;
+        let proxy_num = NonZeroUsize::new(num / NODES_PER_PROXY)
+            .ok_or_else(|| MetaStoreError::InvalidNodeNum)?;
+        let chunk_proxy_num = get_chunk_proxy_num(replica_num);
 
         let proxy_resource_arr = if self.store.enable_ordered_proxy {
-            self.generate_free_chunks_for_ordered_proxy_index(proxy_num, existing_proxy_num)?
+            self.generate_free_chunks_for_ordered_proxy_index(
+                proxy_num,
+                existing_proxy_num,
+                chunk_proxy_num,
+            )?
         } else {
-            self.generate_free_chunks(proxy_num)?
+            self.generate_free_chunks(proxy_num, chunk_proxy_num)?
         };
         let mut chunks = Self::proxy_resource_to_chunk_store(proxy_resource_arr, false);
 
@@ -517,42 +512,80 @@ impl<'a> MetaStoreUpdate<'a> {
         Ok(())
     }
 
+    // Try to put the proxies of a chunk, which hold the masters and their replicas,
+    // in different zones first, then different racks, and finally different hosts.
     fn generate_free_chunks(
         &self,
         proxy_num: NonZeroUsize,
-    ) -> Result<Vec<[ProxyResource; CHUNK_HALF_NODE_NUM]>, MetaStoreError> {
-        let mut host_proxies = self.generate_free_host_proxies();
-
-        host_proxies = Self::remove_redundant_chunks(host_proxies, proxy_num)?;
-
-        let link_table = self.build_link_table();
+        chunk_proxy_num: usize,
+    ) -> Result<Vec<Vec<ProxyResource>>, MetaStoreError> {
+        let mut res = Err(MetaStoreError::NoAvailableResource);
+        for level in self.get_location_levels().into_iter() {
+            let location_proxies = self.generate_free_location_proxies(level);
+            res = Self::remove_redundant_chunks(location_proxies, proxy_num, chunk_proxy_num)
+                .and_then(|location_proxies| {
+                    let link_table = self.build_link_table(level);
+                    Self::allocate_chunk(location_proxies, link_table, proxy_num, chunk_proxy_num)
+                });
+            match res {
+                Ok(_) => break,
+                Err(ref err) => info!(
+                    "generate_free_chunks: cannot allocate chunks with different {:?}: {}",
+                    level, err
+                ),
+            }
+        }
 
-        let new_added_proxy_resource = Self::allocate_chunk(host_proxies, link_table, proxy_num)?;
+        let new_added_proxy_resource = res?;
         let new_proxies = new_added_proxy_resource
             .into_iter()
-            .map(|[a, b]| {
-                [
-                    self.store
-                        .all_proxies
-                        .get(&a)
-                        .expect("consume_proxy: get proxy resource")
-                        .clone(),
-                    self.store
-                        .all_proxies
-                        .get(&b)
-                        .expect("consume_proxy: get proxy resource")
-                        .clone(),
-                ]
+            .map(|addresses| {
+                addresses
+                    .iter()
+                    .map(|address| {
+                        self.store
+                            .all_proxies
+                            .get(address)
+                            .expect("consume_proxy: get proxy resource")
+                            .clone()
+                    })
+                    .collect()
             })
             .collect();
         Ok(new_proxies)
     }
 
+    // Zone and rack are only used when all the proxies have them
+    // and they could at least split the proxies into two parts.
+    fn get_location_levels(&self) -> Vec<LocationLevel> {
+        let mut levels = vec![];
+        for level in [LocationLevel::Zone, LocationLevel::Rack].iter() {
+            let mut locations = HashSet::new();
+            let all_set =
+                self.store
+                    .all_proxies
+                    .values()
+                    .all(|proxy| match proxy.get_location(*level) {
+                        Some(location) => {
+                            locations.insert(location);
+                            true
+                        }
+                        None => false,
+                    });
+            if all_set && locations.len() > 1 {
+                levels.push(*level);
+            }
+        }
+        levels.push(LocationLevel::Host);
+        levels
+    }
+
     fn generate_free_chunks_for_ordered_proxy_index(
         &self,
         proxy_num: NonZeroUsize,
         first_index: usize,
-    ) -> Result<Vec<[ProxyResource; CHUNK_HALF_NODE_NUM]>, MetaStoreError> {
+        chunk_proxy_num: usize,
+    ) -> Result<Vec<Vec<ProxyResource>>, MetaStoreError> {
         let mut host_proxies = MetaStoreQuery::new(&self.store).get_free_proxy_resource();
         if host_proxies.len() < proxy_num.get() {
             return Err(MetaStoreError::NoAvailableResource);
@@ -578,50 +611,47 @@ impl<'a> MetaStoreUpdate<'a> {
         }
 
         let mut proxy_resources = vec![];
-        for mut chunk in host_proxies
-            .into_iter()
-            .chunks(CHUNK_HALF_NODE_NUM)
-            .into_iter()
-        {
-            let first = chunk.next().ok_or_else(|| {
-                error!("Invalid state. Cannot get first host proxy.");
-                MetaStoreError::InvalidNodeNum
-            })?;
-            let second = chunk.next().ok_or_else(|| {
-                error!("Invalid state. Cannot get second host proxy.");
-                MetaStoreError::InvalidNodeNum
-            })?;
-
-            proxy_resources.push([first, second]);
+        for chunk in host_proxies.into_iter().chunks(chunk_proxy_num).into_iter() {
+            let proxies: Vec<ProxyResource> = chunk.collect();
+            if proxies.len() != chunk_proxy_num {
+                error!("Invalid state. Cannot get enough host proxies for chunk.");
+                return Err(MetaStoreError::InvalidNodeNum);
+            }
+            proxy_resources.push(proxies);
         }
 
         Ok(proxy_resources)
     }
 
     fn generate_free_host_proxies(&self) -> HashMap<String, Vec<String>> {
-        // host => proxies
-        let mut host_proxies: HashMap<String, Vec<String>> = HashMap::new();
-        for host_proxy in MetaStoreQuery::new(&self.store)
-            .get_free_proxies()
+        self.generate_free_location_proxies(LocationLevel::Host)
+    }
+
+    fn generate_free_location_proxies(&self, level: LocationLevel) -> HashMap<String, Vec<String>> {
+        // location => proxies
+        let mut location_proxies: HashMap<String, Vec<String>> = HashMap::new();
+        for proxy_resource in MetaStoreQuery::new(&self.store)
+            .get_free_proxy_resource()
             .into_iter()
         {
-            let HostProxy {
-                host,
-                proxy_address,
-            } = host_proxy;
-            host_proxies
-                .entry(host)
+            let location = match proxy_resource.get_location(level) {
+                Some(location) => location,
+                None => continue,
+            };
+            location_proxies
+                .entry(location)
                 .or_insert_with(Vec::new)
-                .push(proxy_address);
+                .push(proxy_resource.proxy_address);
         }
-        host_proxies
+        location_proxies
     }
 
     fn allocate_chunk(
         mut host_proxies: HashMap<String, Vec<String>>,
         mut link_table: HashMap<String, HashMap<String, usize>>,
         expected_num: NonZeroUsize,
-    ) -> Result<Vec<[String; CHUNK_HALF_NODE_NUM]>, MetaStoreError> {
+        chunk_proxy_num: usize,
+    ) -> Result<Vec<Vec<String>>, MetaStoreError> {
         let max_proxy_num = host_proxies
             .values()
             .map(|proxies| proxies.len())
@@ -633,12 +663,13 @@ impl<'a> MetaStoreUpdate<'a> {
             return Err(MetaStoreError::NoAvailableResource);
         }
 
-        if max_proxy_num * 2 > sum_proxy_num {
+        // Every proxy of a chunk needs to be in a different host.
+        if max_proxy_num * chunk_proxy_num > sum_proxy_num {
             return Err(MetaStoreError::ResourceNotBalance);
         }
 
-        let mut new_proxy_pairs = vec![];
-        while new_proxy_pairs.len() * 2 < expected_num.get() {
+        let mut new_proxy_chunks = vec![];
+        while new_proxy_chunks.len() * chunk_proxy_num < expected_num.get() {
             let (first_host, first_address) = {
                 let (max_host, max_proxy_host) = host_proxies
                     .iter_mut()
@@ -648,14 +679,16 @@ impl<'a> MetaStoreUpdate<'a> {
                     max_host.clone(),
                     max_proxy_host
                         .pop()
-                        .expect("allocate_chunk: cannot find free proxy"),
+                        .ok_or_else(|| MetaStoreError::NoAvailableResource)?,
                 )
             };
+            let mut chunk_hosts = vec![first_host.clone()];
+            let mut chunk_addresses = vec![first_address];
 
-            let (second_host, second_address) = {
+            if chunk_proxy_num > 1 {
                 let peers = link_table
                     .get(&first_host)
-                    .expect("allocate_chunk: invalid state, cannot get link table entry");
+                    .ok_or_else(|| MetaStoreError::ResourceNotBalance)?;
 
                 let second_host = peers
                     .iter()
@@ -673,54 +706,66 @@ impl<'a> MetaStoreUpdate<'a> {
                         )
                     })
                     .map(|t| t.0.clone())
-                    .expect("allocate_chunk: invalid state, cannot get free proxy");
+                    .ok_or_else(|| MetaStoreError::ResourceNotBalance)?;
 
                 let second_address = host_proxies
                     .get_mut(&second_host)
-                    .expect("allocate_chunk: get second host")
+                    .and_then(|proxies| proxies.pop())
+                    .ok_or_else(|| MetaStoreError::NoAvailableResource)?;
+                chunk_hosts.push(second_host);
+                chunk_addresses.push(second_address);
+            }
+
+            // The proxies only holding replicas go to the hosts with most free proxies.
+            while chunk_addresses.len() < chunk_proxy_num {
+                let (host, proxies) = host_proxies
+                    .iter_mut()
+                    .filter(|(host, proxies)| !proxies.is_empty() && !chunk_hosts.contains(host))
+                    .max_by_key(|(_host, proxies)| proxies.len())
+                    .ok_or_else(|| MetaStoreError::ResourceNotBalance)?;
+                let address = proxies
                     .pop()
-                    .expect("allocate_chunk: get second address");
-                (second_host, second_address)
-            };
+                    .ok_or_else(|| MetaStoreError::NoAvailableResource)?;
+                chunk_hosts.push(host.clone());
+                chunk_addresses.push(address);
+            }
 
-            *link_table
-                .get_mut(&first_host)
-                .expect("allocate_chunk: link table")
-                .get_mut(&second_host)
-                .expect("allocate_chunk: link table") += 1;
-            *link_table
-                .get_mut(&second_host)
-                .expect("allocate_chunk: link table")
-                .get_mut(&first_host)
-                .expect("allocate_chunk: link table") += 1;
-
-            new_proxy_pairs.push([first_address, second_address]);
+            for first_host in chunk_hosts.iter() {
+                for second_host in chunk_hosts.iter() {
+                    if first_host == second_host {
+                        continue;
+                    }
+                    *link_table
+                        .entry(first_host.clone())
+                        .or_insert_with(HashMap::new)
+                        .entry(second_host.clone())
+                        .or_insert(0) += 1;
+                }
+            }
+
+            new_proxy_chunks.push(chunk_addresses);
         }
 
-        Ok(new_proxy_pairs)
+        Ok(new_proxy_chunks)
     }
 
     fn remove_redundant_chunks(
         mut host_proxies: HashMap<String, Vec<String>>,
         expected_num: NonZeroUsize,
+        chunk_proxy_num: usize,
     ) -> Result<HashMap<String, Vec<String>>, MetaStoreError> {
         let mut free_proxy_num: usize = host_proxies.values().map(|proxies| proxies.len()).sum();
-        let mut max_proxy_num = host_proxies
-            .values()
-            .map(|proxies| proxies.len())
-            .max()
-            .unwrap_or(0);
 
-        for proxies in host_proxies.values_mut() {
-            if proxies.len() == max_proxy_num {
-                // Only remove proxies in the host which as too many proxies.
-                while max_proxy_num * 2 > free_proxy_num {
-                    proxies.pop();
-                    free_proxy_num -= 1;
-                    max_proxy_num -= 1;
-                }
+        // Only remove proxies in the hosts which have too many proxies.
+        while let Some(proxies) = host_proxies
+            .values_mut()
+            .max_by_key(|proxies| proxies.len())
+        {
+            if proxies.len() * chunk_proxy_num <= free_proxy_num {
                 break;
             }
+            proxies.pop();
+            free_proxy_num -= 1;
         }
 
         if free_proxy_num < expected_num.get() {
@@ -738,7 +783,7 @@ impl<'a> MetaStoreUpdate<'a> {
             "generate_new_free_proxy: free host proxies {:?}",
             free_host_proxies
         );
-        let link_table = self.build_link_table();
+        let link_table = self.build_link_table(LocationLevel::Host);
         info!("generate_new_free_proxy: link table {:?}", link_table);
 
         let failed_proxy_host = self
@@ -749,98 +794,211 @@ impl<'a> MetaStoreUpdate<'a> {
             .host
             .clone();
 
-        let link_count_table = link_table
-            .get(&failed_proxy_host)
-            .expect("consume_new_proxy: cannot find failed proxy");
-        let peer_host = link_count_table
-            .iter()
-            .filter(|(peer_host, _)| free_host_proxies.contains_key(*peer_host))
+        // The new proxy will hold the replicas of the masters in the peer proxies of the chunk.
+        let peer_proxies = self.get_chunk_peer_proxies(&failed_proxy_address);
+        let free_host_proxies = if peer_proxies.is_empty() {
+            free_host_proxies
+        } else {
+            self.filter_free_proxies_apart(free_host_proxies, &peer_proxies)
+        };
+
+        // The link table could be empty when all the proxies are in the same host,
+        // which is only allowed for the clusters without replicas.
+        let empty_link_count_table = HashMap::new();
+        let link_count_table = match link_table.get(&failed_proxy_host) {
+            Some(link_count_table) => link_count_table,
+            None if peer_proxies.is_empty() => &empty_link_count_table,
+            None => {
+                error!("consume_new_proxy: cannot find failed proxy");
+                return Err(MetaStoreError::NoAvailableResource);
+            }
+        };
+        let peer_host = free_host_proxies
+            .keys()
+            .filter(|host| peer_proxies.is_empty() || link_count_table.contains_key(*host))
+            .map(|host| (host, link_count_table.get(host).cloned().unwrap_or(0)))
             .min_by(|(host1, count1), (host2, count2)| {
                 Self::second_host_cmp(
                     host1.as_str(),
-                    **count1,
+                    *count1,
                     host2.as_str(),
-                    **count2,
+                    *count2,
                     &free_host_proxies,
                 )
             })
             .map(|(peer_host, _)| peer_host)
             .ok_or_else(|| MetaStoreError::NoAvailableResource)?;
 
-        let peer_proxy = MetaStoreQuery::new(&self.store)
-            .get_free_proxies()
-            .iter()
-            .find(|host_proxy| peer_host == &host_proxy.host)
-            .expect("consume_new_proxy: get peer address")
-            .proxy_address
-            .clone();
+        let peer_proxy = free_host_proxies
+            .get(peer_host)
+            .and_then(|proxies| proxies.first())
+            .expect("consume_new_proxy: get peer address");
 
         let new_proxy = self
             .store
             .all_proxies
-            .get(&peer_proxy)
+            .get(peer_proxy)
             .expect("consume_new_proxy: cannot find peer proxy")
             .clone();
         Ok(new_proxy)
     }
 
-    fn build_link_table(&self) -> HashMap<String, HashMap<String, usize>> {
-        // Remove the fully occupied hosts or there will be severe performance problems.
-        let free_hosts: HashSet<String> = self
+    fn get_chunk_peer_proxies(&self, proxy_address: &str) -> Vec<&ProxyResource> {
+        let cluster = match self
+            .store
+            .all_proxies
+            .get(proxy_address)
+            .and_then(|proxy| proxy.cluster.as_ref())
+            .and_then(|cluster_name| self.store.clusters.get(cluster_name))
+        {
+            Some(cluster) => cluster,
+            None => return vec![],
+        };
+        let chunk = match cluster
+            .chunks
+            .iter()
+            .find(|chunk| chunk.proxy_addresses.iter().any(|a| a == proxy_address))
+        {
+            Some(chunk) => chunk,
+            None => return vec![],
+        };
+        chunk
+            .proxy_addresses
+            .iter()
+            .filter(|address| *address != proxy_address)
+            .filter_map(|address| self.store.all_proxies.get(address))
+            .collect()
+    }
+
+    // Only keep the free proxies not sharing the zone with any of `peer_proxies`.
+    // Fall back to racks and hosts when there are not enough resources.
+    fn filter_free_proxies_apart(
+        &self,
+        free_host_proxies: HashMap<String, Vec<String>>,
+        peer_proxies: &[&ProxyResource],
+    ) -> HashMap<String, Vec<String>> {
+        for level in self.get_location_levels().into_iter() {
+            let filtered =
+                self.filter_free_proxies_at_level(&free_host_proxies, peer_proxies, level);
+            if !filtered.is_empty() {
+                return filtered;
+            }
+        }
+        free_host_proxies
+    }
+
+    fn filter_free_proxies_at_level(
+        &self,
+        free_host_proxies: &HashMap<String, Vec<String>>,
+        peer_proxies: &[&ProxyResource],
+        level: LocationLevel,
+    ) -> HashMap<String, Vec<String>> {
+        let peer_locations: Vec<Option<String>> = peer_proxies
+            .iter()
+            .map(|proxy| proxy.get_location(level))
+            .collect();
+        free_host_proxies
+            .iter()
+            .filter_map(|(host, proxies)| {
+                let proxies: Vec<String> = proxies
+                    .iter()
+                    .filter(|address| {
+                        self.store
+                            .all_proxies
+                            .get(*address)
+                            .map(|proxy| !peer_locations.contains(&proxy.get_location(level)))
+                            .unwrap_or(false)
+                    })
+                    .cloned()
+                    .collect();
+                if proxies.is_empty() {
+                    None
+                } else {
+                    Some((host.clone(), proxies))
+                }
+            })
+            .collect()
+    }
+
+    fn build_link_table(&self, level: LocationLevel) -> HashMap<String, HashMap<String, usize>> {
+        // Remove the fully occupied locations or there will be severe performance problems.
+        let free_locations: HashSet<String> = self
             .store
             .all_proxies
             .values()
             .filter_map(|proxy| {
                 if proxy.cluster.is_none() {
-                    Some(proxy.host.clone())
+                    proxy.get_location(level)
                 } else {
                     None
                 }
             })
             .collect();
+        let locations: HashSet<String> = self
+            .store
+            .all_proxies
+            .values()
+            .filter_map(|proxy| proxy.get_location(level))
+            .collect();
 
         let mut link_table: HashMap<String, HashMap<String, usize>> = HashMap::new();
-        for proxy_resource in self.store.all_proxies.values() {
-            let first_host = proxy_resource.host.clone();
-
-            for proxy_resource in self.store.all_proxies.values() {
-                let second_host = proxy_resource.host.clone();
-                if first_host == second_host {
+        for first_location in locations.iter() {
+            for second_location in locations.iter() {
+                if first_location == second_location {
                     continue;
                 }
-                if !free_hosts.contains(&first_host) && !free_hosts.contains(&second_host) {
+                if !free_locations.contains(first_location)
+                    && !free_locations.contains(second_location)
+                {
                     continue;
                 }
 
                 link_table
-                    .entry(first_host.clone())
+                    .entry(first_location.clone())
                     .or_insert_with(HashMap::new)
-                    .entry(second_host.clone())
+                    .entry(second_location.clone())
                     .or_insert(0);
                 link_table
-                    .entry(second_host.clone())
+                    .entry(second_location.clone())
                     .or_insert_with(HashMap::new)
-                    .entry(first_host.clone())
+                    .entry(first_location.clone())
                     .or_insert(0);
             }
         }
 
+        let all_proxies = &self.store.all_proxies;
+        let get_chunk_location = |chunk: &ChunkStore, i: usize| -> Option<String> {
+            match all_proxies.get(&chunk.proxy_addresses[i]) {
+                Some(proxy) => proxy.get_location(level),
+                None if level == LocationLevel::Host => Some(chunk.hosts[i].clone()),
+                None => None,
+            }
+        };
+
         for cluster in self.store.clusters.values() {
             for chunk in cluster.chunks.iter() {
-                let first_host = chunk.hosts[0].clone();
-                let second_host = chunk.hosts[1].clone();
-                let linked_num = link_table
-                    .entry(first_host.clone())
-                    .or_insert_with(HashMap::new)
-                    .entry(second_host.clone())
-                    .or_insert(0);
-                *linked_num += 1;
-                let linked_num = link_table
-                    .entry(second_host)
-                    .or_insert_with(HashMap::new)
-                    .entry(first_host)
-                    .or_insert(0);
-                *linked_num += 1;
+                let proxy_num = chunk.proxy_addresses.len();
+                for i in 0..proxy_num {
+                    for j in (i + 1)..proxy_num {
+                        let (first_location, second_location) =
+                            match (get_chunk_location(chunk, i), get_chunk_location(chunk, j)) {
+                                (Some(first), Some(second)) => (first, second),
+                                _ => continue,
+                            };
+                        let linked_num = link_table
+                            .entry(first_location.clone())
+                            .or_insert_with(HashMap::new)
+                            .entry(second_location.clone())
+                            .or_insert(0);
+                        *linked_num += 1;
+                        let linked_num = link_table
+                            .entry(second_location)
+                            .or_insert_with(HashMap::new)
+                            .entry(first_location)
+                            .or_insert(0);
+                        *linked_num += 1;
+                    }
+                }
             }
         }
         link_table
@@ -850,6 +1008,23 @@ impl<'a> MetaStoreUpdate<'a> {
         &mut self,
         failed_proxy_address: String,
         migration_limit: u64,
+    ) -> Result<Option<Proxy>, MetaStoreError> {
+        self.replace_failed_proxy_with_repl_offsets(
+            failed_proxy_address,
+            migration_limit,
+            &HashMap::new(),
+            0,
+        )
+    }
+
+    // The replication offsets of the nodes are used to pick the most up-to-date replicas
+    // as the new masters and to estimate the lost data.
+    pub fn replace_failed_proxy_with_repl_offsets(
+        &mut self,
+        failed_proxy_address: String,
+        migration_limit: u64,
+        repl_offsets: &HashMap<String, u64>,
+        max_repl_lag: u64,
     ) -> Result<Option<Proxy>, MetaStoreError> {
         let cluster_name = match self.store.all_proxies.get(&failed_proxy_address) {
             None => return Err(MetaStoreError::ProxyNotFound),
@@ -865,7 +1040,13 @@ impl<'a> MetaStoreUpdate<'a> {
             Some(cluster_name) => cluster_name,
         };
 
-        self.takeover_master(&cluster_name, failed_proxy_address.clone())?;
+        self.takeover_master(
+            &cluster_name,
+            failed_proxy_address.clone(),
+            repl_offsets,
+            max_repl_lag,
+        )?;
+        self.store.switchovers.remove(&failed_proxy_address);
 
         // If enable_ordered_proxy is true, we won't replace the proxy.
         if self.store.enable_ordered_proxy {
@@ -886,17 +1067,8 @@ impl<'a> MetaStoreUpdate<'a> {
                 .get_mut(&cluster_name)
                 .expect("replace_failed_proxy: get cluster");
             for chunk in cluster.chunks.iter_mut() {
-                if chunk.proxy_addresses[0] == failed_proxy_address {
-                    chunk.hosts[0] = proxy_resource.host.clone();
-                    chunk.proxy_addresses[0] = proxy_resource.proxy_address.clone();
-                    chunk.node_addresses[0] = proxy_resource.node_addresses[0].clone();
-                    chunk.node_addresses[1] = proxy_resource.node_addresses[1].clone();
-                    break;
-                } else if chunk.proxy_addresses[1] == failed_proxy_address {
-                    chunk.hosts[1] = proxy_resource.host.clone();
-                    chunk.proxy_addresses[1] = proxy_resource.proxy_address.clone();
-                    chunk.node_addresses[2] = proxy_resource.node_addresses[0].clone();
-                    chunk.node_addresses[3] = proxy_resource.node_addresses[1].clone();
+                if let Some(proxy_index) = chunk.get_proxy_index(&failed_proxy_address) {
+                    chunk.replace_proxy(proxy_index, &proxy_resource);
                     break;
                 }
             }
@@ -926,37 +1098,195 @@ impl<'a> MetaStoreUpdate<'a> {
         &mut self,
         cluster_name: &ClusterName,
         failed_proxy_address: String,
+        repl_offsets: &HashMap<String, u64>,
+        max_repl_lag: u64,
     ) -> Result<(), MetaStoreError> {
-        let new_epoch = self.store.bump_global_epoch();
+        let failed_proxies = &self.store.failed_proxies;
+        let failures = &self.store.failures;
+        let is_failed = |address: &String| -> bool {
+            failed_proxies.contains(address) || failures.contains_key(address)
+        };
+
+        let cluster = self
+            .store
+            .clusters
+            .get(cluster_name)
+            .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
+
+        let mut changed_chunk = None;
+
+        for (chunk_index, chunk) in cluster.chunks.iter().enumerate() {
+            let proxy_index = match chunk.get_proxy_index(&failed_proxy_address) {
+                Some(proxy_index) => proxy_index,
+                None => continue,
+            };
+            let lost_parts: Vec<usize> = (0..CHUNK_PARTS)
+                .filter(|part| chunk.get_master_proxy_index(*part) == proxy_index)
+                .collect();
+            let healthy_replica_proxies: Vec<usize> = (CHUNK_PARTS..chunk.proxy_addresses.len())
+                .filter(|i| !is_failed(&chunk.proxy_addresses[*i]))
+                .collect();
+            let both_failed = proxy_index < CHUNK_PARTS
+                && chunk
+                    .proxy_addresses
+                    .iter()
+                    .take(CHUNK_PARTS)
+                    .all(|address| *address == failed_proxy_address || is_failed(address));
+
+            let mut new_chunk = chunk.clone();
+            // Without replicas the masters can't be taken over
+            // and will be moved to the new proxy with the data lost.
+            // The proxies only holding replicas don't affect the masters.
+            let failed_parts = if chunk.get_replica_num() == 0 {
+                vec![0, 1]
+            } else if both_failed && !healthy_replica_proxies.is_empty() {
+                // When both of the first two proxies fail,
+                // move the most up-to-date proxy only holding replicas to the first position
+                // so that it could take over all the masters.
+                let replica_proxy_index = Self::find_most_up_to_date_proxy(
+                    chunk,
+                    &healthy_replica_proxies,
+                    &[0, 1],
+                    repl_offsets,
+                )
+                .expect("takeover_master: empty replica proxies");
+                new_chunk.swap_proxies(0, replica_proxy_index);
+                new_chunk.role_position = ChunkRolePosition::FirstChunkMaster;
+                vec![0, 1]
+            } else if proxy_index == 0 {
+                let mut candidates = vec![1];
+                if !lost_parts.is_empty() {
+                    candidates.extend(healthy_replica_proxies.iter().cloned());
+                }
+                match Self::find_most_up_to_date_proxy(
+                    chunk,
+                    &candidates,
+                    &lost_parts,
+                    repl_offsets,
+                ) {
+                    // The proxy only holding replicas has the same node layout
+                    // as the first proxy so the masters could stay in the first position.
+                    Some(replica_proxy_index) if replica_proxy_index >= CHUNK_PARTS => {
+                        new_chunk.swap_proxies(0, replica_proxy_index);
+                        lost_parts.clone()
+                    }
+                    _ => {
+                        new_chunk.role_position = ChunkRolePosition::SecondChunkMaster;
+                        vec![0]
+                    }
+                }
+            } else if proxy_index == 1 {
+                new_chunk.role_position = ChunkRolePosition::FirstChunkMaster;
+                vec![1]
+            } else {
+                vec![]
+            };
 
+            let lost_bytes = if chunk.get_replica_num() == 0 {
+                0
+            } else {
+                Self::check_repl_lag(chunk, &new_chunk, &lost_parts, repl_offsets, max_repl_lag)?
+            };
+            changed_chunk = Some((chunk_index, new_chunk, failed_parts, lost_bytes));
+            break;
+        }
+
+        let new_epoch = self.store.bump_global_epoch();
         let cluster = self
             .store
             .clusters
             .get_mut(cluster_name)
             .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
 
-        let mut peer_position = HashSet::new();
+        if let Some((chunk_index, new_chunk, failed_parts, lost_bytes)) = changed_chunk {
+            cluster.chunks[chunk_index] = new_chunk;
+            if lost_bytes > 0 {
+                warn!(
+                    "cluster {} might lose {} bytes in failover",
+                    cluster_name, lost_bytes
+                );
+                cluster.failover_lost_bytes =
+                    cluster.failover_lost_bytes.saturating_add(lost_bytes);
+            }
+            Self::bump_migration_epoch(&mut cluster.chunks, chunk_index, &failed_parts, new_epoch);
+        }
+        cluster.epoch = new_epoch;
+        Ok(())
+    }
 
-        for chunk in cluster.chunks.iter_mut() {
-            if chunk.proxy_addresses[0] == failed_proxy_address {
-                chunk.role_position = ChunkRolePosition::SecondChunkMaster;
+    // Returns the first proxy with the largest replication offset of the chunk parts.
+    // The proxies without the offsets reported are the last choices.
+    fn find_most_up_to_date_proxy(
+        chunk: &ChunkStore,
+        candidates: &[usize],
+        chunk_parts: &[usize],
+        repl_offsets: &HashMap<String, u64>,
+    ) -> Option<usize> {
+        let get_offset = |proxy_index: usize| -> Option<u64> {
+            let mut min_offset = u64::MAX;
+            for part in chunk_parts.iter() {
+                let node_index = chunk.get_part_node_index(*part, proxy_index)?;
+                let offset = repl_offsets.get(&chunk.node_addresses[node_index])?;
+                min_offset = cmp::min(min_offset, *offset);
+            }
+            Some(min_offset)
+        };
+        let mut most_up_to_date: Option<(usize, Option<u64>)> = None;
+        for proxy_index in candidates.iter() {
+            let offset = get_offset(*proxy_index);
+            match most_up_to_date {
+                Some((_, max_offset)) if offset <= max_offset => continue,
+                _ => most_up_to_date = Some((*proxy_index, offset)),
+            }
+        }
+        most_up_to_date.map(|(proxy_index, _)| proxy_index)
+    }
 
-                for migrating_slot_range in chunk.migrating_slots[0].iter_mut() {
-                    migrating_slot_range.meta.epoch = new_epoch;
-                    peer_position.insert((
-                        migrating_slot_range.meta.src_chunk_index,
-                        migrating_slot_range.meta.src_chunk_part,
-                    ));
-                    peer_position.insert((
-                        migrating_slot_range.meta.dst_chunk_index,
-                        migrating_slot_range.meta.dst_chunk_part,
-                    ));
-                }
-                break;
-            } else if chunk.proxy_addresses[1] == failed_proxy_address {
-                chunk.role_position = ChunkRolePosition::FirstChunkMaster;
+    // Returns the estimated lost bytes of the new masters.
+    // The failover is refused when any of them lags behind
+    // the failed master more than `max_repl_lag` bytes. Zero disables the check.
+    fn check_repl_lag(
+        chunk: &ChunkStore,
+        new_chunk: &ChunkStore,
+        lost_parts: &[usize],
+        repl_offsets: &HashMap<String, u64>,
+        max_repl_lag: u64,
+    ) -> Result<u64, MetaStoreError> {
+        let mut lost_bytes: u64 = 0;
+        for part in lost_parts.iter() {
+            let old_master = &chunk.node_addresses[chunk.get_master_node_index(*part)];
+            let new_master = &new_chunk.node_addresses[new_chunk.get_master_node_index(*part)];
+            let (old_offset, new_offset) =
+                match (repl_offsets.get(old_master), repl_offsets.get(new_master)) {
+                    (Some(old_offset), Some(new_offset)) => (*old_offset, *new_offset),
+                    _ => continue,
+                };
+            let lag = old_offset.saturating_sub(new_offset);
+            if max_repl_lag > 0 && lag > max_repl_lag {
+                warn!(
+                    "refuse to failover {} to {}: lag {} bytes",
+                    old_master, new_master, lag
+                );
+                return Err(MetaStoreError::ReplicaLagTooLarge);
+            }
+            lost_bytes = lost_bytes.saturating_add(lag);
+        }
+        Ok(lost_bytes)
+    }
 
-                for migrating_slot_range in chunk.migrating_slots[1].iter_mut() {
+    // The migrations of the chunk parts with new masters
+    // and their peers need to be restarted with the new epoch.
+    fn bump_migration_epoch(
+        chunks: &mut [ChunkStore],
+        chunk_index: usize,
+        chunk_parts: &[usize],
+        new_epoch: u64,
+    ) {
+        let mut peer_position = HashSet::new();
+
+        if let Some(chunk) = chunks.get_mut(chunk_index) {
+            for chunk_part in chunk_parts.iter() {
+                for migrating_slot_range in chunk.migrating_slots[*chunk_part].iter_mut() {
                     migrating_slot_range.meta.epoch = new_epoch;
                     peer_position.insert((
                         migrating_slot_range.meta.src_chunk_index,
@@ -967,11 +1297,10 @@ impl<'a> MetaStoreUpdate<'a> {
                         migrating_slot_range.meta.dst_chunk_part,
                     ));
                 }
-                break;
             }
         }
 
-        for chunk in cluster.chunks.iter_mut() {
+        for chunk in chunks.iter_mut() {
             for migrating_slots in chunk.migrating_slots.iter_mut() {
                 for migrating_slot_range in migrating_slots.iter_mut() {
                     let src_index = migrating_slot_range.meta.src_chunk_index;
@@ -986,10 +1315,278 @@ impl<'a> MetaStoreUpdate<'a> {
                 }
             }
         }
+    }
+
+    // Only moves the master of the failed node to the other proxy of the chunk
+    // instead of replacing the whole proxy.
+    pub fn failover_node(&mut self, failed_node_address: String) -> Result<(), MetaStoreError> {
+        let (cluster_name, chunk_index, node_index) = self
+            .store
+            .clusters
+            .iter()
+            .find_map(|(cluster_name, cluster)| {
+                cluster
+                    .chunks
+                    .iter()
+                    .enumerate()
+                    .find_map(|(chunk_index, chunk)| {
+                        chunk
+                            .node_addresses
+                            .iter()
+                            .position(|address| *address == failed_node_address)
+                            .map(|node_index| (cluster_name.clone(), chunk_index, node_index))
+                    })
+            })
+            .ok_or_else(|| MetaStoreError::NodeNotFound)?;
+
+        let role_position = {
+            let failed_proxies = &self.store.failed_proxies;
+            let failures = &self.store.failures;
+            let node_failures = &self.store.node_failures;
+            let chunk = self
+                .store
+                .clusters
+                .get(&cluster_name)
+                .and_then(|cluster| cluster.chunks.get(chunk_index))
+                .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
+
+            if chunk.get_replica_num() == 0 {
+                return Err(MetaStoreError::NodeFailoverNotAvailable);
+            }
+            let chunk_part = chunk.get_node_chunk_part(node_index);
+            // The failed replicas don't affect the service.
+            if chunk.get_master_node_index(chunk_part) != node_index {
+                return Ok(());
+            }
+
+            let mut master_proxy_indices = [0; CHUNK_PARTS];
+            for (part, proxy_index) in master_proxy_indices.iter_mut().enumerate() {
+                *proxy_index = chunk.get_master_proxy_index(part);
+            }
+            let new_proxy_index = 1 - master_proxy_indices[chunk_part];
+            let mut new_master_proxy_indices = master_proxy_indices;
+            new_master_proxy_indices[chunk_part] = new_proxy_index;
+            let role_position = match new_master_proxy_indices {
+                [0, 1] => ChunkRolePosition::Normal,
+                [0, 0] => ChunkRolePosition::FirstChunkMaster,
+                [1, 1] => ChunkRolePosition::SecondChunkMaster,
+                // The first master can't be in the second proxy
+                // while the second master is in the first one,
+                // so both masters are moved to the proxy of the new master.
+                _ if new_proxy_index == 0 => ChunkRolePosition::FirstChunkMaster,
+                _ => ChunkRolePosition::SecondChunkMaster,
+            };
+
+            let mut new_chunk = chunk.clone();
+            new_chunk.role_position = role_position;
+            for part in 0..CHUNK_PARTS {
+                let proxy_address =
+                    &new_chunk.proxy_addresses[new_chunk.get_master_proxy_index(part)];
+                let node_address = &new_chunk.node_addresses[new_chunk.get_master_node_index(part)];
+                if failed_proxies.contains(proxy_address)
+                    || failures.contains_key(proxy_address)
+                    || node_failures.contains_key(node_address)
+                {
+                    return Err(MetaStoreError::NodeFailoverNotAvailable);
+                }
+            }
+            role_position
+        };
+
+        let new_epoch = self.store.bump_global_epoch();
+        let cluster = self
+            .store
+            .clusters
+            .get_mut(&cluster_name)
+            .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
+        let chunk = &mut cluster.chunks[chunk_index];
+        let old_master_proxy_indices: Vec<usize> = (0..CHUNK_PARTS)
+            .map(|part| chunk.get_master_proxy_index(part))
+            .collect();
+        chunk.role_position = role_position;
+        let changed_parts: Vec<usize> = (0..CHUNK_PARTS)
+            .filter(|part| chunk.get_master_proxy_index(*part) != old_master_proxy_indices[*part])
+            .collect();
+
+        Self::bump_migration_epoch(&mut cluster.chunks, chunk_index, &changed_parts, new_epoch);
+        cluster.epoch = new_epoch;
+        Ok(())
+    }
+
+    pub fn add_switchover(&mut self, proxy_address: String) -> Result<(), MetaStoreError> {
+        let cluster_name = match self.store.all_proxies.get(&proxy_address) {
+            None => return Err(MetaStoreError::ProxyNotFound),
+            Some(proxy) => proxy.cluster.clone().ok_or(MetaStoreError::NotInUse)?,
+        };
+        if self.plan_switchover(&proxy_address)?.is_some() {
+            self.store.switchovers.insert(proxy_address, cluster_name);
+        }
+        Ok(())
+    }
+
+    // Either all the masters on the host will be switched over or none of them.
+    pub fn add_host_switchover(&mut self, host: String) -> Result<(), MetaStoreError> {
+        let proxies: Vec<(String, ClusterName)> = self
+            .store
+            .all_proxies
+            .values()
+            .filter(|proxy| proxy.host == host)
+            .filter_map(|proxy| {
+                proxy
+                    .cluster
+                    .clone()
+                    .map(|cluster_name| (proxy.proxy_address.clone(), cluster_name))
+            })
+            .collect();
+        if proxies.is_empty() {
+            return Err(MetaStoreError::ProxyNotFound);
+        }
+
+        let mut switchovers = vec![];
+        for (proxy_address, cluster_name) in proxies.into_iter() {
+            let (_, chunk_index, new_chunk) = match self.plan_switchover(&proxy_address)? {
+                Some(plan) => plan,
+                None => continue,
+            };
+            // The other proxy of the chunk is on the same host.
+            let new_master_hosts_the_same = (0..CHUNK_PARTS)
+                .any(|part| new_chunk.hosts[new_chunk.get_master_proxy_index(part)] == host);
+            if new_master_hosts_the_same {
+                warn!(
+                    "can't switch over chunk {} of {} on host {}",
+                    chunk_index, proxy_address, host
+                );
+                return Err(MetaStoreError::SwitchoverNotAvailable);
+            }
+            switchovers.push((proxy_address, cluster_name));
+        }
+        self.store.switchovers.extend(switchovers);
+        Ok(())
+    }
+
+    pub fn get_switchover_tasks(&self) -> Vec<SwitchoverTask> {
+        let mut tasks = vec![];
+        for (proxy_address, cluster_name) in self.store.switchovers.iter() {
+            let (chunk, new_chunk) = match self.plan_switchover(proxy_address) {
+                Ok(Some((name, chunk_index, new_chunk))) if name == *cluster_name => {
+                    let chunk = &self.store.clusters[&name].chunks[chunk_index];
+                    (chunk, new_chunk)
+                }
+                _ => continue,
+            };
+            let nodes = (0..CHUNK_PARTS)
+                .filter(|part| {
+                    chunk.get_master_proxy_index(*part) != new_chunk.get_master_proxy_index(*part)
+                })
+                .map(|part| SwitchoverNode {
+                    master_node_address: chunk.node_addresses[chunk.get_master_node_index(part)]
+                        .clone(),
+                    replica_node_address: new_chunk.node_addresses
+                        [new_chunk.get_master_node_index(part)]
+                    .clone(),
+                    replica_proxy_address: new_chunk.proxy_addresses
+                        [new_chunk.get_master_proxy_index(part)]
+                    .clone(),
+                })
+                .collect();
+            tasks.push(SwitchoverTask {
+                proxy_address: proxy_address.clone(),
+                nodes,
+            });
+        }
+        tasks
+    }
+
+    // The proxy has paused the writes and the replicas have caught up.
+    pub fn commit_switchover(&mut self, proxy_address: String) -> Result<(), MetaStoreError> {
+        let cluster_name = match self.store.switchovers.remove(&proxy_address) {
+            Some(cluster_name) => cluster_name,
+            // Committed by other coordinators.
+            None => return Ok(()),
+        };
+        let (chunk_index, new_chunk) = match self.plan_switchover(&proxy_address)? {
+            Some((name, chunk_index, new_chunk)) if name == cluster_name => {
+                (chunk_index, new_chunk)
+            }
+            _ => return Ok(()),
+        };
+
+        let new_epoch = self.store.bump_global_epoch();
+        let cluster = self
+            .store
+            .clusters
+            .get_mut(&cluster_name)
+            .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
+        let chunk = &mut cluster.chunks[chunk_index];
+        let changed_parts: Vec<usize> = (0..CHUNK_PARTS)
+            .filter(|part| {
+                chunk.get_master_proxy_index(*part) != new_chunk.get_master_proxy_index(*part)
+            })
+            .collect();
+        *chunk = new_chunk;
+
+        Self::bump_migration_epoch(&mut cluster.chunks, chunk_index, &changed_parts, new_epoch);
         cluster.epoch = new_epoch;
         Ok(())
     }
 
+    // Moves the masters on the proxy to the other proxy of the chunk.
+    // Returns None if the proxy doesn't have any master.
+    fn plan_switchover(
+        &self,
+        proxy_address: &str,
+    ) -> Result<Option<(ClusterName, usize, ChunkStore)>, MetaStoreError> {
+        let cluster_name = match self.store.all_proxies.get(proxy_address) {
+            None => return Err(MetaStoreError::ProxyNotFound),
+            Some(proxy) => match proxy.cluster.clone() {
+                Some(cluster_name) => cluster_name,
+                None => return Ok(None),
+            },
+        };
+        let cluster = self
+            .store
+            .clusters
+            .get(&cluster_name)
+            .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
+        let (chunk_index, chunk, proxy_index) = cluster
+            .chunks
+            .iter()
+            .enumerate()
+            .find_map(|(chunk_index, chunk)| {
+                chunk
+                    .get_proxy_index(proxy_address)
+                    .map(|proxy_index| (chunk_index, chunk, proxy_index))
+            })
+            .ok_or_else(|| MetaStoreError::ProxyNotFound)?;
+
+        let has_master =
+            (0..CHUNK_PARTS).any(|part| chunk.get_master_proxy_index(part) == proxy_index);
+        if !has_master {
+            return Ok(None);
+        }
+        if chunk.get_replica_num() == 0 {
+            return Err(MetaStoreError::SwitchoverNotAvailable);
+        }
+
+        let mut new_chunk = chunk.clone();
+        new_chunk.role_position = if proxy_index == 0 {
+            ChunkRolePosition::SecondChunkMaster
+        } else {
+            ChunkRolePosition::FirstChunkMaster
+        };
+        for part in 0..CHUNK_PARTS {
+            let proxy_address = &new_chunk.proxy_addresses[new_chunk.get_master_proxy_index(part)];
+            let node_address = &new_chunk.node_addresses[new_chunk.get_master_node_index(part)];
+            if self.store.failed_proxies.contains(proxy_address)
+                || self.store.failures.contains_key(proxy_address)
+                || self.store.node_failures.contains_key(node_address)
+            {
+                return Err(MetaStoreError::SwitchoverNotAvailable);
+            }
+        }
+        Ok(Some((cluster_name, chunk_index, new_chunk)))
+    }
+
     fn second_host_cmp(
         host1: &str,
         count1: usize,
@@ -1016,12 +1613,23 @@ impl<'a> MetaStoreUpdate<'a> {
     pub fn balance_masters(&mut self, cluster_name: String) -> Result<(), MetaStoreError> {
         let cluster_name = ClusterName::try_from(cluster_name.as_str())
             .map_err(|_| MetaStoreError::InvalidClusterName)?;
+        if !self.store.clusters.contains_key(&cluster_name) {
+            return Err(MetaStoreError::ClusterNotFound);
+        }
+
+        let relocated_chunks = if self.store.enable_ordered_proxy {
+            HashSet::new()
+        } else {
+            self.relocate_colocated_chunks(&cluster_name)
+        };
+
         let new_epoch = self.store.bump_global_epoch();
 
         let failed_proxies = &self.store.failed_proxies;
         let failures = &self.store.failures;
+        let node_failures = &self.store.node_failures;
 
-        let failed_proxy_exists = |addresses: &[String; CHUNK_PARTS]| -> bool {
+        let failed_proxy_exists = |addresses: &[String]| -> bool {
             for address in addresses.iter() {
                 if failed_proxies.contains(address) || failures.contains_key(address) {
                     return true;
@@ -1029,14 +1637,27 @@ impl<'a> MetaStoreUpdate<'a> {
             }
             false
         };
+        let failed_node_exists = |addresses: &[String]| -> bool {
+            addresses
+                .iter()
+                .any(|address| node_failures.contains_key(address))
+        };
 
         match self.store.clusters.get_mut(&cluster_name) {
             None => return Err(MetaStoreError::ClusterNotFound),
             Some(ref mut cluster) => {
-                for chunk in cluster.chunks.iter_mut() {
+                for (chunk_index, chunk) in cluster.chunks.iter_mut().enumerate() {
                     if failed_proxy_exists(&chunk.proxy_addresses) {
                         continue;
                     }
+                    // Keep the masters away from the failed nodes.
+                    if failed_node_exists(&chunk.node_addresses) {
+                        continue;
+                    }
+                    // The new proxy needs to sync the data from the masters first.
+                    if relocated_chunks.contains(&chunk_index) {
+                        continue;
+                    }
                     chunk.role_position = ChunkRolePosition::Normal;
                 }
                 cluster.set_epoch(new_epoch);
@@ -1046,6 +1667,152 @@ impl<'a> MetaStoreUpdate<'a> {
         Ok(())
     }
 
+    // The proxies of a chunk could be in the same location
+    // after replacing failed proxies without enough resources.
+    // Move one of them to another location if any free proxy is available.
+    // A proxy only holding replicas is moved first.
+    // Otherwise the remaining master proxy will take over all the masters of the chunk,
+    // so `balance_masters` should be called again after the new replicas are synced.
+    // Returns the indices of the relocated chunks.
+    fn relocate_colocated_chunks(&mut self, cluster_name: &ClusterName) -> HashSet<usize> {
+        let mut relocated_chunks = HashSet::new();
+        let level = match self.get_location_levels().first() {
+            Some(level) => *level,
+            None => return relocated_chunks,
+        };
+
+        let chunk_num = match self.store.clusters.get(cluster_name) {
+            Some(cluster) => cluster.chunks.len(),
+            None => return relocated_chunks,
+        };
+
+        for chunk_index in 0..chunk_num {
+            let (replaced_index, role_position, kept_proxies) =
+                match self.get_colocated_chunk(cluster_name, chunk_index, level) {
+                    Some(res) => res,
+                    None => continue,
+                };
+
+            let free_host_proxies = self.generate_free_host_proxies();
+            let kept_proxies: Vec<&ProxyResource> = kept_proxies.iter().collect();
+            let candidates =
+                self.filter_free_proxies_at_level(&free_host_proxies, &kept_proxies, level);
+            let new_proxy = match candidates
+                .values()
+                .max_by_key(|proxies| proxies.len())
+                .and_then(|proxies| proxies.first())
+                .and_then(|address| self.store.all_proxies.get(address))
+            {
+                Some(proxy) => proxy.clone(),
+                None => {
+                    warn!(
+                        "no free proxy for relocating chunk {} of cluster {} in different {:?}",
+                        chunk_index, cluster_name, level
+                    );
+                    break;
+                }
+            };
+
+            let cluster = self
+                .store
+                .clusters
+                .get_mut(cluster_name)
+                .expect("relocate_colocated_chunks: get cluster");
+            let chunk = cluster
+                .chunks
+                .get_mut(chunk_index)
+                .expect("relocate_colocated_chunks: get chunk");
+            let old_proxy_address = chunk.proxy_addresses[replaced_index].clone();
+            chunk.role_position = role_position;
+            chunk.replace_proxy(replaced_index, &new_proxy);
+            info!(
+                "relocate proxy {} to {} for chunk {} of cluster {}",
+                old_proxy_address, new_proxy.proxy_address, chunk_index, cluster_name
+            );
+
+            if let Some(proxy) = self.store.all_proxies.get_mut(&old_proxy_address) {
+                proxy.cluster = None;
+            }
+            if let Some(proxy) = self.store.all_proxies.get_mut(&new_proxy.proxy_address) {
+                proxy.cluster = Some(cluster_name.clone());
+            }
+            relocated_chunks.insert(chunk_index);
+        }
+        relocated_chunks
+    }
+
+    // Returns the index of the proxy which should be replaced,
+    // the new role position, and the other proxies which should be kept
+    // if any two proxies of this chunk are in the same location.
+    fn get_colocated_chunk(
+        &self,
+        cluster_name: &ClusterName,
+        chunk_index: usize,
+        level: LocationLevel,
+    ) -> Option<(usize, ChunkRolePosition, Vec<ProxyResource>)> {
+        let chunk = self
+            .store
+            .clusters
+            .get(cluster_name)?
+            .chunks
+            .get(chunk_index)?;
+        // Skip the migrating chunks since their metadata depends on the proxies.
+        if chunk.migrating_slots.iter().any(|slots| !slots.is_empty()) {
+            return None;
+        }
+        let proxies: Vec<&ProxyResource> = chunk
+            .proxy_addresses
+            .iter()
+            .filter_map(|address| self.store.all_proxies.get(address))
+            .collect();
+        // Nothing could be colocated without replicas.
+        if proxies.len() != chunk.proxy_addresses.len() || proxies.len() < CHUNK_PARTS {
+            return None;
+        }
+        if proxies.iter().any(|proxy| {
+            self.store.failed_proxies.contains(&proxy.proxy_address)
+                || self.store.failures.contains_key(&proxy.proxy_address)
+        }) {
+            return None;
+        }
+
+        let locations: Vec<Option<String>> = proxies
+            .iter()
+            .map(|proxy| proxy.get_location(level))
+            .collect();
+        let is_colocated = |i: usize| {
+            locations
+                .iter()
+                .enumerate()
+                .any(|(j, location)| i != j && *location == locations[i])
+        };
+
+        let (replaced_index, role_position) = match (CHUNK_PARTS..proxies.len())
+            .rev()
+            .find(|i| is_colocated(*i))
+        {
+            Some(i) => (i, chunk.role_position),
+            None if locations[0] == locations[1] => {
+                // Keep the one holding all the masters if failover happened.
+                match chunk.role_position {
+                    ChunkRolePosition::SecondChunkMaster => {
+                        (0, ChunkRolePosition::SecondChunkMaster)
+                    }
+                    _ => (1, ChunkRolePosition::FirstChunkMaster),
+                }
+            }
+            None => return None,
+        };
+
+        let kept_proxies = proxies
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != replaced_index)
+            .map(|(_, proxy)| (*proxy).clone())
+            .collect();
+        Some((replaced_index, role_position, kept_proxies))
+    }
+
     pub fn change_config(
         &mut self,
         cluster_name: String,
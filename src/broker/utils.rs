@@ -1,6 +1,6 @@
 #[cfg(test)]
 pub mod tests {
-    use super::super::store::MetaStore;
+    use super::super::store::{MetaStore, ProxyLocation};
     use crate::common::cluster::{Cluster, Role, SlotRangeTag};
     use crate::common::utils::SLOT_NUM;
 
@@ -14,7 +14,14 @@ pub mod tests {
                 ];
                 let index = host_index * proxy_per_host + i;
                 store
-                    .add_proxy(proxy_address, node_addresses, None, Some(index))
+                    .add_proxy(
+                        proxy_address,
+                        node_addresses,
+                        None,
+                        Some(index),
+                        None,
+                        ProxyLocation::default(),
+                    )
                     .unwrap();
             }
         }
@@ -518,6 +518,139 @@ impl MigrationTaskMeta {
     }
 }
 
+// The traffic of a slot recorded by the server proxy.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SlotStats {
+    pub slot: usize,
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+impl SlotStats {
+    pub fn to_strings(&self) -> Vec<String> {
+        vec![
+            self.slot.to_string(),
+            self.requests.to_string(),
+            self.bytes.to_string(),
+        ]
+    }
+
+    pub fn from_strings<It>(it: &mut It) -> Option<Self>
+    where
+        It: Iterator<Item = String>,
+    {
+        let slot = it.next()?.parse::<usize>().ok()?;
+        if slot >= SLOT_NUM {
+            return None;
+        }
+        let requests = it.next()?.parse::<u64>().ok()?;
+        let bytes = it.next()?.parse::<u64>().ok()?;
+        Some(Self {
+            slot,
+            requests,
+            bytes,
+        })
+    }
+}
+
+// The progress of a migrating task recorded by the server proxy.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MigrationProgress {
+    pub cluster_name: ClusterName,
+    pub src_node_address: String,
+    pub dst_node_address: String,
+    pub keys_scanned: u64,
+    pub keys_migrated: u64,
+    pub bytes_transferred: u64,
+    pub errors: u64,
+    // The estimated ratio of the scanned hash table of the source node, from 0 to 1.
+    pub scanned_ratio: f64,
+    pub elapsed_secs: u64,
+}
+
+impl MigrationProgress {
+    pub fn to_strings(&self) -> Vec<String> {
+        vec![
+            self.cluster_name.to_string(),
+            self.src_node_address.clone(),
+            self.dst_node_address.clone(),
+            self.keys_scanned.to_string(),
+            self.keys_migrated.to_string(),
+            self.bytes_transferred.to_string(),
+            self.errors.to_string(),
+            format!("{:.4}", self.scanned_ratio),
+            self.elapsed_secs.to_string(),
+        ]
+    }
+
+    pub fn from_strings<It>(it: &mut It) -> Option<Self>
+    where
+        It: Iterator<Item = String>,
+    {
+        let cluster_name = ClusterName::try_from(it.next()?.as_str()).ok()?;
+        let src_node_address = it.next()?;
+        let dst_node_address = it.next()?;
+        let keys_scanned = it.next()?.parse::<u64>().ok()?;
+        let keys_migrated = it.next()?.parse::<u64>().ok()?;
+        let bytes_transferred = it.next()?.parse::<u64>().ok()?;
+        let errors = it.next()?.parse::<u64>().ok()?;
+        let scanned_ratio = it.next()?.parse::<f64>().ok()?;
+        if !(0.0..=1.0).contains(&scanned_ratio) {
+            return None;
+        }
+        let elapsed_secs = it.next()?.parse::<u64>().ok()?;
+        Some(Self {
+            cluster_name,
+            src_node_address,
+            dst_node_address,
+            keys_scanned,
+            keys_migrated,
+            bytes_transferred,
+            errors,
+            scanned_ratio,
+            elapsed_secs,
+        })
+    }
+
+    // Assume the keys are evenly distributed in the hash table.
+    pub fn get_eta_secs(&self) -> Option<u64> {
+        if self.scanned_ratio <= 0.0 {
+            return None;
+        }
+        let remaining = self.elapsed_secs as f64 * (1.0 - self.scanned_ratio) / self.scanned_ratio;
+        Some(remaining.ceil() as u64)
+    }
+}
+
+// The masters in `proxy_address` to be moved to the replicas.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SwitchoverTask {
+    pub proxy_address: String,
+    pub nodes: Vec<SwitchoverNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SwitchoverNode {
+    pub master_node_address: String,
+    pub replica_node_address: String,
+    pub replica_proxy_address: String,
+}
+
+// The replication offset of a Redis node read from `INFO replication`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NodeReplStats {
+    pub node_address: String,
+    pub repl_offset: u64,
+}
+
+// The memory usage of a Redis node read from `INFO memory`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NodeMemoryStats {
+    pub node_address: String,
+    pub used_memory: u64,
+    pub maxmemory: u64,
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct ReplPeer {
     pub node_address: String,
@@ -1136,4 +1269,50 @@ mod tests {
         assert_eq!(range_list.get_ranges()[0].start(), 0);
         assert_eq!(range_list.get_ranges()[0].end(), 233);
     }
+
+    #[test]
+    fn test_slot_stats_encoding_and_decoding() {
+        let stats = SlotStats {
+            slot: 233,
+            requests: 7799,
+            bytes: 666,
+        };
+        let strings = stats.to_strings();
+        assert_eq!(strings.join(" "), "233 7799 666");
+        let decoded = SlotStats::from_strings(&mut strings.into_iter()).unwrap();
+        assert_eq!(decoded, stats);
+
+        let mut invalid = vec!["16384", "1", "1"].into_iter().map(|s| s.to_string());
+        assert!(SlotStats::from_strings(&mut invalid).is_none());
+        let mut missing = vec!["1", "1"].into_iter().map(|s| s.to_string());
+        assert!(SlotStats::from_strings(&mut missing).is_none());
+    }
+
+    #[test]
+    fn test_migration_progress_encoding_and_decoding() {
+        let progress = MigrationProgress {
+            cluster_name: ClusterName::try_from("mycluster").unwrap(),
+            src_node_address: "127.0.0.1:7000".to_string(),
+            dst_node_address: "127.0.0.1:7001".to_string(),
+            keys_scanned: 1000,
+            keys_migrated: 500,
+            bytes_transferred: 233,
+            errors: 1,
+            scanned_ratio: 0.25,
+            elapsed_secs: 10,
+        };
+        let strings = progress.to_strings();
+        assert_eq!(
+            strings.join(" "),
+            "mycluster 127.0.0.1:7000 127.0.0.1:7001 1000 500 233 1 0.2500 10"
+        );
+        let decoded = MigrationProgress::from_strings(&mut strings.into_iter()).unwrap();
+        assert_eq!(decoded, progress);
+        assert_eq!(progress.get_eta_secs(), Some(30));
+
+        let mut invalid = "mycluster a b 1 1 1 1 1.5 1"
+            .split(' ')
+            .map(|s| s.to_string());
+        assert!(MigrationProgress::from_strings(&mut invalid).is_none());
+    }
 }
@@ -0,0 +1,519 @@
+use crate::common::utils::byte_to_uppercase;
+use arrayvec::ArrayVec;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
+use std::str;
+use std::str::FromStr;
+
+const MAX_COMMAND_NAME_LENGTH: usize = 64;
+const CATEGORY_PREFIX: char = '@';
+
+const DANGEROUS_COMMANDS: &[&str] = &[
+    "BGREWRITEAOF",
+    "BGSAVE",
+    "CLIENT",
+    "CONFIG",
+    "DEBUG",
+    "FLUSHALL",
+    "FLUSHDB",
+    "KEYS",
+    "MIGRATE",
+    "MONITOR",
+    "REPLICAOF",
+    "RESTORE",
+    "SAVE",
+    "SCRIPT",
+    "SHUTDOWN",
+    "SLAVEOF",
+    "SORT",
+    "SWAPDB",
+];
+
+const SLOW_COMMANDS: &[&str] = &[
+    "EVAL",
+    "EVALSHA",
+    "FLUSHALL",
+    "FLUSHDB",
+    "HGETALL",
+    "HKEYS",
+    "HVALS",
+    "KEYS",
+    "LRANGE",
+    "SDIFF",
+    "SDIFFSTORE",
+    "SINTER",
+    "SINTERSTORE",
+    "SMEMBERS",
+    "SORT",
+    "SUNION",
+    "SUNIONSTORE",
+    "ZINTERSTORE",
+    "ZRANGE",
+    "ZRANGEBYLEX",
+    "ZRANGEBYSCORE",
+    "ZREVRANGE",
+    "ZREVRANGEBYLEX",
+    "ZREVRANGEBYSCORE",
+    "ZUNIONSTORE",
+];
+
+const ADMIN_COMMANDS: &[&str] = &[
+    "BGREWRITEAOF",
+    "BGSAVE",
+    "CLIENT",
+    "CONFIG",
+    "DEBUG",
+    "LASTSAVE",
+    "LATENCY",
+    "MONITOR",
+    "PSYNC",
+    "REPLCONF",
+    "REPLICAOF",
+    "SAVE",
+    "SHUTDOWN",
+    "SLAVEOF",
+    "SLOWLOG",
+    "SYNC",
+];
+
+const WRITE_COMMANDS: &[&str] = &[
+    "APPEND",
+    "BITFIELD",
+    "BITOP",
+    "BLPOP",
+    "BRPOP",
+    "BRPOPLPUSH",
+    "BZPOPMAX",
+    "BZPOPMIN",
+    "DECR",
+    "DECRBY",
+    "DEL",
+    "EXPIRE",
+    "EXPIREAT",
+    "GEOADD",
+    "GETSET",
+    "HDEL",
+    "HINCRBY",
+    "HINCRBYFLOAT",
+    "HMSET",
+    "HSET",
+    "HSETNX",
+    "INCR",
+    "INCRBY",
+    "INCRBYFLOAT",
+    "LINSERT",
+    "LPOP",
+    "LPUSH",
+    "LPUSHX",
+    "LREM",
+    "LSET",
+    "LTRIM",
+    "MOVE",
+    "MSET",
+    "MSETNX",
+    "PERSIST",
+    "PEXPIRE",
+    "PEXPIREAT",
+    "PFADD",
+    "PFMERGE",
+    "PSETEX",
+    "RENAME",
+    "RENAMENX",
+    "RESTORE",
+    "RPOP",
+    "RPOPLPUSH",
+    "RPUSH",
+    "RPUSHX",
+    "SADD",
+    "SDIFFSTORE",
+    "SET",
+    "SETBIT",
+    "SETEX",
+    "SETNX",
+    "SETRANGE",
+    "SINTERSTORE",
+    "SMOVE",
+    "SPOP",
+    "SREM",
+    "SUNIONSTORE",
+    "UNLINK",
+    "XADD",
+    "XDEL",
+    "XTRIM",
+    "ZADD",
+    "ZINCRBY",
+    "ZINTERSTORE",
+    "ZPOPMAX",
+    "ZPOPMIN",
+    "ZREM",
+    "ZREMRANGEBYLEX",
+    "ZREMRANGEBYRANK",
+    "ZREMRANGEBYSCORE",
+    "ZUNIONSTORE",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandCategory {
+    Dangerous,
+    Slow,
+    Admin,
+    Write,
+}
+
+impl FromStr for CommandCategory {
+    type Err = InvalidCommandPolicyStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_lowercase();
+        match lowercase.as_str() {
+            "dangerous" => Ok(Self::Dangerous),
+            "slow" => Ok(Self::Slow),
+            "admin" => Ok(Self::Admin),
+            "write" => Ok(Self::Write),
+            _ => Err(InvalidCommandPolicyStr),
+        }
+    }
+}
+
+impl CommandCategory {
+    pub fn get_commands(self) -> &'static [&'static str] {
+        match self {
+            Self::Dangerous => DANGEROUS_COMMANDS,
+            Self::Slow => SLOW_COMMANDS,
+            Self::Admin => ADMIN_COMMANDS,
+            Self::Write => WRITE_COMMANDS,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidCommandPolicyStr;
+
+// Comma separated command names and categories prefixed with `@`,
+// such as `keys,debug,@admin`.
+// The categories are expanded to the command names on parsing.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DeniedCommands {
+    original: String,
+    commands: HashSet<String>,
+}
+
+impl FromStr for DeniedCommands {
+    type Err = InvalidCommandPolicyStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut commands = HashSet::new();
+        let mut names = vec![];
+        for name in split_list(s) {
+            if name.starts_with(CATEGORY_PREFIX) {
+                let category = CommandCategory::from_str(&name[1..])?;
+                commands.extend(category.get_commands().iter().map(|cmd| cmd.to_string()));
+                names.push(name.to_lowercase());
+            } else {
+                check_command_name(name)?;
+                commands.insert(name.to_uppercase());
+                names.push(name.to_lowercase());
+            }
+        }
+        Ok(Self {
+            original: names.join(","),
+            commands,
+        })
+    }
+}
+
+impl DeniedCommands {
+    pub fn to_str(&self) -> &str {
+        self.original.as_str()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn is_denied(&self, cmd_name: &[u8]) -> bool {
+        if self.commands.is_empty() {
+            return false;
+        }
+        with_uppercase(cmd_name, |cmd_name| self.commands.contains(cmd_name)).unwrap_or(false)
+    }
+}
+
+impl Serialize for DeniedCommands {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for DeniedCommands {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(|_| D::Error::custom(format!("invalid denied commands {}", s)))
+    }
+}
+
+// Comma separated `alias:command` pairs, such as `myflushall:flushall`.
+// Like the `rename-command` of Redis, the original command names could not be used
+// unless they are also the aliases of other commands.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CommandAliases {
+    original: String,
+    aliases: HashMap<String, String>,
+    renamed: HashSet<String>,
+}
+
+impl FromStr for CommandAliases {
+    type Err = InvalidCommandPolicyStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut aliases = HashMap::new();
+        let mut renamed = HashSet::new();
+        let mut pairs = vec![];
+        for pair in split_list(s) {
+            let mut it = pair.splitn(2, ':').map(str::trim);
+            let (alias, command) = match (it.next(), it.next()) {
+                (Some(alias), Some(command)) => (alias, command),
+                _ => return Err(InvalidCommandPolicyStr),
+            };
+            check_command_name(alias)?;
+            check_command_name(command)?;
+            let alias = alias.to_uppercase();
+            let command = command.to_uppercase();
+            if alias == command {
+                return Err(InvalidCommandPolicyStr);
+            }
+            pairs.push(format!("{}:{}", alias, command).to_lowercase());
+            renamed.insert(command.clone());
+            if aliases.insert(alias, command).is_some() {
+                return Err(InvalidCommandPolicyStr);
+            }
+        }
+        Ok(Self {
+            original: pairs.join(","),
+            aliases,
+            renamed,
+        })
+    }
+}
+
+impl CommandAliases {
+    pub fn to_str(&self) -> &str {
+        self.original.as_str()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+
+    // Returns the uppercase name of the real command.
+    pub fn get_command(&self, alias: &[u8]) -> Option<&str> {
+        if self.aliases.is_empty() {
+            return None;
+        }
+        with_uppercase(alias, |alias| self.aliases.get(alias).map(String::as_str)).flatten()
+    }
+
+    pub fn is_renamed(&self, cmd_name: &[u8]) -> bool {
+        if self.renamed.is_empty() {
+            return false;
+        }
+        with_uppercase(cmd_name, |cmd_name| self.renamed.contains(cmd_name)).unwrap_or(false)
+    }
+}
+
+impl Serialize for CommandAliases {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CommandAliases {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(|_| D::Error::custom(format!("invalid command aliases {}", s)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandPolicyResult<'a> {
+    Allowed,
+    // The uppercase name of the real command.
+    Aliased(&'a str),
+    Denied,
+}
+
+// The aliases are resolved before checking the denied commands,
+// so the denied commands could not be run through their aliases.
+pub fn check_command_policy<'a>(
+    denied_commands: &DeniedCommands,
+    command_aliases: &'a CommandAliases,
+    cmd_name: &[u8],
+) -> CommandPolicyResult<'a> {
+    match command_aliases.get_command(cmd_name) {
+        Some(command) => {
+            if denied_commands.is_denied(cmd_name) || denied_commands.is_denied(command.as_bytes())
+            {
+                CommandPolicyResult::Denied
+            } else {
+                CommandPolicyResult::Aliased(command)
+            }
+        }
+        None if command_aliases.is_renamed(cmd_name) || denied_commands.is_denied(cmd_name) => {
+            CommandPolicyResult::Denied
+        }
+        None => CommandPolicyResult::Allowed,
+    }
+}
+
+// The scripts could also write the keys but are not included.
+pub fn is_write_command(cmd_name: &[u8]) -> bool {
+    with_uppercase(cmd_name, |cmd_name| {
+        WRITE_COMMANDS.binary_search(&cmd_name).is_ok()
+    })
+    .unwrap_or(false)
+}
+
+fn split_list(s: &str) -> impl Iterator<Item = &str> {
+    s.split(',').map(str::trim).filter(|name| !name.is_empty())
+}
+
+fn check_command_name(name: &str) -> Result<(), InvalidCommandPolicyStr> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_COMMAND_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(InvalidCommandPolicyStr)
+    }
+}
+
+// Avoid allocating memory for every request.
+fn with_uppercase<F, T>(cmd_name: &[u8], f: F) -> Option<T>
+where
+    F: FnOnce(&str) -> T,
+{
+    let mut stack_cmd_name = ArrayVec::<[u8; MAX_COMMAND_NAME_LENGTH]>::new();
+    for b in cmd_name {
+        stack_cmd_name.try_push(byte_to_uppercase(*b)).ok()?;
+    }
+    let cmd_name = str::from_utf8(&stack_cmd_name).ok()?;
+    Some(f(cmd_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_denied_commands() {
+        let denied = DeniedCommands::from_str(" Keys, debug ,@ADMIN,,").unwrap();
+        assert_eq!(denied.to_str(), "keys,debug,@admin");
+        assert!(denied.is_denied(b"keys"));
+        assert!(denied.is_denied(b"DEBUG"));
+        assert!(denied.is_denied(b"shutdown"));
+        assert!(!denied.is_denied(b"get"));
+
+        let denied = DeniedCommands::from_str("").unwrap();
+        assert!(denied.is_empty());
+        assert!(!denied.is_denied(b"keys"));
+
+        assert!(DeniedCommands::from_str("@unknown").is_err());
+        assert!(DeniedCommands::from_str("get set").is_err());
+    }
+
+    #[test]
+    fn test_command_aliases() {
+        let aliases = CommandAliases::from_str("MyFlushAll:flushall, k:keys").unwrap();
+        assert_eq!(aliases.to_str(), "myflushall:flushall,k:keys");
+        assert_eq!(aliases.get_command(b"myflushall"), Some("FLUSHALL"));
+        assert_eq!(aliases.get_command(b"K"), Some("KEYS"));
+        assert_eq!(aliases.get_command(b"keys"), None);
+        assert!(aliases.is_renamed(b"keys"));
+        assert!(aliases.is_renamed(b"FlushAll"));
+        assert!(!aliases.is_renamed(b"k"));
+
+        assert!(CommandAliases::from_str("").unwrap().is_empty());
+        assert!(CommandAliases::from_str("flushall").is_err());
+        assert!(CommandAliases::from_str("a:get,a:set").is_err());
+        assert!(CommandAliases::from_str("get:get").is_err());
+    }
+
+    #[test]
+    fn test_check_command_policy() {
+        let denied = DeniedCommands::from_str("keys,@dangerous").unwrap();
+        let aliases = CommandAliases::from_str("g:get").unwrap();
+        let check = |cmd_name: &[u8]| check_command_policy(&denied, &aliases, cmd_name);
+
+        assert_eq!(check(b"set"), CommandPolicyResult::Allowed);
+        assert_eq!(check(b"G"), CommandPolicyResult::Aliased("GET"));
+        assert_eq!(check(b"debug"), CommandPolicyResult::Denied);
+        assert_eq!(check(b"keys"), CommandPolicyResult::Denied);
+    }
+
+    #[test]
+    fn test_check_command_policy_denied_alias_target() {
+        let denied = DeniedCommands::from_str("keys,@dangerous").unwrap();
+        let aliases = CommandAliases::from_str("myflushall:flushall,k:keys").unwrap();
+        assert_eq!(
+            check_command_policy(&denied, &aliases, b"k"),
+            CommandPolicyResult::Denied
+        );
+        assert_eq!(
+            check_command_policy(&denied, &aliases, b"MyFlushAll"),
+            CommandPolicyResult::Denied
+        );
+
+        let denied = DeniedCommands::from_str("myflushall").unwrap();
+        assert_eq!(
+            check_command_policy(&denied, &aliases, b"myflushall"),
+            CommandPolicyResult::Denied
+        );
+    }
+
+    #[test]
+    fn test_check_command_policy_renamed_command() {
+        let denied = DeniedCommands::default();
+        let aliases = CommandAliases::from_str("myflushall:flushall,k:keys,keys:get").unwrap();
+        assert_eq!(
+            check_command_policy(&denied, &aliases, b"flushall"),
+            CommandPolicyResult::Denied
+        );
+        assert_eq!(
+            check_command_policy(&denied, &aliases, b"myflushall"),
+            CommandPolicyResult::Aliased("FLUSHALL")
+        );
+        // The original name is still usable as the alias of another command.
+        assert_eq!(
+            check_command_policy(&denied, &aliases, b"keys"),
+            CommandPolicyResult::Aliased("GET")
+        );
+        assert_eq!(
+            check_command_policy(&denied, &aliases, b"get"),
+            CommandPolicyResult::Denied
+        );
+    }
+
+    #[test]
+    fn test_is_write_command() {
+        assert!(is_write_command(b"set"));
+        assert!(is_write_command(b"ZUNIONSTORE"));
+        assert!(!is_write_command(b"get"));
+        assert!(!is_write_command(b"eval"));
+    }
+}
@@ -1,8 +1,11 @@
+use super::command_policy::{CommandAliases, DeniedCommands};
+use super::utils::glob_match;
 use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 use std::str::FromStr;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct ClusterConfig {
@@ -10,6 +13,32 @@ pub struct ClusterConfig {
     pub compression_strategy: CompressionStrategy,
     #[serde(default)]
     pub migration_config: MigrationConfig,
+    // Limit the requests of the whole cluster on each server proxy.
+    // Zero for no limit.
+    #[serde(default)]
+    pub rate_limit_ops_per_sec: u64,
+    #[serde(default)]
+    pub rate_limit_bytes_per_sec: u64,
+    #[serde(default)]
+    pub denied_commands: DeniedCommands,
+    #[serde(default)]
+    pub command_aliases: CommandAliases,
+    #[serde(default)]
+    pub key_prefixes: KeyPrefixes,
+    // The GET replies of the hot keys are cached on each server proxy.
+    // Zero `read_cache_max_keys` disables the cache.
+    #[serde(default)]
+    pub read_cache_max_keys: u64,
+    #[serde(default = "default_read_cache_ttl")]
+    pub read_cache_ttl: u64,
+    #[serde(default)]
+    pub read_cache_key_patterns: KeyPatterns,
+    #[serde(default)]
+    pub read_cache_hot_key_threshold: u64,
+}
+
+fn default_read_cache_ttl() -> u64 {
+    1000
 }
 
 impl Default for ClusterConfig {
@@ -17,6 +46,15 @@ impl Default for ClusterConfig {
         Self {
             compression_strategy: CompressionStrategy::default(),
             migration_config: MigrationConfig::default(),
+            rate_limit_ops_per_sec: 0,
+            rate_limit_bytes_per_sec: 0,
+            denied_commands: DeniedCommands::default(),
+            command_aliases: CommandAliases::default(),
+            key_prefixes: KeyPrefixes::default(),
+            read_cache_max_keys: 0,
+            read_cache_ttl: default_read_cache_ttl(),
+            read_cache_key_patterns: KeyPatterns::default(),
+            read_cache_hot_key_threshold: 0,
         }
     }
 }
@@ -30,6 +68,56 @@ impl ClusterConfig {
                     CompressionStrategy::from_str(&value).map_err(|_| ConfigError::InvalidValue)?;
                 self.compression_strategy = strategy;
             }
+            "rate_limit_ops_per_sec" => {
+                let v = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.rate_limit_ops_per_sec = v;
+            }
+            "rate_limit_bytes_per_sec" => {
+                let v = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.rate_limit_bytes_per_sec = v;
+            }
+            "denied_commands" => {
+                let denied_commands =
+                    DeniedCommands::from_str(value).map_err(|_| ConfigError::InvalidValue)?;
+                self.denied_commands = denied_commands;
+            }
+            "command_aliases" => {
+                let command_aliases =
+                    CommandAliases::from_str(value).map_err(|_| ConfigError::InvalidValue)?;
+                self.command_aliases = command_aliases;
+            }
+            "key_prefixes" => {
+                let key_prefixes =
+                    KeyPrefixes::from_str(value).map_err(|_| ConfigError::InvalidValue)?;
+                self.key_prefixes = key_prefixes;
+            }
+            "read_cache_max_keys" => {
+                let v = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.read_cache_max_keys = v;
+            }
+            "read_cache_ttl" => {
+                let v = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.read_cache_ttl = v;
+            }
+            "read_cache_key_patterns" => {
+                let patterns =
+                    KeyPatterns::from_str(value).map_err(|_| ConfigError::InvalidValue)?;
+                self.read_cache_key_patterns = patterns;
+            }
+            "read_cache_hot_key_threshold" => {
+                let v = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.read_cache_hot_key_threshold = v;
+            }
             _ => {
                 if field.starts_with("migration_") {
                     let f = field
@@ -67,6 +155,51 @@ impl ClusterConfig {
                 "migration_scan_count",
                 self.migration_config.scan_count.to_string(),
             ),
+            (
+                "migration_max_bytes_per_sec",
+                self.migration_config.max_bytes_per_sec.to_string(),
+            ),
+            (
+                "migration_max_keys_per_sec",
+                self.migration_config.max_keys_per_sec.to_string(),
+            ),
+            (
+                "migration_big_key_threshold",
+                self.migration_config.big_key_threshold.to_string(),
+            ),
+            (
+                "migration_big_key_count_threshold",
+                self.migration_config.big_key_count_threshold.to_string(),
+            ),
+            (
+                "migration_scan_by_slot",
+                self.migration_config.scan_by_slot.to_string(),
+            ),
+            (
+                "migration_strategy",
+                self.migration_config.strategy.to_str().to_string(),
+            ),
+            (
+                "rate_limit_ops_per_sec",
+                self.rate_limit_ops_per_sec.to_string(),
+            ),
+            (
+                "rate_limit_bytes_per_sec",
+                self.rate_limit_bytes_per_sec.to_string(),
+            ),
+            ("denied_commands", self.denied_commands.to_str().to_string()),
+            ("command_aliases", self.command_aliases.to_str().to_string()),
+            ("key_prefixes", self.key_prefixes.to_str().to_string()),
+            ("read_cache_max_keys", self.read_cache_max_keys.to_string()),
+            ("read_cache_ttl", self.read_cache_ttl.to_string()),
+            (
+                "read_cache_key_patterns",
+                self.read_cache_key_patterns.to_str().to_string(),
+            ),
+            (
+                "read_cache_hot_key_threshold",
+                self.read_cache_hot_key_threshold.to_string(),
+            ),
         ]
         .into_iter()
         .map(|(k, v)| (k.to_string(), v))
@@ -137,12 +270,242 @@ impl<'de> Deserialize<'de> for CompressionStrategy {
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MigrationStrategy {
+    // Transfer the data through the server proxy by DUMP and RESTORE.
+    DumpRestore = 0,
+    // Let the source Redis send the data to the destination Redis directly by MIGRATE.
+    // Fall back to DUMP and RESTORE on error.
+    Migrate = 1,
+}
+
+impl Default for MigrationStrategy {
+    fn default() -> Self {
+        MigrationStrategy::DumpRestore
+    }
+}
+
+pub struct InvalidMigrationStrategyStr;
+
+impl FromStr for MigrationStrategy {
+    type Err = InvalidMigrationStrategyStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_lowercase();
+        match lowercase.as_str() {
+            "dump_restore" => Ok(Self::DumpRestore),
+            "migrate" => Ok(Self::Migrate),
+            _ => Err(InvalidMigrationStrategyStr),
+        }
+    }
+}
+
+impl MigrationStrategy {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::DumpRestore => "dump_restore",
+            Self::Migrate => "migrate",
+        }
+    }
+
+    fn from_u8(n: u8) -> Self {
+        match n {
+            1 => Self::Migrate,
+            _ => Self::DumpRestore,
+        }
+    }
+}
+
+impl Serialize for MigrationStrategy {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for MigrationStrategy {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s)
+            .map_err(|_| D::Error::custom(format!("invalid migration strategy {}", s)))
+    }
+}
+
+pub struct InvalidKeyPrefixesStr;
+
+// Comma separated `user:prefix` pairs, such as `service1:s1:,service2:s2:`.
+// The clients authenticated by `AUTH user cluster_name` will have all the keys prefixed.
+// The prefix could not contain the hash tag or the glob-style pattern characters.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct KeyPrefixes {
+    original: String,
+    prefixes: HashMap<String, Arc<Vec<u8>>>,
+}
+
+impl FromStr for KeyPrefixes {
+    type Err = InvalidKeyPrefixesStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut prefixes = HashMap::new();
+        let mut pairs = vec![];
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let mut it = pair.splitn(2, ':');
+            let (user, prefix) = match (it.next(), it.next()) {
+                (Some(user), Some(prefix)) => (user, prefix),
+                _ => return Err(InvalidKeyPrefixesStr),
+            };
+            let valid_user = !user.is_empty()
+                && user
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            let valid_prefix = !prefix.is_empty()
+                && prefix
+                    .chars()
+                    .all(|c| c.is_ascii_graphic() && !"{}*?[]\\".contains(c));
+            if !valid_user || !valid_prefix {
+                return Err(InvalidKeyPrefixesStr);
+            }
+            pairs.push(pair.to_string());
+            let prefix = Arc::new(prefix.as_bytes().to_vec());
+            if prefixes.insert(user.to_string(), prefix).is_some() {
+                return Err(InvalidKeyPrefixesStr);
+            }
+        }
+        Ok(Self {
+            original: pairs.join(","),
+            prefixes,
+        })
+    }
+}
+
+impl KeyPrefixes {
+    pub fn to_str(&self) -> &str {
+        self.original.as_str()
+    }
+
+    pub fn get_prefix(&self, user: &str) -> Option<Arc<Vec<u8>>> {
+        self.prefixes.get(user).cloned()
+    }
+}
+
+impl Serialize for KeyPrefixes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyPrefixes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(|_| D::Error::custom(format!("invalid key prefixes {}", s)))
+    }
+}
+
+pub struct InvalidKeyPatternsStr;
+
+// Comma separated glob-style patterns, such as `user:*,config:?`.
+// Only `*` and `?` are supported.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct KeyPatterns {
+    original: String,
+    patterns: Vec<Vec<u8>>,
+}
+
+impl FromStr for KeyPatterns {
+    type Err = InvalidKeyPatternsStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut patterns = vec![];
+        for pattern in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            if !pattern.chars().all(|c| c.is_ascii_graphic()) {
+                return Err(InvalidKeyPatternsStr);
+            }
+            patterns.push(pattern.to_string());
+        }
+        Ok(Self {
+            original: patterns.join(","),
+            patterns: patterns.into_iter().map(String::into_bytes).collect(),
+        })
+    }
+}
+
+impl KeyPatterns {
+    pub fn to_str(&self) -> &str {
+        self.original.as_str()
+    }
+
+    pub fn is_match(&self, key: &[u8]) -> bool {
+        self.patterns.iter().any(|pattern| glob_match(pattern, key))
+    }
+}
+
+impl Serialize for KeyPatterns {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyPatterns {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(|_| D::Error::custom(format!("invalid key patterns {}", s)))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct MigrationConfig {
     pub max_migration_time: u64,
     pub max_blocking_time: u64,
     pub scan_interval: u64,
     pub scan_count: u64,
+    // Zero for no limit.
+    #[serde(default)]
+    pub max_bytes_per_sec: u64,
+    #[serde(default)]
+    pub max_keys_per_sec: u64,
+    // The keys using more memory than this will be migrated in chunks.
+    // Zero to disable it.
+    #[serde(default = "default_big_key_threshold")]
+    pub big_key_threshold: u64,
+    // Used instead of `big_key_threshold` when `MEMORY USAGE` is not available.
+    // The keys with more elements than this will be migrated in chunks.
+    #[serde(default = "default_big_key_count_threshold")]
+    pub big_key_count_threshold: u64,
+    // Use `CLUSTER GETKEYSINSLOT` instead of `SCAN`.
+    // Requires the backend Redis to enable the cluster mode.
+    #[serde(default)]
+    pub scan_by_slot: bool,
+    #[serde(default)]
+    pub strategy: MigrationStrategy,
+}
+
+const DEFAULT_BIG_KEY_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+const DEFAULT_BIG_KEY_COUNT_THRESHOLD: u64 = 100_000;
+
+fn default_big_key_threshold() -> u64 {
+    DEFAULT_BIG_KEY_THRESHOLD
+}
+
+fn default_big_key_count_threshold() -> u64 {
+    DEFAULT_BIG_KEY_COUNT_THRESHOLD
 }
 
 impl MigrationConfig {
@@ -176,6 +539,41 @@ impl MigrationConfig {
                 }
                 self.scan_count = v;
             }
+            "max_bytes_per_sec" => {
+                let v = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.max_bytes_per_sec = v;
+            }
+            "max_keys_per_sec" => {
+                let v = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.max_keys_per_sec = v;
+            }
+            "big_key_threshold" => {
+                let v = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.big_key_threshold = v;
+            }
+            "big_key_count_threshold" => {
+                let v = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.big_key_count_threshold = v;
+            }
+            "scan_by_slot" => {
+                let v = value
+                    .parse::<bool>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.scan_by_slot = v;
+            }
+            "strategy" => {
+                let strategy =
+                    MigrationStrategy::from_str(value).map_err(|_| ConfigError::InvalidValue)?;
+                self.strategy = strategy;
+            }
             _ => return Err(ConfigError::FieldNotFound),
         }
         Ok(())
@@ -189,6 +587,12 @@ impl Default for MigrationConfig {
             max_blocking_time: 10_000,       // 10 seconds waiting for switch
             scan_interval: 500,              // 500 microseconds
             scan_count: 16,
+            max_bytes_per_sec: 0,
+            max_keys_per_sec: 0,
+            big_key_threshold: DEFAULT_BIG_KEY_THRESHOLD,
+            big_key_count_threshold: DEFAULT_BIG_KEY_COUNT_THRESHOLD,
+            scan_by_slot: false,
+            strategy: MigrationStrategy::default(),
         }
     }
 }
@@ -198,6 +602,12 @@ pub struct AtomicMigrationConfig {
     max_blocking_time: AtomicU64,
     scan_interval: AtomicU64,
     scan_count: AtomicU64,
+    max_bytes_per_sec: AtomicU64,
+    max_keys_per_sec: AtomicU64,
+    big_key_threshold: AtomicU64,
+    big_key_count_threshold: AtomicU64,
+    scan_by_slot: AtomicBool,
+    strategy: AtomicU8,
 }
 
 impl Default for AtomicMigrationConfig {
@@ -213,9 +623,52 @@ impl AtomicMigrationConfig {
             max_blocking_time: AtomicU64::new(config.max_blocking_time),
             scan_interval: AtomicU64::new(config.scan_interval),
             scan_count: AtomicU64::new(config.scan_count),
+            max_bytes_per_sec: AtomicU64::new(config.max_bytes_per_sec),
+            max_keys_per_sec: AtomicU64::new(config.max_keys_per_sec),
+            big_key_threshold: AtomicU64::new(config.big_key_threshold),
+            big_key_count_threshold: AtomicU64::new(config.big_key_count_threshold),
+            scan_by_slot: AtomicBool::new(config.scan_by_slot),
+            strategy: AtomicU8::new(config.strategy as u8),
+        }
+    }
+
+    pub fn to_config(&self) -> MigrationConfig {
+        MigrationConfig {
+            max_migration_time: self.get_max_migration_time(),
+            max_blocking_time: self.get_max_blocking_time(),
+            scan_interval: self.get_scan_interval(),
+            scan_count: self.get_scan_count(),
+            max_bytes_per_sec: self.get_max_bytes_per_sec(),
+            max_keys_per_sec: self.get_max_keys_per_sec(),
+            big_key_threshold: self.get_big_key_threshold(),
+            big_key_count_threshold: self.get_big_key_count_threshold(),
+            scan_by_slot: self.get_scan_by_slot(),
+            strategy: self.get_strategy(),
         }
     }
 
+    // Used to change the config of the running migration.
+    pub fn update(&self, config: MigrationConfig) {
+        self.max_migration_time
+            .store(config.max_migration_time, Ordering::SeqCst);
+        self.max_blocking_time
+            .store(config.max_blocking_time, Ordering::SeqCst);
+        self.scan_interval
+            .store(config.scan_interval, Ordering::SeqCst);
+        self.scan_count.store(config.scan_count, Ordering::SeqCst);
+        self.max_bytes_per_sec
+            .store(config.max_bytes_per_sec, Ordering::SeqCst);
+        self.max_keys_per_sec
+            .store(config.max_keys_per_sec, Ordering::SeqCst);
+        self.big_key_threshold
+            .store(config.big_key_threshold, Ordering::SeqCst);
+        self.big_key_count_threshold
+            .store(config.big_key_count_threshold, Ordering::SeqCst);
+        self.scan_by_slot
+            .store(config.scan_by_slot, Ordering::SeqCst);
+        self.strategy.store(config.strategy as u8, Ordering::SeqCst);
+    }
+
     pub fn get_max_migration_time(&self) -> u64 {
         self.max_migration_time.load(Ordering::SeqCst)
     }
@@ -231,6 +684,30 @@ impl AtomicMigrationConfig {
     pub fn get_scan_count(&self) -> u64 {
         self.scan_count.load(Ordering::SeqCst)
     }
+
+    pub fn get_max_bytes_per_sec(&self) -> u64 {
+        self.max_bytes_per_sec.load(Ordering::SeqCst)
+    }
+
+    pub fn get_max_keys_per_sec(&self) -> u64 {
+        self.max_keys_per_sec.load(Ordering::SeqCst)
+    }
+
+    pub fn get_big_key_threshold(&self) -> u64 {
+        self.big_key_threshold.load(Ordering::SeqCst)
+    }
+
+    pub fn get_big_key_count_threshold(&self) -> u64 {
+        self.big_key_count_threshold.load(Ordering::SeqCst)
+    }
+
+    pub fn get_scan_by_slot(&self) -> bool {
+        self.scan_by_slot.load(Ordering::SeqCst)
+    }
+
+    pub fn get_strategy(&self) -> MigrationStrategy {
+        MigrationStrategy::from_u8(self.strategy.load(Ordering::SeqCst))
+    }
 }
 
 #[derive(Debug)]
@@ -238,6 +715,8 @@ pub enum ConfigError {
     ReadonlyField,
     FieldNotFound,
     InvalidValue,
+    NoConfigFile,
+    FileError,
 }
 
 impl ToString for ConfigError {
@@ -246,6 +725,8 @@ impl ToString for ConfigError {
             Self::ReadonlyField => "READONLY_FIELD".to_string(),
             Self::FieldNotFound => "FIELD_NOT_FOUND".to_string(),
             Self::InvalidValue => "INVALID_VALUE".to_string(),
+            Self::NoConfigFile => "NO_CONFIG_FILE".to_string(),
+            Self::FileError => "FILE_ERROR".to_string(),
         }
     }
 }
@@ -265,5 +746,63 @@ mod tests {
             .set_field("migration_scan_count", "666")
             .unwrap();
         assert_eq!(cluster_config.migration_config.scan_count, 666);
+
+        cluster_config
+            .set_field("migration_max_bytes_per_sec", "1048576")
+            .unwrap();
+        assert_eq!(cluster_config.migration_config.max_bytes_per_sec, 1048576);
+        assert!(cluster_config
+            .set_field("migration_max_keys_per_sec", "-1")
+            .is_err());
+
+        assert_eq!(
+            cluster_config.migration_config.big_key_threshold,
+            DEFAULT_BIG_KEY_THRESHOLD
+        );
+        cluster_config
+            .set_field("migration_big_key_threshold", "0")
+            .unwrap();
+        assert_eq!(cluster_config.migration_config.big_key_threshold, 0);
+
+        cluster_config
+            .set_field("migration_scan_by_slot", "true")
+            .unwrap();
+        assert!(cluster_config.migration_config.scan_by_slot);
+        assert!(cluster_config
+            .set_field("migration_scan_by_slot", "1")
+            .is_err());
+
+        cluster_config
+            .set_field("migration_strategy", "migrate")
+            .unwrap();
+        assert_eq!(
+            cluster_config.migration_config.strategy,
+            MigrationStrategy::Migrate
+        );
+        let atomic_config =
+            AtomicMigrationConfig::from_config(cluster_config.migration_config.clone());
+        assert_eq!(atomic_config.get_strategy(), MigrationStrategy::Migrate);
+        assert!(cluster_config
+            .set_field("migration_strategy", "unknown")
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_prefixes() {
+        let mut cluster_config = ClusterConfig::default();
+        cluster_config
+            .set_field("key_prefixes", "service1:s1:, service2:s2")
+            .unwrap();
+        let key_prefixes = &cluster_config.key_prefixes;
+        assert_eq!(key_prefixes.to_str(), "service1:s1:,service2:s2");
+        assert_eq!(
+            key_prefixes.get_prefix("service1").unwrap().as_slice(),
+            b"s1:"
+        );
+        assert!(key_prefixes.get_prefix("service3").is_none());
+
+        for invalid in &["service1", "service1:", "service1:{s1}", "a:s*", "a:1,a:2"] {
+            assert!(cluster_config.set_field("key_prefixes", invalid).is_err());
+        }
     }
 }
@@ -2,11 +2,13 @@ pub mod atomic_lock;
 pub mod batch;
 pub mod biatomic;
 pub mod cluster;
+pub mod command_policy;
 pub mod config;
 pub mod future_group;
 pub mod proto;
 pub mod resp_execution;
 pub mod response;
+pub mod token_bucket;
 pub mod track;
 pub mod try_chunks;
 pub mod utils;
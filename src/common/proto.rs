@@ -599,6 +599,51 @@ mod tests {
             "mycluster",
             "migration_scan_count",
             "16",
+            "mycluster",
+            "migration_max_bytes_per_sec",
+            "0",
+            "mycluster",
+            "migration_max_keys_per_sec",
+            "0",
+            "mycluster",
+            "migration_big_key_threshold",
+            "16777216",
+            "mycluster",
+            "migration_big_key_count_threshold",
+            "100000",
+            "mycluster",
+            "migration_scan_by_slot",
+            "false",
+            "mycluster",
+            "migration_strategy",
+            "dump_restore",
+            "mycluster",
+            "rate_limit_ops_per_sec",
+            "0",
+            "mycluster",
+            "rate_limit_bytes_per_sec",
+            "0",
+            "mycluster",
+            "denied_commands",
+            "",
+            "mycluster",
+            "command_aliases",
+            "",
+            "mycluster",
+            "key_prefixes",
+            "",
+            "mycluster",
+            "read_cache_max_keys",
+            "0",
+            "mycluster",
+            "read_cache_ttl",
+            "1000",
+            "mycluster",
+            "read_cache_key_patterns",
+            "",
+            "mycluster",
+            "read_cache_hot_key_threshold",
+            "0",
             "othercluster",
             "compression_strategy",
             "disabled",
@@ -614,6 +659,51 @@ mod tests {
             "othercluster",
             "migration_scan_count",
             "16",
+            "othercluster",
+            "migration_max_bytes_per_sec",
+            "0",
+            "othercluster",
+            "migration_max_keys_per_sec",
+            "0",
+            "othercluster",
+            "migration_big_key_threshold",
+            "16777216",
+            "othercluster",
+            "migration_big_key_count_threshold",
+            "100000",
+            "othercluster",
+            "migration_scan_by_slot",
+            "false",
+            "othercluster",
+            "migration_strategy",
+            "dump_restore",
+            "othercluster",
+            "rate_limit_ops_per_sec",
+            "0",
+            "othercluster",
+            "rate_limit_bytes_per_sec",
+            "0",
+            "othercluster",
+            "denied_commands",
+            "",
+            "othercluster",
+            "command_aliases",
+            "",
+            "othercluster",
+            "key_prefixes",
+            "",
+            "othercluster",
+            "read_cache_max_keys",
+            "0",
+            "othercluster",
+            "read_cache_ttl",
+            "1000",
+            "othercluster",
+            "read_cache_key_patterns",
+            "",
+            "othercluster",
+            "read_cache_hot_key_threshold",
+            "0",
         ];
         result_args.sort();
         full_args.sort();
@@ -772,6 +862,51 @@ mod tests {
             "cluster_name",
             "migration_scan_count",
             "16",
+            "cluster_name",
+            "migration_max_bytes_per_sec",
+            "0",
+            "cluster_name",
+            "migration_max_keys_per_sec",
+            "0",
+            "cluster_name",
+            "migration_big_key_threshold",
+            "16777216",
+            "cluster_name",
+            "migration_big_key_count_threshold",
+            "100000",
+            "cluster_name",
+            "migration_scan_by_slot",
+            "false",
+            "cluster_name",
+            "migration_strategy",
+            "dump_restore",
+            "cluster_name",
+            "rate_limit_ops_per_sec",
+            "0",
+            "cluster_name",
+            "rate_limit_bytes_per_sec",
+            "0",
+            "cluster_name",
+            "denied_commands",
+            "",
+            "cluster_name",
+            "command_aliases",
+            "",
+            "cluster_name",
+            "key_prefixes",
+            "",
+            "cluster_name",
+            "read_cache_max_keys",
+            "0",
+            "cluster_name",
+            "read_cache_ttl",
+            "1000",
+            "cluster_name",
+            "read_cache_key_patterns",
+            "",
+            "cluster_name",
+            "read_cache_hot_key_threshold",
+            "0",
         ]
         .into_iter()
         .map(|s| s.to_string());
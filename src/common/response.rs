@@ -6,9 +6,19 @@ pub const TASK_NOT_FOUND: &str = "TASK_NOT_FOUND";
 pub const ERR_NOT_THE_SAME_SLOT: &str = "ERR_MULTI_SLOTS slots of the keys are not the same";
 pub const ERR_CLUSTER_NOT_FOUND: &str = "ERR_CLUSTER_NOT_FOUND";
 pub const ERR_BACKEND_CONNECTION: &str = "ERR_BACKEND_CONNECTION";
+pub const ERR_BACKEND_TIMEOUT: &str = "ERR_BACKEND_TIMEOUT";
+pub const ERR_BACKEND_CIRCUIT_OPEN: &str = "ERR_BACKEND_CIRCUIT_OPEN";
+pub const ERR_THROTTLED: &str = "ERR_THROTTLED";
+pub const ERR_COMMAND_DENIED: &str = "ERR_COMMAND_DENIED";
+pub const ERR_USER_NOT_FOUND: &str = "ERR_USER_NOT_FOUND";
+pub const ERR_KEY_PREFIX_GLOBAL_COMMAND: &str =
+    "ERR_KEY_PREFIX command on the whole database is not allowed with key prefix";
+pub const ERR_KEY_PREFIX_UNKNOWN_COMMAND: &str =
+    "ERR_KEY_PREFIX command with unknown keys is not allowed with key prefix";
 pub const ERR_MOVED: &str = "MOVED";
 pub const CMD_NOT_SUPPORTED: &str = "ERR_COMMAND_NOT_SUPPORTED";
 pub const ERR_TOO_MANY_REDIRECTIONS: &str = "ERR_TOO_MANY_REDIRECTIONS";
+pub const ERR_MAX_CLIENTS: &str = "ERR max number of clients reached";
 pub const MIGRATING_FINISHED: &str = "MIGRATING_FINISHED";
 pub const MIGRATION_TASK_NOT_FOUND: &str = "MIGRATION_TASK_NOT_FOUND";
 pub const ERR_MULTI_KEY_PARTIAL_ERROR: &str = "ERR_MULTI_KEY_PARTIAL_ERROR";
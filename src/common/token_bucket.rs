@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+// The rate is passed on each call so that it could be changed at runtime.
+// Consuming more tokens than the remaining ones is allowed
+// so that a large batch won't get stuck forever.
+// The debt needs to be paid by waiting before the next consumption.
+// At most one second of tokens could be accumulated.
+#[derive(Debug)]
+pub struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Default for TokenBucket {
+    fn default() -> Self {
+        Self::new(Instant::now())
+    }
+}
+
+impl TokenBucket {
+    fn new(now: Instant) -> Self {
+        Self {
+            tokens: 0.0,
+            last_refill: now,
+        }
+    }
+
+    // Starts with one second of tokens so that the first burst won't be limited.
+    pub fn with_full_tokens(rate: u64) -> Self {
+        Self {
+            tokens: rate as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    // Zero rate means no limit.
+    pub fn consume(&mut self, rate: u64, n: u64) {
+        self.consume_at(Instant::now(), rate, n)
+    }
+
+    // Returns how long the caller needs to wait before consuming again.
+    pub fn get_wait_time(&mut self, rate: u64) -> Duration {
+        self.get_wait_time_at(Instant::now(), rate)
+    }
+
+    fn consume_at(&mut self, now: Instant, rate: u64, n: u64) {
+        if rate == 0 {
+            return;
+        }
+        self.refill(now, rate);
+        self.tokens -= n as f64;
+    }
+
+    fn get_wait_time_at(&mut self, now: Instant, rate: u64) -> Duration {
+        if rate == 0 {
+            self.tokens = 0.0;
+            self.last_refill = now;
+            return Duration::from_secs(0);
+        }
+        self.refill(now, rate);
+        if self.tokens >= 0.0 {
+            return Duration::from_secs(0);
+        }
+        Duration::from_secs_f64(-self.tokens / rate as f64)
+    }
+
+    fn refill(&mut self, now: Instant, rate: u64) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.last_refill = now;
+        let rate = rate as f64;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(rate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(now);
+        assert_eq!(bucket.get_wait_time_at(now, 100), Duration::from_secs(0));
+
+        bucket.consume_at(now, 100, 50);
+        assert_eq!(
+            bucket.get_wait_time_at(now, 100),
+            Duration::from_millis(500)
+        );
+
+        let now = now + Duration::from_millis(500);
+        assert_eq!(bucket.get_wait_time_at(now, 100), Duration::from_secs(0));
+
+        // At most one second of tokens.
+        let now = now + Duration::from_secs(10);
+        bucket.consume_at(now, 100, 300);
+        assert_eq!(bucket.get_wait_time_at(now, 100), Duration::from_secs(2));
+
+        // No limit.
+        bucket.consume_at(now, 0, 1000);
+        assert_eq!(bucket.get_wait_time_at(now, 0), Duration::from_secs(0));
+        assert_eq!(bucket.get_wait_time_at(now, 100), Duration::from_secs(0));
+    }
+}
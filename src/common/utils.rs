@@ -255,6 +255,34 @@ pub fn byte_to_uppercase(b: u8) -> u8 {
     }
 }
 
+// Only supports `*` and `?`.
+pub fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // The positions to retry when the last `*` needs to match more bytes.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while i < s.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, i));
+                p += 1;
+            }
+            Some(c) if *c == b'?' || *c == s[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_i)) => {
+                    backtrack = Some((star_p, star_i + 1));
+                    p = star_p + 1;
+                    i = star_i + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
 pub struct RetryError<T> {
     inner: T,
 }
@@ -311,6 +339,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"user:*", b"user:1"));
+        assert!(glob_match(b"user:*", b"user:"));
+        assert!(glob_match(b"*:config", b"app:config"));
+        assert!(glob_match(b"a*b?c", b"axxbyc"));
+        assert!(glob_match(b"a*b*c", b"abbbc"));
+        assert!(glob_match(b"*", b""));
+        assert!(!glob_match(b"user:*", b"use"));
+        assert!(!glob_match(b"a?c", b"ac"));
+        assert!(!glob_match(b"a*b", b"acbc"));
+    }
+
     #[test]
     fn test_byte_to_uppercase() {
         assert_eq!(byte_to_uppercase(b'@'), b'@');
@@ -5,16 +5,15 @@ use crate::common::track::TrackedFutureRegistry;
 use crate::common::utils::resolve_first_address;
 use crate::protocol::{Array, BulkStr, Resp, RespPacket, RespVec};
 use crate::proxy::command::{new_command_pair, CmdType, Command, TaskReply};
-use crate::proxy::session::{handle_session, CmdHandler, CmdReplyFuture};
+use crate::proxy::session::{handle_session, CmdHandler, CmdReplyFuture, SessionConfig};
 use crate::proxy::slowlog::Slowlog;
-use futures::{FutureExt, StreamExt};
+use futures::{future, FutureExt, StreamExt};
 use std::num::NonZeroUsize;
 use std::str;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::net::TcpListener;
 
-const SESSION_CHANNEL_SIZE: usize = 1024;
 const SESSION_BATCH_MIN_TIME: usize = 0;
 const SESSION_BATCH_MAX_TIME: usize = 10000;
 const SESSION_BATCH_BUF: usize = 1;
@@ -78,10 +77,13 @@ impl ApiService {
                     future_registry.clone(),
                 )),
                 sock,
-                SESSION_CHANNEL_SIZE,
-                SESSION_BATCH_MIN_TIME,
-                SESSION_BATCH_MAX_TIME,
-                session_batch_buf,
+                SessionConfig {
+                    batch_min_time: SESSION_BATCH_MIN_TIME,
+                    batch_max_time: SESSION_BATCH_MAX_TIME,
+                    batch_buf: session_batch_buf,
+                    idle_timeout: None,
+                },
+                future::pending(),
             );
 
             let desc = format!("session: session_id={} peer={}", curr_session_id, peer);
@@ -229,5 +231,5 @@ impl CmdHandler for CoordCmdHandler {
         CmdReplyFuture::Left(reply_receiver)
     }
 
-    fn handle_slowlog(&self, _request: Box<RespPacket>, _slowlog: Slowlog) {}
+    fn handle_slowlog(&self, _request: Box<RespPacket>, _reply: &RespPacket, _slowlog: Slowlog) {}
 }
@@ -1,4 +1,7 @@
-use crate::common::cluster::{Cluster, ClusterName, MigrationTaskMeta, Proxy};
+use crate::common::cluster::{
+    Cluster, ClusterName, MigrationProgress, MigrationTaskMeta, NodeMemoryStats, NodeReplStats,
+    Proxy, SlotStats, SwitchoverTask,
+};
 use crate::common::utils::ThreadSafe;
 use futures::{Future, Stream};
 use mockall::automock;
@@ -46,6 +49,24 @@ mod trait_mod {
         fn get_failed_proxies<'s>(
             &'s self,
         ) -> Pin<Box<dyn Stream<Item = Result<String, MetaDataBrokerError>> + Send + 's>>;
+
+        fn add_node_failure<'s>(
+            &'s self,
+            node_address: String,
+            reporter_id: String,
+        ) -> Pin<Box<dyn Future<Output = Result<(), MetaDataBrokerError>> + Send + 's>>;
+
+        fn get_node_failures<'s>(
+            &'s self,
+        ) -> Pin<Box<dyn Stream<Item = Result<String, MetaDataBrokerError>> + Send + 's>>;
+
+        fn get_switchover_tasks<'s>(
+            &'s self,
+        ) -> Pin<Box<dyn Stream<Item = Result<SwitchoverTask, MetaDataBrokerError>> + Send + 's>>;
+
+        fn get_paused_migrations<'s>(
+            &'s self,
+        ) -> Pin<Box<dyn Stream<Item = Result<ClusterName, MetaDataBrokerError>> + Send + 's>>;
     }
 
     // Maybe we would want to support other database supporting redis protocol.
@@ -61,10 +82,44 @@ mod trait_mod {
             >,
         >;
 
+        fn failover_node<'s>(
+            &'s self,
+            failed_node_address: String,
+        ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>>;
+
         fn commit_migration<'s>(
             &'s self,
             meta: MigrationTaskMeta,
         ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>>;
+
+        fn commit_switchover<'s>(
+            &'s self,
+            proxy_address: String,
+        ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>>;
+
+        fn add_slot_stats<'s>(
+            &'s self,
+            proxy_address: String,
+            slot_stats: Vec<SlotStats>,
+        ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>>;
+
+        fn add_memory_stats<'s>(
+            &'s self,
+            proxy_address: String,
+            memory_stats: Vec<NodeMemoryStats>,
+        ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>>;
+
+        fn add_repl_stats<'s>(
+            &'s self,
+            proxy_address: String,
+            repl_stats: Vec<NodeReplStats>,
+        ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>>;
+
+        fn add_migration_stats<'s>(
+            &'s self,
+            proxy_address: String,
+            migration_stats: Vec<MigrationProgress>,
+        ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>>;
     }
 }
 
@@ -1,9 +1,10 @@
 use super::broker::{MetaDataBrokerError, MetaManipulationBrokerError};
-use crate::common::cluster::{MigrationTaskMeta, Proxy};
+use crate::common::cluster::{ClusterName, MigrationTaskMeta, Proxy, SlotRangeTag, SwitchoverTask};
 use crate::protocol::RedisClientError;
 use futures::{future, stream, Future, FutureExt, Stream, StreamExt, TryFutureExt};
 use futures_batch::ChunksTimeoutStreamExt;
 use mockall::automock;
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
 use std::io;
@@ -128,6 +129,119 @@ impl<T: ProxiesRetriever, C: FailureChecker, P: FailureReporter> FailureDetector
     }
 }
 
+pub trait NodeFailureChecker: Sync + Send + 'static {
+    // Returns the failed redis nodes behind the server proxy.
+    fn check<'s>(
+        &'s self,
+        proxy_address: String,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<String>, CoordinateError>> + Send + 's>>;
+}
+
+pub trait NodeFailureReporter: Sync + Send + 'static {
+    fn report<'s>(
+        &'s self,
+        node_address: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>>;
+}
+
+pub trait NodeFailureDetector {
+    type Retriever: ProxiesRetriever;
+    type Checker: NodeFailureChecker;
+    type Reporter: NodeFailureReporter;
+
+    fn new(retriever: Self::Retriever, checker: Self::Checker, reporter: Self::Reporter) -> Self;
+    fn run<'s>(&'s self) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>>;
+}
+
+pub struct ParNodeFailureDetector<
+    Retriever: ProxiesRetriever,
+    Checker: NodeFailureChecker,
+    Reporter: NodeFailureReporter,
+> {
+    retriever: Retriever,
+    checker: Arc<Checker>,
+    reporter: Arc<Reporter>,
+}
+
+impl<T: ProxiesRetriever, C: NodeFailureChecker, P: NodeFailureReporter>
+    ParNodeFailureDetector<T, C, P>
+{
+    async fn check_and_report(
+        checker: &C,
+        reporter: &P,
+        proxy_address: String,
+    ) -> Result<(), CoordinateError> {
+        let failed_nodes = checker.check(proxy_address).await?;
+        for node_address in failed_nodes.into_iter() {
+            if let Err(err) = reporter.report(node_address.clone()).await {
+                error!("failed to report node failure: {} {:?}", node_address, err);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    async fn run_impl(&self) -> Result<(), CoordinateError> {
+        let checker = self.checker.clone();
+        let reporter = self.reporter.clone();
+        const BATCH_SIZE: usize = 30;
+        const BATCH_TIME: Duration = Duration::from_millis(1);
+
+        let mut res = Ok(());
+        let mut s = self
+            .retriever
+            .retrieve_proxies()
+            .chunks_timeout(BATCH_SIZE, BATCH_TIME);
+
+        while let Some(results) = s.next().await {
+            let mut proxies = vec![];
+            for r in results {
+                match r {
+                    Ok(proxy) => proxies.push(proxy),
+                    Err(err) => {
+                        error!("failed to get proxy: {:?}", err);
+                        res = Err(err);
+                    }
+                }
+            }
+            let futs: Vec<_> = proxies
+                .into_iter()
+                .map(|address| Self::check_and_report(&checker, &reporter, address))
+                .collect();
+            let results = future::join_all(futs).await;
+            for r in results.into_iter() {
+                if let Err(err) = r {
+                    error!("failed to check and report node failures: {:?}", err);
+                    res = Err(err);
+                }
+            }
+        }
+        res
+    }
+}
+
+impl<T: ProxiesRetriever, C: NodeFailureChecker, P: NodeFailureReporter> NodeFailureDetector
+    for ParNodeFailureDetector<T, C, P>
+{
+    type Retriever = T;
+    type Checker = C;
+    type Reporter = P;
+
+    fn new(retriever: T, checker: C, reporter: P) -> Self {
+        Self {
+            retriever,
+            checker: Arc::new(checker),
+            reporter: Arc::new(reporter),
+        }
+    }
+
+    fn run<'s>(&'s self) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>> {
+        Box::pin(self.run_impl())
+    }
+}
+
+// The node failures are also handled by `ParFailureHandler`
+// with the failed node addresses instead of the proxy addresses.
 pub trait ProxyFailureRetriever: Sync + Send + 'static {
     fn retrieve_proxy_failures<'s>(
         &'s self,
@@ -357,6 +471,18 @@ pub trait MigrationStateChecker: Sync + Send + 'static {
         &'s self,
         address: String,
     ) -> Pin<Box<dyn Stream<Item = Result<MigrationTaskMeta, CoordinateError>> + Send + 's>>;
+    fn set_paused<'s>(
+        &'s self,
+        address: String,
+        cluster_name: ClusterName,
+        paused: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>>;
+}
+
+pub trait MigrationPauseRetriever: Sync + Send + 'static {
+    fn get_paused_clusters<'s>(
+        &'s self,
+    ) -> Pin<Box<dyn Stream<Item = Result<ClusterName, CoordinateError>> + Send + 's>>;
 }
 
 pub trait MigrationCommitter: Sync + Send + 'static {
@@ -372,6 +498,7 @@ pub trait MigrationStateSynchronizer: Sync + Send + 'static {
     type Committer: MigrationCommitter;
     type MRetriever: ProxyMetaRetriever;
     type Sender: ProxyMetaSender;
+    type PauseRetriever: MigrationPauseRetriever;
 
     fn new(
         proxy_retriever: Self::PRetriever,
@@ -379,6 +506,7 @@ pub trait MigrationStateSynchronizer: Sync + Send + 'static {
         committer: Self::Committer,
         meta_retriever: Self::MRetriever,
         sender: Self::Sender,
+        pause_retriever: Self::PauseRetriever,
     ) -> Self;
     fn run<'s>(&'s self) -> Pin<Box<dyn Stream<Item = Result<(), CoordinateError>> + Send + 's>>;
 }
@@ -389,12 +517,14 @@ pub struct ParMigrationStateSynchronizer<
     MC: MigrationCommitter,
     MR: ProxyMetaRetriever,
     S: ProxyMetaSender,
+    PM: MigrationPauseRetriever,
 > {
     proxy_retriever: PR,
     checker: Arc<SC>,
     committer: Arc<MC>,
     meta_retriever: Arc<MR>,
     sender: Arc<S>,
+    pause_retriever: PM,
 }
 
 impl<
@@ -403,7 +533,8 @@ impl<
         MC: MigrationCommitter,
         MR: ProxyMetaRetriever,
         S: ProxyMetaSender,
-    > ParMigrationStateSynchronizer<PR, SC, MC, MR, S>
+        PM: MigrationPauseRetriever,
+    > ParMigrationStateSynchronizer<PR, SC, MC, MR, S, PM>
 {
     async fn set_cluster_meta(
         address: String,
@@ -454,12 +585,44 @@ impl<
         Ok(())
     }
 
+    // Pause or resume the migrating tasks on the proxy
+    // according to the paused clusters in the broker.
+    async fn sync_paused_state(
+        checker: &SC,
+        meta_retriever: &MR,
+        address: String,
+        paused_clusters: &HashSet<ClusterName>,
+    ) -> Result<(), CoordinateError> {
+        let proxy = match meta_retriever.get_proxy_meta(address.clone()).await? {
+            Some(proxy) => proxy,
+            None => return Ok(()),
+        };
+        let migrating_clusters: HashSet<ClusterName> = proxy
+            .get_nodes()
+            .iter()
+            .filter(|node| {
+                node.get_slots()
+                    .iter()
+                    .any(|slot_range| matches!(slot_range.tag, SlotRangeTag::Migrating(_)))
+            })
+            .map(|node| node.get_cluster_name().clone())
+            .collect();
+        for cluster_name in migrating_clusters.into_iter() {
+            let paused = paused_clusters.contains(&cluster_name);
+            checker
+                .set_paused(address.clone(), cluster_name, paused)
+                .await?;
+        }
+        Ok(())
+    }
+
     async fn check_and_sync(
         checker: &SC,
         committer: &MC,
         meta_retriever: &MR,
         sender: &S,
         address: String,
+        paused_clusters: Option<&HashSet<ClusterName>>,
     ) -> Result<(), CoordinateError> {
         let mut s = checker.check(address.clone());
         while let Some(res) = s.next().await {
@@ -469,9 +632,21 @@ impl<
             };
             Self::sync_migration_state(committer, meta_retriever, sender, task_meta).await?;
         }
+        if let Some(paused_clusters) = paused_clusters {
+            Self::sync_paused_state(checker, meta_retriever, address, paused_clusters).await?;
+        }
         Ok(())
     }
 
+    async fn get_paused_clusters(&self) -> Result<HashSet<ClusterName>, CoordinateError> {
+        let mut paused_clusters = HashSet::new();
+        let mut s = self.pause_retriever.get_paused_clusters();
+        while let Some(res) = s.next().await {
+            paused_clusters.insert(res?);
+        }
+        Ok(paused_clusters)
+    }
+
     async fn run_impl(&self) -> Result<(), CoordinateError> {
         let checker = self.checker.clone();
         let committer = self.committer.clone();
@@ -482,6 +657,16 @@ impl<
         const BATCH_SIZE: Duration = Duration::from_millis(1);
 
         let mut res = Ok(());
+
+        // Skip pausing or resuming the migrations this time if the broker fails.
+        let paused_clusters = match self.get_paused_clusters().await {
+            Ok(paused_clusters) => Some(paused_clusters),
+            Err(err) => {
+                error!("failed to get paused migrations: {:?}", err);
+                res = Err(err);
+                None
+            }
+        };
         let mut s = self
             .proxy_retriever
             .retrieve_proxies()
@@ -500,7 +685,14 @@ impl<
             let futs: Vec<_> = proxies
                 .into_iter()
                 .map(|address| {
-                    Self::check_and_sync(&checker, &committer, &meta_retriever, &sender, address)
+                    Self::check_and_sync(
+                        &checker,
+                        &committer,
+                        &meta_retriever,
+                        &sender,
+                        address,
+                        paused_clusters.as_ref(),
+                    )
                 })
                 .collect();
             let results = future::join_all(futs).await;
@@ -521,13 +713,15 @@ impl<
         MC: MigrationCommitter,
         MR: ProxyMetaRetriever,
         S: ProxyMetaSender,
-    > MigrationStateSynchronizer for ParMigrationStateSynchronizer<PR, SC, MC, MR, S>
+        PM: MigrationPauseRetriever,
+    > MigrationStateSynchronizer for ParMigrationStateSynchronizer<PR, SC, MC, MR, S, PM>
 {
     type PRetriever = PR;
     type Checker = SC;
     type Committer = MC;
     type MRetriever = MR;
     type Sender = S;
+    type PauseRetriever = PM;
 
     fn new(
         proxy_retriever: Self::PRetriever,
@@ -535,6 +729,7 @@ impl<
         committer: Self::Committer,
         meta_retriever: Self::MRetriever,
         sender: Self::Sender,
+        pause_retriever: Self::PauseRetriever,
     ) -> Self {
         Self {
             proxy_retriever,
@@ -542,6 +737,7 @@ impl<
             committer: Arc::new(committer),
             meta_retriever: Arc::new(meta_retriever),
             sender: Arc::new(sender),
+            pause_retriever,
         }
     }
 
@@ -554,6 +750,295 @@ impl<
     }
 }
 
+pub trait SwitchoverTaskRetriever: Sync + Send + 'static {
+    fn retrieve_tasks<'s>(
+        &'s self,
+    ) -> Pin<Box<dyn Stream<Item = Result<SwitchoverTask, CoordinateError>> + Send + 's>>;
+}
+
+pub trait SwitchoverExecutor: Sync + Send + 'static {
+    fn execute<'s>(
+        &'s self,
+        task: SwitchoverTask,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>>;
+}
+
+pub trait SwitchoverCommitter: Sync + Send + 'static {
+    fn commit<'s>(
+        &'s self,
+        proxy_address: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>>;
+}
+
+pub trait SwitchoverHandler: Sync + Send + 'static {
+    type TRetriever: SwitchoverTaskRetriever;
+    type Executor: SwitchoverExecutor;
+    type Committer: SwitchoverCommitter;
+    type MRetriever: ProxyMetaRetriever;
+    type Sender: ProxyMetaSender;
+
+    fn new(
+        task_retriever: Self::TRetriever,
+        executor: Self::Executor,
+        committer: Self::Committer,
+        meta_retriever: Self::MRetriever,
+        sender: Self::Sender,
+    ) -> Self;
+    fn run<'s>(&'s self) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>>;
+}
+
+pub struct ParSwitchoverHandler<
+    TR: SwitchoverTaskRetriever,
+    E: SwitchoverExecutor,
+    C: SwitchoverCommitter,
+    MR: ProxyMetaRetriever,
+    S: ProxyMetaSender,
+> {
+    task_retriever: TR,
+    executor: Arc<E>,
+    committer: Arc<C>,
+    meta_retriever: Arc<MR>,
+    sender: Arc<S>,
+}
+
+impl<
+        TR: SwitchoverTaskRetriever,
+        E: SwitchoverExecutor,
+        C: SwitchoverCommitter,
+        MR: ProxyMetaRetriever,
+        S: ProxyMetaSender,
+    > ParSwitchoverHandler<TR, E, C, MR, S>
+{
+    async fn set_cluster_meta(
+        address: String,
+        meta_retriever: &MR,
+        sender: &S,
+    ) -> Result<(), CoordinateError> {
+        match meta_retriever.get_proxy_meta(address.clone()).await? {
+            Some(proxy) => sender.send_meta(proxy).await,
+            None => {
+                error!("proxy can't be found after switchover {}", address);
+                Ok(())
+            }
+        }
+    }
+
+    // The proxy keeps the writes paused until it receives the new metadata.
+    async fn switchover(
+        executor: &E,
+        committer: &C,
+        meta_retriever: &MR,
+        sender: &S,
+        task: SwitchoverTask,
+    ) -> Result<(), CoordinateError> {
+        let proxy_address = task.proxy_address.clone();
+        let mut replica_proxies: Vec<String> = task
+            .nodes
+            .iter()
+            .map(|node| node.replica_proxy_address.clone())
+            .collect();
+        replica_proxies.sort();
+        replica_proxies.dedup();
+
+        executor.execute(task).await?;
+        committer.commit(proxy_address.clone()).await?;
+
+        // Send to the new masters first to make sure the slots will always have owner.
+        for address in replica_proxies.into_iter() {
+            Self::set_cluster_meta(address, meta_retriever, sender).await?;
+        }
+        Self::set_cluster_meta(proxy_address, meta_retriever, sender).await
+    }
+
+    async fn run_impl(&self) -> Result<(), CoordinateError> {
+        let executor = self.executor.clone();
+        let committer = self.committer.clone();
+        let meta_retriever = self.meta_retriever.clone();
+        let sender = self.sender.clone();
+
+        let mut res = Ok(());
+        let mut tasks = vec![];
+        let mut s = self.task_retriever.retrieve_tasks();
+        while let Some(r) = s.next().await {
+            match r {
+                Ok(task) => tasks.push(task),
+                Err(err) => {
+                    error!("failed to get switchover task: {:?}", err);
+                    res = Err(err);
+                }
+            }
+        }
+
+        let futs: Vec<_> = tasks
+            .into_iter()
+            .map(|task| Self::switchover(&executor, &committer, &meta_retriever, &sender, task))
+            .collect();
+        let results = future::join_all(futs).await;
+        for r in results.into_iter() {
+            if let Err(err) = r {
+                error!("failed to switch over: {:?}", err);
+                res = Err(err);
+            }
+        }
+        res
+    }
+}
+
+impl<
+        TR: SwitchoverTaskRetriever,
+        E: SwitchoverExecutor,
+        C: SwitchoverCommitter,
+        MR: ProxyMetaRetriever,
+        S: ProxyMetaSender,
+    > SwitchoverHandler for ParSwitchoverHandler<TR, E, C, MR, S>
+{
+    type TRetriever = TR;
+    type Executor = E;
+    type Committer = C;
+    type MRetriever = MR;
+    type Sender = S;
+
+    fn new(
+        task_retriever: Self::TRetriever,
+        executor: Self::Executor,
+        committer: Self::Committer,
+        meta_retriever: Self::MRetriever,
+        sender: Self::Sender,
+    ) -> Self {
+        Self {
+            task_retriever,
+            executor: Arc::new(executor),
+            committer: Arc::new(committer),
+            meta_retriever: Arc::new(meta_retriever),
+            sender: Arc::new(sender),
+        }
+    }
+
+    fn run<'s>(&'s self) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>> {
+        Box::pin(self.run_impl())
+    }
+}
+
+pub type StatsFuture<'s, S> =
+    Pin<Box<dyn Future<Output = Result<Vec<S>, CoordinateError>> + Send + 's>>;
+
+// The stats are collected from every proxy and reported to the broker.
+pub trait StatsCollector: Sync + Send + 'static {
+    type Stats: Send + 'static;
+
+    fn collect<'s>(&'s self, address: String) -> StatsFuture<'s, Self::Stats>;
+}
+
+pub trait StatsReporter: Sync + Send + 'static {
+    type Stats: Send + 'static;
+
+    fn report<'s>(
+        &'s self,
+        address: String,
+        stats: Vec<Self::Stats>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>>;
+}
+
+pub trait StatsSynchronizer: Sync + Send + 'static {
+    type PRetriever: ProxiesRetriever;
+    type Collector: StatsCollector;
+    type Reporter: StatsReporter<Stats = <Self::Collector as StatsCollector>::Stats>;
+
+    fn new(
+        proxy_retriever: Self::PRetriever,
+        collector: Self::Collector,
+        reporter: Self::Reporter,
+    ) -> Self;
+    fn run<'s>(&'s self) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>>;
+}
+
+pub struct ParStatsSynchronizer<
+    PR: ProxiesRetriever,
+    C: StatsCollector,
+    R: StatsReporter<Stats = C::Stats>,
+> {
+    proxy_retriever: PR,
+    collector: Arc<C>,
+    reporter: Arc<R>,
+}
+
+impl<PR: ProxiesRetriever, C: StatsCollector, R: StatsReporter<Stats = C::Stats>>
+    ParStatsSynchronizer<PR, C, R>
+{
+    async fn collect_and_report(
+        collector: &C,
+        reporter: &R,
+        address: String,
+    ) -> Result<(), CoordinateError> {
+        let stats = collector.collect(address.clone()).await?;
+        if stats.is_empty() {
+            return Ok(());
+        }
+        if let Err(err) = reporter.report(address.clone(), stats).await {
+            error!("failed to report stats: {} {:?}", address, err);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    async fn run_impl(&self) -> Result<(), CoordinateError> {
+        let collector = self.collector.clone();
+        let reporter = self.reporter.clone();
+        const BATCH_SIZE: usize = 10;
+        const BATCH_TIME: Duration = Duration::from_millis(1);
+
+        let mut res = Ok(());
+        let mut s = self
+            .proxy_retriever
+            .retrieve_proxies()
+            .chunks_timeout(BATCH_SIZE, BATCH_TIME);
+        while let Some(results) = s.next().await {
+            let mut proxies = vec![];
+            for r in results {
+                match r {
+                    Ok(proxy) => proxies.push(proxy),
+                    Err(err) => {
+                        error!("failed to get proxy: {:?}", err);
+                        res = Err(err);
+                    }
+                }
+            }
+            let futs: Vec<_> = proxies
+                .into_iter()
+                .map(|address| Self::collect_and_report(&collector, &reporter, address))
+                .collect();
+            let results = future::join_all(futs).await;
+            for r in results.into_iter() {
+                if let Err(err) = r {
+                    error!("failed to collect and report stats: {:?}", err);
+                    res = Err(err);
+                }
+            }
+        }
+        res
+    }
+}
+
+impl<PR: ProxiesRetriever, C: StatsCollector, R: StatsReporter<Stats = C::Stats>> StatsSynchronizer
+    for ParStatsSynchronizer<PR, C, R>
+{
+    type PRetriever = PR;
+    type Collector = C;
+    type Reporter = R;
+
+    fn new(proxy_retriever: PR, collector: C, reporter: R) -> Self {
+        Self {
+            proxy_retriever,
+            collector: Arc::new(collector),
+            reporter: Arc::new(reporter),
+        }
+    }
+
+    fn run<'s>(&'s self) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>> {
+        Box::pin(self.run_impl())
+    }
+}
+
 #[derive(Debug)]
 pub enum CoordinateError {
     Io(io::Error),
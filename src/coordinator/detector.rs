@@ -1,12 +1,16 @@
 use super::broker::MetaDataBroker;
-use super::core::{CoordinateError, FailureChecker, FailureReporter, ProxiesRetriever};
+use super::core::{
+    CoordinateError, FailureChecker, FailureReporter, NodeFailureChecker, NodeFailureReporter,
+    ProxiesRetriever,
+};
 use crate::common::cluster::Cluster;
-use crate::protocol::{RedisClient, RedisClientFactory};
+use crate::protocol::{Array, BulkStr, RedisClient, RedisClientFactory, Resp, RespVec};
 use futures::{future, stream, Future, FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
 use futures_batch::ChunksTimeoutStreamExt;
 use std::cmp;
 use std::collections::HashSet;
 use std::pin::Pin;
+use std::str;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -297,18 +301,123 @@ impl<B: MetaDataBroker> FailureReporter for BrokerFailureReporter<B> {
     }
 }
 
+pub struct NodeHealthRespChecker<F: RedisClientFactory> {
+    client_factory: Arc<F>,
+}
+
+impl<F: RedisClientFactory> NodeHealthRespChecker<F> {
+    pub fn new(client_factory: Arc<F>) -> Self {
+        Self { client_factory }
+    }
+
+    fn parse_node_health(element: &RespVec) -> Option<(String, bool)> {
+        match element {
+            Resp::Arr(Array::Arr(arr)) => match arr.as_slice() {
+                [Resp::Bulk(BulkStr::Str(address)), Resp::Bulk(BulkStr::Str(state))] => {
+                    let address = str::from_utf8(address).ok()?.to_string();
+                    Some((address, state.as_slice() == b"ok"))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // The server proxy PINGs its backend redis nodes itself
+    // so that the coordinator doesn't need to connect to them.
+    async fn check_impl(&self, proxy_address: String) -> Result<Vec<String>, CoordinateError> {
+        let mut client = self
+            .client_factory
+            .create_client(proxy_address.clone())
+            .await
+            .map_err(CoordinateError::Redis)?;
+        let cmd = vec![b"UMCTL".to_vec(), b"NODEHEALTH".to_vec()];
+
+        let reply = client
+            .execute_single(cmd)
+            .await
+            .map_err(CoordinateError::Redis)?;
+
+        match reply {
+            Resp::Arr(Array::Arr(arr)) => {
+                let mut failed_nodes = vec![];
+                for element in arr.iter() {
+                    match Self::parse_node_health(element) {
+                        Some((_, true)) => (),
+                        Some((node_address, false)) => {
+                            error!(
+                                "node {} behind proxy {} failed",
+                                node_address, proxy_address
+                            );
+                            failed_nodes.push(node_address);
+                        }
+                        None => {
+                            error!(
+                                "failed to parse node health {} {:?}",
+                                proxy_address, element
+                            );
+                            return Err(CoordinateError::InvalidReply);
+                        }
+                    }
+                }
+                Ok(failed_nodes)
+            }
+            reply => {
+                error!("failed to send NODEHEALTH, invalid reply {:?}", reply);
+                Err(CoordinateError::InvalidReply)
+            }
+        }
+    }
+}
+
+impl<F: RedisClientFactory> NodeFailureChecker for NodeHealthRespChecker<F> {
+    fn check<'s>(
+        &'s self,
+        proxy_address: String,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<String>, CoordinateError>> + Send + 's>> {
+        Box::pin(self.check_impl(proxy_address))
+    }
+}
+
+pub struct BrokerNodeFailureReporter<B: MetaDataBroker> {
+    reporter_id: String,
+    meta_data_broker: Arc<B>,
+}
+
+impl<B: MetaDataBroker> BrokerNodeFailureReporter<B> {
+    pub fn new(reporter_id: String, meta_data_broker: Arc<B>) -> Self {
+        Self {
+            reporter_id,
+            meta_data_broker,
+        }
+    }
+}
+
+impl<B: MetaDataBroker> NodeFailureReporter for BrokerNodeFailureReporter<B> {
+    fn report<'s>(
+        &'s self,
+        node_address: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>> {
+        Box::pin(
+            self.meta_data_broker
+                .add_node_failure(node_address, self.reporter_id.clone())
+                .map_err(CoordinateError::MetaData),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::broker::{MetaDataBrokerError, MockMetaDataBroker};
-    use super::super::core::{FailureDetector, ParFailureDetector};
+    use super::super::core::{
+        FailureDetector, NodeFailureDetector, ParFailureDetector, ParNodeFailureDetector,
+    };
     use super::*;
     use crate::common::cluster::{
         ClusterName, MigrationMeta, Node, RangeList, ReplMeta, Role, SlotRange, SlotRangeTag,
     };
     use crate::common::config::ClusterConfig;
-    use crate::protocol::{
-        Array, BinSafeStr, OptionalMulti, RedisClient, RedisClientError, Resp, RespVec,
-    };
+    use crate::protocol::{BinSafeStr, OptionalMulti, RedisClientError};
     use futures::{future, stream, StreamExt};
     use std::convert::TryFrom;
     use std::pin::Pin;
@@ -595,4 +704,75 @@ mod tests {
         let res = detector.run().into_future().await;
         assert!(res.is_err());
     }
+
+    const REDIS1: &str = "127.0.0.1:6001";
+    const REDIS2: &str = "127.0.0.1:6002";
+
+    struct NodeHealthClient;
+
+    impl RedisClient for NodeHealthClient {
+        fn execute<'s>(
+            &'s mut self,
+            _command: OptionalMulti<Vec<BinSafeStr>>,
+        ) -> Pin<
+            Box<dyn Future<Output = Result<OptionalMulti<RespVec>, RedisClientError>> + Send + 's>,
+        > {
+            let gen_health = |address: &str, state: &str| {
+                Resp::Arr(Array::Arr(vec![
+                    Resp::Bulk(BulkStr::Str(address.as_bytes().to_vec())),
+                    Resp::Bulk(BulkStr::Str(state.as_bytes().to_vec())),
+                ]))
+            };
+            let reply = Resp::Arr(Array::Arr(vec![
+                gen_health(REDIS1, "ok"),
+                gen_health(REDIS2, "failed"),
+            ]));
+            Box::pin(future::ok(OptionalMulti::Single(reply)))
+        }
+    }
+
+    struct NodeHealthClientFactory;
+
+    impl RedisClientFactory for NodeHealthClientFactory {
+        type Client = NodeHealthClient;
+
+        fn create_client(
+            &self,
+            _address: String,
+        ) -> Pin<Box<dyn Future<Output = Result<Self::Client, RedisClientError>> + Send>> {
+            Box::pin(future::ok(NodeHealthClient))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_node_health_checker() {
+        let checker = NodeHealthRespChecker::new(Arc::new(NodeHealthClientFactory));
+        let failed_nodes = checker.check(NODE1.to_string()).await.unwrap();
+        assert_eq!(failed_nodes, vec![REDIS2.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_node_failure_detector() {
+        let mut mock_broker = MockMetaDataBroker::new();
+        mock_broker
+            .expect_get_proxy_addresses()
+            .returning(move || Box::pin(stream::iter(vec![Ok(NODE1.to_string())])));
+        mock_broker
+            .expect_get_failed_proxies()
+            .returning(|| Box::pin(stream::iter(vec![])));
+        mock_broker
+            .expect_add_node_failure()
+            .withf(|address: &String, _| address == REDIS2)
+            .times(1)
+            .returning(|_, _| Box::pin(future::ok(())));
+
+        let broker = Arc::new(mock_broker);
+        let retriever = BrokerProxiesRetriever::new(broker.clone());
+        let checker = NodeHealthRespChecker::new(Arc::new(NodeHealthClientFactory));
+        let reporter = BrokerNodeFailureReporter::new("test_id".to_string(), broker.clone());
+        let detector = ParNodeFailureDetector::new(retriever, checker, reporter);
+
+        let res = detector.run().into_future().await;
+        assert!(res.is_ok());
+    }
 }
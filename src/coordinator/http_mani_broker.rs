@@ -1,8 +1,11 @@
 use super::broker::{MetaManipulationBroker, MetaManipulationBrokerError};
 use super::service::BrokerAddresses;
 use crate::broker::MEM_BROKER_API_VERSION;
-use crate::common::cluster::{MigrationTaskMeta, Proxy};
+use crate::common::cluster::{
+    MigrationProgress, MigrationTaskMeta, NodeMemoryStats, NodeReplStats, Proxy, SlotStats,
+};
 use futures::Future;
+use serde::Serialize;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
@@ -71,6 +74,76 @@ impl HttpMetaManipulationBroker {
         }
     }
 
+    async fn failover_node_impl(
+        &self,
+        failed_node_address: String,
+    ) -> Result<(), MetaManipulationBrokerError> {
+        let url = self
+            .gen_url(&format!("/nodes/failover/{}", failed_node_address))
+            .ok_or_else(|| MetaManipulationBrokerError::NoBroker)?;
+        let response = self.client.post(&url).send().await.map_err(|e| {
+            error!("Failed to failover node {:?}", e);
+            MetaManipulationBrokerError::RequestFailed
+        })?;
+
+        let status = response.status();
+
+        if status.is_success() {
+            Ok(())
+        } else {
+            error!(
+                "failover_node: Failed to failover node: status code {:?}",
+                status
+            );
+            let result = response.text().await;
+            match result {
+                Ok(body) => {
+                    error!("failover_node: Error body: {:?}", body);
+                    Err(MetaManipulationBrokerError::InvalidReply)
+                }
+                Err(e) => {
+                    error!("failover_node: Failed to get body: {:?}", e);
+                    Err(MetaManipulationBrokerError::InvalidReply)
+                }
+            }
+        }
+    }
+
+    async fn commit_switchover_impl(
+        &self,
+        proxy_address: String,
+    ) -> Result<(), MetaManipulationBrokerError> {
+        let url = self
+            .gen_url(&format!("/proxies/switchover/commit/{}", proxy_address))
+            .ok_or_else(|| MetaManipulationBrokerError::NoBroker)?;
+        let response = self.client.post(&url).send().await.map_err(|e| {
+            error!("Failed to commit switchover {:?}", e);
+            MetaManipulationBrokerError::RequestFailed
+        })?;
+
+        let status = response.status();
+
+        if status.is_success() {
+            Ok(())
+        } else {
+            error!(
+                "commit_switchover: Failed to commit switchover: status code {:?}",
+                status
+            );
+            let result = response.text().await;
+            match result {
+                Ok(body) => {
+                    error!("commit_switchover: Error body: {:?}", body);
+                    Err(MetaManipulationBrokerError::InvalidReply)
+                }
+                Err(e) => {
+                    error!("commit_switchover: Failed to get body: {:?}", e);
+                    Err(MetaManipulationBrokerError::InvalidReply)
+                }
+            }
+        }
+    }
+
     async fn commit_migration_impl(
         &self,
         meta: MigrationTaskMeta,
@@ -115,6 +188,55 @@ impl HttpMetaManipulationBroker {
             }
         }
     }
+
+    // All the stats are posted to `/proxies/<stats_path>/<proxy_address>`.
+    async fn add_stats_impl<P: Serialize>(
+        &self,
+        stats_path: &'static str,
+        proxy_address: String,
+        payload: P,
+    ) -> Result<(), MetaManipulationBrokerError> {
+        let url = self
+            .gen_url(&format!("/proxies/{}/{}", stats_path, proxy_address))
+            .ok_or_else(|| MetaManipulationBrokerError::NoBroker)?;
+
+        let response = self
+            .client
+            .post(&url)
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| {
+                error!("Failed to add {} {:?}", stats_path, e);
+                MetaManipulationBrokerError::RequestFailed
+            })?;
+
+        let status = response.status();
+
+        // The proxy could have just been removed.
+        if status.is_success() || status.as_u16() == 404 {
+            Ok(())
+        } else {
+            error!("Failed to add {} status code {:?}", stats_path, status);
+            let result = response.text().await;
+            match result {
+                Ok(body) => {
+                    error!(
+                        "HttpMetaManipulationBroker::add_stats {} Error body: {:?}",
+                        stats_path, body
+                    );
+                    Err(MetaManipulationBrokerError::InvalidReply)
+                }
+                Err(e) => {
+                    error!(
+                        "HttpMetaManipulationBroker::add_stats {} Failed to get body: {:?}",
+                        stats_path, e
+                    );
+                    Err(MetaManipulationBrokerError::InvalidReply)
+                }
+            }
+        }
+    }
 }
 
 impl MetaManipulationBroker for HttpMetaManipulationBroker {
@@ -126,15 +248,89 @@ impl MetaManipulationBroker for HttpMetaManipulationBroker {
         Box::pin(self.replace_proxy_impl(failed_proxy_address))
     }
 
+    fn failover_node<'s>(
+        &'s self,
+        failed_node_address: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>> {
+        Box::pin(self.failover_node_impl(failed_node_address))
+    }
+
     fn commit_migration<'s>(
         &'s self,
         meta: MigrationTaskMeta,
     ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>> {
         Box::pin(self.commit_migration_impl(meta))
     }
+
+    fn commit_switchover<'s>(
+        &'s self,
+        proxy_address: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>> {
+        Box::pin(self.commit_switchover_impl(proxy_address))
+    }
+
+    fn add_slot_stats<'s>(
+        &'s self,
+        proxy_address: String,
+        slot_stats: Vec<SlotStats>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>> {
+        Box::pin(self.add_stats_impl("slot_stats", proxy_address, SlotStatsPayload { slot_stats }))
+    }
+
+    fn add_memory_stats<'s>(
+        &'s self,
+        proxy_address: String,
+        memory_stats: Vec<NodeMemoryStats>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>> {
+        Box::pin(self.add_stats_impl(
+            "memory_stats",
+            proxy_address,
+            MemoryStatsPayload { memory_stats },
+        ))
+    }
+
+    fn add_repl_stats<'s>(
+        &'s self,
+        proxy_address: String,
+        repl_stats: Vec<NodeReplStats>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>> {
+        Box::pin(self.add_stats_impl("repl_stats", proxy_address, ReplStatsPayload { repl_stats }))
+    }
+
+    fn add_migration_stats<'s>(
+        &'s self,
+        proxy_address: String,
+        migration_stats: Vec<MigrationProgress>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>> {
+        Box::pin(self.add_stats_impl(
+            "migration_stats",
+            proxy_address,
+            MigrationStatsPayload { migration_stats },
+        ))
+    }
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct ReplaceProxyResponse {
     pub proxy: Option<Proxy>,
 }
+
+#[derive(Deserialize, Serialize)]
+pub struct SlotStatsPayload {
+    pub slot_stats: Vec<SlotStats>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct MemoryStatsPayload {
+    pub memory_stats: Vec<NodeMemoryStats>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ReplStatsPayload {
+    pub repl_stats: Vec<NodeReplStats>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct MigrationStatsPayload {
+    pub migration_stats: Vec<MigrationProgress>,
+}
@@ -1,7 +1,7 @@
 use super::broker::{MetaDataBroker, MetaDataBrokerError};
 use super::service::BrokerAddresses;
 use crate::broker::MEM_BROKER_API_VERSION;
-use crate::common::cluster::{Cluster, ClusterName, Proxy};
+use crate::common::cluster::{Cluster, ClusterName, Proxy, SwitchoverTask};
 use crate::common::utils::vec_result_to_stream;
 use futures::{future, stream, Future, FutureExt, Stream, StreamExt};
 use serde_derive::Deserialize;
@@ -168,6 +168,81 @@ impl HttpMetaBroker {
         })?;
         Ok(addresses)
     }
+
+    async fn add_node_failure_impl(
+        &self,
+        node_address: String,
+        reporter_id: String,
+    ) -> Result<(), MetaDataBrokerError> {
+        let url = self
+            .gen_url(&format!("/nodes/failures/{}/{}", node_address, reporter_id))
+            .ok_or_else(|| MetaDataBrokerError::NoBroker)?;
+        let response = self.client.post(&url).send().await.map_err(|e| {
+            error!("failed to add node failures {:?}", e);
+            MetaDataBrokerError::RequestFailed
+        })?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            let result = response.text().await;
+            match result {
+                Err(e) => {
+                    error!("Failed to get body: {:?}", e);
+                    Err(MetaDataBrokerError::InvalidReply)
+                }
+                Ok(body) => {
+                    error!("Error body: {:?}", body);
+                    Err(MetaDataBrokerError::InvalidReply)
+                }
+            }
+        }
+    }
+
+    async fn get_node_failures_impl(&self) -> Result<Vec<String>, MetaDataBrokerError> {
+        let url = self
+            .gen_url("/nodes/failures")
+            .ok_or_else(|| MetaDataBrokerError::NoBroker)?;
+        let response = self.client.get(&url).send().await.map_err(|e| {
+            error!("Failed to get node failures {:?}", e);
+            MetaDataBrokerError::RequestFailed
+        })?;
+        let FailuresPayload { addresses } = response.json().await.map_err(|e| {
+            error!("Failed to get node failures from json {:?}", e);
+            MetaDataBrokerError::InvalidReply
+        })?;
+        Ok(addresses)
+    }
+
+    async fn get_paused_migrations_impl(&self) -> Result<Vec<ClusterName>, MetaDataBrokerError> {
+        let url = self
+            .gen_url("/clusters/migrations/paused")
+            .ok_or_else(|| MetaDataBrokerError::NoBroker)?;
+        let response = self.client.get(&url).send().await.map_err(|e| {
+            error!("Failed to get paused migrations {:?}", e);
+            MetaDataBrokerError::RequestFailed
+        })?;
+        let ClusterNamesPayload { names } = response.json().await.map_err(|e| {
+            error!("Failed to get paused migrations from json {:?}", e);
+            MetaDataBrokerError::InvalidReply
+        })?;
+        Ok(names)
+    }
+
+    async fn get_switchover_tasks_impl(&self) -> Result<Vec<SwitchoverTask>, MetaDataBrokerError> {
+        let url = self
+            .gen_url("/proxies/switchover")
+            .ok_or_else(|| MetaDataBrokerError::NoBroker)?;
+        let response = self.client.get(&url).send().await.map_err(|e| {
+            error!("Failed to get switchover tasks {:?}", e);
+            MetaDataBrokerError::RequestFailed
+        })?;
+        let SwitchoverTasksPayload { tasks } = response.json().await.map_err(|e| {
+            error!("Failed to get switchover tasks from json {:?}", e);
+            MetaDataBrokerError::InvalidReply
+        })?;
+        Ok(tasks)
+    }
 }
 
 impl MetaDataBroker for HttpMetaBroker {
@@ -247,6 +322,44 @@ impl MetaDataBroker for HttpMetaBroker {
                 .flatten_stream(),
         )
     }
+
+    fn add_node_failure<'s>(
+        &'s self,
+        node_address: String,
+        reporter_id: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaDataBrokerError>> + Send + 's>> {
+        Box::pin(self.add_node_failure_impl(node_address, reporter_id))
+    }
+
+    fn get_node_failures<'s>(
+        &'s self,
+    ) -> Pin<Box<dyn Stream<Item = Result<String, MetaDataBrokerError>> + Send + 's>> {
+        Box::pin(
+            self.get_node_failures_impl()
+                .map(vec_result_to_stream)
+                .flatten_stream(),
+        )
+    }
+
+    fn get_switchover_tasks<'s>(
+        &'s self,
+    ) -> Pin<Box<dyn Stream<Item = Result<SwitchoverTask, MetaDataBrokerError>> + Send + 's>> {
+        Box::pin(
+            self.get_switchover_tasks_impl()
+                .map(vec_result_to_stream)
+                .flatten_stream(),
+        )
+    }
+
+    fn get_paused_migrations<'s>(
+        &'s self,
+    ) -> Pin<Box<dyn Stream<Item = Result<ClusterName, MetaDataBrokerError>> + Send + 's>> {
+        Box::pin(
+            self.get_paused_migrations_impl()
+                .map(vec_result_to_stream)
+                .flatten_stream(),
+        )
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -274,6 +387,11 @@ pub struct FailuresPayload {
     pub addresses: Vec<String>,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct SwitchoverTasksPayload {
+    pub tasks: Vec<SwitchoverTask>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct FailedProxiesPayload {
     pub addresses: Vec<String>,
@@ -1,10 +1,12 @@
-use super::broker::MetaManipulationBroker;
-use super::core::{CoordinateError, MigrationCommitter, MigrationStateChecker};
-use crate::common::cluster::MigrationTaskMeta;
-use crate::common::utils::vec_result_to_stream;
+use super::broker::{MetaDataBroker, MetaManipulationBroker};
+use super::core::{
+    CoordinateError, MigrationCommitter, MigrationPauseRetriever, MigrationStateChecker,
+};
+use crate::common::cluster::{ClusterName, MigrationTaskMeta};
+use crate::common::utils::{pretty_print_bytes, vec_result_to_stream};
 use crate::protocol::{Array, BulkStr, Resp};
 use crate::protocol::{RedisClient, RedisClientFactory, RespVec};
-use futures::{Future, FutureExt, Stream, TryFutureExt};
+use futures::{Future, FutureExt, Stream, StreamExt, TryFutureExt};
 use std::pin::Pin;
 use std::str;
 use std::sync::Arc;
@@ -77,6 +79,45 @@ impl<F: RedisClientFactory> MigrationStateRespChecker<F> {
 
         Ok(migrations_task_meta)
     }
+
+    async fn set_paused_impl(
+        &self,
+        address: String,
+        cluster_name: ClusterName,
+        paused: bool,
+    ) -> Result<(), CoordinateError> {
+        let mut client = self
+            .client_factory
+            .create_client(address.clone())
+            .await
+            .map_err(CoordinateError::Redis)?;
+        let sub_cmd = if paused { "PAUSEMGR" } else { "RESUMEMGR" };
+        let cmd = vec![
+            "UMCTL".to_string(),
+            sub_cmd.to_string(),
+            cluster_name.to_string(),
+        ]
+        .into_iter()
+        .map(String::into_bytes)
+        .collect();
+
+        let reply = client
+            .execute_single(cmd)
+            .await
+            .map_err(CoordinateError::Redis)?;
+        match reply {
+            Resp::Error(err) => {
+                error!(
+                    "failed to send {} to {}: {}",
+                    sub_cmd,
+                    address,
+                    pretty_print_bytes(&err)
+                );
+                Err(CoordinateError::InvalidReply)
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 impl<F: RedisClientFactory> MigrationStateChecker for MigrationStateRespChecker<F> {
@@ -90,6 +131,37 @@ impl<F: RedisClientFactory> MigrationStateChecker for MigrationStateRespChecker<
                 .flatten_stream(),
         )
     }
+
+    fn set_paused<'s>(
+        &'s self,
+        address: String,
+        cluster_name: ClusterName,
+        paused: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>> {
+        Box::pin(self.set_paused_impl(address, cluster_name, paused))
+    }
+}
+
+pub struct BrokerMigrationPauseRetriever<DB: MetaDataBroker> {
+    data_broker: Arc<DB>,
+}
+
+impl<DB: MetaDataBroker> BrokerMigrationPauseRetriever<DB> {
+    pub fn new(data_broker: Arc<DB>) -> Self {
+        Self { data_broker }
+    }
+}
+
+impl<DB: MetaDataBroker> MigrationPauseRetriever for BrokerMigrationPauseRetriever<DB> {
+    fn get_paused_clusters<'s>(
+        &'s self,
+    ) -> Pin<Box<dyn Stream<Item = Result<ClusterName, CoordinateError>> + Send + 's>> {
+        Box::pin(
+            self.data_broker
+                .get_paused_migrations()
+                .map(|res| res.map_err(CoordinateError::MetaData)),
+        )
+    }
 }
 
 pub struct BrokerMigrationCommitter<MB: MetaManipulationBroker> {
@@ -254,6 +326,9 @@ mod tests {
         mock_data_broker
             .expect_get_failed_proxies()
             .returning(|| Box::pin(stream::iter(vec![])));
+        mock_data_broker
+            .expect_get_paused_migrations()
+            .returning(|| Box::pin(stream::iter(vec![])));
         mock_data_broker
             .expect_get_proxy()
             .withf(|proxy_addr| proxy_addr == "127.0.0.1:6000")
@@ -267,6 +342,7 @@ mod tests {
         let proxies_retriever = BrokerProxiesRetriever::new(mock_data_broker.clone());
 
         let committer = BrokerMigrationCommitter::new(mock_mani_broker.clone());
+        let pause_retriever = BrokerMigrationPauseRetriever::new(mock_data_broker.clone());
         let meta_retriever = BrokerMetaRetriever::new(mock_data_broker);
 
         let mut mock_meta_sender = MockProxyMetaSender::new();
@@ -287,6 +363,7 @@ mod tests {
             committer,
             meta_retriever,
             mock_meta_sender,
+            pause_retriever,
         );
         let res: Vec<_> = sync.run().collect().await;
         assert_eq!(res.len(), 1);
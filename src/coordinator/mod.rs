@@ -9,4 +9,6 @@ pub mod http_meta_broker;
 mod migration;
 mod recover;
 pub mod service;
+mod stats;
+mod switchover;
 mod sync;
@@ -59,6 +59,58 @@ impl<MB: MetaManipulationBroker> ProxyFailureHandler for ReplaceNodeHandler<MB>
     }
 }
 
+pub struct BrokerNodeFailureRetriever<B: MetaDataBroker> {
+    broker: Arc<B>,
+}
+
+impl<B: MetaDataBroker> BrokerNodeFailureRetriever<B> {
+    pub fn new(broker: Arc<B>) -> Self {
+        Self { broker }
+    }
+}
+
+impl<B: MetaDataBroker> ProxyFailureRetriever for BrokerNodeFailureRetriever<B> {
+    fn retrieve_proxy_failures<'s>(
+        &'s self,
+    ) -> Pin<Box<dyn Stream<Item = Result<String, CoordinateError>> + Send + 's>> {
+        Box::pin(
+            self.broker
+                .get_node_failures()
+                .map_err(CoordinateError::MetaData),
+        )
+    }
+}
+
+pub struct NodeFailoverHandler<MB: MetaManipulationBroker> {
+    mani_broker: Arc<MB>,
+}
+
+impl<MB: MetaManipulationBroker> NodeFailoverHandler<MB> {
+    pub fn new(mani_broker: Arc<MB>) -> Self {
+        Self { mani_broker }
+    }
+}
+
+impl<MB: MetaManipulationBroker> ProxyFailureHandler for NodeFailoverHandler<MB> {
+    fn handle_proxy_failure<'s>(
+        &'s self,
+        node_failure: ProxyFailure,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>> {
+        let node_failure2 = node_failure.clone();
+        Box::pin(
+            self.mani_broker
+                .failover_node(node_failure.clone())
+                .map_err(move |e| {
+                    error!("failed to failover node {} {:?}", node_failure2, e);
+                    CoordinateError::MetaMani(e)
+                })
+                .map_ok(move |()| {
+                    info!("successfully failover node {}", node_failure);
+                }),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::broker::{MockMetaDataBroker, MockMetaManipulationBroker};
@@ -138,4 +190,30 @@ mod tests {
         assert_eq!(res.len(), 1);
         assert!(res[0].is_ok());
     }
+
+    #[tokio::test]
+    async fn test_node_failure_handler() {
+        let failure = "127.0.0.1:7001";
+        let mut mock_data_broker = MockMetaDataBroker::new();
+        mock_data_broker
+            .expect_get_node_failures()
+            .times(1)
+            .returning(move || Box::pin(stream::iter(vec![Ok(failure.to_string())])));
+        let mock_data_broker = Arc::new(mock_data_broker);
+
+        let mut mock_mani_broker = MockMetaManipulationBroker::new();
+        mock_mani_broker
+            .expect_failover_node()
+            .withf(move |f| f == failure)
+            .times(1)
+            .returning(move |_| Box::pin(async { Ok(()) }));
+        let mock_mani_broker = Arc::new(mock_mani_broker);
+
+        let retriever = BrokerNodeFailureRetriever::new(mock_data_broker);
+        let handler = NodeFailoverHandler::new(mock_mani_broker);
+        let failure_handler = ParFailureHandler::new(retriever, handler);
+        let res: Vec<_> = failure_handler.run().collect().await;
+        assert_eq!(res.len(), 1);
+        assert!(res[0].is_ok());
+    }
 }
@@ -2,16 +2,29 @@ use super::api::ApiService;
 use super::broker::{MetaDataBroker, MetaManipulationBroker};
 use super::core::{
     CoordinateError, FailureDetector, FailureHandler, MigrationStateSynchronizer,
-    ParFailureDetector, ParFailureHandler, ParMigrationStateSynchronizer,
-    ProxyMetaRespSynchronizer, ProxyMetaSynchronizer,
+    NodeFailureDetector, ParFailureDetector, ParFailureHandler, ParMigrationStateSynchronizer,
+    ParNodeFailureDetector, ParStatsSynchronizer, ParSwitchoverHandler, ProxyMetaRespSynchronizer,
+    ProxyMetaSynchronizer, StatsCollector, StatsSynchronizer, SwitchoverHandler,
 };
 use super::detector::{
-    BrokerFailureReporter, BrokerOrderedProxiesRetriever, BrokerProxiesRetriever,
-    PingFailureDetector,
+    BrokerFailureReporter, BrokerNodeFailureReporter, BrokerOrderedProxiesRetriever,
+    BrokerProxiesRetriever, NodeHealthRespChecker, PingFailureDetector,
+};
+use super::migration::{
+    BrokerMigrationCommitter, BrokerMigrationPauseRetriever, MigrationStateRespChecker,
+};
+use super::recover::{
+    BrokerNodeFailureRetriever, BrokerProxyFailureRetriever, NodeFailoverHandler,
+    ReplaceNodeHandler,
+};
+use super::stats::{
+    BrokerStats, BrokerStatsReporter, MemoryStatsRespCollector, ProxyStatsRespCollector,
+};
+use super::switchover::{
+    BrokerSwitchoverCommitter, BrokerSwitchoverTaskRetriever, SwitchoverRespExecutor,
 };
-use super::migration::{BrokerMigrationCommitter, MigrationStateRespChecker};
-use super::recover::{BrokerProxyFailureRetriever, ReplaceNodeHandler};
 use super::sync::{BrokerMetaRetriever, ProxyMetaRespSender};
+use crate::common::cluster::{MigrationProgress, NodeReplStats, SlotStats};
 use crate::common::utils::ThreadSafe;
 use crate::protocol::RedisClientFactory;
 use arc_swap::ArcSwap;
@@ -24,6 +37,16 @@ use std::time::Duration;
 
 pub type BrokerAddresses = Arc<ArcSwap<Vec<String>>>;
 
+// The proxies keep accumulating the slot stats
+// so we don't need to collect them as frequently as the other tasks.
+const SLOT_STATS_SYNC_INTERVAL: u64 = 10;
+// The memory usage changes slowly and the broker only needs it for scaling.
+const MEMORY_STATS_SYNC_INTERVAL: u64 = 10;
+// The broker compares the offsets on failover so they need to be fresh.
+const REPL_STATS_SYNC_INTERVAL: u64 = 1;
+// Only used for showing the migration progress.
+const MIGRATION_STATS_SYNC_INTERVAL: u64 = 5;
+
 #[derive(Debug, Clone)]
 pub struct CoordinatorConfig {
     pub address: String,
@@ -79,11 +102,37 @@ impl<DB: MetaDataBroker + ThreadSafe, MB: MetaManipulationBroker, F: RedisClient
     pub async fn run(&self) -> Result<(), CoordinateError> {
         info!("coordinator config: {:?}", self.config);
 
+        let client_factory = &self.client_factory;
+        let data_broker = &self.data_broker;
+
         let futs: Vec<Pin<Box<dyn Future<Output = CoordResult> + Send>>> = vec![
             Box::pin(self.loop_detect()),
             Box::pin(self.loop_proxy_sync()),
             Box::pin(self.loop_failure_handler()),
+            Box::pin(self.loop_node_detect()),
+            Box::pin(self.loop_node_failure_handler()),
             Box::pin(self.loop_migration_sync()),
+            Box::pin(self.loop_switchover()),
+            Box::pin(
+                self.loop_stats_sync("slot stats", SLOT_STATS_SYNC_INTERVAL, || {
+                    ProxyStatsRespCollector::<F, SlotStats>::new(client_factory.clone())
+                }),
+            ),
+            Box::pin(
+                self.loop_stats_sync("memory stats", MEMORY_STATS_SYNC_INTERVAL, || {
+                    MemoryStatsRespCollector::new(client_factory.clone(), data_broker.clone())
+                }),
+            ),
+            Box::pin(
+                self.loop_stats_sync("replication stats", REPL_STATS_SYNC_INTERVAL, || {
+                    ProxyStatsRespCollector::<F, NodeReplStats>::new(client_factory.clone())
+                }),
+            ),
+            Box::pin(self.loop_stats_sync(
+                "migration stats",
+                MIGRATION_STATS_SYNC_INTERVAL,
+                || ProxyStatsRespCollector::<F, MigrationProgress>::new(client_factory.clone()),
+            )),
             Box::pin(self.api_service.run()),
         ];
 
@@ -103,6 +152,17 @@ impl<DB: MetaDataBroker + ThreadSafe, MB: MetaManipulationBroker, F: RedisClient
         ParFailureDetector::new(retriever, checker, reporter)
     }
 
+    fn gen_node_detector(
+        reporter_id: String,
+        data_broker: Arc<DB>,
+        client_factory: Arc<F>,
+    ) -> impl NodeFailureDetector {
+        let retriever = BrokerProxiesRetriever::new(data_broker.clone());
+        let checker = NodeHealthRespChecker::new(client_factory);
+        let reporter = BrokerNodeFailureReporter::new(reporter_id, data_broker);
+        ParNodeFailureDetector::new(retriever, checker, reporter)
+    }
+
     fn gen_proxy_meta_synchronizer(
         data_broker: Arc<DB>,
         client_factory: Arc<F>,
@@ -119,6 +179,12 @@ impl<DB: MetaDataBroker + ThreadSafe, MB: MetaManipulationBroker, F: RedisClient
         ParFailureHandler::new(proxy_retriever, handler)
     }
 
+    fn gen_node_failure_handler(data_broker: Arc<DB>, mani_broker: Arc<MB>) -> impl FailureHandler {
+        let node_retriever = BrokerNodeFailureRetriever::new(data_broker);
+        let handler = NodeFailoverHandler::new(mani_broker);
+        ParFailureHandler::new(node_retriever, handler)
+    }
+
     fn gen_migration_state_synchronizer(
         data_broker: Arc<DB>,
         mani_broker: Arc<MB>,
@@ -127,6 +193,7 @@ impl<DB: MetaDataBroker + ThreadSafe, MB: MetaManipulationBroker, F: RedisClient
         let proxy_retriever = BrokerProxiesRetriever::new(data_broker.clone());
         let checker = MigrationStateRespChecker::new(client_factory.clone());
         let committer = BrokerMigrationCommitter::new(mani_broker);
+        let pause_retriever = BrokerMigrationPauseRetriever::new(data_broker.clone());
         let meta_retriever = BrokerMetaRetriever::new(data_broker);
         let sender = ProxyMetaRespSender::new(client_factory);
         ParMigrationStateSynchronizer::new(
@@ -135,9 +202,36 @@ impl<DB: MetaDataBroker + ThreadSafe, MB: MetaManipulationBroker, F: RedisClient
             committer,
             meta_retriever,
             sender,
+            pause_retriever,
         )
     }
 
+    fn gen_switchover_handler(
+        data_broker: Arc<DB>,
+        mani_broker: Arc<MB>,
+        client_factory: Arc<F>,
+    ) -> impl SwitchoverHandler {
+        let task_retriever = BrokerSwitchoverTaskRetriever::new(data_broker.clone());
+        let executor = SwitchoverRespExecutor::new(client_factory.clone());
+        let committer = BrokerSwitchoverCommitter::new(mani_broker);
+        let meta_retriever = BrokerMetaRetriever::new(data_broker);
+        let sender = ProxyMetaRespSender::new(client_factory);
+        ParSwitchoverHandler::new(task_retriever, executor, committer, meta_retriever, sender)
+    }
+
+    fn gen_stats_synchronizer<C: StatsCollector>(
+        data_broker: Arc<DB>,
+        mani_broker: Arc<MB>,
+        collector: C,
+    ) -> impl StatsSynchronizer
+    where
+        C::Stats: BrokerStats,
+    {
+        let proxy_retriever = BrokerProxiesRetriever::new(data_broker);
+        let reporter = BrokerStatsReporter::<MB, C::Stats>::new(mani_broker);
+        ParStatsSynchronizer::new(proxy_retriever, collector, reporter)
+    }
+
     async fn loop_detect(&self) -> Result<(), CoordinateError> {
         let data_broker = self.data_broker.clone();
         let client_factory = self.client_factory.clone();
@@ -194,6 +288,44 @@ impl<DB: MetaDataBroker + ThreadSafe, MB: MetaManipulationBroker, F: RedisClient
         }
     }
 
+    async fn loop_node_detect(&self) -> Result<(), CoordinateError> {
+        let data_broker = self.data_broker.clone();
+        let client_factory = self.client_factory.clone();
+        let reporter_id = self.config.reporter_id.clone();
+        loop {
+            trace!("start detecting node failures");
+            defer!(trace!("detecting node failures finished a round"));
+            if let Err(e) = Self::gen_node_detector(
+                reporter_id.clone(),
+                data_broker.clone(),
+                client_factory.clone(),
+            )
+            .run()
+            .await
+            {
+                error!("node detector stream err {:?}", e);
+            }
+            Delay::new(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn loop_node_failure_handler(&self) -> Result<(), CoordinateError> {
+        let data_broker = self.data_broker.clone();
+        let mani_broker = self.mani_broker.clone();
+        loop {
+            trace!("start handling node failures");
+            defer!(trace!("handling node failures finished a round"));
+            let handler = Self::gen_node_failure_handler(data_broker.clone(), mani_broker.clone());
+            let mut s = handler.run();
+            while let Some(r) = s.next().await {
+                if let Err(e) = r {
+                    error!("node failure handler stream err {:?}", e)
+                }
+            }
+            Delay::new(Duration::from_secs(1)).await;
+        }
+    }
+
     async fn loop_migration_sync(&self) -> Result<(), CoordinateError> {
         let data_broker = self.data_broker.clone();
         let mani_broker = self.mani_broker.clone();
@@ -215,4 +347,54 @@ impl<DB: MetaDataBroker + ThreadSafe, MB: MetaManipulationBroker, F: RedisClient
             Delay::new(Duration::from_secs(1)).await;
         }
     }
+    async fn loop_switchover(&self) -> Result<(), CoordinateError> {
+        let data_broker = self.data_broker.clone();
+        let mani_broker = self.mani_broker.clone();
+        let client_factory = self.client_factory.clone();
+        loop {
+            trace!("start handling switchover");
+            defer!(trace!("handling switchover finished a round"));
+            if let Err(e) = Self::gen_switchover_handler(
+                data_broker.clone(),
+                mani_broker.clone(),
+                client_factory.clone(),
+            )
+            .run()
+            .await
+            {
+                error!("switchover err {:?}", e);
+            }
+            Delay::new(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn loop_stats_sync<C, G>(
+        &self,
+        name: &str,
+        interval: u64,
+        gen_collector: G,
+    ) -> CoordResult
+    where
+        C: StatsCollector,
+        C::Stats: BrokerStats,
+        G: Fn() -> C,
+    {
+        let data_broker = self.data_broker.clone();
+        let mani_broker = self.mani_broker.clone();
+        loop {
+            trace!("start syncing {}", name);
+            defer!(trace!("{} sync finished a round", name));
+            if let Err(e) = Self::gen_stats_synchronizer(
+                data_broker.clone(),
+                mani_broker.clone(),
+                gen_collector(),
+            )
+            .run()
+            .await
+            {
+                error!("{} sync err {:?}", name, e);
+            }
+            Delay::new(Duration::from_secs(interval)).await;
+        }
+    }
 }
@@ -0,0 +1,647 @@
+use super::broker::{MetaDataBroker, MetaManipulationBroker, MetaManipulationBrokerError};
+use super::core::{CoordinateError, StatsCollector, StatsFuture, StatsReporter};
+use crate::common::cluster::{MigrationProgress, NodeMemoryStats, NodeReplStats, Role, SlotStats};
+use crate::protocol::{Array, BinSafeStr, BulkStr, RedisClient, RedisClientFactory, Resp, RespVec};
+use futures::{future, Future, TryFutureExt};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::str;
+use std::sync::Arc;
+
+// The stats retrieved from the server proxies by a `UMCTL` command.
+pub trait ProxyStats: Sized + Send + Sync + 'static {
+    fn get_command() -> Vec<BinSafeStr>;
+    fn parse_reply(address: &str, reply: RespVec) -> Result<Vec<Self>, CoordinateError>;
+}
+
+// The stats which could be sent to the broker.
+pub trait BrokerStats: Sized + Send + Sync + 'static {
+    fn add_to_broker<'s, MB: MetaManipulationBroker>(
+        mani_broker: &'s MB,
+        address: String,
+        stats: Vec<Self>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>>;
+}
+
+pub struct ProxyStatsRespCollector<F: RedisClientFactory, S: ProxyStats> {
+    client_factory: Arc<F>,
+    phantom: PhantomData<S>,
+}
+
+impl<F: RedisClientFactory, S: ProxyStats> ProxyStatsRespCollector<F, S> {
+    pub fn new(client_factory: Arc<F>) -> Self {
+        Self {
+            client_factory,
+            phantom: PhantomData,
+        }
+    }
+
+    async fn collect_impl(&self, address: String) -> Result<Vec<S>, CoordinateError> {
+        let mut client = self
+            .client_factory
+            .create_client(address.clone())
+            .await
+            .map_err(CoordinateError::Redis)?;
+        let reply = client
+            .execute_single(S::get_command())
+            .await
+            .map_err(CoordinateError::Redis)?;
+        S::parse_reply(&address, reply)
+    }
+}
+
+impl<F: RedisClientFactory, S: ProxyStats> StatsCollector for ProxyStatsRespCollector<F, S> {
+    type Stats = S;
+
+    fn collect<'s>(&'s self, address: String) -> StatsFuture<'s, S> {
+        Box::pin(self.collect_impl(address))
+    }
+}
+
+pub struct BrokerStatsReporter<MB: MetaManipulationBroker, S: BrokerStats> {
+    mani_broker: Arc<MB>,
+    phantom: PhantomData<S>,
+}
+
+impl<MB: MetaManipulationBroker, S: BrokerStats> BrokerStatsReporter<MB, S> {
+    pub fn new(mani_broker: Arc<MB>) -> Self {
+        Self {
+            mani_broker,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<MB: MetaManipulationBroker, S: BrokerStats> StatsReporter for BrokerStatsReporter<MB, S> {
+    type Stats = S;
+
+    fn report<'s>(
+        &'s self,
+        address: String,
+        stats: Vec<S>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>> {
+        Box::pin(
+            S::add_to_broker(self.mani_broker.as_ref(), address, stats)
+                .map_err(CoordinateError::MetaMani),
+        )
+    }
+}
+
+// Parses the replies consisting of bulk strings with space separated fields.
+fn parse_string_array<T, P>(
+    address: &str,
+    reply: RespVec,
+    parse: P,
+) -> Result<Vec<T>, CoordinateError>
+where
+    P: Fn(&mut dyn Iterator<Item = String>) -> Option<T>,
+{
+    let arr = match reply {
+        Resp::Arr(Array::Arr(arr)) => arr,
+        reply => {
+            error!(
+                "failed to get stats from {}, invalid reply {:?}",
+                address, reply
+            );
+            return Err(CoordinateError::InvalidReply);
+        }
+    };
+    let mut stats = vec![];
+    for element in arr.iter() {
+        let parsed = match element {
+            Resp::Bulk(BulkStr::Str(s)) => str::from_utf8(s)
+                .ok()
+                .and_then(|data| parse(&mut data.split(' ').map(ToString::to_string))),
+            _ => None,
+        };
+        match parsed {
+            Some(s) => stats.push(s),
+            None => {
+                error!("failed to parse stats {} {:?}", address, element);
+                return Err(CoordinateError::InvalidReply);
+            }
+        }
+    }
+    Ok(stats)
+}
+
+// Take the increments so that multiple coordinators won't report the same traffic twice.
+impl ProxyStats for SlotStats {
+    fn get_command() -> Vec<BinSafeStr> {
+        vec![b"UMCTL".to_vec(), b"SLOTSTATS".to_vec(), b"TAKE".to_vec()]
+    }
+
+    fn parse_reply(address: &str, reply: RespVec) -> Result<Vec<Self>, CoordinateError> {
+        parse_string_array(address, reply, |mut it| SlotStats::from_strings(&mut it))
+    }
+}
+
+impl BrokerStats for SlotStats {
+    fn add_to_broker<'s, MB: MetaManipulationBroker>(
+        mani_broker: &'s MB,
+        address: String,
+        stats: Vec<Self>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>> {
+        mani_broker.add_slot_stats(address, stats)
+    }
+}
+
+impl ProxyStats for MigrationProgress {
+    fn get_command() -> Vec<BinSafeStr> {
+        vec![b"UMCTL".to_vec(), b"INFOMGR".to_vec(), b"PROGRESS".to_vec()]
+    }
+
+    fn parse_reply(address: &str, reply: RespVec) -> Result<Vec<Self>, CoordinateError> {
+        parse_string_array(address, reply, |mut it| {
+            MigrationProgress::from_strings(&mut it)
+        })
+    }
+}
+
+impl BrokerStats for MigrationProgress {
+    fn add_to_broker<'s, MB: MetaManipulationBroker>(
+        mani_broker: &'s MB,
+        address: String,
+        stats: Vec<Self>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>> {
+        mani_broker.add_migration_stats(address, stats)
+    }
+}
+
+// Each element is an array of lines like `node_address:127.0.0.1:7000\n`.
+fn parse_repl_stats(element: &RespVec) -> Option<NodeReplStats> {
+    let lines = match element {
+        Resp::Arr(Array::Arr(lines)) => lines,
+        _ => return None,
+    };
+    let mut node_address = None;
+    let mut repl_offset = None;
+    for line in lines.iter() {
+        let line = match line {
+            Resp::Bulk(BulkStr::Str(s)) => str::from_utf8(s).ok()?,
+            _ => return None,
+        };
+        let mut it = line.trim().splitn(2, ':');
+        match (it.next(), it.next()) {
+            (Some("node_address"), Some(value)) => node_address = Some(value.to_string()),
+            (Some("repl_offset"), Some(value)) => repl_offset = value.parse::<u64>().ok(),
+            _ => continue,
+        }
+    }
+    Some(NodeReplStats {
+        node_address: node_address?,
+        repl_offset: repl_offset?,
+    })
+}
+
+impl ProxyStats for NodeReplStats {
+    fn get_command() -> Vec<BinSafeStr> {
+        vec![b"UMCTL".to_vec(), b"INFOREPL".to_vec()]
+    }
+
+    // The nodes failing to respond to the proxy don't have an offset and will be skipped.
+    fn parse_reply(address: &str, reply: RespVec) -> Result<Vec<Self>, CoordinateError> {
+        match reply {
+            Resp::Arr(Array::Arr(arr)) => Ok(arr.iter().filter_map(parse_repl_stats).collect()),
+            reply => {
+                error!(
+                    "failed to send INFOREPL to {}, invalid reply {:?}",
+                    address, reply
+                );
+                Err(CoordinateError::InvalidReply)
+            }
+        }
+    }
+}
+
+impl BrokerStats for NodeReplStats {
+    fn add_to_broker<'s, MB: MetaManipulationBroker>(
+        mani_broker: &'s MB,
+        address: String,
+        stats: Vec<Self>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>> {
+        mani_broker.add_repl_stats(address, stats)
+    }
+}
+
+impl BrokerStats for NodeMemoryStats {
+    fn add_to_broker<'s, MB: MetaManipulationBroker>(
+        mani_broker: &'s MB,
+        address: String,
+        stats: Vec<Self>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), MetaManipulationBrokerError>> + Send + 's>> {
+        mani_broker.add_memory_stats(address, stats)
+    }
+}
+
+pub struct MemoryStatsRespCollector<F: RedisClientFactory, DB: MetaDataBroker> {
+    client_factory: Arc<F>,
+    data_broker: Arc<DB>,
+}
+
+impl<F: RedisClientFactory, DB: MetaDataBroker> MemoryStatsRespCollector<F, DB> {
+    pub fn new(client_factory: Arc<F>, data_broker: Arc<DB>) -> Self {
+        Self {
+            client_factory,
+            data_broker,
+        }
+    }
+
+    fn parse_memory_info(node_address: String, info: &[u8]) -> Option<NodeMemoryStats> {
+        let info = str::from_utf8(info).ok()?;
+        let mut used_memory = None;
+        let mut maxmemory = None;
+        for line in info.lines() {
+            let mut it = line.trim().splitn(2, ':');
+            match (it.next(), it.next()) {
+                (Some("used_memory"), Some(value)) => used_memory = value.parse::<u64>().ok(),
+                (Some("maxmemory"), Some(value)) => maxmemory = value.parse::<u64>().ok(),
+                _ => continue,
+            }
+        }
+        Some(NodeMemoryStats {
+            node_address,
+            used_memory: used_memory?,
+            maxmemory: maxmemory?,
+        })
+    }
+
+    async fn get_node_memory(
+        &self,
+        node_address: String,
+    ) -> Result<NodeMemoryStats, CoordinateError> {
+        let mut client = self
+            .client_factory
+            .create_client(node_address.clone())
+            .await
+            .map_err(CoordinateError::Redis)?;
+        let cmd = vec![b"INFO".to_vec(), b"memory".to_vec()];
+
+        let reply = client
+            .execute_single(cmd)
+            .await
+            .map_err(CoordinateError::Redis)?;
+
+        match reply {
+            Resp::Bulk(BulkStr::Str(info)) => Self::parse_memory_info(node_address.clone(), &info)
+                .ok_or_else(|| {
+                    error!("failed to parse memory info {}", node_address);
+                    CoordinateError::InvalidReply
+                }),
+            reply => {
+                error!("failed to send INFO memory, invalid reply {:?}", reply);
+                Err(CoordinateError::InvalidReply)
+            }
+        }
+    }
+
+    // Only the masters are collected since the replicas have the same data.
+    // The masters failing to reply are skipped so that the others could still be reported.
+    async fn collect_impl(&self, address: String) -> Result<Vec<NodeMemoryStats>, CoordinateError> {
+        let proxy = match self
+            .data_broker
+            .get_proxy(address.clone())
+            .await
+            .map_err(CoordinateError::MetaData)?
+        {
+            Some(proxy) => proxy,
+            None => return Ok(vec![]),
+        };
+
+        let futs: Vec<_> = proxy
+            .get_nodes()
+            .iter()
+            .filter(|node| node.get_role() == Role::Master)
+            .map(|node| self.get_node_memory(node.get_address().to_string()))
+            .collect();
+
+        let mut memory_stats = vec![];
+        let mut failures = 0;
+        let mut last_err = None;
+        for res in future::join_all(futs).await.into_iter() {
+            match res {
+                Ok(stats) => memory_stats.push(stats),
+                Err(err) => {
+                    failures += 1;
+                    last_err = Some(err);
+                }
+            }
+        }
+        if let Some(err) = last_err {
+            warn!(
+                "failed to get memory stats of {} masters in proxy {}: {:?}",
+                failures, address, err
+            );
+            if memory_stats.is_empty() {
+                return Err(err);
+            }
+        }
+        Ok(memory_stats)
+    }
+}
+
+impl<F: RedisClientFactory, DB: MetaDataBroker> StatsCollector for MemoryStatsRespCollector<F, DB> {
+    type Stats = NodeMemoryStats;
+
+    fn collect<'s>(&'s self, address: String) -> StatsFuture<'s, NodeMemoryStats> {
+        Box::pin(self.collect_impl(address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::broker::{MockMetaDataBroker, MockMetaManipulationBroker};
+    use super::super::core::{ParStatsSynchronizer, StatsSynchronizer};
+    use super::super::detector::BrokerProxiesRetriever;
+    use super::*;
+    use crate::common::cluster::{ClusterName, Node, Proxy, ReplMeta};
+    use crate::common::utils::ThreadSafe;
+    use crate::protocol::{BinSafeStr, DummyRedisClientFactory, MockRedisClient};
+    use futures::stream;
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+    use tokio;
+
+    const PROXY_ADDRESS: &str = "127.0.0.1:6000";
+
+    fn gen_mock_data_broker() -> MockMetaDataBroker {
+        let mut mock_data_broker = MockMetaDataBroker::new();
+        mock_data_broker
+            .expect_get_proxy_addresses()
+            .returning(move || {
+                let results = vec![Ok(PROXY_ADDRESS.to_string())];
+                Box::pin(stream::iter(results))
+            });
+        mock_data_broker
+            .expect_get_failed_proxies()
+            .returning(|| Box::pin(stream::iter(vec![])));
+        mock_data_broker
+    }
+
+    // Syncs the stats of `PROXY_ADDRESS`.
+    // The expected stats are checked by `mock_mani_broker`.
+    async fn run_stats_sync<C>(
+        collector: C,
+        mock_data_broker: Arc<MockMetaDataBroker>,
+        mock_mani_broker: MockMetaManipulationBroker,
+    ) where
+        C: StatsCollector,
+        C::Stats: BrokerStats,
+    {
+        let reporter = BrokerStatsReporter::<_, C::Stats>::new(Arc::new(mock_mani_broker));
+        let proxies_retriever = BrokerProxiesRetriever::new(mock_data_broker);
+        let sync = ParStatsSynchronizer::new(proxies_retriever, collector, reporter);
+        sync.run().await.unwrap();
+    }
+
+    async fn run_proxy_stats_sync<S, C, F>(
+        create_client_func: F,
+        mock_mani_broker: MockMetaManipulationBroker,
+    ) where
+        S: ProxyStats + BrokerStats,
+        C: RedisClient + Sync + 'static,
+        F: Fn() -> C + ThreadSafe,
+    {
+        let factory = Arc::new(DummyRedisClientFactory::new(create_client_func));
+        let collector = ProxyStatsRespCollector::<_, S>::new(factory);
+        let mock_data_broker = Arc::new(gen_mock_data_broker());
+        run_stats_sync(collector, mock_data_broker, mock_mani_broker).await;
+    }
+
+    fn create_client_func() -> impl RedisClient {
+        let mut mock_client = MockRedisClient::new();
+
+        let slot_stats_cmd = vec![b"UMCTL".to_vec(), b"SLOTSTATS".to_vec(), b"TAKE".to_vec()];
+        mock_client
+            .expect_execute_single()
+            .withf(move |command: &Vec<BinSafeStr>| command.eq(&slot_stats_cmd))
+            .times(1)
+            .returning(|_| {
+                let reply = Resp::Arr(Array::Arr(vec![
+                    Resp::Bulk(BulkStr::Str(b"1 2 30".to_vec())),
+                    Resp::Bulk(BulkStr::Str(b"233 1 10".to_vec())),
+                ]));
+                Box::pin(async { Ok(reply) })
+            });
+
+        mock_client
+    }
+
+    fn gen_expected_slot_stats() -> Vec<SlotStats> {
+        vec![
+            SlotStats {
+                slot: 1,
+                requests: 2,
+                bytes: 30,
+            },
+            SlotStats {
+                slot: 233,
+                requests: 1,
+                bytes: 10,
+            },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_slot_stats_collector() {
+        let factory = DummyRedisClientFactory::new(create_client_func);
+        let collector = ProxyStatsRespCollector::<_, SlotStats>::new(Arc::new(factory));
+        let slot_stats = collector.collect("127.0.0.1:6000".to_string()).await;
+        assert_eq!(slot_stats.unwrap(), gen_expected_slot_stats());
+    }
+
+    #[tokio::test]
+    async fn test_slot_stats_sync() {
+        let mut mock_mani_broker = MockMetaManipulationBroker::new();
+        mock_mani_broker
+            .expect_add_slot_stats()
+            .withf(|address, slot_stats| {
+                address == PROXY_ADDRESS && slot_stats == &gen_expected_slot_stats()
+            })
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        run_proxy_stats_sync::<SlotStats, _, _>(create_client_func, mock_mani_broker).await;
+    }
+
+    fn create_memory_client_func() -> impl RedisClient {
+        let mut mock_client = MockRedisClient::new();
+
+        let info_cmd = vec![b"INFO".to_vec(), b"memory".to_vec()];
+        mock_client
+            .expect_execute_single()
+            .withf(move |command: &Vec<BinSafeStr>| command.eq(&info_cmd))
+            .times(1)
+            .returning(|_| {
+                let info = b"# Memory\r\nused_memory:1024\r\nused_memory_human:1.00K\r\nmaxmemory:4096\r\n";
+                let reply = Resp::Bulk(BulkStr::Str(info.to_vec()));
+                Box::pin(async { Ok(reply) })
+            });
+
+        mock_client
+    }
+
+    fn gen_proxy() -> Proxy {
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let gen_node = |address: &str, role| {
+            Node::new(
+                address.to_string(),
+                "127.0.0.1:6000".to_string(),
+                cluster_name.clone(),
+                vec![],
+                ReplMeta::new(role, vec![]),
+            )
+        };
+        let nodes = vec![
+            gen_node("127.0.0.1:7000", Role::Master),
+            gen_node("127.0.0.1:7001", Role::Replica),
+        ];
+        Proxy::new(
+            "127.0.0.1:6000".to_string(),
+            1,
+            nodes,
+            vec![],
+            vec![],
+            HashMap::new(),
+        )
+    }
+
+    fn gen_expected_memory_stats() -> Vec<NodeMemoryStats> {
+        vec![NodeMemoryStats {
+            node_address: "127.0.0.1:7000".to_string(),
+            used_memory: 1024,
+            maxmemory: 4096,
+        }]
+    }
+
+    #[tokio::test]
+    async fn test_memory_stats_sync() {
+        // The memory stats collector also needs the roles of the nodes from the broker.
+        let mut mock_data_broker = gen_mock_data_broker();
+        mock_data_broker
+            .expect_get_proxy()
+            .withf(|address| address == PROXY_ADDRESS)
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(Some(gen_proxy())) }));
+        let mock_data_broker = Arc::new(mock_data_broker);
+        let factory = Arc::new(DummyRedisClientFactory::new(create_memory_client_func));
+        let collector = MemoryStatsRespCollector::new(factory, mock_data_broker.clone());
+
+        let mut mock_mani_broker = MockMetaManipulationBroker::new();
+        mock_mani_broker
+            .expect_add_memory_stats()
+            .withf(|address, memory_stats| {
+                address == PROXY_ADDRESS && memory_stats == &gen_expected_memory_stats()
+            })
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        run_stats_sync(collector, mock_data_broker, mock_mani_broker).await;
+    }
+
+    fn create_repl_client_func() -> impl RedisClient {
+        let mut mock_client = MockRedisClient::new();
+
+        let info_repl_cmd = vec![b"UMCTL".to_vec(), b"INFOREPL".to_vec()];
+        mock_client
+            .expect_execute_single()
+            .withf(move |command: &Vec<BinSafeStr>| command.eq(&info_repl_cmd))
+            .times(1)
+            .returning(|_| {
+                let gen_meta = |lines: Vec<&str>| {
+                    Resp::Arr(Array::Arr(
+                        lines
+                            .into_iter()
+                            .map(|line| Resp::Bulk(BulkStr::Str(line.as_bytes().to_vec())))
+                            .collect(),
+                    ))
+                };
+                let reply = Resp::Arr(Array::Arr(vec![
+                    gen_meta(vec![
+                        "cluster:mycluster\n",
+                        "role:master\n",
+                        "node_address:127.0.0.1:7000\n",
+                        "repl_offset:2333\n",
+                        "replica:127.0.0.1:7001@127.0.0.1:6001\n",
+                    ]),
+                    gen_meta(vec![
+                        "cluster:mycluster\n",
+                        "role:replica\n",
+                        "node_address:127.0.0.1:7002\n",
+                        "master:127.0.0.1:7003@127.0.0.1:6001\n",
+                    ]),
+                ]));
+                Box::pin(async { Ok(reply) })
+            });
+
+        mock_client
+    }
+
+    fn gen_expected_repl_stats() -> Vec<NodeReplStats> {
+        vec![NodeReplStats {
+            node_address: "127.0.0.1:7000".to_string(),
+            repl_offset: 2333,
+        }]
+    }
+
+    #[tokio::test]
+    async fn test_repl_stats_sync() {
+        let mut mock_mani_broker = MockMetaManipulationBroker::new();
+        mock_mani_broker
+            .expect_add_repl_stats()
+            .withf(|address, repl_stats| {
+                address == PROXY_ADDRESS && repl_stats == &gen_expected_repl_stats()
+            })
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        run_proxy_stats_sync::<NodeReplStats, _, _>(create_repl_client_func, mock_mani_broker)
+            .await;
+    }
+
+    fn create_migration_client_func() -> impl RedisClient {
+        let mut mock_client = MockRedisClient::new();
+
+        let progress_cmd = vec![b"UMCTL".to_vec(), b"INFOMGR".to_vec(), b"PROGRESS".to_vec()];
+        mock_client
+            .expect_execute_single()
+            .withf(move |command: &Vec<BinSafeStr>| command.eq(&progress_cmd))
+            .times(1)
+            .returning(|_| {
+                let reply = Resp::Arr(Array::Arr(vec![Resp::Bulk(BulkStr::Str(
+                    b"mycluster 127.0.0.1:7000 127.0.0.1:7001 100 50 2333 0 0.5000 10".to_vec(),
+                ))]));
+                Box::pin(async { Ok(reply) })
+            });
+
+        mock_client
+    }
+
+    fn gen_expected_migration_stats() -> Vec<MigrationProgress> {
+        vec![MigrationProgress {
+            cluster_name: ClusterName::try_from("mycluster").unwrap(),
+            src_node_address: "127.0.0.1:7000".to_string(),
+            dst_node_address: "127.0.0.1:7001".to_string(),
+            keys_scanned: 100,
+            keys_migrated: 50,
+            bytes_transferred: 2333,
+            errors: 0,
+            scanned_ratio: 0.5,
+            elapsed_secs: 10,
+        }]
+    }
+
+    #[tokio::test]
+    async fn test_migration_stats_sync() {
+        let mut mock_mani_broker = MockMetaManipulationBroker::new();
+        mock_mani_broker
+            .expect_add_migration_stats()
+            .withf(|address, migration_stats| {
+                address == PROXY_ADDRESS && migration_stats == &gen_expected_migration_stats()
+            })
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        run_proxy_stats_sync::<MigrationProgress, _, _>(
+            create_migration_client_func,
+            mock_mani_broker,
+        )
+        .await;
+    }
+}
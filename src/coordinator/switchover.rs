@@ -0,0 +1,214 @@
+use super::broker::{MetaDataBroker, MetaManipulationBroker};
+use super::core::{
+    CoordinateError, SwitchoverCommitter, SwitchoverExecutor, SwitchoverTaskRetriever,
+};
+use crate::common::cluster::SwitchoverTask;
+use crate::common::utils::pretty_print_bytes;
+use crate::protocol::{RedisClient, RedisClientFactory, Resp};
+use futures::{Future, Stream, StreamExt, TryFutureExt};
+use std::pin::Pin;
+use std::sync::Arc;
+
+// The proxy waits for the replicas to catch up for at most this long
+// and keeps the writes paused until it receives the new metadata or it times out.
+// It should be less than `proxy_timeout`.
+const SWITCHOVER_TIMEOUT_MS: u64 = 1000;
+
+fn gen_switchover_cmd(task: &SwitchoverTask) -> Vec<Vec<u8>> {
+    let mut cmd = vec![
+        "UMCTL".to_string(),
+        "SWITCHOVER".to_string(),
+        SWITCHOVER_TIMEOUT_MS.to_string(),
+    ];
+    for node in task.nodes.iter() {
+        cmd.push(node.master_node_address.clone());
+        cmd.push(node.replica_node_address.clone());
+    }
+    cmd.into_iter().map(String::into_bytes).collect()
+}
+
+pub struct BrokerSwitchoverTaskRetriever<DB: MetaDataBroker> {
+    data_broker: Arc<DB>,
+}
+
+impl<DB: MetaDataBroker> BrokerSwitchoverTaskRetriever<DB> {
+    pub fn new(data_broker: Arc<DB>) -> Self {
+        Self { data_broker }
+    }
+}
+
+impl<DB: MetaDataBroker> SwitchoverTaskRetriever for BrokerSwitchoverTaskRetriever<DB> {
+    fn retrieve_tasks<'s>(
+        &'s self,
+    ) -> Pin<Box<dyn Stream<Item = Result<SwitchoverTask, CoordinateError>> + Send + 's>> {
+        Box::pin(
+            self.data_broker
+                .get_switchover_tasks()
+                .map(|res| res.map_err(CoordinateError::MetaData)),
+        )
+    }
+}
+
+pub struct SwitchoverRespExecutor<F: RedisClientFactory> {
+    client_factory: Arc<F>,
+}
+
+impl<F: RedisClientFactory> SwitchoverRespExecutor<F> {
+    pub fn new(client_factory: Arc<F>) -> Self {
+        Self { client_factory }
+    }
+
+    async fn execute_impl(&self, task: SwitchoverTask) -> Result<(), CoordinateError> {
+        let mut client = self
+            .client_factory
+            .create_client(task.proxy_address.clone())
+            .await
+            .map_err(CoordinateError::Redis)?;
+        let cmd = gen_switchover_cmd(&task);
+
+        let reply = client
+            .execute_single(cmd)
+            .await
+            .map_err(CoordinateError::Redis)?;
+
+        match reply {
+            Resp::Error(err) => {
+                error!(
+                    "failed to switch over {:?}: {}",
+                    task,
+                    pretty_print_bytes(&err)
+                );
+                Err(CoordinateError::InvalidReply)
+            }
+            _ => {
+                info!("paused the writes and promoted the replicas {:?}", task);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<F: RedisClientFactory> SwitchoverExecutor for SwitchoverRespExecutor<F> {
+    fn execute<'s>(
+        &'s self,
+        task: SwitchoverTask,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>> {
+        Box::pin(self.execute_impl(task))
+    }
+}
+
+pub struct BrokerSwitchoverCommitter<MB: MetaManipulationBroker> {
+    mani_broker: Arc<MB>,
+}
+
+impl<MB: MetaManipulationBroker> BrokerSwitchoverCommitter<MB> {
+    pub fn new(mani_broker: Arc<MB>) -> Self {
+        Self { mani_broker }
+    }
+}
+
+impl<MB: MetaManipulationBroker> SwitchoverCommitter for BrokerSwitchoverCommitter<MB> {
+    fn commit<'s>(
+        &'s self,
+        proxy_address: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CoordinateError>> + Send + 's>> {
+        Box::pin(
+            self.mani_broker
+                .commit_switchover(proxy_address.clone())
+                .map_err(move |e| {
+                    error!("failed to commit switchover {} {:?}", proxy_address, e);
+                    CoordinateError::MetaMani(e)
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::broker::{MockMetaDataBroker, MockMetaManipulationBroker};
+    use super::super::core::{MockProxyMetaSender, ParSwitchoverHandler, SwitchoverHandler};
+    use super::super::sync::BrokerMetaRetriever;
+    use super::*;
+    use crate::common::cluster::{Proxy, SwitchoverNode};
+    use crate::protocol::{BinSafeStr, DummyRedisClientFactory, MockRedisClient};
+    use futures::stream;
+    use std::collections::HashMap;
+    use tokio;
+
+    const PROXY_ADDRESS: &str = "127.0.0.1:6000";
+    const PEER_PROXY_ADDRESS: &str = "127.0.0.1:6001";
+
+    fn gen_task() -> SwitchoverTask {
+        SwitchoverTask {
+            proxy_address: PROXY_ADDRESS.to_string(),
+            nodes: vec![SwitchoverNode {
+                master_node_address: "127.0.0.1:7000".to_string(),
+                replica_node_address: "127.0.0.1:7003".to_string(),
+                replica_proxy_address: PEER_PROXY_ADDRESS.to_string(),
+            }],
+        }
+    }
+
+    fn create_client_func() -> impl RedisClient {
+        let mut mock_client = MockRedisClient::new();
+
+        let switchover_cmd = gen_switchover_cmd(&gen_task());
+        mock_client
+            .expect_execute_single()
+            .withf(move |command: &Vec<BinSafeStr>| command.eq(&switchover_cmd))
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(Resp::Simple(b"OK".to_vec())) }));
+
+        mock_client
+    }
+
+    #[tokio::test]
+    async fn test_switchover() {
+        let mut mock_data_broker = MockMetaDataBroker::new();
+        mock_data_broker
+            .expect_get_switchover_tasks()
+            .times(1)
+            .returning(|| Box::pin(stream::iter(vec![Ok(gen_task())])));
+        mock_data_broker
+            .expect_get_proxy()
+            .times(2)
+            .returning(|address| {
+                let proxy = Proxy::new(address, 7799, vec![], vec![], vec![], HashMap::new());
+                Box::pin(async { Ok(Some(proxy)) })
+            });
+        let mock_data_broker = Arc::new(mock_data_broker);
+
+        let mut mock_mani_broker = MockMetaManipulationBroker::new();
+        mock_mani_broker
+            .expect_commit_switchover()
+            .withf(|address| address == PROXY_ADDRESS)
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        // The new masters should get the metadata first.
+        let mut sender = MockProxyMetaSender::new();
+        let mut seq = mockall::Sequence::new();
+        sender
+            .expect_send_meta()
+            .withf(|proxy| proxy.get_address() == PEER_PROXY_ADDRESS)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Box::pin(async { Ok(()) }));
+        sender
+            .expect_send_meta()
+            .withf(|proxy| proxy.get_address() == PROXY_ADDRESS)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        let factory = Arc::new(DummyRedisClientFactory::new(create_client_func));
+        let handler = ParSwitchoverHandler::new(
+            BrokerSwitchoverTaskRetriever::new(mock_data_broker.clone()),
+            SwitchoverRespExecutor::new(factory),
+            BrokerSwitchoverCommitter::new(Arc::new(mock_mani_broker)),
+            BrokerMetaRetriever::new(mock_data_broker),
+            sender,
+        );
+        handler.run().await.unwrap();
+    }
+}
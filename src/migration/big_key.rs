@@ -0,0 +1,438 @@
+use super::task::ScanResponse;
+use crate::common::utils::{generate_slot, get_hash_tag, get_resp_bytes, pretty_print_bytes};
+use crate::protocol::{BinSafeStr, BulkStr, RedisClient, RedisClientError, Resp, RespVec};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// The elements of a big key are moved in chunks of this size.
+const BIG_KEY_CHUNK_SIZE: usize = 256;
+const TMP_KEY_PREFIX: &[u8] = b"__undermoon_migrating_big_key:";
+
+// Big keys of other types will still be migrated by DUMP and RESTORE.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BigKeyType {
+    Hash,
+    Set,
+    ZSet,
+    List,
+}
+
+impl BigKeyType {
+    pub fn from_type_name(type_name: &[u8]) -> Option<Self> {
+        match type_name {
+            b"hash" => Some(Self::Hash),
+            b"set" => Some(Self::Set),
+            b"zset" => Some(Self::ZSet),
+            b"list" => Some(Self::List),
+            _ => None,
+        }
+    }
+}
+
+// The temporary key needs to be in the same slot as the key
+// so that it won't be left in the slots not owned by the destination.
+pub fn gen_tmp_key(key: &[u8]) -> Option<BinSafeStr> {
+    let mut tmp_key = TMP_KEY_PREFIX.to_vec();
+    tmp_key.push(b'{');
+    tmp_key.extend_from_slice(get_hash_tag(key));
+    tmp_key.push(b'}');
+    if generate_slot(&tmp_key) != generate_slot(key) {
+        return None;
+    }
+    Some(tmp_key)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BigKeyThreshold {
+    // Zero to disable the big key migration.
+    pub bytes: u64,
+    // Only used when `MEMORY USAGE` fails. Zero to disable the fallback.
+    pub count: u64,
+}
+
+// Only log the failure of `MEMORY USAGE` once to avoid flooding the log,
+// since it usually means the command is disabled on all the backends.
+static MEMORY_USAGE_FAILURE_LOGGED: AtomicBool = AtomicBool::new(false);
+
+// Returns the type of each key if it's a big key that could be migrated in chunks.
+// `MEMORY USAGE` only samples a few elements by default so it won't block the source.
+// If it fails, the element counts are used instead.
+pub async fn detect_big_keys<C: RedisClient>(
+    client: &mut C,
+    keys: &[BinSafeStr],
+    threshold: BigKeyThreshold,
+) -> Result<Vec<Option<BigKeyType>>, RedisClientError> {
+    if threshold.bytes == 0 || keys.is_empty() {
+        return Ok(keys.iter().map(|_| None).collect());
+    }
+
+    let mut commands = Vec::with_capacity(2 * keys.len());
+    for key in keys.iter() {
+        commands.push(vec![b"MEMORY".to_vec(), b"USAGE".to_vec(), key.clone()]);
+        commands.push(vec![b"TYPE".to_vec(), key.clone()]);
+    }
+    let resps = client.execute_multi(commands).await?;
+    if resps.len() != 2 * keys.len() {
+        error!(
+            "mismatch batch result number, expected {}, found {}",
+            2 * keys.len(),
+            resps.len()
+        );
+        return Err(RedisClientError::InvalidReply);
+    }
+
+    let mut big_key_types = Vec::with_capacity(keys.len());
+    // The indices of the keys which need to be checked by the element counts.
+    let mut unknown_usage_keys = vec![];
+    let mut resp_iter = resps.into_iter();
+    for (i, key) in keys.iter().enumerate() {
+        let (usage_resp, type_resp) = match (resp_iter.next(), resp_iter.next()) {
+            (Some(usage_resp), Some(type_resp)) => (usage_resp, type_resp),
+            _ => {
+                error!("invalid state, can't get resp");
+                return Err(RedisClientError::InvalidState);
+            }
+        };
+        let key_type = match type_resp {
+            Resp::Simple(type_name) if gen_tmp_key(key).is_some() => {
+                BigKeyType::from_type_name(&type_name)
+            }
+            _ => None,
+        };
+        let big_key_type = match usage_resp {
+            Resp::Integer(usage) => {
+                let usage = btoi::btoi::<u64>(&usage).unwrap_or(0);
+                key_type.filter(|_| usage >= threshold.bytes)
+            }
+            // The key does not exist.
+            Resp::Bulk(BulkStr::Nil) => None,
+            // MEMORY USAGE could be unsupported or disabled.
+            others => {
+                if !MEMORY_USAGE_FAILURE_LOGGED.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "failed to get MEMORY USAGE, fall back to the element counts: {:?}",
+                        others
+                    );
+                }
+                if let Some(key_type) = key_type {
+                    if threshold.count != 0 {
+                        unknown_usage_keys.push((i, key_type));
+                    }
+                }
+                None
+            }
+        };
+        big_key_types.push(big_key_type);
+    }
+
+    if unknown_usage_keys.is_empty() {
+        return Ok(big_key_types);
+    }
+
+    let commands = unknown_usage_keys
+        .iter()
+        .filter_map(|(i, key_type)| keys.get(*i).map(|key| gen_count_cmd(*key_type, key)))
+        .collect();
+    let resps = client.execute_multi(commands).await?;
+    if resps.len() != unknown_usage_keys.len() {
+        error!(
+            "mismatch batch result number, expected {}, found {}",
+            unknown_usage_keys.len(),
+            resps.len()
+        );
+        return Err(RedisClientError::InvalidReply);
+    }
+    for ((i, key_type), resp) in unknown_usage_keys.into_iter().zip(resps) {
+        let count = match resp {
+            Resp::Integer(count) => btoi::btoi::<u64>(&count).unwrap_or(0),
+            // The key could have been changed to another type.
+            // Just migrate it by DUMP and RESTORE.
+            others => {
+                debug!("failed to get element count: {:?}", others);
+                0
+            }
+        };
+        if count >= threshold.count {
+            if let Some(big_key_type) = big_key_types.get_mut(i) {
+                *big_key_type = Some(key_type);
+            }
+        }
+    }
+    Ok(big_key_types)
+}
+
+fn gen_count_cmd(key_type: BigKeyType, key: &[u8]) -> Vec<BinSafeStr> {
+    let cmd_name: &[u8] = match key_type {
+        BigKeyType::Hash => b"HLEN",
+        BigKeyType::Set => b"SCARD",
+        BigKeyType::ZSet => b"ZCARD",
+        BigKeyType::List => b"LLEN",
+    };
+    vec![cmd_name.to_vec(), key.to_vec()]
+}
+
+// Copy the elements to a temporary key on the destination
+// and rename it to the key atomically in the end,
+// so that the clients will never see a partial key.
+// Returns the transferred bytes, or None if the key is gone.
+pub async fn migrate_big_key<C: RedisClient>(
+    src_client: &mut C,
+    dst_client: &mut C,
+    key: BinSafeStr,
+    key_type: BigKeyType,
+) -> Result<Option<usize>, RedisClientError> {
+    let tmp_key = match gen_tmp_key(&key) {
+        Some(tmp_key) => tmp_key,
+        None => {
+            error!(
+                "can't generate temporary key for {:?}",
+                pretty_print_bytes(&key)
+            );
+            return Err(RedisClientError::InvalidState);
+        }
+    };
+
+    // Remove the leftover of the last failed migration.
+    check_reply(
+        dst_client
+            .execute_single(vec![b"DEL".to_vec(), tmp_key.clone()])
+            .await?,
+    )?;
+
+    let mut bytes = 0;
+    let mut cursor: u64 = 0;
+    loop {
+        let (next_cursor, elements) = read_chunk(src_client, &key, key_type, cursor).await?;
+        if !elements.is_empty() {
+            bytes += elements.iter().map(|e| e.len()).sum::<usize>();
+            let cmd = gen_write_cmd(key_type, &tmp_key, elements);
+            check_reply(dst_client.execute_single(cmd).await?)?;
+        }
+        if next_cursor == 0 {
+            break;
+        }
+        cursor = next_cursor;
+    }
+
+    // Only the keys deleted by the clients on the destination could be removed from the source.
+    let pttl = match src_client
+        .execute_single(vec![b"PTTL".to_vec(), key.clone()])
+        .await?
+    {
+        Resp::Integer(pttl) => {
+            btoi::btoi::<i64>(&pttl).map_err(|_| RedisClientError::InvalidReply)?
+        }
+        others => {
+            error!("failed to get PTTL: {:?}", others);
+            return Err(RedisClientError::InvalidReply);
+        }
+    };
+    if pttl == -2 {
+        check_reply(
+            dst_client
+                .execute_single(vec![b"DEL".to_vec(), tmp_key])
+                .await?,
+        )?;
+        return Ok(None);
+    }
+    if pttl >= 0 {
+        let cmd = vec![
+            b"PEXPIRE".to_vec(),
+            tmp_key.clone(),
+            pttl.to_string().into_bytes(),
+        ];
+        check_reply(dst_client.execute_single(cmd).await?)?;
+    }
+
+    // The key could have been restored on the destination by the clients.
+    // The one on the destination is newer.
+    let cmd = vec![b"RENAMENX".to_vec(), tmp_key.clone(), key.clone()];
+    match dst_client.execute_single(cmd).await? {
+        Resp::Integer(n) if n.as_slice() == b"1" => (),
+        Resp::Integer(_) => {
+            check_reply(
+                dst_client
+                    .execute_single(vec![b"DEL".to_vec(), tmp_key])
+                    .await?,
+            )?;
+        }
+        others => {
+            error!("failed to rename big key: {:?}", others);
+            return Err(RedisClientError::InvalidReply);
+        }
+    }
+
+    // Avoid blocking the source on deleting the big key.
+    check_reply(
+        src_client
+            .execute_single(vec![b"UNLINK".to_vec(), key])
+            .await?,
+    )?;
+    Ok(Some(bytes))
+}
+
+async fn read_chunk<C: RedisClient>(
+    src_client: &mut C,
+    key: &[u8],
+    key_type: BigKeyType,
+    cursor: u64,
+) -> Result<(u64, Vec<BinSafeStr>), RedisClientError> {
+    let chunk_size = BIG_KEY_CHUNK_SIZE.to_string().into_bytes();
+    let cmd_name: &[u8] = match key_type {
+        BigKeyType::Hash => b"HSCAN",
+        BigKeyType::Set => b"SSCAN",
+        BigKeyType::ZSet => b"ZSCAN",
+        BigKeyType::List => {
+            // The cursor is the start index of the list.
+            let end = cursor + BIG_KEY_CHUNK_SIZE as u64 - 1;
+            let cmd = vec![
+                b"LRANGE".to_vec(),
+                key.to_vec(),
+                cursor.to_string().into_bytes(),
+                end.to_string().into_bytes(),
+            ];
+            let resp = src_client.execute_single(cmd).await?;
+            let elements = get_resp_bytes(&resp).ok_or_else(|| {
+                error!("invalid LRANGE reply: {:?}", resp);
+                RedisClientError::InvalidReply
+            })?;
+            let next_cursor = if elements.len() < BIG_KEY_CHUNK_SIZE {
+                0
+            } else {
+                end + 1
+            };
+            return Ok((next_cursor, elements));
+        }
+    };
+    let cmd = vec![
+        cmd_name.to_vec(),
+        key.to_vec(),
+        cursor.to_string().into_bytes(),
+        b"COUNT".to_vec(),
+        chunk_size,
+    ];
+    let resp = src_client.execute_single(cmd).await?;
+    let ScanResponse { next_index, keys } = ScanResponse::parse_scan(&resp).ok_or_else(|| {
+        error!(
+            "invalid {:?} reply: {:?}",
+            pretty_print_bytes(cmd_name),
+            resp
+        );
+        RedisClientError::InvalidReply
+    })?;
+    Ok((next_index, keys))
+}
+
+fn gen_write_cmd(
+    key_type: BigKeyType,
+    tmp_key: &[u8],
+    elements: Vec<BinSafeStr>,
+) -> Vec<BinSafeStr> {
+    let cmd_name: &[u8] = match key_type {
+        BigKeyType::Hash => b"HSET",
+        BigKeyType::Set => b"SADD",
+        BigKeyType::ZSet => b"ZADD",
+        BigKeyType::List => b"RPUSH",
+    };
+    let mut cmd = Vec::with_capacity(elements.len() + 2);
+    cmd.push(cmd_name.to_vec());
+    cmd.push(tmp_key.to_vec());
+    if key_type == BigKeyType::ZSet {
+        // ZSCAN returns members and scores while ZADD takes scores and members.
+        let mut it = elements.into_iter();
+        while let (Some(member), Some(score)) = (it.next(), it.next()) {
+            cmd.push(score);
+            cmd.push(member);
+        }
+    } else {
+        cmd.extend(elements);
+    }
+    cmd
+}
+
+fn check_reply(resp: RespVec) -> Result<(), RedisClientError> {
+    match resp {
+        Resp::Error(err) => {
+            error!("failed to migrate big key: {:?}", pretty_print_bytes(&err));
+            Err(RedisClientError::InvalidReply)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::MockRedisClient;
+
+    #[test]
+    fn test_gen_tmp_key() {
+        for key in &["key", "{user}.name", "a{b"] {
+            let tmp_key = gen_tmp_key(key.as_bytes()).unwrap();
+            assert_eq!(generate_slot(&tmp_key), generate_slot(key.as_bytes()));
+            assert!(tmp_key.starts_with(TMP_KEY_PREFIX));
+        }
+        // These keys will be migrated by DUMP and RESTORE.
+        assert!(gen_tmp_key(b"a}b").is_none());
+        assert!(gen_tmp_key(b"{}x").is_none());
+    }
+
+    #[test]
+    fn test_gen_zset_write_cmd() {
+        let elements = vec![b"m1".to_vec(), b"1".to_vec(), b"m2".to_vec(), b"2".to_vec()];
+        let cmd = gen_write_cmd(BigKeyType::ZSet, b"tmp", elements);
+        let expected: Vec<BinSafeStr> = vec!["ZADD", "tmp", "1", "m1", "2", "m2"]
+            .into_iter()
+            .map(|s| s.as_bytes().to_vec())
+            .collect();
+        assert_eq!(cmd, expected);
+    }
+
+    fn gen_memory_usage_error() -> RespVec {
+        Resp::Error(b"ERR unknown command `MEMORY`".to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_detect_big_keys_by_count() {
+        let mut mock_client = MockRedisClient::new();
+        mock_client
+            .expect_execute_multi()
+            .withf(|cmds: &Vec<Vec<BinSafeStr>>| cmds.len() == 6)
+            .times(1)
+            .returning(|_| {
+                let resps = vec![
+                    gen_memory_usage_error(),
+                    Resp::Simple(b"hash".to_vec()),
+                    gen_memory_usage_error(),
+                    Resp::Simple(b"list".to_vec()),
+                    gen_memory_usage_error(),
+                    Resp::Simple(b"string".to_vec()),
+                ];
+                Box::pin(async { Ok(resps) })
+            });
+        let count_cmds = vec![
+            vec![b"HLEN".to_vec(), b"big".to_vec()],
+            vec![b"LLEN".to_vec(), b"small".to_vec()],
+        ];
+        mock_client
+            .expect_execute_multi()
+            .withf(move |cmds: &Vec<Vec<BinSafeStr>>| cmds.eq(&count_cmds))
+            .times(1)
+            .returning(|_| {
+                let resps = vec![
+                    Resp::Integer(b"200".to_vec()),
+                    Resp::Integer(b"10".to_vec()),
+                ];
+                Box::pin(async { Ok(resps) })
+            });
+
+        let keys = vec![b"big".to_vec(), b"small".to_vec(), b"str".to_vec()];
+        let threshold = BigKeyThreshold {
+            bytes: 1024,
+            count: 100,
+        };
+        let big_key_types = detect_big_keys(&mut mock_client, &keys, threshold)
+            .await
+            .unwrap();
+        assert_eq!(big_key_types, vec![Some(BigKeyType::Hash), None, None]);
+    }
+}
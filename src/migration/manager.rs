@@ -1,6 +1,8 @@
 use super::scan_task::{RedisScanImportingTask, RedisScanMigratingTask};
 use super::task::{ImportingTask, MigratingTask, MigrationError, MigrationState, SwitchArg};
-use crate::common::cluster::{ClusterName, MigrationTaskMeta, RangeList, SlotRangeTag};
+use crate::common::cluster::{
+    ClusterName, MigrationProgress, MigrationTaskMeta, RangeList, SlotRangeTag,
+};
 use crate::common::config::{AtomicMigrationConfig, ClusterConfig};
 use crate::common::proto::{ClusterConfigMap, ProxyClusterMap};
 use crate::common::track::TrackedFutureRegistry;
@@ -215,12 +217,22 @@ where
                 let mut lines = vec![format!("name: {}", cluster_name)];
                 for (task_meta, mgr_task) in tasks.iter() {
                     if let Some(migration_meta) = task_meta.slot_range.tag.get_migration_meta() {
-                        let state = match &mgr_task.task {
-                            Either::Left(task) => task.get_state(),
-                            Either::Right(task) => task.get_state(),
+                        let (state, suffix) = match &mgr_task.task {
+                            Either::Left(task) => {
+                                let progress = task.get_progress();
+                                let suffix = format!(
+                                    " keys_migrated:{} bytes:{} scanned:{:.2}%{}",
+                                    progress.keys_migrated,
+                                    progress.bytes_transferred,
+                                    progress.scanned_ratio * 100.0,
+                                    if task.is_paused() { " PAUSED" } else { "" },
+                                );
+                                (task.get_state(), suffix)
+                            }
+                            Either::Right(task) => (task.get_state(), String::new()),
                         };
                         lines.push(format!(
-                            "{} {} -> {} {}",
+                            "{} {} -> {} {}{}",
                             task_meta
                                 .slot_range
                                 .range_list
@@ -230,6 +242,7 @@ where
                             migration_meta.src_node_address,
                             migration_meta.dst_node_address,
                             state,
+                            suffix,
                         ));
                     } else {
                         error!("invalid slot range migration meta");
@@ -383,6 +396,12 @@ where
                                 .get(cluster_name)
                                 .and_then(|tasks| tasks.get(&migration_meta))
                             {
+                                // The config could be changed during the migration.
+                                if let (Either::Left(task), Some(cluster_config)) =
+                                    (&migrating_task.task, cluster_config_map.get(cluster_name))
+                                {
+                                    task.update_config(cluster_config.migration_config);
+                                }
                                 let tasks = migration_clusters
                                     .entry(cluster_name.clone())
                                     .or_insert_with(HashMap::new);
@@ -437,7 +456,10 @@ where
                                         cluster_config.migration_config,
                                     ))
                                 }
-                                None => mgr_config.clone(),
+                                // Don't share the config so that it could be updated separately.
+                                None => Arc::new(AtomicMigrationConfig::from_config(
+                                    mgr_config.to_config(),
+                                )),
                             };
                             let ctrl = blocking_ctrl_factory.create(meta.src_node_address.clone());
                             let task = Arc::new(RedisScanMigratingTask::new(
@@ -560,6 +582,33 @@ where
         Err(SwitchError::TaskNotFound)
     }
 
+    pub fn get_progress(&self) -> Vec<MigrationProgress> {
+        self.task_map
+            .values()
+            .flat_map(|tasks| tasks.values())
+            .filter_map(|mgr_task| match &mgr_task.task {
+                Either::Left(migrating_task) => Some(migrating_task.get_progress()),
+                Either::Right(_) => None,
+            })
+            .collect()
+    }
+
+    // Returns whether there're any migrating tasks of the cluster.
+    pub fn set_paused(&self, cluster_name: &ClusterName, paused: bool) -> bool {
+        let tasks = match self.task_map.get(cluster_name) {
+            Some(tasks) => tasks,
+            None => return false,
+        };
+        let mut found = false;
+        for mgr_task in tasks.values() {
+            if let Either::Left(migrating_task) = &mgr_task.task {
+                migrating_task.set_paused(paused);
+                found = true;
+            }
+        }
+        found
+    }
+
     pub fn get_finished_tasks(&self) -> Vec<MigrationTaskMeta> {
         let mut metadata = vec![];
         {
@@ -1,3 +1,4 @@
+mod big_key;
 pub mod manager;
 pub mod scan_migration;
 mod scan_task;
@@ -1,11 +1,13 @@
-use super::task::{ScanResponse, SlotRangeArray};
+use super::big_key::{detect_big_keys, migrate_big_key, BigKeyThreshold, BigKeyType};
+use super::task::{AtomicMigrationStats, ScanResponse, SlotRangeArray};
 use crate::common::cluster::SlotRange;
-use crate::common::config::AtomicMigrationConfig;
+use crate::common::config::{AtomicMigrationConfig, MigrationStrategy};
 use crate::common::future_group::{new_auto_drop_future, FutureAutoStopHandle};
 use crate::common::resp_execution::keep_connecting_and_sending_cmd_with_cached_client;
 use crate::common::response;
+use crate::common::token_bucket::TokenBucket;
 use crate::common::try_chunks::TryChunksStreamExt;
-use crate::common::utils::pretty_print_bytes;
+use crate::common::utils::{get_resp_bytes, pretty_print_bytes};
 use crate::common::yield_now::YieldNow;
 use crate::migration::task::MigrationError;
 use crate::protocol::{
@@ -17,10 +19,10 @@ use atomic_option::AtomicOption;
 use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use futures::{future, Future, FutureExt, StreamExt};
 use futures_timer::Delay;
-use std::cmp::min;
+use std::cmp::{max, min};
 use std::num::NonZeroUsize;
 use std::pin::Pin;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -28,6 +30,9 @@ pub const PTTL_NO_EXPIRE: &[u8] = b"-1";
 pub const PTTL_KEY_NOT_FOUND: &[u8] = b"-2";
 pub const RESTORE_NO_EXPIRE: &[u8] = b"0";
 const BUSYKEY_ERROR: &[u8] = b"BUSYKEY";
+const MIGRATE_NOKEY: &[u8] = b"NOKEY";
+// Should be less than the timeout of the redis client.
+const MIGRATE_TIMEOUT_MS: u64 = 500;
 
 pub fn pttl_to_restore_expire_time(pttl: Vec<u8>) -> Vec<u8> {
     let mut expire_time = pttl;
@@ -62,6 +67,47 @@ struct DataEntry {
 
 type MgrFut = Pin<Box<dyn Future<Output = Result<(), MigrationError>> + Send>>;
 
+// Limits the bandwidth and the keys per second of the migration.
+// The limits are read from the config every time so that they could be changed at runtime.
+struct MigrationThrottle {
+    config: Arc<AtomicMigrationConfig>,
+    bytes_bucket: TokenBucket,
+    keys_bucket: TokenBucket,
+}
+
+impl MigrationThrottle {
+    fn new(config: Arc<AtomicMigrationConfig>) -> Self {
+        Self {
+            config,
+            bytes_bucket: TokenBucket::default(),
+            keys_bucket: TokenBucket::default(),
+        }
+    }
+
+    fn consume(&mut self, key_num: usize, bytes: usize) {
+        self.keys_bucket
+            .consume(self.config.get_max_keys_per_sec(), key_num as u64);
+        self.bytes_bucket
+            .consume(self.config.get_max_bytes_per_sec(), bytes as u64);
+    }
+
+    fn get_wait_time(&mut self) -> Duration {
+        let keys_wait_time = self
+            .keys_bucket
+            .get_wait_time(self.config.get_max_keys_per_sec());
+        let bytes_wait_time = self
+            .bytes_bucket
+            .get_wait_time(self.config.get_max_bytes_per_sec());
+        max(keys_wait_time, bytes_wait_time)
+    }
+}
+
+#[derive(Debug)]
+enum ScanBySlotError {
+    Unsupported(String),
+    RedisClient(RedisClientError),
+}
+
 pub struct ScanMigrationTask<T: CmdTask> {
     handle: AtomicOption<FutureAutoStopHandle>, // once this task get dropped, the future will stop.
     fut: AtomicOption<MgrFut>,
@@ -75,6 +121,8 @@ impl<T: CmdTask> ScanMigrationTask<T> {
         slot_range: SlotRange,
         client_factory: Arc<F>,
         config: Arc<AtomicMigrationConfig>,
+        paused: Arc<AtomicBool>,
+        stats: Arc<AtomicMigrationStats>,
     ) -> Self {
         let ranges = slot_range.to_range_list();
         let slot_ranges = SlotRangeArray::new(ranges);
@@ -87,6 +135,8 @@ impl<T: CmdTask> ScanMigrationTask<T> {
             sender.clone(),
             receiver,
             config,
+            paused,
+            stats,
         );
 
         Self {
@@ -132,6 +182,7 @@ impl<T: CmdTask> ScanMigrationTask<T> {
         Err(RedisClientError::Done)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn gen_future<F: RedisClientFactory>(
         src_address: String,
         dst_address: String,
@@ -140,6 +191,8 @@ impl<T: CmdTask> ScanMigrationTask<T> {
         sync_tasks_sender: UnboundedSender<T>,
         sync_tasks_receiver: UnboundedReceiver<T>,
         config: Arc<AtomicMigrationConfig>,
+        paused: Arc<AtomicBool>,
+        stats: Arc<AtomicMigrationStats>,
     ) -> (MgrFut, FutureAutoStopHandle) {
         let interval = min(
             Duration::from_micros(config.get_scan_interval()),
@@ -161,6 +214,8 @@ impl<T: CmdTask> ScanMigrationTask<T> {
             sync_tasks_sender,
             sync_tasks_receiver,
             config,
+            paused,
+            stats,
         );
 
         let (send, handle) = new_auto_drop_future(send);
@@ -168,7 +223,7 @@ impl<T: CmdTask> ScanMigrationTask<T> {
         (Box::pin(send), handle)
     }
 
-    #[allow(clippy::cognitive_complexity)]
+    #[allow(clippy::cognitive_complexity, clippy::too_many_arguments)]
     async fn keep_migrating<F: RedisClientFactory>(
         src_address: String,
         dst_address: String,
@@ -177,8 +232,11 @@ impl<T: CmdTask> ScanMigrationTask<T> {
         sync_tasks_sender: UnboundedSender<T>,
         mut sync_tasks_receiver: UnboundedReceiver<T>,
         config: Arc<AtomicMigrationConfig>,
+        paused: Arc<AtomicBool>,
+        stats: Arc<AtomicMigrationStats>,
     ) -> Result<(), MigrationError> {
         const SLEEP_BATCH_TIMES: u64 = 10;
+        const IDLE_INTERVAL: Duration = Duration::from_millis(10);
 
         let interval = min(
             Duration::from_micros(config.get_scan_interval() * SLEEP_BATCH_TIMES),
@@ -204,8 +262,12 @@ impl<T: CmdTask> ScanMigrationTask<T> {
             Some(chunk_size) => chunk_size,
         };
         let mut sync_tasks_receiver = sync_tasks_receiver.try_chunks(chunk_size);
+        let mut throttle = MigrationThrottle::new(config.clone());
 
+        let mut scan_by_slot = config.get_scan_by_slot();
+        let mut strategy = config.get_strategy();
         let mut scan_index = 0;
+        let mut last_slot_keys = vec![];
         let mut cached_dst_client = None;
         let mut sleep_count = 0;
         loop {
@@ -213,12 +275,47 @@ impl<T: CmdTask> ScanMigrationTask<T> {
                 Ok(client) => client,
                 Err(err) => {
                     error!("failed to create redis client: {:?}", err);
+                    stats.incr_errors();
                     Delay::new(interval).await;
                     continue;
                 }
             };
+            if scan_by_slot {
+                match Self::support_scan_by_slot(&mut src_client).await {
+                    Ok(()) => (),
+                    Err(ScanBySlotError::Unsupported(reason)) => {
+                        error!(
+                            "migration_scan_by_slot requires cluster-enabled on {}: {}. Fall back to SCAN",
+                            src_address, reason
+                        );
+                        scan_by_slot = false;
+                        scan_index = 0;
+                    }
+                    Err(ScanBySlotError::RedisClient(err)) => {
+                        error!("failed to check CLUSTER GETKEYSINSLOT: {:?}", err);
+                        stats.incr_errors();
+                        Delay::new(interval).await;
+                        continue;
+                    }
+                }
+            }
             loop {
-                let sync_tasks = if sleep_count >= SLEEP_BATCH_TIMES {
+                let is_paused = paused.load(Ordering::SeqCst);
+                let throttle_time = throttle.get_wait_time();
+                let is_throttled = throttle_time > Duration::from_secs(0);
+                // The keys accessed by the clients still need to be migrated
+                // when paused or throttled.
+                let sync_tasks = if is_paused || is_throttled {
+                    let idle_time = if is_paused {
+                        IDLE_INTERVAL
+                    } else {
+                        min(throttle_time, IDLE_INTERVAL)
+                    };
+                    match future::select(sync_tasks_receiver.next(), Delay::new(idle_time)).await {
+                        future::Either::Left((Some(cmd_tasks), _)) => Some(cmd_tasks),
+                        _ => None,
+                    }
+                } else if sleep_count >= SLEEP_BATCH_TIMES {
                     sleep_count = 0;
                     if interval == Duration::from_secs(0) {
                         // Need yield so that we won't get stuck in the unit tests.
@@ -251,11 +348,19 @@ impl<T: CmdTask> ScanMigrationTask<T> {
                             dst_address.clone(),
                             client_factory.clone(),
                             cmd_tasks,
+                            BigKeyThreshold {
+                                bytes: config.get_big_key_threshold(),
+                                count: config.get_big_key_count_threshold(),
+                            },
+                            &mut strategy,
+                            &stats,
+                            &mut throttle,
                         )
                         .await;
                         match res {
                             Err(err) => {
                                 error!("failed to handle blocking requests {:?}", err);
+                                stats.incr_errors();
                                 break;
                             }
                             Ok(dst_client) => {
@@ -264,6 +369,7 @@ impl<T: CmdTask> ScanMigrationTask<T> {
                         }
                         continue;
                     }
+                    None if is_paused || is_throttled => continue,
                     None => {
                         Self::scan_and_migrate_keys(
                             &slot_ranges,
@@ -273,6 +379,15 @@ impl<T: CmdTask> ScanMigrationTask<T> {
                             dst_address.clone(),
                             client_factory.clone(),
                             scan_count,
+                            &mut scan_by_slot,
+                            &mut last_slot_keys,
+                            BigKeyThreshold {
+                                bytes: config.get_big_key_threshold(),
+                                count: config.get_big_key_count_threshold(),
+                            },
+                            &mut strategy,
+                            &stats,
+                            &mut throttle,
                         )
                         .await
                     }
@@ -281,10 +396,12 @@ impl<T: CmdTask> ScanMigrationTask<T> {
                 match res {
                     Err(err) => {
                         error!("failed to scan and migrate {:?}", err);
+                        stats.incr_errors();
                         break;
                     }
                     Ok((new_scan_index, dst_client)) => {
                         if new_scan_index == 0 {
+                            stats.set_scan_finished();
                             sync_tasks_sender.close_channel();
                             while let Some(cmd_tasks) = sync_tasks_receiver.next().await {
                                 for cmd_task in cmd_tasks.into_iter() {
@@ -296,6 +413,12 @@ impl<T: CmdTask> ScanMigrationTask<T> {
                             return Ok(());
                         }
                         scan_index = new_scan_index;
+                        if scan_by_slot {
+                            let slot = scan_index as usize - 1;
+                            stats.set_scanned_ratio(slot_ranges.get_slot_ratio(slot));
+                        } else {
+                            stats.set_scan_cursor(scan_index);
+                        }
                         cached_dst_client = dst_client;
                     }
                 }
@@ -304,6 +427,7 @@ impl<T: CmdTask> ScanMigrationTask<T> {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn scan_and_migrate_keys<F: RedisClientFactory>(
         slot_ranges: &SlotRangeArray,
         index: u64,
@@ -312,23 +436,53 @@ impl<T: CmdTask> ScanMigrationTask<T> {
         dst_address: String,
         client_factory: Arc<F>,
         scan_count: u64,
+        scan_by_slot: &mut bool,
+        last_slot_keys: &mut Vec<BinSafeStr>,
+        big_key_threshold: BigKeyThreshold,
+        strategy: &mut MigrationStrategy,
+        stats: &AtomicMigrationStats,
+        throttle: &mut MigrationThrottle,
     ) -> Result<(u64, Option<F::Client>), RedisClientError> {
-        let ScanResponse { next_index, keys } =
-            Self::scan_keys(src_client, index, scan_count).await?;
-
-        let entries = Self::produce_entries(slot_ranges, keys, src_client).await?;
-        if entries.is_empty() {
-            return Ok((next_index, dst_client));
-        }
-
-        let transferred_keys: Vec<_> = entries.iter().map(|entry| entry.key.clone()).collect();
-        let dst_client =
-            Self::forward_entries(dst_address, dst_client, client_factory, entries).await;
-
-        Self::delete_keys(src_client, transferred_keys).await?;
-        Ok((next_index, Some(dst_client)))
+        let slot_scan_res = if *scan_by_slot {
+            Self::scan_slot_keys(src_client, slot_ranges, index, scan_count, last_slot_keys).await?
+        } else {
+            None
+        };
+        let ScanResponse { next_index, keys } = match slot_scan_res {
+            Some(scan_res) => scan_res,
+            None if *scan_by_slot => {
+                // The keys could not be removed from the source Redis.
+                // SCAN won't get stuck since it only visits each key once.
+                error!("CLUSTER GETKEYSINSLOT keeps returning the same keys, fall back to SCAN");
+                stats.incr_errors();
+                *scan_by_slot = false;
+                last_slot_keys.clear();
+                Self::scan_keys(src_client, 0, scan_count).await?
+            }
+            None => Self::scan_keys(src_client, index, scan_count).await?,
+        };
+        stats.add_scanned_keys(keys.len());
+
+        let (keys, big_keys) =
+            Self::split_keys(slot_ranges, keys, src_client, big_key_threshold).await?;
+        let dst_client = Self::migrate_keys(
+            keys,
+            big_keys,
+            dst_client,
+            src_client,
+            dst_address,
+            client_factory,
+            strategy,
+            stats,
+            throttle,
+        )
+        .await?;
+        Ok((next_index, dst_client))
     }
 
+    // The keys accessed by the clients are not throttled to avoid the latency
+    // but they still count in the limits.
+    #[allow(clippy::too_many_arguments)]
     async fn handle_blocking_requests<F: RedisClientFactory>(
         slot_ranges: &SlotRangeArray,
         dst_client: Option<F::Client>,
@@ -336,27 +490,32 @@ impl<T: CmdTask> ScanMigrationTask<T> {
         dst_address: String,
         client_factory: Arc<F>,
         cmd_tasks: Vec<T>,
+        big_key_threshold: BigKeyThreshold,
+        strategy: &mut MigrationStrategy,
+        stats: &AtomicMigrationStats,
+        throttle: &mut MigrationThrottle,
     ) -> Result<Option<F::Client>, RedisClientError> {
         let keys = cmd_tasks
             .iter()
             .filter_map(|t| t.get_key().map(|b| b.to_vec()))
             .collect();
 
-        let res = match Self::produce_entries(slot_ranges, keys, src_client).await {
-            Ok(entries) => {
-                if entries.is_empty() {
-                    Ok(dst_client)
-                } else {
-                    let transferred_keys: Vec<_> =
-                        entries.iter().map(|entry| entry.key.clone()).collect();
-                    let dst_client =
-                        Self::forward_entries(dst_address, dst_client, client_factory, entries)
-                            .await;
-
-                    Self::delete_keys(src_client, transferred_keys)
-                        .await
-                        .map(move |()| Some(dst_client))
-                }
+        // The big keys are also fully migrated before replying
+        // so that the commands on the importing proxy won't see a partial key.
+        let res = match Self::split_keys(slot_ranges, keys, src_client, big_key_threshold).await {
+            Ok((keys, big_keys)) => {
+                Self::migrate_keys(
+                    keys,
+                    big_keys,
+                    dst_client,
+                    src_client,
+                    dst_address,
+                    client_factory,
+                    strategy,
+                    stats,
+                    throttle,
+                )
+                .await
             }
             Err(err) => Err(err),
         };
@@ -394,16 +553,230 @@ impl<T: CmdTask> ScanMigrationTask<T> {
         })
     }
 
-    async fn produce_entries<C: RedisClient>(
+    // `CLUSTER GETKEYSINSLOT` only works when the backend Redis enables `cluster-enabled`.
+    async fn support_scan_by_slot<C: RedisClient>(
+        src_client: &mut C,
+    ) -> Result<(), ScanBySlotError> {
+        let cmd = vec![
+            b"CLUSTER".to_vec(),
+            b"GETKEYSINSLOT".to_vec(),
+            b"0".to_vec(),
+            b"0".to_vec(),
+        ];
+        let resp = src_client
+            .execute_single(cmd)
+            .await
+            .map_err(ScanBySlotError::RedisClient)?;
+        match resp {
+            Resp::Error(err) => Err(ScanBySlotError::Unsupported(
+                String::from_utf8_lossy(&err).to_string(),
+            )),
+            resp if get_resp_bytes(&resp).is_some() => Ok(()),
+            others => Err(ScanBySlotError::Unsupported(format!(
+                "invalid reply {:?}",
+                others
+            ))),
+        }
+    }
+
+    // The index is the current slot plus one, and zero for the end.
+    // The migrated keys are removed from the source Redis,
+    // so it keeps fetching the same slot until it's empty.
+    // Returns None if the same keys of the slot are returned twice,
+    // which means they can't be removed and it will never end.
+    async fn scan_slot_keys<C: RedisClient>(
+        src_client: &mut C,
+        slot_ranges: &SlotRangeArray,
+        index: u64,
+        scan_count: u64,
+        last_keys: &mut Vec<BinSafeStr>,
+    ) -> Result<Option<ScanResponse>, RedisClientError> {
+        let mut slot_opt = match index {
+            0 => slot_ranges.next_slot(None),
+            index => Some(index as usize - 1),
+        };
+        while let Some(slot) = slot_opt {
+            let cmd = vec![
+                b"CLUSTER".to_vec(),
+                b"GETKEYSINSLOT".to_vec(),
+                slot.to_string().into_bytes(),
+                scan_count.to_string().into_bytes(),
+            ];
+            let resp = src_client.execute_single(cmd).await?;
+            let keys = get_resp_bytes(&resp).ok_or_else(|| {
+                error!("Invalid CLUSTER GETKEYSINSLOT reply: {:?}", resp);
+                RedisClientError::InvalidReply
+            })?;
+            if !keys.is_empty() {
+                if index == slot as u64 + 1 && keys == *last_keys {
+                    return Ok(None);
+                }
+                *last_keys = keys.clone();
+                return Ok(Some(ScanResponse {
+                    next_index: slot as u64 + 1,
+                    keys,
+                }));
+            }
+            slot_opt = slot_ranges.next_slot(Some(slot));
+        }
+        Ok(Some(ScanResponse {
+            next_index: 0,
+            keys: vec![],
+        }))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn migrate_keys<F: RedisClientFactory>(
+        keys: Vec<BinSafeStr>,
+        big_keys: Vec<(BinSafeStr, BigKeyType)>,
+        dst_client: Option<F::Client>,
+        src_client: &mut F::Client,
+        dst_address: String,
+        client_factory: Arc<F>,
+        strategy: &mut MigrationStrategy,
+        stats: &AtomicMigrationStats,
+        throttle: &mut MigrationThrottle,
+    ) -> Result<Option<F::Client>, RedisClientError> {
+        let mut keys = keys;
+        if *strategy == MigrationStrategy::Migrate && !keys.is_empty() {
+            keys =
+                Self::migrate_by_redis(src_client, &dst_address, keys, strategy, stats, throttle)
+                    .await?;
+        }
+        let entries = Self::produce_entries(keys, src_client).await?;
+
+        let mut dst_client = dst_client;
+        if !entries.is_empty() {
+            let transferred_keys: Vec<_> = entries.iter().map(|entry| entry.key.clone()).collect();
+            let client = Self::forward_entries(
+                dst_address.clone(),
+                dst_client,
+                client_factory.clone(),
+                entries,
+                stats,
+                throttle,
+            )
+            .await;
+            dst_client = Some(client);
+            Self::delete_keys(src_client, transferred_keys).await?;
+        }
+
+        if big_keys.is_empty() {
+            return Ok(dst_client);
+        }
+        let mut client = match dst_client {
+            Some(client) => client,
+            None => client_factory.create_client(dst_address).await?,
+        };
+        for (key, key_type) in big_keys.into_iter() {
+            info!(
+                "migrate big key {} in chunks",
+                pretty_print_bytes(key.as_slice())
+            );
+            if let Some(bytes) = migrate_big_key(src_client, &mut client, key, key_type).await? {
+                stats.add_migrated_keys(1, bytes);
+                throttle.consume(1, bytes);
+            }
+        }
+        Ok(Some(client))
+    }
+
+    // Returns the keys that need to fall back to DUMP and RESTORE.
+    async fn migrate_by_redis<C: RedisClient>(
+        src_client: &mut C,
+        dst_address: &str,
+        keys: Vec<BinSafeStr>,
+        strategy: &mut MigrationStrategy,
+        stats: &AtomicMigrationStats,
+        throttle: &mut MigrationThrottle,
+    ) -> Result<Vec<BinSafeStr>, RedisClientError> {
+        let mut segs = dst_address.rsplitn(2, ':');
+        let (port, host) = match (segs.next(), segs.next()) {
+            (Some(port), Some(host)) => (port, host),
+            _ => {
+                error!(
+                    "invalid destination address {}, fall back to DUMP and RESTORE",
+                    dst_address
+                );
+                *strategy = MigrationStrategy::DumpRestore;
+                return Ok(keys);
+            }
+        };
+
+        // Without COPY and REPLACE, the keys will be removed from the source
+        // and the existing keys in the destination won't be replaced.
+        let mut cmd = vec![
+            b"MIGRATE".to_vec(),
+            host.as_bytes().to_vec(),
+            port.as_bytes().to_vec(),
+            b"".to_vec(),
+            b"0".to_vec(),
+            MIGRATE_TIMEOUT_MS.to_string().into_bytes(),
+            b"KEYS".to_vec(),
+        ];
+        cmd.extend(keys.iter().cloned());
+        let resp = src_client.execute_single(cmd).await?;
+        match resp {
+            // The error is forwarded from the destination Redis like
+            // `ERR Target instance replied with error: BUSYKEY ...`
+            Resp::Error(err) if err.windows(BUSYKEY_ERROR.len()).any(|w| w == BUSYKEY_ERROR) => {
+                // Some keys have been restored by the importing proxy.
+                // Let the fallback handle them.
+                Ok(keys)
+            }
+            Resp::Error(err) => {
+                // Probably the source Redis can't connect to the destination Redis.
+                // Don't retry it in this task.
+                error!(
+                    "MIGRATE failed, fall back to DUMP and RESTORE: {}",
+                    pretty_print_bytes(&err)
+                );
+                stats.incr_errors();
+                *strategy = MigrationStrategy::DumpRestore;
+                Ok(keys)
+            }
+            Resp::Simple(s) if s == MIGRATE_NOKEY => Ok(vec![]),
+            _ => {
+                // The data does not go through the server proxy so the bytes are unknown.
+                stats.add_migrated_keys(keys.len(), 0);
+                throttle.consume(keys.len(), 0);
+                Ok(vec![])
+            }
+        }
+    }
+
+    // Splits the keys into the small keys and the big keys.
+    async fn split_keys<C: RedisClient>(
         slot_ranges: &SlotRangeArray,
         keys: Vec<BinSafeStr>,
         client: &mut C,
-    ) -> Result<Vec<DataEntry>, RedisClientError> {
+        big_key_threshold: BigKeyThreshold,
+    ) -> Result<(Vec<BinSafeStr>, Vec<(BinSafeStr, BigKeyType)>), RedisClientError> {
         let keys: Vec<_> = keys
             .into_iter()
             .filter(|key| slot_ranges.is_key_inside(key.as_slice()))
             .collect();
+
+        let big_key_types = detect_big_keys(client, &keys, big_key_threshold).await?;
+        let mut big_keys = vec![];
+        let mut small_keys = vec![];
+        for (key, big_key_type) in keys.into_iter().zip(big_key_types) {
+            match big_key_type {
+                Some(key_type) => big_keys.push((key, key_type)),
+                None => small_keys.push(key),
+            }
+        }
+        Ok((small_keys, big_keys))
+    }
+
+    async fn produce_entries<C: RedisClient>(
+        keys: Vec<BinSafeStr>,
+        client: &mut C,
+    ) -> Result<Vec<DataEntry>, RedisClientError> {
         let key_num = keys.len();
+        if key_num == 0 {
+            return Ok(vec![]);
+        }
 
         let mut commands = vec![];
         for key in &keys {
@@ -472,7 +845,11 @@ impl<T: CmdTask> ScanMigrationTask<T> {
         cached_dst_client: Option<F::Client>,
         client_factory: Arc<F>,
         entries: Vec<DataEntry>,
+        stats: &AtomicMigrationStats,
+        throttle: &mut MigrationThrottle,
     ) -> F::Client {
+        let key_num = entries.len();
+        let bytes = entries.iter().map(|entry| entry.raw_data.len()).sum();
         let mut commands = Vec::with_capacity(entries.len());
         for entry in entries.into_iter() {
             let DataEntry {
@@ -494,7 +871,7 @@ impl<T: CmdTask> ScanMigrationTask<T> {
         }
 
         let retry_interval = Duration::from_millis(1);
-        keep_connecting_and_sending_cmd_with_cached_client(
+        let dst_client = keep_connecting_and_sending_cmd_with_cached_client(
             cached_dst_client,
             client_factory,
             dst_address,
@@ -502,7 +879,10 @@ impl<T: CmdTask> ScanMigrationTask<T> {
             retry_interval,
             Self::handle_forward,
         )
-        .await
+        .await;
+        stats.add_migrated_keys(key_num, bytes);
+        throttle.consume(key_num, bytes);
+        dst_client
     }
 
     async fn delete_keys<C: RedisClient>(
@@ -528,3 +908,67 @@ impl<T: CmdTask> Drop for ScanMigrationTask<T> {
         self.stop();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::cluster::{Range, RangeList};
+    use crate::protocol::{Array, MockRedisClient};
+    use crate::proxy::session::CmdCtx;
+
+    type Task = ScanMigrationTask<CmdCtx>;
+
+    fn gen_keys_reply(keys: &[&str]) -> RespVec {
+        Resp::Arr(Array::Arr(
+            keys.iter()
+                .map(|k| Resp::Bulk(BulkStr::Str(k.as_bytes().to_vec())))
+                .collect(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_support_scan_by_slot() {
+        let mut mock_client = MockRedisClient::new();
+        mock_client
+            .expect_execute_single()
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(gen_keys_reply(&[])) }));
+        assert!(Task::support_scan_by_slot(&mut mock_client).await.is_ok());
+
+        let mut mock_client = MockRedisClient::new();
+        mock_client.expect_execute_single().times(1).returning(|_| {
+            let err = b"ERR This instance has cluster support disabled".to_vec();
+            Box::pin(async { Ok(Resp::Error(err)) })
+        });
+        match Task::support_scan_by_slot(&mut mock_client).await {
+            Err(ScanBySlotError::Unsupported(reason)) => {
+                assert!(reason.contains("cluster support disabled"))
+            }
+            others => panic!("unexpected result {:?}", others),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_slot_keys_with_same_keys() {
+        let mut mock_client = MockRedisClient::new();
+        mock_client
+            .expect_execute_single()
+            .times(2)
+            .returning(|_| Box::pin(async { Ok(gen_keys_reply(&["a", "b"])) }));
+
+        let slot_ranges = SlotRangeArray::new(RangeList::new(vec![Range(5, 6)]));
+        let mut last_keys = vec![];
+        let res = Task::scan_slot_keys(&mut mock_client, &slot_ranges, 0, 2, &mut last_keys)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(res.next_index, 6);
+        assert_eq!(res.keys, vec![b"a".to_vec(), b"b".to_vec()]);
+
+        // The keys are not removed from the slot.
+        let res = Task::scan_slot_keys(&mut mock_client, &slot_ranges, 6, 2, &mut last_keys)
+            .await
+            .unwrap();
+        assert!(res.is_none());
+    }
+}
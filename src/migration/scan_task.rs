@@ -1,12 +1,13 @@
 use super::scan_migration::ScanMigrationTask;
 use super::task::{
-    AtomicMigrationState, ImportingTask, MgrSubCmd, MigratingTask, MigrationError, MigrationState,
-    SwitchArg,
+    AtomicMigrationState, AtomicMigrationStats, ImportingTask, MgrSubCmd, MigratingTask,
+    MigrationError, MigrationState, SwitchArg,
 };
 use crate::common::cluster::{
-    ClusterName, MigrationMeta, MigrationTaskMeta, RangeMap, SlotRange, SlotRangeTag,
+    ClusterName, MigrationMeta, MigrationProgress, MigrationTaskMeta, RangeMap, SlotRange,
+    SlotRangeTag,
 };
-use crate::common::config::AtomicMigrationConfig;
+use crate::common::config::{AtomicMigrationConfig, MigrationConfig};
 use crate::common::resp_execution::keep_connecting_and_sending_cmd;
 use crate::common::response;
 use crate::common::utils::{gen_moved, pretty_print_bytes, ThreadSafe};
@@ -29,7 +30,7 @@ use futures::{future, select, Future, FutureExt, TryFutureExt};
 use futures_timer::Delay;
 use std::marker::PhantomData;
 use std::pin::Pin;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -59,6 +60,8 @@ where
     blocking_ctrl: Arc<BC>,
     phantom: PhantomData<T>,
     active_redirection: bool,
+    paused: Arc<AtomicBool>,
+    stats: Arc<AtomicMigrationStats>,
 }
 
 impl<RCF, T, BC> RedisScanMigratingTask<RCF, T, BC>
@@ -78,12 +81,16 @@ where
         blocking_ctrl: Arc<BC>,
     ) -> Self {
         let (stop_signal_sender, stop_signal_receiver) = oneshot::channel();
+        let paused = Arc::new(AtomicBool::new(false));
+        let stats = Arc::new(AtomicMigrationStats::default());
         let task = ScanMigrationTask::new(
             meta.src_node_address.clone(),
             meta.dst_node_address.clone(),
             slot_range.clone(),
             client_factory.clone(),
             mgr_config.clone(),
+            paused.clone(),
+            stats.clone(),
         );
         let range_map = RangeMap::from(slot_range.get_range_list());
         let active_redirection = config.active_redirection;
@@ -101,6 +108,8 @@ where
             blocking_ctrl,
             phantom: PhantomData,
             active_redirection,
+            paused,
+            stats,
         }
     }
 
@@ -309,7 +318,7 @@ where
         let final_switch = self.final_switch();
 
         let timeout = Duration::from_secs(self.mgr_config.get_max_migration_time());
-        let mut timeout_fut = Delay::new(timeout).fuse();
+        let mut timeout_fut = Box::pin(self.wait_for_migration_timeout(timeout).fuse());
         select! {
             () = timeout_fut => error!("migration timeout after {:?}, force to commit migration", timeout),
             res = self.run_migration().fuse() => res?,
@@ -319,6 +328,18 @@ where
         Ok(())
     }
 
+    // The time paused by the operators does not count.
+    async fn wait_for_migration_timeout(&self, timeout: Duration) {
+        const TICK: Duration = Duration::from_millis(100);
+        let mut elapsed = Duration::from_secs(0);
+        while elapsed < timeout {
+            Delay::new(TICK).await;
+            if !self.paused.load(Ordering::SeqCst) {
+                elapsed += TICK;
+            }
+        }
+    }
+
     async fn run_migration(&self) -> Result<(), MigrationError> {
         let pre_check = self.pre_check();
         let pre_block = self.pre_block();
@@ -439,6 +460,25 @@ where
         self.range_map.contains_slot(slot)
     }
 
+    fn set_paused(&self, paused: bool) {
+        if self.paused.swap(paused, Ordering::SeqCst) != paused {
+            info!("migration paused: {} {:?}", paused, self.meta);
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    fn get_progress(&self) -> MigrationProgress {
+        self.stats
+            .to_progress(self.cluster_name.clone(), &self.meta)
+    }
+
+    fn update_config(&self, config: MigrationConfig) {
+        self.mgr_config.update(config);
+    }
+
     fn get_stop_handle(&self) -> Option<Box<dyn Drop + Send + Sync + 'static>> {
         let handle = MigratingTaskHandle {
             task: self.task.clone(),
@@ -1,4 +1,7 @@
-use crate::common::cluster::{MigrationTaskMeta, Range, RangeList, RangeMap};
+use crate::common::cluster::{
+    ClusterName, MigrationMeta, MigrationProgress, MigrationTaskMeta, Range, RangeList, RangeMap,
+};
+use crate::common::config::MigrationConfig;
 use crate::common::utils::{generate_slot, get_resp_bytes, get_resp_strings, ThreadSafe};
 use crate::protocol::{Array, BinSafeStr, BulkStr, RedisClientError, Resp, RespSlice, RespVec};
 use crate::proxy::backend::CmdTask;
@@ -7,13 +10,15 @@ use crate::proxy::cluster::ClusterSendError;
 use crate::replication::replicator::ReplicatorError;
 use futures::Future;
 use itertools::Itertools;
+use std::cmp::min;
 use std::error::Error;
 use std::fmt;
 use std::io;
 use std::iter::Peekable;
 use std::pin::Pin;
 use std::str;
-use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use std::time::Instant;
 
 #[derive(Debug)]
 pub enum MgrSubCmd {
@@ -84,6 +89,90 @@ impl AtomicMigrationState {
     }
 }
 
+// Updated by the scanning task without blocking the migration.
+#[derive(Debug)]
+pub struct AtomicMigrationStats {
+    keys_scanned: AtomicU64,
+    keys_migrated: AtomicU64,
+    bytes_transferred: AtomicU64,
+    errors: AtomicU64,
+    scan_cursor: AtomicU64,
+    start_time: Instant,
+}
+
+impl Default for AtomicMigrationStats {
+    fn default() -> Self {
+        Self {
+            keys_scanned: AtomicU64::new(0),
+            keys_migrated: AtomicU64::new(0),
+            bytes_transferred: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            scan_cursor: AtomicU64::new(0),
+            start_time: Instant::now(),
+        }
+    }
+}
+
+impl AtomicMigrationStats {
+    pub fn add_scanned_keys(&self, key_num: usize) {
+        self.keys_scanned
+            .fetch_add(key_num as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_migrated_keys(&self, key_num: usize, bytes: usize) {
+        self.keys_migrated
+            .fetch_add(key_num as u64, Ordering::Relaxed);
+        self.bytes_transferred
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn incr_errors(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_scan_cursor(&self, cursor: u64) {
+        self.scan_cursor.store(cursor, Ordering::Relaxed);
+    }
+
+    // The cursor of SCAN will wrap back to zero in the end.
+    pub fn set_scan_finished(&self) {
+        self.scan_cursor.store(u64::MAX, Ordering::Relaxed);
+    }
+
+    // Used when the keys are scanned by slots instead of SCAN.
+    pub fn set_scanned_ratio(&self, ratio: f64) {
+        let cursor = (ratio * u64::MAX as f64) as u64;
+        self.scan_cursor
+            .store(cursor.reverse_bits(), Ordering::Relaxed);
+    }
+
+    // Redis increments the reversed bits of the SCAN cursor,
+    // so the reversed cursor indicates the ratio of the scanned buckets
+    // even if the hash table is resized.
+    pub fn get_scanned_ratio(&self) -> f64 {
+        let reversed = self.scan_cursor.load(Ordering::Relaxed).reverse_bits();
+        reversed as f64 / u64::MAX as f64
+    }
+
+    pub fn to_progress(
+        &self,
+        cluster_name: ClusterName,
+        meta: &MigrationMeta,
+    ) -> MigrationProgress {
+        MigrationProgress {
+            cluster_name,
+            src_node_address: meta.src_node_address.clone(),
+            dst_node_address: meta.dst_node_address.clone(),
+            keys_scanned: self.keys_scanned.load(Ordering::Relaxed),
+            keys_migrated: self.keys_migrated.load(Ordering::Relaxed),
+            bytes_transferred: self.bytes_transferred.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            scanned_ratio: self.get_scanned_ratio(),
+            elapsed_secs: self.start_time.elapsed().as_secs(),
+        }
+    }
+}
+
 pub trait MigratingTask: ThreadSafe {
     type Task: CmdTask;
 
@@ -99,6 +188,11 @@ pub trait MigratingTask: ThreadSafe {
     ) -> Result<(), ClusterSendError<BlockingHintTask<Self::Task>>>;
     fn get_state(&self) -> MigrationState;
     fn contains_slot(&self, slot: usize) -> bool;
+    // Only the scanning is paused. The keys accessed by the clients are still migrated.
+    fn set_paused(&self, paused: bool);
+    fn is_paused(&self) -> bool;
+    fn get_progress(&self) -> MigrationProgress;
+    fn update_config(&self, config: MigrationConfig);
     fn get_stop_handle(&self) -> Option<Box<dyn Drop + Send + Sync + 'static>>;
 }
 
@@ -218,6 +312,36 @@ impl SlotRangeArray {
         self.range_map.contains_slot(slot)
     }
 
+    // Returns the first slot after `slot` inside the ranges.
+    pub fn next_slot(&self, slot: Option<usize>) -> Option<usize> {
+        self.ranges
+            .get_ranges()
+            .iter()
+            .filter_map(|range| match slot {
+                None => Some(range.start()),
+                Some(slot) if slot < range.start() => Some(range.start()),
+                Some(slot) if slot < range.end() => Some(slot + 1),
+                _ => None,
+            })
+            .min()
+    }
+
+    // The ratio of the slots before `slot`.
+    pub fn get_slot_ratio(&self, slot: usize) -> f64 {
+        let mut total = 0;
+        let mut before = 0;
+        for range in self.ranges.get_ranges().iter() {
+            total += range.end() - range.start() + 1;
+            if slot > range.start() {
+                before += min(slot, range.end() + 1) - range.start();
+            }
+        }
+        if total == 0 {
+            return 1.0;
+        }
+        before as f64 / total as f64
+    }
+
     pub fn info(&self) -> String {
         self.ranges
             .get_ranges()
@@ -249,3 +373,27 @@ impl ScanResponse {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slot_range_array_next_slot() {
+        let ranges = RangeList::new(vec![Range(5, 6), Range(10, 11)]);
+        let slot_ranges = SlotRangeArray::new(ranges);
+
+        let mut slots = vec![];
+        let mut slot = None;
+        while let Some(s) = slot_ranges.next_slot(slot) {
+            slots.push(s);
+            slot = Some(s);
+        }
+        assert_eq!(slots, vec![5, 6, 10, 11]);
+
+        assert_eq!(slot_ranges.get_slot_ratio(5), 0.0);
+        assert_eq!(slot_ranges.get_slot_ratio(10), 0.5);
+        assert_eq!(slot_ranges.get_slot_ratio(11), 0.75);
+        assert_eq!(slot_ranges.get_slot_ratio(12), 1.0);
+    }
+}
@@ -1,7 +1,9 @@
+use super::circuit_breaker::CircuitBreaker;
 use super::command::{CommandError, CommandResult};
 use super::service::ServerProxyConfig;
 use super::slowlog::TaskEvent;
 use crate::common::batch::TryChunksTimeoutStreamExt;
+use crate::common::response;
 use crate::common::utils::{resolve_first_address, RetryError, ThreadSafe};
 use crate::protocol::{
     new_simple_packet_codec, DecodeError, EncodeError, EncodedPacket, FromResp, MonoPacket,
@@ -9,7 +11,9 @@ use crate::protocol::{
 };
 use either::Either;
 use futures::channel::mpsc;
-use futures::{select, stream, Future, FutureExt, Sink, SinkExt, Stream, StreamExt, TryStreamExt};
+use futures::{
+    future, select, stream, Future, FutureExt, Sink, SinkExt, Stream, StreamExt, TryStreamExt,
+};
 use futures_timer::Delay;
 use std::boxed::Box;
 use std::error::Error;
@@ -62,6 +66,12 @@ pub trait CmdTask: ThreadSafe {
         Self: Sized;
 
     fn log_event(&mut self, event: TaskEvent);
+
+    // Blocking commands like BLPOP could take arbitrary time
+    // so they are not limited by the request timeout.
+    fn is_blocking_cmd(&self) -> bool {
+        false
+    }
 }
 
 pub trait IntoTask<T: CmdTask>: CmdTask {
@@ -219,20 +229,34 @@ impl<T: CmdTask> CmdTask for ReqTask<T> {
             }
         }
     }
+
+    fn is_blocking_cmd(&self) -> bool {
+        match self {
+            Self::Simple(t) => t.is_blocking_cmd(),
+            Self::Multi(v) => v.iter().any(|t| t.is_blocking_cmd()),
+        }
+    }
 }
 
 #[derive(Debug)]
-pub struct BackendSendError<T>(T);
+pub enum BackendSendError<T> {
+    Closed(T),
+    CircuitOpen(T),
+}
 
 impl<T> BackendSendError<T> {
     pub fn into_inner(self) -> T {
-        self.0
+        match self {
+            Self::Closed(t) => t,
+            Self::CircuitOpen(t) => t,
+        }
     }
 }
 
 pub struct BackendNode<H: CmdTaskResultHandler> {
     tx: mpsc::UnboundedSender<H::Task>,
     conn_failed: Arc<AtomicBool>,
+    circuit_breaker: Arc<CircuitBreaker>,
 }
 
 impl<H: CmdTaskResultHandler> BackendNode<H> {
@@ -241,6 +265,7 @@ impl<H: CmdTaskResultHandler> BackendNode<H> {
         handler: Arc<H>,
         config: Arc<ServerProxyConfig>,
         conn_factory: Arc<CF>,
+        circuit_breaker: Arc<CircuitBreaker>,
     ) -> (
         BackendNode<H>,
         impl Future<Output = Result<(), BackendError>> + Send,
@@ -257,23 +282,33 @@ impl<H: CmdTaskResultHandler> BackendNode<H> {
             rx,
             conn_failed.clone(),
             address,
-            config.backend_batch_min_time,
-            config.backend_batch_max_time,
-            config.backend_batch_buf,
+            config.get_backend_batch_min_time(),
+            config.get_backend_batch_max_time(),
+            config.get_backend_batch_buf(),
             conn_factory,
+            config.clone(),
+            circuit_breaker.clone(),
         );
-        (Self { tx, conn_failed }, handle_backend_fut)
+        let node = Self {
+            tx,
+            conn_failed,
+            circuit_breaker,
+        };
+        (node, handle_backend_fut)
     }
 
     pub fn send(&self, mut cmd_task: H::Task) -> Result<(), BackendSendError<H::Task>> {
         cmd_task.log_event(TaskEvent::SentToWritingQueue);
         if self.conn_failed.load(Ordering::SeqCst) {
-            return Err(BackendSendError(cmd_task));
+            return Err(BackendSendError::Closed(cmd_task));
+        }
+        if !self.circuit_breaker.allow_request() {
+            return Err(BackendSendError::CircuitOpen(cmd_task));
         }
         self.tx
             .unbounded_send(cmd_task)
             .map(|_| ())
-            .map_err(|e| BackendSendError(e.into_inner()))
+            .map_err(|e| BackendSendError::Closed(e.into_inner()))
     }
 
     pub fn is_closed(&self) -> bool {
@@ -369,6 +404,8 @@ pub async fn handle_backend<H, F>(
     backend_batch_max_time: usize,
     backend_batch_buf: NonZeroUsize,
     conn_factory: Arc<F>,
+    config: Arc<ServerProxyConfig>,
+    circuit_breaker: Arc<CircuitBreaker>,
 ) -> Result<(), BackendError>
 where
     H: CmdTaskResultHandler,
@@ -431,6 +468,8 @@ where
             handler.clone(),
             backend_batch_buf,
             retry_state.take(),
+            &config,
+            &circuit_breaker,
         )
         .await;
         match res {
@@ -447,6 +486,7 @@ where
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_conn<H, S>(
     mut writer: ConnSink<<<H as CmdTaskResultHandler>::Task as CmdTask>::Pkt>,
     mut reader: ConnStream<<<H as CmdTaskResultHandler>::Task as CmdTask>::Pkt>,
@@ -454,6 +494,8 @@ async fn handle_conn<H, S>(
     handler: Arc<H>,
     backend_batch_buf: NonZeroUsize,
     mut retry_state_opt: Option<RetryState<H::Task>>,
+    config: &ServerProxyConfig,
+    circuit_breaker: &CircuitBreaker,
 ) -> Result<(), (BackendError, Option<RetryState<H::Task>>)>
 where
     H: CmdTaskResultHandler,
@@ -491,6 +533,15 @@ where
             return Err((err, retry_state));
         }
 
+        // All the tasks in the batch are sent at the same time
+        // so they share the same deadline.
+        let request_timeout = config.get_backend_request_timeout();
+        let mut deadline = if request_timeout == 0 {
+            None
+        } else {
+            Some(Delay::new(Duration::from_millis(request_timeout)))
+        };
+
         let mut tasks_iter = tasks.into_iter();
         // `while let` will consume ownership.
         #[allow(clippy::while_let_loop)]
@@ -499,7 +550,30 @@ where
                 Some(task) => task,
                 None => break,
             };
-            let packet_res = match reader.next().await {
+            // The replies after the blocking command also need to wait for it.
+            if task.is_blocking_cmd() {
+                deadline = None;
+            }
+            let pkt_opt = match deadline.as_mut() {
+                None => reader.next().await,
+                Some(deadline) => match future::select(reader.next(), deadline).await {
+                    future::Either::Left((pkt_opt, _)) => pkt_opt,
+                    future::Either::Right(_) => {
+                        error!("backend request timeout");
+                        circuit_breaker.on_timeout();
+                        // The commands could have been processed
+                        // so they can't be retried.
+                        let err_msg = response::ERR_BACKEND_TIMEOUT.to_string().into_bytes();
+                        task.set_resp_result(Ok(Resp::Error(err_msg.clone())));
+                        for t in tasks_iter {
+                            t.set_resp_result(Ok(Resp::Error(err_msg.clone())));
+                        }
+                        // The connection will be recreated.
+                        return Err((BackendError::Timeout, None));
+                    }
+                },
+            };
+            let packet_res = match pkt_opt {
                 Some(pkt) => pkt,
                 None => {
                     error!("Failed to read packet. Connection is closed.");
@@ -512,6 +586,7 @@ where
             };
 
             task.log_event(TaskEvent::ReceivedFromBackend);
+            circuit_breaker.on_success();
             handler.handle_task(task, packet_res);
         }
     }
@@ -552,6 +627,7 @@ pub enum BackendError {
     InvalidAddress,
     Canceled,
     InvalidState,
+    Timeout,
 }
 
 impl BackendError {
@@ -563,6 +639,7 @@ impl BackendError {
             SenderBackendError::InvalidAddress => Either::Left(BackendError::InvalidAddress),
             SenderBackendError::Canceled => Either::Left(BackendError::Canceled),
             SenderBackendError::InvalidState => Either::Left(BackendError::InvalidState),
+            SenderBackendError::Timeout => Either::Left(BackendError::Timeout),
             SenderBackendError::Retry(task) => Either::Right(RetryError::new(task)),
         }
     }
@@ -594,6 +671,7 @@ pub enum SenderBackendError<T> {
     InvalidAddress,
     Canceled,
     InvalidState,
+    Timeout,
     Retry(T),
 }
 
@@ -606,6 +684,7 @@ impl<T> SenderBackendError<T> {
             BackendError::InvalidAddress => SenderBackendError::InvalidAddress,
             BackendError::Canceled => SenderBackendError::Canceled,
             BackendError::InvalidState => SenderBackendError::InvalidState,
+            BackendError::Timeout => SenderBackendError::Timeout,
         }
     }
 
@@ -621,6 +700,7 @@ impl<T> SenderBackendError<T> {
             Self::InvalidAddress => SenderBackendError::InvalidAddress,
             Self::Canceled => SenderBackendError::Canceled,
             Self::InvalidState => SenderBackendError::InvalidState,
+            Self::Timeout => SenderBackendError::Timeout,
             Self::Retry(task) => SenderBackendError::Retry(f(task)),
         }
     }
@@ -635,6 +715,7 @@ impl<T> fmt::Debug for SenderBackendError<T> {
             Self::InvalidAddress => write!(f, "backendError::InvalidAddress"),
             Self::Canceled => write!(f, "backendError::Canceled"),
             Self::InvalidState => write!(f, "backendError::InvalidState"),
+            Self::Timeout => write!(f, "backendError::Timeout"),
             Self::Retry(_) => write!(f, "BackendError::Retry"),
         }
     }
@@ -658,3 +739,137 @@ impl<T> Error for SenderBackendError<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::command::{new_command_pair, CmdReplyReceiver, Command};
+    use super::super::reply::ReplyCommitHandler;
+    use super::super::service::gen_testing_config;
+    use super::super::session::CmdCtx;
+    use super::*;
+    use crate::common::cluster::ClusterName;
+    use crate::protocol::{Array, BinSafeStr, BulkStr, RespPacket};
+    use std::convert::TryFrom;
+    use std::sync::atomic::AtomicUsize;
+    use tokio;
+
+    // The first connection replies after `first_reply_delay`, or never replies if it's None.
+    // The connections created later reply immediately.
+    struct DelayedConnFactory {
+        created: Arc<AtomicUsize>,
+        first_reply_delay: Option<Duration>,
+    }
+
+    impl ConnFactory for DelayedConnFactory {
+        type Pkt = RespPacket;
+
+        fn create_conn(
+            &self,
+            _addr: SocketAddr,
+        ) -> Pin<Box<dyn Future<Output = CreateConnResult<Self::Pkt>> + Send>> {
+            let created = self.created.fetch_add(1, Ordering::SeqCst);
+            let writer: ConnSink<RespPacket> =
+                Box::pin(futures::sink::drain().sink_map_err(|_| BackendError::Canceled));
+            let gen_reply = || Ok(RespPacket::Data(Resp::Simple(b"OK".to_vec())));
+            let reader: ConnStream<RespPacket> = match (created, self.first_reply_delay) {
+                (0, None) => Box::pin(stream::pending()),
+                (0, Some(delay)) => Box::pin(
+                    stream::once(async move {
+                        Delay::new(delay).await;
+                        gen_reply()
+                    })
+                    .chain(stream::pending()),
+                ),
+                _ => Box::pin(stream::repeat(()).map(move |()| gen_reply())),
+            };
+            Box::pin(async move { Ok((writer, reader)) })
+        }
+    }
+
+    fn gen_cmd_ctx(command: Vec<&'static str>) -> (CmdCtx, CmdReplyReceiver) {
+        let resp = Resp::Arr(Array::Arr(
+            command
+                .into_iter()
+                .map(|s| Resp::Bulk(BulkStr::Str(s.to_string().into_bytes())))
+                .collect(),
+        ));
+        let cluster = ClusterName::try_from("mycluster").unwrap();
+        let cmd = Command::new(Box::new(RespPacket::from_resp_vec(resp)));
+        let (reply_sender, reply_receiver) = new_command_pair(&cmd);
+        let cmd_ctx = CmdCtx::new(cluster, cmd, reply_sender, 0, true);
+        (cmd_ctx, reply_receiver)
+    }
+
+    async fn get_reply(reply_receiver: CmdReplyReceiver) -> BinSafeStr {
+        let (_, packet, _) = reply_receiver.await.unwrap().into_inner();
+        match packet.to_resp_slice() {
+            Resp::Simple(s) => s.to_vec(),
+            Resp::Error(err) => err.to_vec(),
+            others => format!("invalid_reply {:?}", others).into_bytes(),
+        }
+    }
+
+    fn run_backend(
+        first_reply_delay: Option<Duration>,
+    ) -> (
+        mpsc::UnboundedSender<CmdCtx>,
+        Arc<AtomicUsize>,
+        tokio::task::JoinHandle<Result<(), BackendError>>,
+    ) {
+        let config = gen_testing_config();
+        config.backend_request_timeout.store(50, Ordering::SeqCst);
+        let created = Arc::new(AtomicUsize::new(0));
+        let conn_factory = DelayedConnFactory {
+            created: created.clone(),
+            first_reply_delay,
+        };
+        let (sender, receiver) = mpsc::unbounded();
+        let handle = tokio::spawn(handle_backend(
+            Arc::new(ReplyCommitHandler),
+            receiver,
+            Arc::new(AtomicBool::new(false)),
+            "127.0.0.1:6379".to_string(),
+            0,
+            0,
+            NonZeroUsize::new(1).unwrap(),
+            Arc::new(conn_factory),
+            Arc::new(config),
+            Arc::new(CircuitBreaker::new(0, Duration::from_secs(1))),
+        ));
+        (sender, created, handle)
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_recreates_conn() {
+        let (sender, created, handle) = run_backend(None);
+
+        let (cmd_ctx, reply_receiver) = gen_cmd_ctx(vec!["GET", "key"]);
+        sender.unbounded_send(cmd_ctx).unwrap();
+        assert_eq!(
+            get_reply(reply_receiver).await,
+            response::ERR_BACKEND_TIMEOUT.as_bytes()
+        );
+
+        // The timed out connection is dropped and a new one is created.
+        let (cmd_ctx, reply_receiver) = gen_cmd_ctx(vec!["GET", "key"]);
+        sender.unbounded_send(cmd_ctx).unwrap();
+        assert_eq!(get_reply(reply_receiver).await, b"OK");
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+
+        drop(sender);
+        assert!(matches!(handle.await.unwrap(), Err(BackendError::Canceled)));
+    }
+
+    #[tokio::test]
+    async fn test_blocking_cmd_without_deadline() {
+        let (sender, created, handle) = run_backend(Some(Duration::from_millis(200)));
+
+        let (cmd_ctx, reply_receiver) = gen_cmd_ctx(vec!["BZPOPMIN", "key", "0"]);
+        sender.unbounded_send(cmd_ctx).unwrap();
+        assert_eq!(get_reply(reply_receiver).await, b"OK");
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+
+        drop(sender);
+        assert!(matches!(handle.await.unwrap(), Err(BackendError::Canceled)));
+    }
+}
@@ -68,7 +68,7 @@ where
     CF::Pkt: Send,
 {
     RRSenderGroupFactory::new(
-        config.backend_conn_num,
+        config.clone(),
         RecoverableBackendNodeFactory::new(
             config.clone(),
             reply_handler_factory,
@@ -516,6 +516,10 @@ impl<T: CmdTask> CmdTask for CounterTask<T> {
     fn log_event(&mut self, event: TaskEvent) {
         self.inner.log_event(event)
     }
+
+    fn is_blocking_cmd(&self) -> bool {
+        self.inner.is_blocking_cmd()
+    }
 }
 
 pub struct BlockingHintTask<T: CmdTask> {
@@ -579,6 +583,10 @@ impl<T: CmdTask> CmdTask for BlockingHintTask<T> {
     fn log_event(&mut self, event: TaskEvent) {
         self.inner.log_event(event)
     }
+
+    fn is_blocking_cmd(&self) -> bool {
+        self.inner.is_blocking_cmd()
+    }
 }
 
 impl<T: CmdTask + ClusterTag> ClusterTag for BlockingHintTask<T> {
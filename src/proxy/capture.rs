@@ -0,0 +1,330 @@
+use crate::common::cluster::ClusterName;
+use crate::protocol::{encode_resp, BinSafeStr, RespPacket};
+use arc_swap::ArcSwapOption;
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::cmp::max;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+const CAPTURE_MAGIC: &[u8] = b"UMCAP\x01";
+// The records are dropped when the writer thread can't catch up.
+const CAPTURE_CHANNEL_SIZE: usize = 4096;
+
+// The capture file starts with `CAPTURE_MAGIC` followed by the records.
+// All the integers are in big endian:
+// - timestamp when the request is received in nanoseconds (u64)
+// - latency in nanoseconds (u64)
+// - session id (u64)
+// - the number of the request elements (u32), followed by every element
+//   as the length (u32) and the bytes
+// - the length of the reply (u32), followed by the reply in RESP
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureRecord {
+    pub timestamp: u64,
+    pub latency: u64,
+    pub session_id: u64,
+    pub request: Vec<BinSafeStr>,
+    pub reply: Vec<u8>,
+}
+
+impl CaptureRecord {
+    // `request` is the command received from the client
+    // before being modified by the proxy, e.g. adding the key prefix.
+    pub fn new(
+        timestamp: u64,
+        latency: u64,
+        session_id: u64,
+        request: Vec<BinSafeStr>,
+        reply: &RespPacket,
+    ) -> io::Result<Self> {
+        let mut reply_buf = vec![];
+        encode_resp(&mut reply_buf, &reply.to_resp_slice())?;
+        Ok(Self {
+            timestamp,
+            latency,
+            session_id,
+            request,
+            reply: reply_buf,
+        })
+    }
+
+    pub fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.timestamp.to_be_bytes())?;
+        writer.write_all(&self.latency.to_be_bytes())?;
+        writer.write_all(&self.session_id.to_be_bytes())?;
+        write_len(writer, self.request.len())?;
+        for element in self.request.iter() {
+            write_len(writer, element.len())?;
+            writer.write_all(element)?;
+        }
+        write_len(writer, self.reply.len())?;
+        writer.write_all(&self.reply)
+    }
+
+    // Returns None at the end of the file.
+    pub fn decode<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut buf = [0; 8];
+        match reader.read_exact(&mut buf) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let timestamp = u64::from_be_bytes(buf);
+        reader.read_exact(&mut buf)?;
+        let latency = u64::from_be_bytes(buf);
+        reader.read_exact(&mut buf)?;
+        let session_id = u64::from_be_bytes(buf);
+
+        let element_num = read_len(reader)?;
+        let mut request = Vec::with_capacity(element_num);
+        for _ in 0..element_num {
+            request.push(read_bytes(reader)?);
+        }
+        let reply = read_bytes(reader)?;
+        Ok(Some(Self {
+            timestamp,
+            latency,
+            session_id,
+            request,
+            reply,
+        }))
+    }
+}
+
+pub fn write_capture_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(CAPTURE_MAGIC)
+}
+
+pub fn read_capture_header<R: Read>(reader: &mut R) -> io::Result<()> {
+    let mut buf = [0; CAPTURE_MAGIC.len()];
+    reader.read_exact(&mut buf)?;
+    if buf[..] != *CAPTURE_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid capture file",
+        ));
+    }
+    Ok(())
+}
+
+fn write_len<W: Write>(writer: &mut W, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "data too large"))?;
+    writer.write_all(&len.to_be_bytes())
+}
+
+fn read_len<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf) as usize)
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_len(reader)?;
+    let mut data = vec![0; len];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+// Only a file name is accepted so that the clients can't write files
+// outside `capture_dir`.
+pub fn get_capture_path(capture_dir: &str, file_name: &str) -> Option<PathBuf> {
+    let mut components = Path::new(file_name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Some(Path::new(capture_dir).join(file_name)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaptureStats {
+    pub records: u64,
+    pub dropped: u64,
+}
+
+struct CaptureTask {
+    cluster_name: ClusterName,
+    sample_rate: u64,
+    count: AtomicU64,
+    records: AtomicU64,
+    dropped: AtomicU64,
+    sender: Sender<CaptureRecord>,
+}
+
+// Captures the sampled requests and replies of one cluster into a local file
+// which could be replayed by the `replay` binary.
+// Only one cluster could be captured at the same time.
+// The records are written by a dedicated thread so the sessions never block on the file.
+#[derive(Default)]
+pub struct TrafficCapture {
+    task: ArcSwapOption<CaptureTask>,
+}
+
+impl TrafficCapture {
+    // The running capture will be stopped.
+    pub fn start(
+        &self,
+        cluster_name: ClusterName,
+        path: &Path,
+        sample_rate: u64,
+    ) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_capture_header(&mut writer)?;
+        let (sender, receiver) = crossbeam_channel::bounded(CAPTURE_CHANNEL_SIZE);
+        thread::Builder::new()
+            .name("capture-writer".to_string())
+            .spawn(move || write_records(writer, receiver))?;
+        let task = CaptureTask {
+            cluster_name,
+            sample_rate: max(1, sample_rate),
+            count: AtomicU64::new(0),
+            records: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            sender,
+        };
+        self.task.store(Some(Arc::new(task)));
+        Ok(())
+    }
+
+    // The writer thread exits after writing the records left in the channel.
+    pub fn stop(&self) -> Option<CaptureStats> {
+        let task = self.task.swap(None)?;
+        Some(CaptureStats {
+            records: task.records.load(Ordering::Relaxed),
+            dropped: task.dropped.load(Ordering::Relaxed),
+        })
+    }
+
+    pub fn is_capturing(&self, cluster_name: &ClusterName) -> bool {
+        match self.task.load() {
+            Some(task) => task.cluster_name == *cluster_name,
+            None => false,
+        }
+    }
+
+    // Returns whether the request of this cluster should be captured.
+    pub fn sample(&self, cluster_name: &ClusterName) -> bool {
+        let task = match self.task.load() {
+            Some(task) => task,
+            None => return false,
+        };
+        if task.cluster_name != *cluster_name {
+            return false;
+        }
+        task.count.fetch_add(1, Ordering::Relaxed) % task.sample_rate == 0
+    }
+
+    // The record should be sampled by `sample`.
+    pub fn record(&self, record: CaptureRecord) {
+        let task = match self.task.load() {
+            Some(task) => task,
+            None => return,
+        };
+        match task.sender.try_send(record) {
+            Ok(()) => task.records.fetch_add(1, Ordering::Relaxed),
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                task.dropped.fetch_add(1, Ordering::Relaxed)
+            }
+        };
+    }
+}
+
+fn write_records(mut writer: BufWriter<File>, receiver: Receiver<CaptureRecord>) {
+    for record in receiver.iter() {
+        if let Err(err) = record.encode(&mut writer) {
+            error!("failed to write capture record: {}", err);
+            return;
+        }
+        if receiver.is_empty() {
+            if let Err(err) = writer.flush() {
+                error!("failed to flush capture file: {}", err);
+                return;
+            }
+        }
+    }
+    if let Err(err) = writer.flush() {
+        error!("failed to flush capture file: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{BulkStr, Resp};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_capture_record() {
+        let request = vec![b"GET".to_vec(), b"key".to_vec()];
+        let reply = RespPacket::from_resp_vec(Resp::Bulk(BulkStr::Str(b"value".to_vec())));
+        let record = CaptureRecord::new(233, 10, 1, request, &reply).unwrap();
+        assert_eq!(record.request, vec![b"GET".to_vec(), b"key".to_vec()]);
+        assert_eq!(record.reply, b"$5\r\nvalue\r\n".to_vec());
+
+        let mut buf = vec![];
+        write_capture_header(&mut buf).unwrap();
+        record.encode(&mut buf).unwrap();
+        record.encode(&mut buf).unwrap();
+
+        let mut reader = Cursor::new(buf);
+        read_capture_header(&mut reader).unwrap();
+        assert_eq!(
+            CaptureRecord::decode(&mut reader).unwrap(),
+            Some(record.clone())
+        );
+        assert_eq!(CaptureRecord::decode(&mut reader).unwrap(), Some(record));
+        assert_eq!(CaptureRecord::decode(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_capture_header() {
+        let mut reader = Cursor::new(b"invalid".to_vec());
+        assert!(read_capture_header(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_traffic_capture() {
+        let capture = TrafficCapture::default();
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let other_cluster_name = ClusterName::try_from("othercluster").unwrap();
+        let path = std::env::temp_dir().join("undermoon_test_traffic_capture");
+        capture.start(cluster_name.clone(), &path, 2).unwrap();
+        assert!(capture.is_capturing(&cluster_name));
+        assert!(!capture.is_capturing(&other_cluster_name));
+        assert!(!capture.sample(&other_cluster_name));
+        assert!(capture.sample(&cluster_name));
+        assert!(!capture.sample(&cluster_name));
+
+        let reply = RespPacket::from_resp_vec(Resp::Simple(b"OK".to_vec()));
+        let record = CaptureRecord::new(233, 10, 1, vec![b"PING".to_vec()], &reply).unwrap();
+        capture.record(record);
+        assert_eq!(
+            capture.stop(),
+            Some(CaptureStats {
+                records: 1,
+                dropped: 0
+            })
+        );
+        assert!(!capture.is_capturing(&cluster_name));
+        assert_eq!(capture.stop(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_get_capture_path() {
+        assert_eq!(
+            get_capture_path("/tmp/capture", "cap1"),
+            Some(PathBuf::from("/tmp/capture/cap1"))
+        );
+        assert_eq!(get_capture_path("/tmp/capture", ""), None);
+        assert_eq!(get_capture_path("/tmp/capture", ".."), None);
+        assert_eq!(get_capture_path("/tmp/capture", "../cap1"), None);
+        assert_eq!(get_capture_path("/tmp/capture", "dir/cap1"), None);
+        assert_eq!(get_capture_path("/tmp/capture", "/etc/passwd"), None);
+    }
+}
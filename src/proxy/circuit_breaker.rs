@@ -0,0 +1,127 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Shared by all the connections to the same backend address.
+// After `threshold` consecutive request timeouts, the circuit is open
+// and the requests will fail fast.
+// While it's open, only one request will be sent as a probe for each `open_time`.
+// Any reply from the backend closes the circuit.
+pub struct CircuitBreaker {
+    // Zero to disable it.
+    threshold: u64,
+    open_time: Duration,
+    timeouts: AtomicU64,
+    // In milliseconds since `start_time`.
+    next_probe_time: AtomicU64,
+    start_time: Instant,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u64, open_time: Duration) -> Self {
+        Self {
+            threshold,
+            open_time,
+            timeouts: AtomicU64::new(0),
+            next_probe_time: AtomicU64::new(0),
+            start_time: Instant::now(),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.threshold != 0 && self.timeouts.load(Ordering::Relaxed) >= self.threshold
+    }
+
+    // Returns false if the request should fail fast.
+    pub fn allow_request(&self) -> bool {
+        self.allow_request_at(Instant::now())
+    }
+
+    pub fn on_success(&self) {
+        // Avoid the writes in the most common case.
+        if self.timeouts.load(Ordering::Relaxed) != 0 {
+            self.timeouts.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn on_timeout(&self) {
+        self.on_timeout_at(Instant::now())
+    }
+
+    fn allow_request_at(&self, now: Instant) -> bool {
+        if !self.is_open() {
+            return true;
+        }
+        let now = self.to_millis(now);
+        let next_probe_time = self.next_probe_time.load(Ordering::SeqCst);
+        if now < next_probe_time {
+            return false;
+        }
+        self.next_probe_time
+            .compare_exchange(
+                next_probe_time,
+                now + self.open_time.as_millis() as u64,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+    }
+
+    fn on_timeout_at(&self, now: Instant) {
+        let timeouts = self.timeouts.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.threshold != 0 && timeouts >= self.threshold {
+            let next_probe_time = self.to_millis(now) + self.open_time.as_millis() as u64;
+            self.next_probe_time
+                .store(next_probe_time, Ordering::SeqCst);
+        }
+    }
+
+    fn to_millis(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.start_time).as_millis() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(1));
+        let now = breaker.start_time;
+        assert!(breaker.allow_request_at(now));
+
+        breaker.on_timeout_at(now);
+        assert!(!breaker.is_open());
+        breaker.on_success();
+        breaker.on_timeout_at(now);
+        assert!(!breaker.is_open());
+        breaker.on_timeout_at(now);
+        assert!(breaker.is_open());
+        assert!(!breaker.allow_request_at(now));
+
+        // Only one probe.
+        let now = now + Duration::from_secs(1);
+        assert!(breaker.allow_request_at(now));
+        assert!(!breaker.allow_request_at(now));
+
+        // The probe timed out.
+        breaker.on_timeout_at(now);
+        assert!(!breaker.allow_request_at(now + Duration::from_millis(500)));
+
+        let now = now + Duration::from_secs(1);
+        assert!(breaker.allow_request_at(now));
+        breaker.on_success();
+        assert!(!breaker.is_open());
+        assert!(breaker.allow_request_at(now));
+    }
+
+    #[test]
+    fn test_disabled_circuit_breaker() {
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(1));
+        for _ in 0..10 {
+            breaker.on_timeout();
+        }
+        assert!(!breaker.is_open());
+        assert!(breaker.allow_request());
+    }
+}
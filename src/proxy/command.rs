@@ -1,6 +1,6 @@
 use super::slowlog::Slowlog;
 use crate::common::utils::{byte_to_uppercase, generate_slot};
-use crate::protocol::{BinSafeStr, RespPacket, RespSlice, RespVec};
+use crate::protocol::{Array, BinSafeStr, BulkStr, Resp, RespPacket, RespSlice, RespVec};
 use arrayvec::ArrayVec;
 use backtrace::Backtrace;
 use futures::channel::oneshot;
@@ -323,6 +323,14 @@ impl Command {
         self.request.get_array_last_element()
     }
 
+    // The total length of the elements without the protocol overhead.
+    pub fn get_command_bytes(&self) -> usize {
+        (0..self.get_command_len().unwrap_or(0))
+            .filter_map(|i| self.get_command_element(i))
+            .map(<[u8]>::len)
+            .sum()
+    }
+
     pub fn get_command_name(&self) -> Option<&str> {
         self.request.get_command_name()
     }
@@ -331,6 +339,25 @@ impl Command {
         self.request.change_bulk_array_element(index, data)
     }
 
+    pub fn rename(&mut self, cmd_name: Vec<u8>) -> bool {
+        if !self.request.change_bulk_array_element(0, cmd_name) {
+            return false;
+        }
+        self.info = CommandInfo::new(&self.request);
+        true
+    }
+
+    pub fn replace_elements(&mut self, elements: Vec<BinSafeStr>) {
+        let resp = Resp::Arr(Array::Arr(
+            elements
+                .into_iter()
+                .map(|element| Resp::Bulk(BulkStr::Str(element)))
+                .collect(),
+        ));
+        *self.request = RespPacket::from_resp_vec(resp);
+        self.info = CommandInfo::new(&self.request);
+    }
+
     pub fn extract_inner_cmd(&mut self, removed_num: usize) -> Option<usize> {
         let remaining = self.request.left_trim_cmd(removed_num)?;
         self.info = CommandInfo::new(&self.request);
@@ -360,6 +387,24 @@ impl Command {
     pub fn get_slot(&self) -> Option<usize> {
         self.info.slot
     }
+
+    // The commands which could wait on the server side for arbitrary time.
+    pub fn is_blocking(&self) -> bool {
+        let cmd_name = match self.get_command_name() {
+            Some(cmd_name) => cmd_name.to_uppercase(),
+            None => return false,
+        };
+        match cmd_name.as_str() {
+            "BLPOP" | "BRPOP" | "BRPOPLPUSH" | "BLMOVE" | "BLMPOP" | "BZPOPMIN" | "BZPOPMAX"
+            | "BZMPOP" | "WAIT" => true,
+            // XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+            "XREAD" | "XREADGROUP" => (1..self.get_command_len().unwrap_or(0))
+                .filter_map(|i| self.get_command_element(i))
+                .take_while(|element| !element.eq_ignore_ascii_case(b"STREAMS"))
+                .any(|element| element.eq_ignore_ascii_case(b"BLOCK")),
+            _ => false,
+        }
+    }
 }
 
 pub struct TaskReply {
@@ -534,6 +579,49 @@ mod tests {
         assert_eq!(DataCmdType::from_cmd_name(b"HMGET"), DataCmdType::Others);
     }
 
+    fn gen_command(elements: &[&str]) -> Command {
+        let request = RespPacket::Data(Resp::Arr(Array::Arr(
+            elements
+                .iter()
+                .map(|e| Resp::Bulk(BulkStr::Str(e.as_bytes().to_vec())))
+                .collect(),
+        )));
+        Command::new(Box::new(request))
+    }
+
+    #[test]
+    fn test_blocking_command() {
+        assert!(gen_command(&["blpop", "key", "0"]).is_blocking());
+        assert!(gen_command(&["BRPOP", "key", "0"]).is_blocking());
+        assert!(gen_command(&["BRPOPLPUSH", "a", "b", "0"]).is_blocking());
+        assert!(gen_command(&["BLMOVE", "a", "b", "LEFT", "RIGHT", "0"]).is_blocking());
+        assert!(gen_command(&["BLMPOP", "0", "1", "key", "LEFT"]).is_blocking());
+        assert!(gen_command(&["BZPOPMIN", "key", "1"]).is_blocking());
+        assert!(gen_command(&["BZPOPMAX", "key", "1"]).is_blocking());
+        assert!(gen_command(&["bzmpop", "0", "1", "key", "MIN"]).is_blocking());
+        assert!(gen_command(&["WAIT", "1", "0"]).is_blocking());
+        assert!(
+            gen_command(&["XREAD", "COUNT", "1", "block", "0", "STREAMS", "s", "$"]).is_blocking()
+        );
+        assert!(gen_command(&[
+            "XREADGROUP",
+            "GROUP",
+            "g",
+            "c",
+            "BLOCK",
+            "0",
+            "STREAMS",
+            "s",
+            ">"
+        ])
+        .is_blocking());
+        assert!(!gen_command(&["XREAD", "STREAMS", "block", "0"]).is_blocking());
+        assert!(
+            !gen_command(&["XREADGROUP", "GROUP", "g", "c", "STREAMS", "s", ">"]).is_blocking()
+        );
+        assert!(!gen_command(&["GET", "key"]).is_blocking());
+    }
+
     #[test]
     fn test_umforward() {
         let request = RespPacket::Data(Resp::Arr(Array::Arr(vec![
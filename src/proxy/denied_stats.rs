@@ -0,0 +1,77 @@
+use crate::common::cluster::ClusterName;
+use crate::protocol::{Array, BulkStr, Resp, RespVec};
+use dashmap::DashMap;
+use std::str;
+
+// Records the requests rejected by the `denied_commands` of each cluster.
+#[derive(Debug, Default)]
+pub struct DeniedCommandStats {
+    denied: DashMap<(ClusterName, String), u64>,
+}
+
+impl DeniedCommandStats {
+    pub fn record(&self, cluster_name: &ClusterName, cmd_name: &[u8]) {
+        let cmd_name = str::from_utf8(cmd_name)
+            .map(str::to_lowercase)
+            .unwrap_or_else(|_| "invalid_command".to_string());
+        *self
+            .denied
+            .entry((cluster_name.clone(), cmd_name))
+            .or_insert(0) += 1;
+    }
+
+    pub fn get(&self) -> Vec<(ClusterName, String, u64)> {
+        let mut stats: Vec<(ClusterName, String, u64)> = self
+            .denied
+            .iter()
+            .map(|item| {
+                let (cluster_name, cmd_name) = item.key();
+                (cluster_name.clone(), cmd_name.clone(), *item.value())
+            })
+            .collect();
+        stats.sort_by(|(cluster1, cmd1, _), (cluster2, cmd2, _)| {
+            (cluster1.as_str(), cmd1).cmp(&(cluster2.as_str(), cmd2))
+        });
+        stats
+    }
+
+    pub fn reset(&self) {
+        self.denied.clear();
+    }
+}
+
+pub fn denied_stats_to_resp(stats: Vec<(ClusterName, String, u64)>) -> RespVec {
+    let elements = stats
+        .into_iter()
+        .map(|(cluster_name, cmd_name, denied)| {
+            Resp::Bulk(BulkStr::Str(
+                format!("{} {} {}", cluster_name, cmd_name, denied).into_bytes(),
+            ))
+        })
+        .collect();
+    Resp::Arr(Array::Arr(elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_denied_command_stats() {
+        let stats = DeniedCommandStats::default();
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        stats.record(&cluster_name, b"KEYS");
+        stats.record(&cluster_name, b"keys");
+        stats.record(&cluster_name, b"debug");
+        assert_eq!(
+            stats.get(),
+            vec![
+                (cluster_name.clone(), "debug".to_string(), 1),
+                (cluster_name, "keys".to_string(), 2),
+            ]
+        );
+        stats.reset();
+        assert!(stats.get().is_empty());
+    }
+}
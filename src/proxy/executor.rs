@@ -1,12 +1,23 @@
 use super::backend::{CmdTask, CmdTaskFactory, ConnFactory};
+use super::capture::{get_capture_path, TrafficCapture};
 use super::cluster::{ClusterMetaError, ClusterTag};
-use super::command::{CmdReplyReceiver, CmdType, DataCmdType, TaskResult};
+use super::command::{CmdReplyReceiver, CmdType, DataCmdType, TaskReply, TaskResult};
 use super::compress::{CmdCompressor, CompressionError, CompressionStrategyMetaMapConfig};
-use super::manager::{MetaManager, SharedMetaMap};
+use super::denied_stats::{denied_stats_to_resp, DeniedCommandStats};
+use super::key_prefix::{
+    add_key_prefix, get_key_indices, remove_key_prefix, KeyPrefixError, KeyReplyType,
+};
+use super::key_stats::{key_stats_to_resp, KeyStatsType};
+use super::manager::{MetaManager, SharedMetaMap, SwitchoverError};
+use super::rate_limit::{throttle_stats_to_resp, ClusterRateLimiter, ThrottleStats};
+use super::read_cache::{clears_read_cache, read_cache_stats_to_resp, CacheLookup, ReadCache};
 use super::service::ServerProxyConfig;
-use super::session::{CmdCtx, CmdCtxFactory, CmdCtxHandler, CmdReplyFuture};
+use super::session::{CmdCtx, CmdCtxFactory, CmdCtxHandler, CmdReplyFuture, SessionState};
+use super::shutdown::ShutdownSignal;
+use super::slot_stats::{slot_stats_to_resp, SlotStatsCounter};
 use super::slowlog::{slowlogs_to_resp, SlowRequestLogger};
 use crate::common::cluster::ClusterName;
+use crate::common::command_policy::{check_command_policy, is_write_command, CommandPolicyResult};
 use crate::common::config::ClusterConfig;
 use crate::common::proto::ProxyClusterMeta;
 use crate::common::response;
@@ -18,7 +29,10 @@ use crate::common::version::UNDERMOON_VERSION;
 use crate::migration::manager::SwitchError;
 use crate::migration::task::parse_switch_command;
 use crate::migration::task::MgrSubCmd;
-use crate::protocol::{Array, BulkStr, RedisClientFactory, Resp, RespPacket, RespVec, VFunctor};
+use crate::protocol::{
+    Array, BinSafeStr, BulkStr, RedisClient, RedisClientFactory, Resp, RespPacket, RespVec,
+    VFunctor,
+};
 use crate::replication::replicator::ReplicatorMeta;
 use atoi::atoi;
 use btoi::btou;
@@ -27,7 +41,7 @@ use futures_timer::Delay;
 use std::convert::TryFrom;
 use std::str;
 use std::sync::{self, Arc};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub struct SharedForwardHandler<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> {
     handler: sync::Arc<ForwardHandler<F, C>>,
@@ -50,6 +64,7 @@ where
     F: RedisClientFactory,
     C: ConnFactory<Pkt = RespPacket>,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: Arc<ServerProxyConfig>,
         cluster_config: ClusterConfig,
@@ -58,6 +73,9 @@ where
         meta_map: SharedMetaMap<C>,
         conn_factory: Arc<C>,
         future_registry: Arc<TrackedFutureRegistry>,
+        shutdown: Arc<ShutdownSignal>,
+        throttle_stats: Arc<ThrottleStats>,
+        capture: Arc<TrafficCapture>,
     ) -> Self {
         Self {
             handler: sync::Arc::new(ForwardHandler::new(
@@ -68,6 +86,9 @@ where
                 meta_map,
                 conn_factory,
                 future_registry,
+                shutdown,
+                throttle_stats,
+                capture,
             )),
         }
     }
@@ -82,19 +103,28 @@ where
         &self,
         cmd_ctx: CmdCtx,
         reply_receiver: CmdReplyReceiver,
-        session_cluster_name: &sync::RwLock<ClusterName>,
+        session_state: &sync::RwLock<SessionState>,
     ) -> CmdReplyFuture {
         self.handler
-            .handle_cmd_ctx(cmd_ctx, reply_receiver, session_cluster_name)
+            .handle_cmd_ctx(cmd_ctx, reply_receiver, session_state)
     }
 }
 
 pub struct ForwardHandler<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> {
     config: Arc<ServerProxyConfig>,
     manager: MetaManager<F, C>,
+    client_factory: Arc<F>,
     slow_request_logger: Arc<SlowRequestLogger>,
+    slot_stats: SlotStatsCounter,
     compressor: CmdCompressor<CompressionStrategyMetaMapConfig<C>>,
     future_registry: Arc<TrackedFutureRegistry>,
+    shutdown: Arc<ShutdownSignal>,
+    meta_map: SharedMetaMap<C>,
+    rate_limiter: ClusterRateLimiter,
+    throttle_stats: Arc<ThrottleStats>,
+    denied_stats: DeniedCommandStats,
+    read_cache: ReadCache,
+    capture: Arc<TrafficCapture>,
 }
 
 impl<F, C> ForwardHandler<F, C>
@@ -102,6 +132,7 @@ where
     F: RedisClientFactory,
     C: ConnFactory<Pkt = RespPacket>,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: Arc<ServerProxyConfig>,
         cluster_config: ClusterConfig,
@@ -110,20 +141,32 @@ where
         meta_map: SharedMetaMap<C>,
         conn_factory: Arc<C>,
         future_registry: Arc<TrackedFutureRegistry>,
+        shutdown: Arc<ShutdownSignal>,
+        throttle_stats: Arc<ThrottleStats>,
+        capture: Arc<TrafficCapture>,
     ) -> Self {
         Self {
             config: config.clone(),
             manager: MetaManager::new(
                 config,
                 cluster_config,
-                client_factory,
+                client_factory.clone(),
                 conn_factory,
                 meta_map.clone(),
                 future_registry.clone(),
             ),
+            client_factory,
             slow_request_logger,
-            compressor: CmdCompressor::new(CompressionStrategyMetaMapConfig::new(meta_map)),
+            slot_stats: SlotStatsCounter::default(),
+            compressor: CmdCompressor::new(CompressionStrategyMetaMapConfig::new(meta_map.clone())),
             future_registry,
+            shutdown,
+            meta_map,
+            rate_limiter: ClusterRateLimiter::default(),
+            throttle_stats,
+            denied_stats: DeniedCommandStats::default(),
+            read_cache: ReadCache::default(),
+            capture,
         }
     }
 }
@@ -133,8 +176,14 @@ where
     F: RedisClientFactory,
     C: ConnFactory<Pkt = RespPacket>,
 {
-    fn handle_auth(&self, mut cmd_ctx: CmdCtx, session_cluster_name: &sync::RwLock<ClusterName>) {
-        let key = cmd_ctx.get_key();
+    // AUTH <cluster_name>
+    // AUTH <user> <cluster_name>
+    // The user is used to find the key prefix in the `key_prefixes` of the cluster config.
+    fn handle_auth(&self, mut cmd_ctx: CmdCtx, session_state: &sync::RwLock<SessionState>) {
+        let (user, key) = match cmd_ctx.get_cmd().get_command_element(2) {
+            Some(cluster_name) => (cmd_ctx.get_key(), Some(cluster_name)),
+            None => (None, cmd_ctx.get_key()),
+        };
         let cluster = match key {
             None => {
                 return cmd_ctx.set_resp_result(Ok(Resp::Error(
@@ -159,13 +208,39 @@ where
             }
         };
 
-        *session_cluster_name
-            .write()
-            .expect("ForwardHandler::handle_auth") = cluster_name.clone();
+        let user = match user {
+            None => None,
+            Some(user) => {
+                let user = str::from_utf8(user)
+                    .ok()
+                    .filter(|user| self.get_key_prefix(&cluster_name, user).is_some());
+                match user {
+                    Some(user) => Some(Arc::from(user)),
+                    None => {
+                        return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                            response::ERR_USER_NOT_FOUND.to_string().into_bytes(),
+                        )))
+                    }
+                }
+            }
+        };
+
+        *session_state.write().expect("ForwardHandler::handle_auth") = SessionState {
+            cluster_name: cluster_name.clone(),
+            user,
+        };
         cmd_ctx.set_cluster_name(cluster_name);
         cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())));
     }
 
+    fn get_key_prefix(&self, cluster_name: &ClusterName, user: &str) -> Option<Arc<Vec<u8>>> {
+        self.meta_map
+            .lease()
+            .get_cluster_map()
+            .get_config(cluster_name)
+            .and_then(|config| config.key_prefixes.get_prefix(user))
+    }
+
     fn handle_cluster(&self, cmd_ctx: CmdCtx) {
         let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 1) {
             Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
@@ -224,10 +299,31 @@ where
         Some((cmd_ctx, sub_cmd))
     }
 
-    fn handle_umctl(&self, cmd_ctx: CmdCtx) {
+    fn parse_switchover_args(cmd_ctx: &CmdCtx) -> Option<(Duration, Vec<(String, String)>)> {
+        let cmd = cmd_ctx.get_cmd();
+        let timeout_ms = cmd
+            .get_command_element(2)
+            .and_then(|e| btou::<u64>(e).ok())?;
+        let mut addresses = vec![];
+        let mut index = 3;
+        while let Some(element) = cmd.get_command_element(index) {
+            addresses.push(str::from_utf8(element).ok()?.to_string());
+            index += 1;
+        }
+        if addresses.is_empty() || addresses.len() % 2 != 0 {
+            return None;
+        }
+        let nodes = addresses
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+        Some((Duration::from_millis(timeout_ms), nodes))
+    }
+
+    fn handle_umctl(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> CmdReplyFuture {
         let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 1) {
             Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
-            None => return,
+            None => return CmdReplyFuture::Left(reply_receiver),
         };
 
         let sub_cmd = sub_cmd.to_uppercase();
@@ -247,9 +343,15 @@ where
             let resp = self.manager.info();
             cmd_ctx.set_resp_result(Ok(resp));
         } else if sub_cmd.eq("INFOREPL") {
-            self.handle_umctl_info_repl(cmd_ctx);
+            return CmdReplyFuture::Right(Box::pin(
+                self.handle_umctl_info_repl(cmd_ctx, reply_receiver),
+            ));
         } else if sub_cmd.eq("INFOMGR") {
             self.handle_umctl_info_migration(cmd_ctx);
+        } else if sub_cmd.eq("PAUSEMGR") {
+            self.handle_umctl_pause_migration(cmd_ctx, true);
+        } else if sub_cmd.eq("RESUMEMGR") {
+            self.handle_umctl_pause_migration(cmd_ctx, false);
         } else if sub_cmd.eq(MgrSubCmd::PreCheck.as_str()) {
             self.handle_umctl_mgr_cmd(cmd_ctx, MgrSubCmd::PreCheck);
         } else if sub_cmd.eq(MgrSubCmd::PreSwitch.as_str()) {
@@ -258,17 +360,40 @@ where
             self.handle_umctl_mgr_cmd(cmd_ctx, MgrSubCmd::FinalSwitch);
         } else if sub_cmd.eq("SLOWLOG") {
             self.handle_umctl_slowlog(cmd_ctx);
+        } else if sub_cmd.eq("SLOTSTATS") {
+            self.handle_umctl_slot_stats(cmd_ctx);
+        } else if sub_cmd.eq("THROTTLESTATS") {
+            self.handle_umctl_throttle_stats(cmd_ctx);
+        } else if sub_cmd.eq("DENIEDSTATS") {
+            self.handle_umctl_denied_stats(cmd_ctx);
+        } else if sub_cmd.eq("READCACHESTATS") {
+            self.handle_umctl_read_cache_stats(cmd_ctx);
+        } else if sub_cmd.eq("CAPTURE") {
+            self.handle_umctl_capture(cmd_ctx);
+        } else if sub_cmd.eq("HOTKEYS") {
+            self.handle_umctl_key_stats(cmd_ctx, KeyStatsType::Hot);
+        } else if sub_cmd.eq("BIGKEYS") {
+            self.handle_umctl_key_stats(cmd_ctx, KeyStatsType::Big);
         } else if sub_cmd.eq("DEBUG") {
             self.handle_umctl_debug(cmd_ctx);
         } else if sub_cmd.eq("GETEPOCH") {
             self.handle_umctl_get_epoch(cmd_ctx);
         } else if sub_cmd.eq("READY") {
             self.handle_umctl_ready(cmd_ctx);
+        } else if sub_cmd.eq("SWITCHOVER") {
+            return CmdReplyFuture::Right(Box::pin(
+                self.handle_umctl_switchover(cmd_ctx, reply_receiver),
+            ));
+        } else if sub_cmd.eq("NODEHEALTH") {
+            return CmdReplyFuture::Right(Box::pin(
+                self.handle_umctl_node_health(cmd_ctx, reply_receiver),
+            ));
         } else {
             cmd_ctx.set_resp_result(Ok(Resp::Error(
                 String::from("Invalid sub command").into_bytes(),
             )));
         }
+        CmdReplyFuture::Left(reply_receiver)
     }
 
     fn handle_umctl_set_cluster(&self, cmd_ctx: CmdCtx) {
@@ -336,9 +461,45 @@ where
         }
     }
 
-    fn handle_umctl_info_repl(&self, cmd_ctx: CmdCtx) {
-        let report = self.manager.get_replication_info();
+    async fn handle_umctl_info_repl(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> TaskResult {
+        let report = self.manager.get_replication_info().await;
         cmd_ctx.set_resp_result(Ok(report));
+        reply_receiver.await
+    }
+
+    // UMCTL SWITCHOVER <timeout_ms> <master address> <replica address> ...
+    async fn handle_umctl_switchover(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> TaskResult {
+        let (timeout, nodes) = match Self::parse_switchover_args(&cmd_ctx) {
+            Some(args) => args,
+            None => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    String::from("Invalid arguments").into_bytes(),
+                )));
+                return reply_receiver.await;
+            }
+        };
+
+        match self.manager.switchover(nodes, timeout).await {
+            Ok(()) => cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes()))),
+            Err(err) => {
+                let err_str = match err {
+                    SwitchoverError::NotMaster(address) => {
+                        format!("not a local master: {}", address)
+                    }
+                    SwitchoverError::Replication(err) => format!("switchover failed: {:?}", err),
+                };
+                cmd_ctx.set_resp_result(Ok(Resp::Error(err_str.into_bytes())));
+            }
+        }
+        reply_receiver.await
     }
 
     fn handle_umctl_mgr_cmd(&self, cmd_ctx: CmdCtx, sub_cmd: MgrSubCmd) {
@@ -371,6 +532,24 @@ where
     }
 
     fn handle_umctl_info_migration(&self, cmd_ctx: CmdCtx) {
+        let show_progress = cmd_ctx
+            .get_cmd()
+            .get_command_element(2)
+            .and_then(|element| str::from_utf8(element).ok())
+            .map(|s| s.to_uppercase() == "PROGRESS")
+            .unwrap_or(false);
+        if show_progress {
+            let packet: Vec<RespVec> = self
+                .manager
+                .get_migration_progress()
+                .into_iter()
+                .map(|progress| progress.to_strings().join(" "))
+                .map(|s| Resp::Bulk(BulkStr::Str(s.into_bytes())))
+                .collect();
+            cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(packet))));
+            return;
+        }
+
         let finished_tasks = self.manager.get_finished_migration_tasks();
         let packet: Vec<RespVec> = finished_tasks
             .into_iter()
@@ -380,6 +559,29 @@ where
         cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(packet))))
     }
 
+    fn handle_umctl_pause_migration(&self, cmd_ctx: CmdCtx, paused: bool) {
+        let cluster_name = match cmd_ctx
+            .get_cmd()
+            .get_command_element(2)
+            .and_then(|element| str::from_utf8(element).ok())
+            .and_then(|s| ClusterName::try_from(s).ok())
+        {
+            Some(cluster_name) => cluster_name,
+            None => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    String::from("Invalid cluster name").into_bytes(),
+                )));
+                return;
+            }
+        };
+        // Ignore the clusters without migrating tasks
+        // since the tasks may not be created yet or have finished.
+        if !self.manager.set_migration_paused(&cluster_name, paused) {
+            debug!("no migrating task found for {}", cluster_name);
+        }
+        cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())));
+    }
+
     fn handle_umctl_slowlog(&self, cmd_ctx: CmdCtx) {
         let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 2) {
             Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
@@ -406,6 +608,194 @@ where
         }
     }
 
+    fn handle_umctl_slot_stats(&self, cmd_ctx: CmdCtx) {
+        let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 2) {
+            Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
+            None => return,
+        };
+
+        let sub_cmd = sub_cmd.to_uppercase();
+
+        if sub_cmd.eq("GET") {
+            let reply = slot_stats_to_resp(self.slot_stats.get());
+            cmd_ctx.set_resp_result(Ok(reply));
+        } else if sub_cmd.eq("TAKE") {
+            let reply = slot_stats_to_resp(self.slot_stats.take());
+            cmd_ctx.set_resp_result(Ok(reply));
+        } else if sub_cmd.eq("RESET") {
+            self.slot_stats.reset();
+            cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())));
+        } else {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                "invalid slotstats sub-command".to_string().into_bytes(),
+            )))
+        }
+    }
+
+    fn handle_umctl_throttle_stats(&self, cmd_ctx: CmdCtx) {
+        let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 2) {
+            Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
+            None => return,
+        };
+
+        let sub_cmd = sub_cmd.to_uppercase();
+
+        if sub_cmd.eq("GET") {
+            let reply = throttle_stats_to_resp(self.throttle_stats.get());
+            cmd_ctx.set_resp_result(Ok(reply));
+        } else if sub_cmd.eq("RESET") {
+            self.throttle_stats.reset();
+            cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())));
+        } else {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                "invalid throttlestats sub-command".to_string().into_bytes(),
+            )))
+        }
+    }
+
+    fn handle_umctl_denied_stats(&self, cmd_ctx: CmdCtx) {
+        let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 2) {
+            Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
+            None => return,
+        };
+
+        let sub_cmd = sub_cmd.to_uppercase();
+
+        if sub_cmd.eq("GET") {
+            let reply = denied_stats_to_resp(self.denied_stats.get());
+            cmd_ctx.set_resp_result(Ok(reply));
+        } else if sub_cmd.eq("RESET") {
+            self.denied_stats.reset();
+            cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())));
+        } else {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                "invalid deniedstats sub-command".to_string().into_bytes(),
+            )))
+        }
+    }
+
+    fn handle_umctl_read_cache_stats(&self, cmd_ctx: CmdCtx) {
+        let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 2) {
+            Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
+            None => return,
+        };
+
+        let sub_cmd = sub_cmd.to_uppercase();
+
+        if sub_cmd.eq("GET") {
+            let reply = read_cache_stats_to_resp(self.read_cache.get_stats());
+            cmd_ctx.set_resp_result(Ok(reply));
+        } else if sub_cmd.eq("RESET") {
+            self.read_cache.reset_stats();
+            cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())));
+        } else {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                "invalid readcachestats sub-command"
+                    .to_string()
+                    .into_bytes(),
+            )))
+        }
+    }
+
+    fn handle_umctl_capture(&self, cmd_ctx: CmdCtx) {
+        let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 2) {
+            Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
+            None => return,
+        };
+
+        let sub_cmd = sub_cmd.to_uppercase();
+
+        if sub_cmd.eq("START") {
+            let cmd = cmd_ctx.get_cmd();
+            let cluster_name = cmd
+                .get_command_element(3)
+                .and_then(|element| str::from_utf8(element).ok())
+                .and_then(|s| ClusterName::try_from(s).ok());
+            let file_name = cmd
+                .get_command_element(4)
+                .and_then(|element| str::from_utf8(element).ok());
+            let sample_rate = match cmd.get_command_element(5) {
+                Some(element) => btou::<u64>(element).ok(),
+                None => Some(1),
+            };
+            let (cluster_name, file_name, sample_rate) =
+                match (cluster_name, file_name, sample_rate) {
+                    (Some(cluster_name), Some(file_name), Some(sample_rate)) => {
+                        (cluster_name, file_name, sample_rate)
+                    }
+                    _ => {
+                        cmd_ctx.set_resp_result(Ok(Resp::Error(
+                            "invalid capture arguments".to_string().into_bytes(),
+                        )));
+                        return;
+                    }
+                };
+            let capture_dir = match self.config.capture_dir.as_ref() {
+                Some(capture_dir) => capture_dir,
+                None => {
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(
+                        "capture_dir is not configured".to_string().into_bytes(),
+                    )));
+                    return;
+                }
+            };
+            let path = match get_capture_path(capture_dir, file_name) {
+                Some(path) => path,
+                None => {
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(
+                        "invalid capture file name".to_string().into_bytes(),
+                    )));
+                    return;
+                }
+            };
+            match self.capture.start(cluster_name, &path, sample_rate) {
+                Ok(()) => {
+                    cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())))
+                }
+                Err(err) => cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    format!("failed to start capture: {}", err).into_bytes(),
+                ))),
+            }
+        } else if sub_cmd.eq("STOP") {
+            let (records, dropped) = match self.capture.stop() {
+                Some(stats) => (stats.records, stats.dropped),
+                None => (0, 0),
+            };
+            cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(vec![
+                Resp::Integer(records.to_string().into_bytes()),
+                Resp::Integer(dropped.to_string().into_bytes()),
+            ]))));
+        } else {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                "invalid capture sub-command".to_string().into_bytes(),
+            )))
+        }
+    }
+
+    fn handle_umctl_key_stats(&self, cmd_ctx: CmdCtx, stats_type: KeyStatsType) {
+        let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 2) {
+            Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
+            None => return,
+        };
+
+        let sub_cmd = sub_cmd.to_uppercase();
+        let key_stats = self.slow_request_logger.get_key_stats();
+
+        if sub_cmd.eq("GET") {
+            let reply = key_stats_to_resp(key_stats.get(stats_type));
+            cmd_ctx.set_resp_result(Ok(reply));
+        } else if sub_cmd.eq("RESET") {
+            key_stats.reset(stats_type);
+            cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())));
+        } else {
+            let err_msg = match stats_type {
+                KeyStatsType::Hot => "invalid hotkeys sub-command",
+                KeyStatsType::Big => "invalid bigkeys sub-command",
+            };
+            cmd_ctx.set_resp_result(Ok(Resp::Error(err_msg.to_string().into_bytes())))
+        }
+    }
+
     fn handle_umctl_debug(&self, cmd_ctx: CmdCtx) {
         let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 2) {
             Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
@@ -437,11 +827,56 @@ where
     }
 
     fn handle_umctl_ready(&self, cmd_ctx: CmdCtx) {
-        let is_ready = self.manager.is_ready(cmd_ctx.get_cluster());
+        // Let the rolling update move on to the next server proxy while draining.
+        let is_ready = !self.shutdown.is_draining() && self.manager.is_ready(cmd_ctx.get_cluster());
         let n = if is_ready { 1 } else { 0 };
         cmd_ctx.set_resp_result(Ok(Resp::Integer(n.to_string().into_bytes())))
     }
 
+    async fn handle_umctl_node_health(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> TaskResult {
+        let node_addresses = self.manager.get_local_node_addresses();
+        let checks = node_addresses
+            .into_iter()
+            .map(|address| self.check_node_health(address));
+        let elements = future::join_all(checks)
+            .await
+            .into_iter()
+            .map(|(address, healthy)| {
+                let state = if healthy { "ok" } else { "failed" };
+                Resp::Arr(Array::Arr(vec![
+                    Resp::Bulk(BulkStr::Str(address.into_bytes())),
+                    Resp::Bulk(BulkStr::Str(state.to_string().into_bytes())),
+                ]))
+            })
+            .collect();
+        cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(elements))));
+        reply_receiver.await
+    }
+
+    async fn check_node_health(&self, address: String) -> (String, bool) {
+        const RETRY: usize = 3;
+        for _ in 0..RETRY {
+            let mut client = match self.client_factory.create_client(address.clone()).await {
+                Ok(client) => client,
+                Err(err) => {
+                    warn!("failed to connect to node {} {:?}", address, err);
+                    continue;
+                }
+            };
+            // The connection pool might get a stale connection so we need to retry.
+            let ping_command = vec![b"PING".to_vec()];
+            match client.execute_single(ping_command).await {
+                Ok(_) => return (address, true),
+                Err(err) => warn!("failed to send PING to node {} {:?}", address, err),
+            }
+        }
+        (address, false)
+    }
+
     fn handle_config(&self, cmd_ctx: CmdCtx) {
         let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 1) {
             Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd.to_uppercase()),
@@ -480,6 +915,15 @@ where
                     cmd_ctx.set_resp_result(Ok(Resp::Error(format!("{:?}", err).into_bytes())))
                 }
             }
+        } else if sub_cmd.eq("REWRITE") {
+            match self.config.rewrite() {
+                Ok(()) => {
+                    cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())))
+                }
+                Err(err) => {
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(format!("{:?}", err).into_bytes())))
+                }
+            }
         } else {
             cmd_ctx.set_resp_result(Ok(Resp::Error(
                 "invalid config sub-command".to_string().into_bytes(),
@@ -488,6 +932,180 @@ where
     }
 
     fn handle_data_cmd(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> CmdReplyFuture {
+        let cmd_ctx = match self.apply_command_policy(cmd_ctx) {
+            Some(cmd_ctx) => cmd_ctx,
+            None => return CmdReplyFuture::Left(reply_receiver),
+        };
+
+        if !self.try_acquire_cluster_quota(&cmd_ctx) {
+            self.throttle_stats.record(cmd_ctx.get_cluster_name());
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_THROTTLED.to_string().into_bytes(),
+            )));
+            return CmdReplyFuture::Left(reply_receiver);
+        }
+
+        let user = match cmd_ctx.get_user() {
+            Some(user) => user,
+            None => return self.handle_read_cache(cmd_ctx, reply_receiver),
+        };
+        // The user could have been removed from `key_prefixes` after `AUTH`.
+        let key_prefix = match self.get_key_prefix(cmd_ctx.get_cluster_name(), &user) {
+            Some(key_prefix) => key_prefix,
+            None => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    response::ERR_USER_NOT_FOUND.to_string().into_bytes(),
+                )));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+        };
+        let mut cmd_ctx = cmd_ctx;
+        let (elements, key_reply_type) = match add_key_prefix(cmd_ctx.get_cmd(), &key_prefix) {
+            Ok(res) => res,
+            Err(err) => {
+                let err_msg = match err {
+                    KeyPrefixError::GlobalCommand => response::ERR_KEY_PREFIX_GLOBAL_COMMAND,
+                    KeyPrefixError::UnknownCommand => response::ERR_KEY_PREFIX_UNKNOWN_COMMAND,
+                };
+                cmd_ctx.set_resp_result(Ok(Resp::Error(err_msg.to_string().into_bytes())));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+        };
+        cmd_ctx.replace_cmd_elements(elements);
+        let reply_fut = self.handle_read_cache(cmd_ctx, reply_receiver);
+        if key_reply_type == KeyReplyType::NoKey {
+            return reply_fut;
+        }
+        CmdReplyFuture::Right(Box::pin(async move {
+            let (request, packet, slowlog) = reply_fut.await?.into_inner();
+            let resp = remove_key_prefix(packet.into_resp_vec(), &key_prefix, key_reply_type);
+            let packet = Box::new(RespPacket::from_resp_vec(resp));
+            Ok(Box::new(TaskReply::new(request, packet, slowlog)))
+        }))
+    }
+
+    // The writes invalidate the cached keys before being sent
+    // so that the following GETs won't read the stale data,
+    // and again after getting their replies. See `invalidate_on_reply`.
+    fn handle_read_cache(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> CmdReplyFuture {
+        let cluster_name = cmd_ctx.get_cluster_name().clone();
+        let ticket = {
+            let meta_map = self.meta_map.lease();
+            let config = match meta_map.get_cluster_map().get_config(&cluster_name) {
+                Some(config) => config,
+                None => return self.dispatch_data_cmd(cmd_ctx, reply_receiver),
+            };
+            if config.read_cache_max_keys == 0 && !self.read_cache.contains(&cluster_name) {
+                return self.dispatch_data_cmd(cmd_ctx, reply_receiver);
+            }
+
+            let cmd = cmd_ctx.get_cmd();
+            let cmd_name = cmd.get_command_element(0).unwrap_or(b"");
+            if cmd.get_data_cmd_type() != DataCmdType::GET {
+                if is_write_command(cmd_name) {
+                    let key_indices = match get_key_indices(cmd) {
+                        Ok((key_indices, _)) => key_indices,
+                        Err(KeyPrefixError::GlobalCommand) => vec![],
+                        // Same as how the proxy routes the unknown commands.
+                        Err(KeyPrefixError::UnknownCommand) => vec![1],
+                    };
+                    let keys: Vec<BinSafeStr> = key_indices
+                        .into_iter()
+                        .filter_map(|i| cmd.get_command_element(i))
+                        .map(|key| key.to_vec())
+                        .collect();
+                    self.read_cache
+                        .invalidate(&cluster_name, keys.iter().map(|key| key.as_slice()));
+                    return self.invalidate_on_reply(
+                        cluster_name.clone(),
+                        Some(keys),
+                        cmd_ctx,
+                        reply_receiver,
+                    );
+                } else if clears_read_cache(cmd_name) {
+                    self.read_cache.clear(&cluster_name);
+                    return self.invalidate_on_reply(
+                        cluster_name.clone(),
+                        None,
+                        cmd_ctx,
+                        reply_receiver,
+                    );
+                }
+                return self.dispatch_data_cmd(cmd_ctx, reply_receiver);
+            }
+
+            let key = match cmd.get_key() {
+                Some(key) => key,
+                None => return self.dispatch_data_cmd(cmd_ctx, reply_receiver),
+            };
+            match self
+                .read_cache
+                .lookup(&cluster_name, key, config, Instant::now())
+            {
+                CacheLookup::Hit(value) => {
+                    let resp = match value {
+                        Some(value) => Resp::Bulk(BulkStr::Str(value)),
+                        None => Resp::Bulk(BulkStr::Nil),
+                    };
+                    cmd_ctx.set_resp_result(Ok(resp));
+                    return CmdReplyFuture::Left(reply_receiver);
+                }
+                CacheLookup::Miss(ticket) => (key.to_vec(), ticket),
+                CacheLookup::Uncacheable => return self.dispatch_data_cmd(cmd_ctx, reply_receiver),
+            }
+        };
+
+        let (key, ticket) = ticket;
+        let read_cache = &self.read_cache;
+        let reply_fut = self.dispatch_data_cmd(cmd_ctx, reply_receiver);
+        CmdReplyFuture::Right(Box::pin(async move {
+            let reply = reply_fut.await?;
+            let (request, packet, slowlog) = reply.into_inner();
+            let value = match packet.to_resp_slice() {
+                Resp::Bulk(BulkStr::Str(value)) => Some(Some(value.to_vec())),
+                Resp::Bulk(BulkStr::Nil) => Some(None),
+                _ => None,
+            };
+            if let Some(value) = value {
+                read_cache.insert(&cluster_name, key, value, ticket, Instant::now());
+            }
+            Ok(Box::new(TaskReply::new(request, packet, slowlog)))
+        }))
+    }
+
+    // The GETs sent after the write could be processed by Redis before it
+    // and get the old value, so the keys are invalidated again after the write is done.
+    // None for clearing the whole cache.
+    fn invalidate_on_reply(
+        &self,
+        cluster_name: ClusterName,
+        keys: Option<Vec<BinSafeStr>>,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> CmdReplyFuture {
+        let read_cache = &self.read_cache;
+        let reply_fut = self.dispatch_data_cmd(cmd_ctx, reply_receiver);
+        CmdReplyFuture::Right(Box::pin(async move {
+            let reply = reply_fut.await;
+            match keys {
+                Some(keys) => {
+                    read_cache.invalidate(&cluster_name, keys.iter().map(|key| key.as_slice()))
+                }
+                None => read_cache.clear(&cluster_name),
+            }
+            reply
+        }))
+    }
+
+    fn dispatch_data_cmd(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> CmdReplyFuture {
         match cmd_ctx.get_data_cmd_type() {
             DataCmdType::MGET => {
                 CmdReplyFuture::Right(Box::pin(self.handle_mget(cmd_ctx, reply_receiver)))
@@ -869,6 +1487,8 @@ where
     }
 
     fn handle_single_key_data_cmd(&self, cmd_ctx: CmdCtx) {
+        self.record_slot_stats(&cmd_ctx);
+
         let mut cmd_ctx = cmd_ctx;
         match self.compressor.try_compressing_cmd_ctx(&mut cmd_ctx) {
             Ok(())
@@ -891,6 +1511,75 @@ where
         self.manager.send(cmd_ctx);
     }
 
+    fn record_slot_stats(&self, cmd_ctx: &CmdCtx) {
+        let cmd = cmd_ctx.get_cmd();
+        let slot = match cmd.get_slot() {
+            Some(slot) => slot,
+            None => return,
+        };
+        self.slot_stats.record(slot, cmd.get_command_bytes() as u64);
+    }
+
+    // Both the command names sent by the clients and the aliased commands are checked
+    // against the denied commands. The original names of the aliased commands are denied.
+    fn apply_command_policy(&self, mut cmd_ctx: CmdCtx) -> Option<CmdCtx> {
+        let cmd_name = cmd_ctx.get_cmd().get_command_element(0)?;
+        let (denied, command) = {
+            let meta_map = self.meta_map.lease();
+            let config = match meta_map
+                .get_cluster_map()
+                .get_config(cmd_ctx.get_cluster_name())
+            {
+                Some(config) => config,
+                None => return Some(cmd_ctx),
+            };
+            match check_command_policy(&config.denied_commands, &config.command_aliases, cmd_name) {
+                CommandPolicyResult::Allowed => (false, None),
+                CommandPolicyResult::Aliased(command) => (false, Some(command.as_bytes().to_vec())),
+                CommandPolicyResult::Denied => (true, None),
+            }
+        };
+
+        if denied {
+            self.denied_stats
+                .record(cmd_ctx.get_cluster_name(), cmd_name);
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_COMMAND_DENIED.to_string().into_bytes(),
+            )));
+            return None;
+        }
+
+        if let Some(command) = command {
+            if !cmd_ctx.rename_cmd(command) {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    "failed to replace the command alias"
+                        .to_string()
+                        .into_bytes(),
+                )));
+                return None;
+            }
+        }
+        Some(cmd_ctx)
+    }
+
+    // The multi-key commands only count as one request.
+    fn try_acquire_cluster_quota(&self, cmd_ctx: &CmdCtx) -> bool {
+        let cluster_name = cmd_ctx.get_cluster_name();
+        let (ops_per_sec, bytes_per_sec) = {
+            let meta_map = self.meta_map.lease();
+            match meta_map.get_cluster_map().get_config(cluster_name) {
+                Some(config) => (
+                    config.rate_limit_ops_per_sec,
+                    config.rate_limit_bytes_per_sec,
+                ),
+                None => return true,
+            }
+        };
+        let bytes = cmd_ctx.get_cmd().get_command_bytes() as u64;
+        self.rate_limiter
+            .try_acquire(cluster_name, ops_per_sec, bytes_per_sec, bytes)
+    }
+
     fn handle_umforward(
         &self,
         cmd_ctx: CmdCtx,
@@ -936,10 +1625,10 @@ where
         &self,
         cmd_ctx: CmdCtx,
         reply_receiver: CmdReplyReceiver,
-        session_cluster_name: &sync::RwLock<ClusterName>,
+        session_state: &sync::RwLock<SessionState>,
     ) -> CmdReplyFuture {
         let mut cmd_ctx = cmd_ctx;
-        if self.config.auto_select_cluster {
+        if self.config.get_auto_select_cluster() {
             cmd_ctx = self.manager.try_select_cluster(cmd_ctx);
         }
 
@@ -951,7 +1640,7 @@ where
             CmdType::Info => cmd_ctx.set_resp_result(Ok(Resp::Bulk(BulkStr::Str(
                 format!("version:{}\r\n", UNDERMOON_VERSION,).into_bytes(),
             )))),
-            CmdType::Auth => self.handle_auth(cmd_ctx, session_cluster_name),
+            CmdType::Auth => self.handle_auth(cmd_ctx, session_state),
             CmdType::Quit => {
                 cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())))
             }
@@ -971,7 +1660,7 @@ where
             CmdType::Invalid => cmd_ctx.set_resp_result(Ok(Resp::Error(
                 String::from("Invalid command").into_bytes(),
             ))),
-            CmdType::UmCtl => self.handle_umctl(cmd_ctx),
+            CmdType::UmCtl => return self.handle_umctl(cmd_ctx, reply_receiver),
             CmdType::UmForward => return self.handle_umforward(cmd_ctx, reply_receiver),
             CmdType::UmSync => self.handle_umsync(cmd_ctx),
             CmdType::Cluster => self.handle_cluster(cmd_ctx),
@@ -0,0 +1,603 @@
+use super::command::Command;
+use crate::common::utils::byte_to_uppercase;
+use crate::protocol::{Array, BinSafeStr, BulkStr, Resp, RespVec};
+use btoi::btou;
+use std::cmp::min;
+
+// The replies containing keys which need to have the prefix removed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyReplyType {
+    NoKey,
+    // KEYS
+    KeyArray,
+    // SCAN
+    ScanResult,
+    // BLPOP, BRPOP, BZPOPMIN, BZPOPMAX, LMPOP, BLMPOP, ZMPOP, BZMPOP
+    FirstElement,
+    // XREAD, XREADGROUP
+    NestedFirstElements,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyPrefixError {
+    // The commands working on the whole database could not be limited to the prefix.
+    GlobalCommand,
+    // The positions of the keys are unknown.
+    UnknownCommand,
+}
+
+// Mirrors the key specs in the reply of `COMMAND`.
+#[derive(Debug)]
+enum KeySpec {
+    // The keys from `first` to `last` with `step`.
+    // A negative `last` counts from the end like the `lastkey` of `COMMAND`.
+    Range {
+        first: usize,
+        last: isize,
+        step: usize,
+    },
+    // The number of keys is at `index` and the keys follow it.
+    KeyNum {
+        index: usize,
+    },
+    // The key follows `keyword` searched from `start`.
+    Keyword {
+        keyword: &'static [u8],
+        start: usize,
+    },
+    // All the elements after `keyword` searched from `start` are keys.
+    KeywordRest {
+        keyword: &'static [u8],
+        start: usize,
+    },
+    // The first half of the elements after `STREAMS` searched from `start`.
+    Streams {
+        start: usize,
+    },
+    // The key could be an empty string when the `KEYS` option is used.
+    NonEmpty {
+        index: usize,
+    },
+    // The `BY` and `GET` patterns of `SORT` also refer to keys.
+    SortPatterns,
+}
+
+const fn range(first: usize, last: isize, step: usize) -> KeySpec {
+    KeySpec::Range { first, last, step }
+}
+
+const NO_KEY: &[KeySpec] = &[];
+const FIRST_KEY: &[KeySpec] = &[range(1, 1, 1)];
+const SECOND_KEY: &[KeySpec] = &[range(2, 2, 1)];
+const TWO_KEYS: &[KeySpec] = &[range(1, 2, 1)];
+const ALL_KEYS: &[KeySpec] = &[range(1, -1, 1)];
+const KEY_VALUE_PAIRS: &[KeySpec] = &[range(1, -1, 2)];
+const KEYS_BEFORE_TIMEOUT: &[KeySpec] = &[range(1, -2, 1)];
+const KEYS_AFTER_OPERATION: &[KeySpec] = &[range(2, -1, 1)];
+const NUM_KEYS: &[KeySpec] = &[KeySpec::KeyNum { index: 1 }];
+const TIMEOUT_NUM_KEYS: &[KeySpec] = &[KeySpec::KeyNum { index: 2 }];
+const SCRIPT_KEYS: &[KeySpec] = &[KeySpec::KeyNum { index: 2 }];
+const DEST_NUM_KEYS: &[KeySpec] = &[range(1, 1, 1), KeySpec::KeyNum { index: 2 }];
+const GEORADIUS_KEYS: &[KeySpec] = &[
+    range(1, 1, 1),
+    KeySpec::Keyword {
+        keyword: b"STORE",
+        start: 6,
+    },
+    KeySpec::Keyword {
+        keyword: b"STOREDIST",
+        start: 6,
+    },
+];
+const GEORADIUSBYMEMBER_KEYS: &[KeySpec] = &[
+    range(1, 1, 1),
+    KeySpec::Keyword {
+        keyword: b"STORE",
+        start: 5,
+    },
+    KeySpec::Keyword {
+        keyword: b"STOREDIST",
+        start: 5,
+    },
+];
+const SORT_KEYS: &[KeySpec] = &[
+    range(1, 1, 1),
+    KeySpec::Keyword {
+        keyword: b"STORE",
+        start: 2,
+    },
+    KeySpec::SortPatterns,
+];
+const SORT_RO_KEYS: &[KeySpec] = &[range(1, 1, 1), KeySpec::SortPatterns];
+const XREAD_KEYS: &[KeySpec] = &[KeySpec::Streams { start: 1 }];
+// XREADGROUP GROUP group consumer ... STREAMS key ... id ...
+const XREADGROUP_KEYS: &[KeySpec] = &[KeySpec::Streams { start: 4 }];
+// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [AUTH ...] [KEYS key ...]
+const MIGRATE_KEYS: &[KeySpec] = &[
+    KeySpec::NonEmpty { index: 3 },
+    KeySpec::KeywordRest {
+        keyword: b"KEYS",
+        start: 6,
+    },
+];
+
+// Returns the key specs of the commands forwarded to Redis.
+fn get_key_specs(cmd_name: &[u8]) -> Result<(&'static [KeySpec], KeyReplyType), KeyPrefixError> {
+    let specs = match cmd_name {
+        // RANDOMKEY could keep returning the keys of other users.
+        b"DBSIZE" | b"FLUSHALL" | b"FLUSHDB" | b"SCRIPT" | b"FUNCTION" | b"RANDOMKEY"
+        | b"SWAPDB" | b"MONITOR" | b"DEBUG" => return Err(KeyPrefixError::GlobalCommand),
+        b"TIME" | b"LASTSAVE" | b"WAIT" | b"UNWATCH" => NO_KEY,
+        // The pattern is prefixed in the same way as the keys.
+        b"KEYS" => return Ok((FIRST_KEY, KeyReplyType::KeyArray)),
+        b"SCAN" => return Ok((NO_KEY, KeyReplyType::ScanResult)),
+
+        // String commands
+        b"APPEND" | b"DECR" | b"DECRBY" | b"GET" | b"GETDEL" | b"GETEX" | b"GETRANGE"
+        | b"GETSET" | b"INCR" | b"INCRBY" | b"INCRBYFLOAT" | b"PSETEX" | b"SET" | b"SETEX"
+        | b"SETNX" | b"SETRANGE" | b"STRLEN" | b"SUBSTR" | b"BITCOUNT" | b"BITFIELD"
+        | b"BITFIELD_RO" | b"BITPOS" | b"GETBIT" | b"SETBIT" => FIRST_KEY,
+        b"MGET" => ALL_KEYS,
+        b"MSET" | b"MSETNX" => KEY_VALUE_PAIRS,
+        b"BITOP" => KEYS_AFTER_OPERATION,
+        b"LCS" => TWO_KEYS,
+
+        // Key commands
+        b"DEL" | b"EXISTS" | b"UNLINK" | b"TOUCH" | b"WATCH" => ALL_KEYS,
+        b"EXPIRE" | b"EXPIREAT" | b"PEXPIRE" | b"PEXPIREAT" | b"EXPIRETIME" | b"PEXPIRETIME"
+        | b"PERSIST" | b"TTL" | b"PTTL" | b"TYPE" | b"DUMP" | b"RESTORE" | b"MOVE" => FIRST_KEY,
+        b"RENAME" | b"RENAMENX" | b"COPY" => TWO_KEYS,
+        b"OBJECT" | b"MEMORY" => SECOND_KEY,
+        b"SORT" => SORT_KEYS,
+        b"SORT_RO" => SORT_RO_KEYS,
+        b"MIGRATE" => MIGRATE_KEYS,
+
+        // List commands
+        b"BLPOP" | b"BRPOP" => return Ok((KEYS_BEFORE_TIMEOUT, KeyReplyType::FirstElement)),
+        b"LMPOP" => return Ok((NUM_KEYS, KeyReplyType::FirstElement)),
+        b"BLMPOP" => return Ok((TIMEOUT_NUM_KEYS, KeyReplyType::FirstElement)),
+        b"RPOPLPUSH" | b"BRPOPLPUSH" | b"LMOVE" | b"BLMOVE" => TWO_KEYS,
+        b"LINDEX" | b"LINSERT" | b"LLEN" | b"LPOP" | b"LPOS" | b"LPUSH" | b"LPUSHX" | b"LRANGE"
+        | b"LREM" | b"LSET" | b"LTRIM" | b"RPOP" | b"RPUSH" | b"RPUSHX" => FIRST_KEY,
+
+        // Hash commands
+        b"HDEL" | b"HEXISTS" | b"HGET" | b"HGETALL" | b"HINCRBY" | b"HINCRBYFLOAT" | b"HKEYS"
+        | b"HLEN" | b"HMGET" | b"HMSET" | b"HRANDFIELD" | b"HSCAN" | b"HSET" | b"HSETNX"
+        | b"HSTRLEN" | b"HVALS" => FIRST_KEY,
+
+        // Set commands
+        b"SADD" | b"SCARD" | b"SISMEMBER" | b"SMISMEMBER" | b"SMEMBERS" | b"SPOP"
+        | b"SRANDMEMBER" | b"SREM" | b"SSCAN" => FIRST_KEY,
+        b"SDIFF" | b"SINTER" | b"SUNION" | b"SDIFFSTORE" | b"SINTERSTORE" | b"SUNIONSTORE" => {
+            ALL_KEYS
+        }
+        b"SMOVE" => TWO_KEYS,
+        b"SINTERCARD" => NUM_KEYS,
+
+        // Sorted Set commands
+        b"ZADD" | b"ZCARD" | b"ZCOUNT" | b"ZINCRBY" | b"ZLEXCOUNT" | b"ZMSCORE" | b"ZPOPMAX"
+        | b"ZPOPMIN" | b"ZRANDMEMBER" | b"ZRANGE" | b"ZRANGEBYLEX" | b"ZRANGEBYSCORE"
+        | b"ZRANK" | b"ZREM" | b"ZREMRANGEBYLEX" | b"ZREMRANGEBYRANK" | b"ZREMRANGEBYSCORE"
+        | b"ZREVRANGE" | b"ZREVRANGEBYLEX" | b"ZREVRANGEBYSCORE" | b"ZREVRANK" | b"ZSCAN"
+        | b"ZSCORE" => FIRST_KEY,
+        b"BZPOPMIN" | b"BZPOPMAX" => return Ok((KEYS_BEFORE_TIMEOUT, KeyReplyType::FirstElement)),
+        b"ZMPOP" => return Ok((NUM_KEYS, KeyReplyType::FirstElement)),
+        b"BZMPOP" => return Ok((TIMEOUT_NUM_KEYS, KeyReplyType::FirstElement)),
+        b"ZRANGESTORE" => TWO_KEYS,
+        b"ZUNION" | b"ZINTER" | b"ZDIFF" | b"ZINTERCARD" => NUM_KEYS,
+        b"ZUNIONSTORE" | b"ZINTERSTORE" | b"ZDIFFSTORE" => DEST_NUM_KEYS,
+
+        // HyperLogLog commands
+        b"PFADD" => FIRST_KEY,
+        b"PFCOUNT" | b"PFMERGE" => ALL_KEYS,
+
+        // Geo commands
+        b"GEOADD"
+        | b"GEODIST"
+        | b"GEOHASH"
+        | b"GEOPOS"
+        | b"GEOSEARCH"
+        | b"GEORADIUS_RO"
+        | b"GEORADIUSBYMEMBER_RO" => FIRST_KEY,
+        b"GEORADIUS" => GEORADIUS_KEYS,
+        b"GEORADIUSBYMEMBER" => GEORADIUSBYMEMBER_KEYS,
+        b"GEOSEARCHSTORE" => TWO_KEYS,
+
+        // Stream commands
+        b"XACK" | b"XADD" | b"XAUTOCLAIM" | b"XCLAIM" | b"XDEL" | b"XLEN" | b"XPENDING"
+        | b"XRANGE" | b"XREVRANGE" | b"XSETID" | b"XTRIM" => FIRST_KEY,
+        b"XINFO" | b"XGROUP" => SECOND_KEY,
+        b"XREAD" => return Ok((XREAD_KEYS, KeyReplyType::NestedFirstElements)),
+        b"XREADGROUP" => return Ok((XREADGROUP_KEYS, KeyReplyType::NestedFirstElements)),
+
+        // Scripting commands
+        b"EVAL" | b"EVALSHA" | b"EVAL_RO" | b"EVALSHA_RO" | b"FCALL" | b"FCALL_RO" => SCRIPT_KEYS,
+
+        _ => return Err(KeyPrefixError::UnknownCommand),
+    };
+    Ok((specs, KeyReplyType::NoKey))
+}
+
+// Returns the indices of the keys in the command.
+// The users with key prefixes can only run the commands with known key positions.
+pub fn get_key_indices(cmd: &Command) -> Result<(Vec<usize>, KeyReplyType), KeyPrefixError> {
+    let len = cmd.get_command_len().unwrap_or(0);
+    let cmd_name: Vec<u8> = match cmd.get_command_element(0) {
+        Some(cmd_name) => cmd_name.iter().cloned().map(byte_to_uppercase).collect(),
+        None => return Ok((vec![], KeyReplyType::NoKey)),
+    };
+    let (specs, key_reply_type) = get_key_specs(&cmd_name)?;
+
+    let mut key_indices = vec![];
+    for spec in specs.iter() {
+        match *spec {
+            KeySpec::Range { first, last, step } => {
+                let end = if last < 0 {
+                    len as isize + last
+                } else {
+                    min(last, len as isize - 1)
+                };
+                if end >= first as isize {
+                    key_indices.extend((first..=end as usize).step_by(step));
+                }
+            }
+            KeySpec::KeyNum { index } => {
+                let key_num = get_key_num(cmd, index);
+                key_indices.extend((index + 1)..min((index + 1).saturating_add(key_num), len));
+            }
+            KeySpec::Keyword { keyword, start } => {
+                if let Some(i) = find_keyword(cmd, keyword, start) {
+                    key_indices.extend((i + 1)..min(i + 2, len));
+                }
+            }
+            KeySpec::KeywordRest { keyword, start } => {
+                if let Some(i) = find_keyword(cmd, keyword, start) {
+                    key_indices.extend((i + 1)..len);
+                }
+            }
+            KeySpec::Streams { start } => {
+                if let Some(i) = find_keyword(cmd, b"STREAMS", start) {
+                    let key_num = (len - i - 1) / 2;
+                    key_indices.extend((i + 1)..(i + 1 + key_num));
+                }
+            }
+            KeySpec::NonEmpty { index } => {
+                if cmd
+                    .get_command_element(index)
+                    .map_or(false, |k| !k.is_empty())
+                {
+                    key_indices.push(index);
+                }
+            }
+            KeySpec::SortPatterns => key_indices.extend(get_sort_pattern_indices(cmd)),
+        }
+    }
+    key_indices.sort_unstable();
+    key_indices.dedup();
+    Ok((key_indices, key_reply_type))
+}
+
+fn find_keyword(cmd: &Command, keyword: &[u8], start: usize) -> Option<usize> {
+    let len = cmd.get_command_len().unwrap_or(0);
+    (start..len).find(|i| {
+        cmd.get_command_element(*i)
+            .map_or(false, |e| e.eq_ignore_ascii_case(keyword))
+    })
+}
+
+// SORT key [BY pattern] [LIMIT offset count] [GET pattern ...] [ASC|DESC] [ALPHA] [STORE destination]
+// The `BY` pattern without `*` skips the sorting. The `GET #` returns the element itself.
+fn get_sort_pattern_indices(cmd: &Command) -> Vec<usize> {
+    let len = cmd.get_command_len().unwrap_or(0);
+    let mut indices = vec![];
+    let mut i = 2;
+    while i + 1 < len {
+        let (option, arg) = match (cmd.get_command_element(i), cmd.get_command_element(i + 1)) {
+            (Some(option), Some(arg)) => (option, arg),
+            _ => break,
+        };
+        if option.eq_ignore_ascii_case(b"BY") {
+            if arg.contains(&b'*') {
+                indices.push(i + 1);
+            }
+            i += 2;
+        } else if option.eq_ignore_ascii_case(b"GET") {
+            if arg != b"#" {
+                indices.push(i + 1);
+            }
+            i += 2;
+        } else if option.eq_ignore_ascii_case(b"LIMIT") {
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    indices
+}
+
+// Returns the elements with the prefix added to all the keys.
+// The prefix is prepended to the whole key so that the hash tag is not changed
+// and the keys with the same hash tag are still in the same slot.
+pub fn add_key_prefix(
+    cmd: &Command,
+    prefix: &[u8],
+) -> Result<(Vec<BinSafeStr>, KeyReplyType), KeyPrefixError> {
+    let (key_indices, key_reply_type) = get_key_indices(cmd)?;
+    let len = cmd.get_command_len().unwrap_or(0);
+    let mut elements: Vec<BinSafeStr> = (0..len)
+        .filter_map(|i| cmd.get_command_element(i))
+        .map(|element| element.to_vec())
+        .collect();
+    if key_reply_type == KeyReplyType::ScanResult {
+        add_scan_match_prefix(&mut elements, prefix);
+    }
+
+    for i in key_indices.into_iter() {
+        if let Some(key) = elements.get_mut(i) {
+            let mut prefixed_key = Vec::with_capacity(prefix.len() + key.len());
+            prefixed_key.extend_from_slice(prefix);
+            prefixed_key.extend_from_slice(key);
+            *key = prefixed_key;
+        }
+    }
+    Ok((elements, key_reply_type))
+}
+
+// The keys of other tenants are removed from the reply.
+pub fn remove_key_prefix(resp: RespVec, prefix: &[u8], key_reply_type: KeyReplyType) -> RespVec {
+    match (key_reply_type, resp) {
+        (KeyReplyType::NoKey, resp) => resp,
+        (KeyReplyType::KeyArray, Resp::Arr(Array::Arr(keys))) => {
+            Resp::Arr(Array::Arr(strip_keys(keys, prefix)))
+        }
+        (KeyReplyType::ScanResult, Resp::Arr(Array::Arr(mut elements))) => {
+            if let Some(Resp::Arr(Array::Arr(keys))) = elements.get_mut(1) {
+                let stripped = strip_keys(std::mem::take(keys), prefix);
+                *keys = stripped;
+            }
+            Resp::Arr(Array::Arr(elements))
+        }
+        (KeyReplyType::FirstElement, resp) => strip_first_element(resp, prefix),
+        (KeyReplyType::NestedFirstElements, Resp::Arr(Array::Arr(elements))) => {
+            let elements = elements
+                .into_iter()
+                .map(|element| strip_first_element(element, prefix))
+                .collect();
+            Resp::Arr(Array::Arr(elements))
+        }
+        (_, resp) => resp,
+    }
+}
+
+fn strip_first_element(resp: RespVec, prefix: &[u8]) -> RespVec {
+    match resp {
+        Resp::Arr(Array::Arr(mut elements)) => {
+            if let Some(Resp::Bulk(BulkStr::Str(key))) = elements.get_mut(0) {
+                if key.starts_with(prefix) {
+                    key.drain(..prefix.len());
+                }
+            }
+            Resp::Arr(Array::Arr(elements))
+        }
+        others => others,
+    }
+}
+
+fn add_scan_match_prefix(elements: &mut Vec<BinSafeStr>, prefix: &[u8]) {
+    // SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+    let mut i = 2;
+    while i + 1 < elements.len() {
+        if elements[i].eq_ignore_ascii_case(b"MATCH") {
+            let mut pattern = prefix.to_vec();
+            pattern.extend_from_slice(&elements[i + 1]);
+            elements[i + 1] = pattern;
+            return;
+        }
+        i += 2;
+    }
+    let mut pattern = prefix.to_vec();
+    pattern.push(b'*');
+    elements.push(b"MATCH".to_vec());
+    elements.push(pattern);
+}
+
+fn get_key_num(cmd: &Command, index: usize) -> usize {
+    cmd.get_command_element(index)
+        .and_then(|n| btou::<usize>(n).ok())
+        .unwrap_or(0)
+}
+
+fn strip_keys(keys: Vec<RespVec>, prefix: &[u8]) -> Vec<RespVec> {
+    keys.into_iter()
+        .filter_map(|key| match key {
+            Resp::Bulk(BulkStr::Str(key)) => {
+                strip_key(key, prefix).map(|k| Resp::Bulk(BulkStr::Str(k)))
+            }
+            others => Some(others),
+        })
+        .collect()
+}
+
+fn strip_key(mut key: BinSafeStr, prefix: &[u8]) -> Option<BinSafeStr> {
+    if !key.starts_with(prefix) {
+        return None;
+    }
+    key.drain(..prefix.len());
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::utils::generate_slot;
+    use crate::protocol::RespPacket;
+
+    fn gen_cmd(args: &[&str]) -> Command {
+        let resp = Resp::Arr(Array::Arr(
+            args.iter()
+                .map(|arg| Resp::Bulk(BulkStr::Str(arg.as_bytes().to_vec())))
+                .collect(),
+        ));
+        Command::new(Box::new(RespPacket::from_resp_vec(resp)))
+    }
+
+    fn to_strings(elements: Vec<BinSafeStr>) -> Vec<String> {
+        elements
+            .into_iter()
+            .map(|e| String::from_utf8(e).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_add_key_prefix() {
+        let (elements, key_reply_type) = add_key_prefix(&gen_cmd(&["get", "{a}b"]), b"p:").unwrap();
+        assert_eq!(to_strings(elements), vec!["get", "p:{a}b"]);
+        assert_eq!(key_reply_type, KeyReplyType::NoKey);
+        assert_eq!(generate_slot(b"p:{a}b"), generate_slot(b"{a}b"));
+
+        let (elements, _) = add_key_prefix(&gen_cmd(&["mset", "a", "1", "b", "2"]), b"p:").unwrap();
+        assert_eq!(to_strings(elements), vec!["mset", "p:a", "1", "p:b", "2"]);
+
+        let (elements, key_reply_type) =
+            add_key_prefix(&gen_cmd(&["blpop", "a", "b", "0"]), b"p:").unwrap();
+        assert_eq!(to_strings(elements), vec!["blpop", "p:a", "p:b", "0"]);
+        assert_eq!(key_reply_type, KeyReplyType::FirstElement);
+
+        let (elements, _) =
+            add_key_prefix(&gen_cmd(&["eval", "script", "2", "a", "b", "c"]), b"p:").unwrap();
+        assert_eq!(
+            to_strings(elements),
+            vec!["eval", "script", "2", "p:a", "p:b", "c"]
+        );
+
+        let (elements, _) = add_key_prefix(&gen_cmd(&["scan", "0", "count", "10"]), b"p:").unwrap();
+        assert_eq!(
+            to_strings(elements),
+            vec!["scan", "0", "count", "10", "MATCH", "p:*"]
+        );
+
+        let (elements, _) = add_key_prefix(&gen_cmd(&["scan", "0", "match", "a*"]), b"p:").unwrap();
+        assert_eq!(to_strings(elements), vec!["scan", "0", "match", "p:a*"]);
+
+        assert_eq!(
+            add_key_prefix(&gen_cmd(&["flushall"]), b"p:"),
+            Err(KeyPrefixError::GlobalCommand)
+        );
+        assert_eq!(
+            add_key_prefix(&gen_cmd(&["randomkey"]), b"p:"),
+            Err(KeyPrefixError::GlobalCommand)
+        );
+        assert_eq!(
+            add_key_prefix(&gen_cmd(&["unknowncmd", "a"]), b"p:"),
+            Err(KeyPrefixError::UnknownCommand)
+        );
+    }
+
+    fn get_keys(args: &[&str]) -> Vec<String> {
+        let cmd = gen_cmd(args);
+        let (key_indices, _) = get_key_indices(&cmd).unwrap();
+        key_indices
+            .into_iter()
+            .map(|i| String::from_utf8(cmd.get_command_element(i).unwrap().to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_get_key_indices() {
+        assert_eq!(
+            get_keys(&["XREAD", "COUNT", "2", "STREAMS", "s1", "s2", "0", "0"]),
+            vec!["s1", "s2"]
+        );
+        assert_eq!(
+            get_keys(&["XREADGROUP", "GROUP", "g", "streams", "STREAMS", "s1", ">"]),
+            vec!["s1"]
+        );
+        assert_eq!(
+            get_keys(&[
+                "SORT", "l", "BY", "w_*", "LIMIT", "0", "1", "GET", "#", "GET", "o_*", "STORE", "d"
+            ]),
+            vec!["l", "w_*", "o_*", "d"]
+        );
+        assert_eq!(get_keys(&["SORT", "l", "BY", "nosort"]), vec!["l"]);
+        assert_eq!(
+            get_keys(&["GEORADIUS", "g", "0", "0", "1", "km", "STORE", "d"]),
+            vec!["g", "d"]
+        );
+        assert_eq!(
+            get_keys(&[
+                "GEORADIUSBYMEMBER",
+                "g",
+                "store",
+                "1",
+                "km",
+                "STOREDIST",
+                "d"
+            ]),
+            vec!["g", "d"]
+        );
+        assert_eq!(
+            get_keys(&["ZRANGESTORE", "d", "s", "0", "-1"]),
+            vec!["d", "s"]
+        );
+        assert_eq!(
+            get_keys(&["ZUNION", "2", "a", "b", "WITHSCORES"]),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            get_keys(&["ZINTERSTORE", "d", "2", "a", "b", "WEIGHTS", "1", "2"]),
+            vec!["d", "a", "b"]
+        );
+        assert_eq!(get_keys(&["MEMORY", "USAGE", "k"]), vec!["k"]);
+        assert_eq!(
+            get_keys(&["MIGRATE", "h", "6379", "", "0", "1000", "KEYS", "a", "b"]),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            get_keys(&["MIGRATE", "h", "6379", "k", "0", "1000"]),
+            vec!["k"]
+        );
+        assert_eq!(
+            get_keys(&["BLMPOP", "0", "2", "a", "b", "LEFT"]),
+            vec!["a", "b"]
+        );
+        assert!(get_keys(&["TIME"]).is_empty());
+    }
+
+    #[test]
+    fn test_remove_key_prefix() {
+        let key = |s: &str| Resp::Bulk(BulkStr::Str(s.as_bytes().to_vec()));
+
+        let resp = Resp::Arr(Array::Arr(vec![key("p:a"), key("other")]));
+        let resp = remove_key_prefix(resp, b"p:", KeyReplyType::KeyArray);
+        assert_eq!(resp, Resp::Arr(Array::Arr(vec![key("a")])));
+
+        let resp = Resp::Arr(Array::Arr(vec![
+            key("0"),
+            Resp::Arr(Array::Arr(vec![key("p:a"), key("p:b")])),
+        ]));
+        let resp = remove_key_prefix(resp, b"p:", KeyReplyType::ScanResult);
+        assert_eq!(
+            resp,
+            Resp::Arr(Array::Arr(vec![
+                key("0"),
+                Resp::Arr(Array::Arr(vec![key("a"), key("b")])),
+            ]))
+        );
+
+        let stream = |k: &str| Resp::Arr(Array::Arr(vec![key(k), Resp::Arr(Array::Arr(vec![]))]));
+        let resp = Resp::Arr(Array::Arr(vec![stream("p:s1"), stream("p:s2")]));
+        let resp = remove_key_prefix(resp, b"p:", KeyReplyType::NestedFirstElements);
+        assert_eq!(
+            resp,
+            Resp::Arr(Array::Arr(vec![stream("s1"), stream("s2")]))
+        );
+
+        let resp = Resp::Arr(Array::Arr(vec![key("p:a"), key("p:value")]));
+        let resp = remove_key_prefix(resp, b"p:", KeyReplyType::FirstElement);
+        assert_eq!(resp, Resp::Arr(Array::Arr(vec![key("a"), key("p:value")])));
+
+        let resp = Resp::Error(b"ERR".to_vec());
+        let resp = remove_key_prefix(resp.clone(), b"p:", KeyReplyType::KeyArray);
+        assert_eq!(resp, Resp::Error(b"ERR".to_vec()));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::common::cluster::ClusterName;
+use crate::protocol::{Array, BinSafeStr, BulkStr, Resp, RespPacket, RespVec};
+use dashmap::DashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+const SKETCH_DEPTH: usize = 4;
+const SKETCH_WIDTH: usize = 4096;
+
+// Estimates the request count of every key with fixed memory.
+// The estimation could only be larger than the real count.
+struct CountMinSketch {
+    counters: Vec<u64>,
+}
+
+impl Default for CountMinSketch {
+    fn default() -> Self {
+        Self {
+            counters: vec![0; SKETCH_DEPTH * SKETCH_WIDTH],
+        }
+    }
+}
+
+impl CountMinSketch {
+    // Returns the estimated count after adding.
+    fn add(&mut self, key: &[u8]) -> u64 {
+        let mut estimated = u64::max_value();
+        for row in 0..SKETCH_DEPTH {
+            let mut hasher = DefaultHasher::new();
+            row.hash(&mut hasher);
+            key.hash(&mut hasher);
+            let index = row * SKETCH_WIDTH + (hasher.finish() as usize) % SKETCH_WIDTH;
+            if let Some(counter) = self.counters.get_mut(index) {
+                *counter += 1;
+                estimated = estimated.min(*counter);
+            }
+        }
+        estimated
+    }
+}
+
+// Keeps the `k` keys with the largest values.
+// `k` is small so the smallest one is simply found by scanning.
+struct TopKeys {
+    k: usize,
+    keys: HashMap<BinSafeStr, u64>,
+}
+
+impl TopKeys {
+    fn new(k: usize) -> Self {
+        Self {
+            k,
+            keys: HashMap::new(),
+        }
+    }
+
+    fn update(&mut self, key: &[u8], value: u64) {
+        if let Some(v) = self.keys.get_mut(key) {
+            *v = (*v).max(value);
+            return;
+        }
+        if self.keys.len() < self.k {
+            self.keys.insert(key.to_vec(), value);
+            return;
+        }
+        let min_key = match self.keys.iter().min_by_key(|(_, v)| **v) {
+            Some((min_key, min_value)) if *min_value < value => min_key.clone(),
+            _ => return,
+        };
+        self.keys.remove(&min_key);
+        self.keys.insert(key.to_vec(), value);
+    }
+
+    fn get(&self) -> Vec<(BinSafeStr, u64)> {
+        let mut keys: Vec<(BinSafeStr, u64)> = self
+            .keys
+            .iter()
+            .map(|(key, value)| (key.clone(), *value))
+            .collect();
+        keys.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        keys
+    }
+}
+
+struct ClusterKeyStats {
+    sketch: CountMinSketch,
+    hot_keys: TopKeys,
+    big_keys: TopKeys,
+}
+
+impl ClusterKeyStats {
+    fn new(k: usize) -> Self {
+        Self {
+            sketch: CountMinSketch::default(),
+            hot_keys: TopKeys::new(k),
+            big_keys: TopKeys::new(k),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyStatsType {
+    Hot,
+    Big,
+}
+
+// Records the hot keys by the request count and the big keys by the reply size
+// for every cluster. Only the requests sampled by `slowlog_sample_rate` are recorded
+// so the counts should be multiplied by the sample rate to get the real ones.
+pub struct KeyStats {
+    top_k: usize,
+    clusters: DashMap<ClusterName, ClusterKeyStats>,
+}
+
+impl KeyStats {
+    pub fn new(top_k: usize) -> Self {
+        Self {
+            top_k,
+            clusters: DashMap::new(),
+        }
+    }
+
+    pub fn record(&self, cluster_name: &ClusterName, key: &[u8], reply_size: usize) {
+        if self.top_k == 0 {
+            return;
+        }
+        let top_k = self.top_k;
+        let mut stats = self
+            .clusters
+            .entry(cluster_name.clone())
+            .or_insert_with(|| ClusterKeyStats::new(top_k));
+        let count = stats.sketch.add(key);
+        stats.hot_keys.update(key, count);
+        stats.big_keys.update(key, reply_size as u64);
+    }
+
+    // Returns the keys of all the clusters with the request counts or the reply sizes.
+    pub fn get(&self, stats_type: KeyStatsType) -> Vec<(ClusterName, BinSafeStr, u64)> {
+        let mut result = vec![];
+        for item in self.clusters.iter() {
+            let stats = item.value();
+            let keys = match stats_type {
+                KeyStatsType::Hot => stats.hot_keys.get(),
+                KeyStatsType::Big => stats.big_keys.get(),
+            };
+            for (key, value) in keys.into_iter() {
+                result.push((item.key().clone(), key, value));
+            }
+        }
+        result.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        result
+    }
+
+    pub fn reset(&self, stats_type: KeyStatsType) {
+        let top_k = self.top_k;
+        for mut item in self.clusters.iter_mut() {
+            let stats = item.value_mut();
+            match stats_type {
+                KeyStatsType::Hot => {
+                    stats.sketch = CountMinSketch::default();
+                    stats.hot_keys = TopKeys::new(top_k);
+                }
+                KeyStatsType::Big => stats.big_keys = TopKeys::new(top_k),
+            }
+        }
+    }
+}
+
+// The length of the raw data for the replies from Redis,
+// or the total length of the strings for the generated replies.
+pub fn get_reply_size(reply: &RespPacket) -> usize {
+    match reply {
+        RespPacket::Indexed(indexed_resp) => indexed_resp.get_data().len(),
+        RespPacket::Data(resp) => get_resp_size(resp),
+    }
+}
+
+fn get_resp_size(resp: &RespVec) -> usize {
+    match resp {
+        Resp::Error(s) | Resp::Simple(s) | Resp::Integer(s) => s.len(),
+        Resp::Bulk(BulkStr::Str(s)) => s.len(),
+        Resp::Bulk(BulkStr::Nil) | Resp::Arr(Array::Nil) => 0,
+        Resp::Arr(Array::Arr(resps)) => resps.iter().map(get_resp_size).sum(),
+    }
+}
+
+pub fn key_stats_to_resp(stats: Vec<(ClusterName, BinSafeStr, u64)>) -> RespVec {
+    let elements = stats
+        .into_iter()
+        .map(|(cluster_name, key, value)| {
+            let mut element = format!("{} ", cluster_name).into_bytes();
+            element.extend_from_slice(&key);
+            element.extend_from_slice(format!(" {}", value).as_bytes());
+            Resp::Bulk(BulkStr::Str(element))
+        })
+        .collect();
+    Resp::Arr(Array::Arr(elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_hot_keys() {
+        let stats = KeyStats::new(2);
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        for (key, times) in &[("a", 3), ("b", 1), ("c", 2)] {
+            for _ in 0..*times {
+                stats.record(&cluster_name, key.as_bytes(), 1);
+            }
+        }
+        assert_eq!(
+            stats.get(KeyStatsType::Hot),
+            vec![
+                (cluster_name.clone(), b"a".to_vec(), 3),
+                (cluster_name.clone(), b"c".to_vec(), 2),
+            ]
+        );
+
+        stats.reset(KeyStatsType::Hot);
+        assert!(stats.get(KeyStatsType::Hot).is_empty());
+        assert_eq!(stats.get(KeyStatsType::Big).len(), 2);
+    }
+
+    #[test]
+    fn test_big_keys() {
+        let stats = KeyStats::new(2);
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        stats.record(&cluster_name, b"a", 10);
+        stats.record(&cluster_name, b"b", 1000);
+        stats.record(&cluster_name, b"a", 5);
+        stats.record(&cluster_name, b"c", 1);
+        assert_eq!(
+            stats.get(KeyStatsType::Big),
+            vec![
+                (cluster_name.clone(), b"b".to_vec(), 1000),
+                (cluster_name, b"a".to_vec(), 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disabled_key_stats() {
+        let stats = KeyStats::new(0);
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        stats.record(&cluster_name, b"a", 10);
+        assert!(stats.get(KeyStatsType::Hot).is_empty());
+    }
+
+    #[test]
+    fn test_reply_size() {
+        let resp = Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(b"abc".to_vec())),
+            Resp::Bulk(BulkStr::Nil),
+            Resp::Integer(b"12".to_vec()),
+        ]));
+        assert_eq!(get_reply_size(&RespPacket::from_resp_vec(resp)), 5);
+    }
+}
@@ -1,7 +1,8 @@
 use super::backend::{CmdTask, ConnFactory, IntoTask, SenderBackendError};
 use super::blocking::{
     gen_basic_blocking_sender_factory, gen_blocking_sender_factory, BasicBlockingSenderFactory,
-    BlockingBackendSenderFactory, BlockingCmdTaskSender, BlockingMap, CounterTask,
+    BlockingBackendSenderFactory, BlockingCmdTaskSender, BlockingHandle, BlockingMap, CounterTask,
+    TaskBlockingController,
 };
 use super::cluster::{ClusterBackendMap, ClusterMetaError, ClusterSendError, ClusterTag};
 use super::reply::{DecompressCommitHandlerFactory, ReplyCommitHandlerFactory};
@@ -12,7 +13,7 @@ use super::sender::{
 use super::service::ServerProxyConfig;
 use super::session::{CmdCtx, CmdCtxFactory};
 use super::slowlog::TaskEvent;
-use crate::common::cluster::{ClusterName, MigrationTaskMeta, SlotRangeTag};
+use crate::common::cluster::{ClusterName, MigrationProgress, MigrationTaskMeta, SlotRangeTag};
 use crate::common::config::ClusterConfig;
 use crate::common::proto::ProxyClusterMeta;
 use crate::common::response;
@@ -23,11 +24,15 @@ use crate::migration::task::MgrSubCmd;
 use crate::migration::task::SwitchArg;
 use crate::protocol::{Array, BulkStr, RedisClientFactory, Resp, RespPacket, RespVec};
 use crate::replication::manager::ReplicatorManager;
-use crate::replication::replicator::ReplicatorMeta;
+use crate::replication::replicator::{ReplicatorError, ReplicatorMeta};
 use arc_swap::{ArcSwap, Lease};
+use futures::future;
+use futures_timer::Delay;
+use std::collections::HashMap;
 use std::num::NonZeroUsize;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 pub struct MetaMap<S: CmdTaskSender, P: CmdTaskSender, T>
 where
@@ -82,6 +87,10 @@ type ProxyMetaMap<C> = MetaMap<
 >;
 pub type SharedMetaMap<C> = Arc<ArcSwap<ProxyMetaMap<C>>>;
 
+// master node address => (switchover id, the handle pausing the writes to the master)
+type SwitchoverPauses<C> =
+    Arc<Mutex<HashMap<String, (u64, BlockingHandle<BlockingTaskRetrySender<C>>)>>>;
+
 pub struct MetaManager<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> {
     config: Arc<ServerProxyConfig>,
     // Now replicator is not in meta_map, if later we need consistency
@@ -101,6 +110,8 @@ pub struct MetaManager<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>>
     peer_sender_factory: PeerSenderFactory<C>,
     blocking_map: Arc<BlockingMap<BasicSenderFactory<C>, BlockingTaskRetrySender<C>>>,
     cluster_config: ClusterConfig,
+    switchover_pauses: SwitchoverPauses<C>,
+    switchover_id: AtomicU64,
 }
 
 impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C> {
@@ -115,8 +126,7 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
         let reply_handler_factory = Arc::new(DecompressCommitHandlerFactory::new(meta_map.clone()));
         let blocking_task_sender = Arc::new(BlockingTaskRetrySender::new(
             meta_map.clone(),
-            config.max_redirections,
-            config.default_redirection_address.clone(),
+            config.clone(),
         ));
         let basic_sender_factory = gen_basic_blocking_sender_factory(
             config.clone(),
@@ -170,6 +180,8 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
             peer_sender_factory,
             blocking_map,
             cluster_config,
+            switchover_pauses: Arc::new(Mutex::new(HashMap::new())),
+            switchover_id: AtomicU64::new(0),
         }
     }
 
@@ -237,6 +249,18 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
             self.epoch.store(cluster_meta.get_epoch(), Ordering::SeqCst);
 
             self.migration_manager.run_tasks(new_tasks);
+
+            // The paused masters which are not masters anymore have been switched over.
+            // Releasing the pauses will redirect the blocked commands to the new masters.
+            let local_nodes = cluster_meta.get_local().get_map();
+            self.switchover_pauses
+                .lock()
+                .expect("MetaManager::set_meta switchover_pauses")
+                .retain(|node_address, _| {
+                    local_nodes
+                        .values()
+                        .any(|nodes| nodes.contains_key(node_address))
+                });
         };
 
         Ok(())
@@ -246,8 +270,116 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
         self.replicator_manager.update_replicators(meta)
     }
 
-    pub fn get_replication_info(&self) -> RespVec {
-        self.replicator_manager.get_metadata_report()
+    pub async fn get_replication_info(&self) -> RespVec {
+        let node_addresses = self.get_local_node_addresses();
+        let queries = node_addresses.into_iter().map(|address| async move {
+            let offset = self
+                .replicator_manager
+                .get_repl_offset(address.clone())
+                .await;
+            offset.map(|offset| (address, offset))
+        });
+        let repl_offsets: HashMap<String, u64> = future::join_all(queries)
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+        self.replicator_manager
+            .get_metadata_report_with_offsets(&repl_offsets)
+    }
+
+    // Pause the writes to the masters, wait for the replicas to catch up and promote them.
+    // The writes keep paused until the new metadata without these masters is set
+    // or it times out.
+    pub async fn switchover(
+        &self,
+        nodes: Vec<(String, String)>,
+        timeout: Duration,
+    ) -> Result<(), SwitchoverError> {
+        let (master_metadata, _) = self.replicator_manager.get_metadata();
+        for (master_node_address, _) in nodes.iter() {
+            if !master_metadata
+                .iter()
+                .any(|meta| &meta.master_node_address == master_node_address)
+            {
+                return Err(SwitchoverError::NotMaster(master_node_address.clone()));
+            }
+        }
+
+        let handles: Vec<_> = nodes
+            .iter()
+            .map(|(master_node_address, _)| {
+                let queue = self
+                    .blocking_map
+                    .get_blocking_queue(master_node_address.clone());
+                let handle = queue.start_blocking();
+                (queue, handle)
+            })
+            .collect();
+        for (queue, _) in handles.iter() {
+            while !queue.blocking_done() {
+                Delay::new(Duration::from_millis(1)).await;
+            }
+        }
+
+        let syncs = nodes
+            .iter()
+            .map(|(master_node_address, replica_node_address)| {
+                self.replicator_manager.wait_for_replica(
+                    master_node_address.clone(),
+                    replica_node_address.clone(),
+                    timeout,
+                )
+            });
+        future::try_join_all(syncs)
+            .await
+            .map_err(SwitchoverError::Replication)?;
+
+        let promotions = nodes.iter().map(|(_, replica_node_address)| {
+            self.replicator_manager
+                .promote_replica(replica_node_address.clone())
+        });
+        future::try_join_all(promotions)
+            .await
+            .map_err(SwitchoverError::Replication)?;
+
+        let switchover_id = self.switchover_id.fetch_add(1, Ordering::SeqCst);
+        {
+            let mut pauses = self
+                .switchover_pauses
+                .lock()
+                .expect("MetaManager::switchover");
+            for ((master_node_address, _), (_, handle)) in nodes.iter().zip(handles) {
+                pauses.insert(master_node_address.clone(), (switchover_id, handle));
+            }
+        }
+
+        let pauses = self.switchover_pauses.clone();
+        tokio::spawn(async move {
+            Delay::new(timeout).await;
+            pauses
+                .lock()
+                .expect("MetaManager::switchover timeout")
+                .retain(|_, (id, _)| *id != switchover_id);
+        });
+        Ok(())
+    }
+
+    // The backend redis nodes of this server proxy.
+    pub fn get_local_node_addresses(&self) -> Vec<String> {
+        let (master_metadata, replica_metadata) = self.replicator_manager.get_metadata();
+        let mut addresses: Vec<String> = master_metadata
+            .into_iter()
+            .map(|meta| meta.master_node_address)
+            .chain(
+                replica_metadata
+                    .into_iter()
+                    .map(|meta| meta.replica_node_address),
+            )
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+        addresses
     }
 
     pub fn info(&self) -> RespVec {
@@ -297,12 +429,23 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
         )
     }
 
+    pub fn get_migration_progress(&self) -> Vec<MigrationProgress> {
+        self.meta_map.load().migration_map.get_progress()
+    }
+
+    pub fn set_migration_paused(&self, cluster_name: &ClusterName, paused: bool) -> bool {
+        self.meta_map
+            .load()
+            .migration_map
+            .set_paused(cluster_name, paused)
+    }
+
     pub fn get_finished_migration_tasks(&self) -> Vec<MigrationTaskMeta> {
         self.meta_map.load().migration_map.get_finished_tasks()
     }
 
     pub fn send(&self, cmd_ctx: CmdCtx) {
-        let max_redirections = self.config.max_redirections;
+        let max_redirections = self.config.get_max_redirections();
         let default_redirection_address = self.config.default_redirection_address.as_ref();
         loop_send_cmd_ctx(
             &self.meta_map,
@@ -541,23 +684,20 @@ fn send_cmd_ctx_to_remote_directly<C: ConnFactory<Pkt = RespPacket>>(
     }
 }
 
+#[derive(Debug)]
+pub enum SwitchoverError {
+    NotMaster(String),
+    Replication(ReplicatorError),
+}
+
 pub struct BlockingTaskRetrySender<C: ConnFactory<Pkt = RespPacket>> {
     meta_map: SharedMetaMap<C>,
-    max_redirections: Option<NonZeroUsize>,
-    default_redirection_address: Option<String>,
+    config: Arc<ServerProxyConfig>,
 }
 
 impl<C: ConnFactory<Pkt = RespPacket>> BlockingTaskRetrySender<C> {
-    fn new(
-        meta_map: SharedMetaMap<C>,
-        max_redirections: Option<NonZeroUsize>,
-        default_redirection_address: Option<String>,
-    ) -> Self {
-        Self {
-            meta_map,
-            max_redirections,
-            default_redirection_address,
-        }
+    fn new(meta_map: SharedMetaMap<C>, config: Arc<ServerProxyConfig>) -> Self {
+        Self { meta_map, config }
     }
 }
 
@@ -568,8 +708,8 @@ impl<C: ConnFactory<Pkt = RespPacket>> CmdTaskSender for BlockingTaskRetrySender
         loop_send_cmd_ctx(
             &self.meta_map,
             cmd_task,
-            self.max_redirections,
-            self.default_redirection_address.as_ref(),
+            self.config.get_max_redirections(),
+            self.config.default_redirection_address.as_ref(),
         );
         Ok(())
     }
@@ -1,14 +1,23 @@
 pub mod backend;
 pub mod blocking;
+pub mod capture;
+pub mod circuit_breaker;
 pub mod cluster;
 pub mod command;
 mod compress;
+pub mod denied_stats;
 pub mod executor;
+pub mod key_prefix;
+pub mod key_stats;
 pub mod manager;
 pub mod migration_backend;
+pub mod rate_limit;
+pub mod read_cache;
 pub mod reply;
 pub mod sender;
 pub mod service;
 pub mod session;
+pub mod shutdown;
 mod slot;
+pub mod slot_stats;
 pub mod slowlog;
@@ -0,0 +1,136 @@
+use crate::common::cluster::ClusterName;
+use crate::common::token_bucket::TokenBucket;
+use crate::protocol::{Array, BulkStr, Resp, RespVec};
+use dashmap::DashMap;
+use std::time::Duration;
+
+// Limits the requests and the request bytes per second.
+// The throttled requests are rejected instead of being delayed
+// so that they won't pile up in the server proxy.
+// Zero rate means no limit.
+#[derive(Debug)]
+pub struct RateLimiter {
+    ops_bucket: TokenBucket,
+    bytes_bucket: TokenBucket,
+}
+
+impl RateLimiter {
+    pub fn new(ops_per_sec: u64, bytes_per_sec: u64) -> Self {
+        Self {
+            ops_bucket: TokenBucket::with_full_tokens(ops_per_sec),
+            bytes_bucket: TokenBucket::with_full_tokens(bytes_per_sec),
+        }
+    }
+
+    // Returns false if the request should be throttled.
+    pub fn try_acquire(&mut self, ops_per_sec: u64, bytes_per_sec: u64, bytes: u64) -> bool {
+        let no_wait = Duration::from_secs(0);
+        if self.ops_bucket.get_wait_time(ops_per_sec) > no_wait
+            || self.bytes_bucket.get_wait_time(bytes_per_sec) > no_wait
+        {
+            return false;
+        }
+        self.ops_bucket.consume(ops_per_sec, 1);
+        self.bytes_bucket.consume(bytes_per_sec, bytes);
+        true
+    }
+}
+
+// The limiters of the clusters on this server proxy.
+#[derive(Debug, Default)]
+pub struct ClusterRateLimiter {
+    limiters: DashMap<ClusterName, RateLimiter>,
+}
+
+impl ClusterRateLimiter {
+    pub fn try_acquire(
+        &self,
+        cluster_name: &ClusterName,
+        ops_per_sec: u64,
+        bytes_per_sec: u64,
+        bytes: u64,
+    ) -> bool {
+        if ops_per_sec == 0 && bytes_per_sec == 0 {
+            return true;
+        }
+        let mut limiter = self
+            .limiters
+            .entry(cluster_name.clone())
+            .or_insert_with(|| RateLimiter::new(ops_per_sec, bytes_per_sec));
+        limiter.try_acquire(ops_per_sec, bytes_per_sec, bytes)
+    }
+}
+
+// Records the throttled requests of each cluster,
+// including the ones throttled by the limits of the sessions.
+#[derive(Debug, Default)]
+pub struct ThrottleStats {
+    throttled: DashMap<ClusterName, u64>,
+}
+
+impl ThrottleStats {
+    pub fn record(&self, cluster_name: &ClusterName) {
+        *self.throttled.entry(cluster_name.clone()).or_insert(0) += 1;
+    }
+
+    pub fn get(&self) -> Vec<(ClusterName, u64)> {
+        let mut stats: Vec<(ClusterName, u64)> = self
+            .throttled
+            .iter()
+            .map(|item| (item.key().clone(), *item.value()))
+            .collect();
+        stats.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        stats
+    }
+
+    pub fn reset(&self) {
+        self.throttled.clear();
+    }
+}
+
+pub fn throttle_stats_to_resp(stats: Vec<(ClusterName, u64)>) -> RespVec {
+    let elements = stats
+        .into_iter()
+        .map(|(cluster_name, throttled)| {
+            Resp::Bulk(BulkStr::Str(
+                format!("{} {}", cluster_name, throttled).into_bytes(),
+            ))
+        })
+        .collect();
+    Resp::Arr(Array::Arr(elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_rate_limiter() {
+        // The last one uses the tokens of the future.
+        let mut limiter = RateLimiter::new(10, 0);
+        for _ in 0..11 {
+            assert!(limiter.try_acquire(10, 0, 1));
+        }
+        assert!(!limiter.try_acquire(10, 0, 1));
+
+        // A large request is allowed but the following ones need to wait.
+        let mut limiter = RateLimiter::new(0, 100);
+        assert!(limiter.try_acquire(0, 100, 1000));
+        assert!(!limiter.try_acquire(0, 100, 1));
+
+        // No limit.
+        assert!(limiter.try_acquire(0, 0, 1000));
+    }
+
+    #[test]
+    fn test_throttle_stats() {
+        let stats = ThrottleStats::default();
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        stats.record(&cluster_name);
+        stats.record(&cluster_name);
+        assert_eq!(stats.get(), vec![(cluster_name, 2)]);
+        stats.reset();
+        assert!(stats.get().is_empty());
+    }
+}
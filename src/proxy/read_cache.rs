@@ -0,0 +1,415 @@
+use crate::common::cluster::ClusterName;
+use crate::common::config::ClusterConfig;
+use crate::common::utils::{bytes_ascii_case_insensitive_eq, generate_slot, SLOT_NUM};
+use crate::protocol::{Array, BinSafeStr, BulkStr, Resp, RespVec};
+use dashmap::DashMap;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// The GETs of each key are counted in this window to detect the hot keys.
+const HOT_KEY_WINDOW: Duration = Duration::from_secs(1);
+// Avoid using too much memory for counting when there are too many keys.
+const MAX_COUNTED_KEYS: usize = 65536;
+// The scripts could write any key.
+const CLEARING_COMMANDS: &[&[u8]] = &[b"EVAL", b"EVALSHA", b"FLUSHALL", b"FLUSHDB"];
+
+pub fn clears_read_cache(cmd_name: &[u8]) -> bool {
+    CLEARING_COMMANDS
+        .iter()
+        .any(|name| bytes_ascii_case_insensitive_eq(name, cmd_name))
+}
+
+pub enum CacheLookup {
+    Hit(Option<BinSafeStr>),
+    // The reply of the GET could be inserted with this ticket.
+    Miss(CacheTicket),
+    Uncacheable,
+}
+
+pub struct CacheTicket {
+    slot: usize,
+    version: u64,
+    max_keys: usize,
+    ttl: Duration,
+}
+
+struct CacheEntry {
+    value: Option<BinSafeStr>,
+    expire_at: Instant,
+    seq: u64,
+}
+
+struct ClusterReadCache {
+    entries: HashMap<BinSafeStr, CacheEntry>,
+    // Keys in the inserting order for eviction.
+    // The stale ones are skipped by comparing the `seq`.
+    queue: VecDeque<(BinSafeStr, u64)>,
+    seq: u64,
+    // The version of each slot is increased on every invalidation of its keys
+    // so that the replies of the GETs sent before the writes won't be cached.
+    // It's per slot so that the writes won't stop caching the unrelated keys.
+    slot_versions: Vec<u64>,
+    counters: HashMap<BinSafeStr, u64>,
+    window_start: Instant,
+    hits: u64,
+    misses: u64,
+}
+
+impl ClusterReadCache {
+    fn new(now: Instant) -> Self {
+        Self {
+            entries: HashMap::new(),
+            queue: VecDeque::new(),
+            seq: 0,
+            slot_versions: vec![0; SLOT_NUM],
+            counters: HashMap::new(),
+            window_start: now,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn is_hot_key(&mut self, key: &[u8], threshold: u64, now: Instant) -> bool {
+        if threshold == 0 {
+            return false;
+        }
+        if now.duration_since(self.window_start) >= HOT_KEY_WINDOW {
+            self.counters.clear();
+            self.window_start = now;
+        }
+        if let Some(count) = self.counters.get_mut(key) {
+            *count += 1;
+            return *count >= threshold;
+        }
+        if self.counters.len() < MAX_COUNTED_KEYS {
+            self.counters.insert(key.to_vec(), 1);
+        }
+        threshold <= 1
+    }
+
+    fn evict(&mut self, max_keys: usize) {
+        while self.entries.len() > max_keys || self.queue.len() > 2 * max_keys {
+            let (key, seq) = match self.queue.pop_front() {
+                Some(item) => item,
+                None => break,
+            };
+            if self.entries.get(&key).map(|entry| entry.seq) == Some(seq) {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+// Caches the GET replies for the keys matching `read_cache_key_patterns`
+// or getting more than `read_cache_hot_key_threshold` GETs per second.
+// The cached keys are invalidated by the writes through this server proxy
+// and the `read_cache_ttl`, so the clients could read stale data
+// written through other server proxies within the TTL.
+#[derive(Default)]
+pub struct ReadCache {
+    caches: DashMap<ClusterName, ClusterReadCache>,
+}
+
+impl ReadCache {
+    pub fn contains(&self, cluster_name: &ClusterName) -> bool {
+        self.caches.contains_key(cluster_name)
+    }
+
+    pub fn lookup(
+        &self,
+        cluster_name: &ClusterName,
+        key: &[u8],
+        config: &ClusterConfig,
+        now: Instant,
+    ) -> CacheLookup {
+        if config.read_cache_max_keys == 0 {
+            if self.caches.contains_key(cluster_name) {
+                self.caches.remove(cluster_name);
+            }
+            return CacheLookup::Uncacheable;
+        }
+
+        let mut cache = self
+            .caches
+            .entry(cluster_name.clone())
+            .or_insert_with(|| ClusterReadCache::new(now));
+
+        let cached = match cache.entries.get(key) {
+            Some(entry) if entry.expire_at > now => Some(entry.value.clone()),
+            Some(_) => {
+                cache.entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        if let Some(value) = cached {
+            cache.hits += 1;
+            return CacheLookup::Hit(value);
+        }
+
+        let cacheable = config.read_cache_key_patterns.is_match(key)
+            || cache.is_hot_key(key, config.read_cache_hot_key_threshold, now);
+        if !cacheable {
+            return CacheLookup::Uncacheable;
+        }
+        cache.misses += 1;
+        let slot = generate_slot(key);
+        CacheLookup::Miss(CacheTicket {
+            slot,
+            version: cache.slot_versions.get(slot).cloned().unwrap_or(0),
+            max_keys: config.read_cache_max_keys as usize,
+            ttl: Duration::from_millis(config.read_cache_ttl),
+        })
+    }
+
+    pub fn insert(
+        &self,
+        cluster_name: &ClusterName,
+        key: BinSafeStr,
+        value: Option<BinSafeStr>,
+        ticket: CacheTicket,
+        now: Instant,
+    ) {
+        let mut cache = match self.caches.get_mut(cluster_name) {
+            Some(cache) => cache,
+            None => return,
+        };
+        if cache.slot_versions.get(ticket.slot) != Some(&ticket.version) {
+            return;
+        }
+        cache.seq += 1;
+        let seq = cache.seq;
+        let entry = CacheEntry {
+            value,
+            expire_at: now + ticket.ttl,
+            seq,
+        };
+        cache.entries.insert(key.clone(), entry);
+        cache.queue.push_back((key, seq));
+        cache.evict(ticket.max_keys);
+    }
+
+    // Called both before sending the writes and after getting their replies.
+    // The GETs sent after the writes could still get the old values
+    // if they are processed by Redis before the writes.
+    pub fn invalidate<'a, It: Iterator<Item = &'a [u8]>>(
+        &self,
+        cluster_name: &ClusterName,
+        keys: It,
+    ) {
+        if let Some(mut cache) = self.caches.get_mut(cluster_name) {
+            for key in keys {
+                if let Some(version) = cache.slot_versions.get_mut(generate_slot(key)) {
+                    *version += 1;
+                }
+                cache.entries.remove(key);
+            }
+        }
+    }
+
+    pub fn clear(&self, cluster_name: &ClusterName) {
+        if let Some(mut cache) = self.caches.get_mut(cluster_name) {
+            for version in cache.slot_versions.iter_mut() {
+                *version += 1;
+            }
+            cache.entries.clear();
+            cache.queue.clear();
+        }
+    }
+
+    // Returns the hits, misses and the number of cached keys of each cluster.
+    pub fn get_stats(&self) -> Vec<(ClusterName, u64, u64, usize)> {
+        let mut stats: Vec<(ClusterName, u64, u64, usize)> = self
+            .caches
+            .iter()
+            .map(|item| {
+                let cache = item.value();
+                (
+                    item.key().clone(),
+                    cache.hits,
+                    cache.misses,
+                    cache.entries.len(),
+                )
+            })
+            .collect();
+        stats.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        stats
+    }
+
+    pub fn reset_stats(&self) {
+        for mut item in self.caches.iter_mut() {
+            let cache = item.value_mut();
+            cache.hits = 0;
+            cache.misses = 0;
+        }
+    }
+}
+
+pub fn read_cache_stats_to_resp(stats: Vec<(ClusterName, u64, u64, usize)>) -> RespVec {
+    let elements = stats
+        .into_iter()
+        .map(|(cluster_name, hits, misses, keys)| {
+            Resp::Bulk(BulkStr::Str(
+                format!("{} {} {} {}", cluster_name, hits, misses, keys).into_bytes(),
+            ))
+        })
+        .collect();
+    Resp::Arr(Array::Arr(elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn gen_config() -> ClusterConfig {
+        let mut config = ClusterConfig::default();
+        config.set_field("read_cache_max_keys", "2").unwrap();
+        config.set_field("read_cache_ttl", "1000").unwrap();
+        config
+            .set_field("read_cache_key_patterns", "cached:*")
+            .unwrap();
+        config
+    }
+
+    fn get_ticket(lookup: CacheLookup) -> CacheTicket {
+        match lookup {
+            CacheLookup::Miss(ticket) => ticket,
+            _ => panic!("unexpected lookup result"),
+        }
+    }
+
+    #[test]
+    fn test_read_cache() {
+        let cache = ReadCache::default();
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let config = gen_config();
+        let now = Instant::now();
+
+        assert!(matches!(
+            cache.lookup(&cluster_name, b"other", &config, now),
+            CacheLookup::Uncacheable
+        ));
+
+        let ticket = get_ticket(cache.lookup(&cluster_name, b"cached:a", &config, now));
+        cache.insert(
+            &cluster_name,
+            b"cached:a".to_vec(),
+            Some(b"v".to_vec()),
+            ticket,
+            now,
+        );
+        assert!(matches!(
+            cache.lookup(&cluster_name, b"cached:a", &config, now),
+            CacheLookup::Hit(Some(_))
+        ));
+
+        // Expired
+        let later = now + Duration::from_secs(2);
+        assert!(matches!(
+            cache.lookup(&cluster_name, b"cached:a", &config, later),
+            CacheLookup::Miss(_)
+        ));
+
+        // The reply of the GET sent before the write is not cached.
+        let ticket = get_ticket(cache.lookup(&cluster_name, b"cached:a", &config, now));
+        cache.invalidate(&cluster_name, vec![b"cached:a" as &[u8]].into_iter());
+        cache.insert(&cluster_name, b"cached:a".to_vec(), None, ticket, now);
+        assert!(matches!(
+            cache.lookup(&cluster_name, b"cached:a", &config, now),
+            CacheLookup::Miss(_)
+        ));
+
+        assert_eq!(cache.get_stats(), vec![(cluster_name.clone(), 1, 4, 0)]);
+        cache.reset_stats();
+        assert_eq!(cache.get_stats(), vec![(cluster_name, 0, 0, 0)]);
+    }
+
+    fn invalidate(cache: &ReadCache, cluster_name: &ClusterName, key: &'static [u8]) {
+        cache.invalidate(cluster_name, vec![key].into_iter());
+    }
+
+    #[test]
+    fn test_interleaved_get_and_set_replies() {
+        let cache = ReadCache::default();
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let config = gen_config();
+        let now = Instant::now();
+
+        // SET sent, GET sent, GET replied with the old value, SET replied.
+        invalidate(&cache, &cluster_name, b"cached:a");
+        let ticket = get_ticket(cache.lookup(&cluster_name, b"cached:a", &config, now));
+        let old_value = Some(b"old".to_vec());
+        cache.insert(&cluster_name, b"cached:a".to_vec(), old_value, ticket, now);
+        invalidate(&cache, &cluster_name, b"cached:a");
+        assert!(matches!(
+            cache.lookup(&cluster_name, b"cached:a", &config, now),
+            CacheLookup::Miss(_)
+        ));
+
+        // SET sent, GET sent, SET replied, GET replied with the old value.
+        invalidate(&cache, &cluster_name, b"cached:a");
+        let ticket = get_ticket(cache.lookup(&cluster_name, b"cached:a", &config, now));
+        invalidate(&cache, &cluster_name, b"cached:a");
+        let old_value = Some(b"old".to_vec());
+        cache.insert(&cluster_name, b"cached:a".to_vec(), old_value, ticket, now);
+        assert!(matches!(
+            cache.lookup(&cluster_name, b"cached:a", &config, now),
+            CacheLookup::Miss(_)
+        ));
+
+        // The writes of the keys in other slots don't affect the GET.
+        assert_ne!(generate_slot(b"cached:a"), generate_slot(b"cached:b"));
+        let ticket = get_ticket(cache.lookup(&cluster_name, b"cached:a", &config, now));
+        invalidate(&cache, &cluster_name, b"cached:b");
+        let value = Some(b"new".to_vec());
+        cache.insert(&cluster_name, b"cached:a".to_vec(), value, ticket, now);
+        assert!(matches!(
+            cache.lookup(&cluster_name, b"cached:a", &config, now),
+            CacheLookup::Hit(Some(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_cache_eviction() {
+        let cache = ReadCache::default();
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let config = gen_config();
+        let now = Instant::now();
+
+        for key in &["cached:a", "cached:b", "cached:c"] {
+            let ticket = get_ticket(cache.lookup(&cluster_name, key.as_bytes(), &config, now));
+            cache.insert(&cluster_name, key.as_bytes().to_vec(), None, ticket, now);
+        }
+        assert!(matches!(
+            cache.lookup(&cluster_name, b"cached:a", &config, now),
+            CacheLookup::Miss(_)
+        ));
+        assert!(matches!(
+            cache.lookup(&cluster_name, b"cached:c", &config, now),
+            CacheLookup::Hit(None)
+        ));
+    }
+
+    #[test]
+    fn test_hot_key_detection() {
+        let cache = ReadCache::default();
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let mut config = gen_config();
+        config
+            .set_field("read_cache_hot_key_threshold", "3")
+            .unwrap();
+        let now = Instant::now();
+
+        for _ in 0..2 {
+            assert!(matches!(
+                cache.lookup(&cluster_name, b"hot", &config, now),
+                CacheLookup::Uncacheable
+            ));
+        }
+        assert!(matches!(
+            cache.lookup(&cluster_name, b"hot", &config, now),
+            CacheLookup::Miss(_)
+        ));
+    }
+}
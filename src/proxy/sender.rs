@@ -1,16 +1,17 @@
 use super::backend::{
-    BackendError, BackendNode, CmdTask, CmdTaskResultHandler, CmdTaskResultHandlerFactory,
-    ConnFactory, ReqTask, SenderBackendError,
+    BackendError, BackendNode, BackendSendError, CmdTask, CmdTaskResultHandler,
+    CmdTaskResultHandlerFactory, ConnFactory, ReqTask, SenderBackendError,
 };
+use super::circuit_breaker::CircuitBreaker;
 use super::service::ServerProxyConfig;
 use crate::common::response;
 use crate::common::track::TrackedFutureRegistry;
 use crate::protocol::Resp;
 use either::Either;
 use std::collections::HashMap;
-use std::num::NonZeroUsize;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
 
 pub trait CmdTaskSender {
     type Task: CmdTask;
@@ -34,11 +35,18 @@ impl<F: CmdTaskResultHandlerFactory> CmdTaskSender for RecoverableBackendNode<F>
 
     fn send(&self, cmd_task: Self::Task) -> Result<(), SenderBackendError<Self::Task>> {
         self.node.send(cmd_task).map_err(|e| {
-            let cmd_task = e.into_inner();
+            let (cmd_task, err_str) = match e {
+                BackendSendError::Closed(cmd_task) => {
+                    error!("backend node is closed");
+                    (cmd_task, response::ERR_BACKEND_CONNECTION)
+                }
+                BackendSendError::CircuitOpen(cmd_task) => {
+                    (cmd_task, response::ERR_BACKEND_CIRCUIT_OPEN)
+                }
+            };
             cmd_task.set_resp_result(Ok(Resp::Error(
-                format!("{}: {}", response::ERR_BACKEND_CONNECTION, self.address).into_bytes(),
+                format!("{}: {}", err_str, self.address).into_bytes(),
             )));
-            error!("backend node is closed");
             SenderBackendError::Canceled
         })
     }
@@ -53,6 +61,8 @@ where
     handler_factory: Arc<F>,
     conn_factory: Arc<CF>,
     future_registry: Arc<TrackedFutureRegistry>,
+    // The connections to the same address share the same circuit breaker.
+    circuit_breakers: RwLock<HashMap<String, Weak<CircuitBreaker>>>,
 }
 
 impl<F: CmdTaskResultHandlerFactory, CF: ConnFactory> RecoverableBackendNodeFactory<F, CF>
@@ -71,7 +81,36 @@ where
             handler_factory,
             conn_factory,
             future_registry,
+            circuit_breakers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn get_circuit_breaker(&self, address: &str) -> Arc<CircuitBreaker> {
+        if let Some(circuit_breaker) = self
+            .circuit_breakers
+            .read()
+            .expect("RecoverableBackendNodeFactory::get_circuit_breaker")
+            .get(address)
+            .and_then(|weak| weak.upgrade())
+        {
+            return circuit_breaker;
+        }
+
+        let mut guard = self
+            .circuit_breakers
+            .write()
+            .expect("RecoverableBackendNodeFactory::get_circuit_breaker");
+        if let Some(circuit_breaker) = guard.get(address).and_then(|weak| weak.upgrade()) {
+            return circuit_breaker;
         }
+        // Remove the circuit breakers of the removed backends.
+        guard.retain(|_, weak| weak.strong_count() > 0);
+        let circuit_breaker = Arc::new(CircuitBreaker::new(
+            self.config.backend_circuit_breaker_threshold,
+            Duration::from_millis(self.config.backend_circuit_breaker_open_time),
+        ));
+        guard.insert(address.to_string(), Arc::downgrade(&circuit_breaker));
+        circuit_breaker
     }
 }
 
@@ -89,6 +128,7 @@ where
             Arc::new(self.handler_factory.create()),
             self.config.clone(),
             self.conn_factory.clone(),
+            self.get_circuit_breaker(&address),
         );
         let desc = format!("backend::RecoverableBackendNode: address={}", address);
         let fut = TrackedFutureRegistry::wrap(self.future_registry.clone(), fut, desc);
@@ -194,15 +234,17 @@ impl<S: CmdTaskSender> CmdTaskSender for RRSenderGroup<S> {
     }
 }
 
+// The group size is `backend_conn_num` when the group is created,
+// so the changed `backend_conn_num` only takes effect on the new groups.
 pub struct RRSenderGroupFactory<F: CmdTaskSenderFactory> {
-    group_size: NonZeroUsize,
+    config: Arc<ServerProxyConfig>,
     inner_factory: F,
 }
 
 impl<F: CmdTaskSenderFactory> RRSenderGroupFactory<F> {
-    pub fn new(group_size: NonZeroUsize, inner_factory: F) -> Self {
+    pub fn new(config: Arc<ServerProxyConfig>, inner_factory: F) -> Self {
         Self {
-            group_size,
+            config,
             inner_factory,
         }
     }
@@ -213,7 +255,7 @@ impl<F: CmdTaskSenderFactory> CmdTaskSenderFactory for RRSenderGroupFactory<F> {
 
     fn create(&self, address: String) -> Self::Sender {
         let mut senders = Vec::new();
-        for _ in 0..self.group_size.get() {
+        for _ in 0..self.config.get_backend_conn_num().get() {
             senders.push(self.inner_factory.create(address.clone()));
         }
         Self::Sender {
@@ -298,7 +340,7 @@ where
     CF::Pkt: Send,
 {
     CachedSenderFactory::new(RRSenderGroupFactory::new(
-        config.backend_conn_num,
+        config.clone(),
         RecoverableBackendNodeFactory::new(
             config.clone(),
             reply_handler_factory,
@@ -322,7 +364,7 @@ where
     CF::Pkt: Send,
 {
     RRSenderGroupFactory::new(
-        config.backend_conn_num,
+        config.clone(),
         ReqAdaptorSenderFactory::new(RecoverableBackendNodeFactory::new(
             config.clone(),
             reply_handler_factory,
@@ -1,41 +1,112 @@
+use super::capture::TrafficCapture;
+use super::rate_limit::ThrottleStats;
 use super::session::CmdCtxHandler;
-use super::session::{handle_session, Session};
+use super::session::{handle_session, Session, SessionConfig};
+use super::shutdown::ShutdownSignal;
 use super::slowlog::SlowRequestLogger;
 use crate::common::config::ConfigError;
+use crate::common::response::ERR_MAX_CLIENTS;
 use crate::common::track::TrackedFutureRegistry;
 use crate::common::utils::{resolve_first_address, ThreadSafe};
-use futures::{FutureExt, StreamExt};
+use crate::protocol::{resp_to_buf, Resp};
+use crate::MAX_REDIRECTIONS;
+use futures::{future, FutureExt, StreamExt};
+use std::cmp::{max, min};
+use std::collections::HashSet;
 use std::error::Error;
+use std::fs;
 use std::num::NonZeroUsize;
-use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use string_error::into_err;
-use tokio::net::TcpListener;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+const DRAINING_CHECK_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Debug)]
 pub struct ServerProxyConfig {
     pub address: String,
     pub announce_address: String,
-    pub auto_select_cluster: bool,
+    pub auto_select_cluster: AtomicBool,
     pub slowlog_len: NonZeroUsize,
     pub slowlog_log_slower_than: AtomicI64,
     pub slowlog_sample_rate: AtomicU64,
+    // The number of the hot keys and big keys recorded for each cluster. Zero to disable it.
+    pub key_stats_top_k: usize,
     pub thread_number: NonZeroUsize,
+    // Not used since the channels of the sessions and the backend connections are unbounded.
     pub session_channel_size: usize,
     pub backend_channel_size: usize,
-    pub backend_conn_num: NonZeroUsize,
-    pub backend_batch_min_time: usize,
-    pub backend_batch_max_time: usize,
-    pub backend_batch_buf: NonZeroUsize,
-    pub session_batch_min_time: usize,
-    pub session_batch_max_time: usize,
-    pub session_batch_buf: NonZeroUsize,
+    // The fields below could be changed at runtime
+    // and take effect on the new sessions and backend connections.
+    // Should not be zero.
+    pub backend_conn_num: AtomicUsize,
+    pub backend_batch_min_time: AtomicUsize,
+    pub backend_batch_max_time: AtomicUsize,
+    // Should not be zero.
+    pub backend_batch_buf: AtomicUsize,
+    pub session_batch_min_time: AtomicUsize,
+    pub session_batch_max_time: AtomicUsize,
+    // Should not be zero.
+    pub session_batch_buf: AtomicUsize,
     pub active_redirection: bool,
-    pub max_redirections: Option<NonZeroUsize>,
+    // Zero to disable it.
+    pub max_redirections: AtomicUsize,
     pub default_redirection_address: Option<String>,
+    // In milliseconds. Zero to disable it.
+    pub backend_request_timeout: AtomicU64,
+    // Consecutive timeouts to open the circuit breaker. Zero to disable it.
+    pub backend_circuit_breaker_threshold: u64,
+    // In milliseconds.
+    pub backend_circuit_breaker_open_time: u64,
+    // In milliseconds. The max time to wait for the sessions to be closed on shutdown.
+    pub shutdown_timeout: u64,
+    // Limit the data commands of each session. Zero for no limit.
+    pub session_rate_limit_ops_per_sec: AtomicU64,
+    pub session_rate_limit_bytes_per_sec: AtomicU64,
+    // In seconds. Close the client connections idle for longer than it. Zero to disable it.
+    pub timeout: AtomicU64,
+    // The max number of the client connections. Zero for no limit.
+    pub maxclients: AtomicUsize,
+    // The directory of the files of `UMCTL CAPTURE`. None to disable capturing.
+    pub capture_dir: Option<String>,
+    // Used by `CONFIG REWRITE`.
+    pub config_file: Option<String>,
 }
 
+// The fields which could be changed by `CONFIG SET`, reloaded on SIGHUP
+// and persisted by `CONFIG REWRITE`.
+const RUNTIME_FIELDS: &[&str] = &[
+    "auto_select_cluster",
+    "slowlog_log_slower_than",
+    "slowlog_sample_rate",
+    "backend_conn_num",
+    "backend_batch_min_time",
+    "backend_batch_max_time",
+    "backend_batch_buf",
+    "session_batch_min_time",
+    "session_batch_max_time",
+    "session_batch_buf",
+    "max_redirections",
+    "backend_request_timeout",
+    "session_rate_limit_ops_per_sec",
+    "session_rate_limit_bytes_per_sec",
+    "timeout",
+    "maxclients",
+];
+
 impl ServerProxyConfig {
+    pub fn get_auto_select_cluster(&self) -> bool {
+        self.auto_select_cluster.load(Ordering::Relaxed)
+    }
+
+    pub fn set_auto_select_cluster(&self, auto_select_cluster: bool) {
+        self.auto_select_cluster
+            .store(auto_select_cluster, Ordering::Relaxed)
+    }
+
     pub fn get_slowlog_log_slower_than(&self) -> i64 {
         self.slowlog_log_slower_than.load(Ordering::Relaxed)
     }
@@ -52,6 +123,82 @@ impl ServerProxyConfig {
         self.slowlog_sample_rate
             .store(slowlog_sample_rate, Ordering::Relaxed)
     }
+
+    pub fn get_backend_conn_num(&self) -> NonZeroUsize {
+        load_non_zero(&self.backend_conn_num)
+    }
+
+    pub fn get_backend_batch_min_time(&self) -> usize {
+        self.backend_batch_min_time.load(Ordering::Relaxed)
+    }
+
+    pub fn get_backend_batch_max_time(&self) -> usize {
+        self.backend_batch_max_time.load(Ordering::Relaxed)
+    }
+
+    pub fn get_backend_batch_buf(&self) -> NonZeroUsize {
+        load_non_zero(&self.backend_batch_buf)
+    }
+
+    pub fn get_session_batch_min_time(&self) -> usize {
+        self.session_batch_min_time.load(Ordering::Relaxed)
+    }
+
+    pub fn get_session_batch_max_time(&self) -> usize {
+        self.session_batch_max_time.load(Ordering::Relaxed)
+    }
+
+    pub fn get_session_batch_buf(&self) -> NonZeroUsize {
+        load_non_zero(&self.session_batch_buf)
+    }
+
+    pub fn get_max_redirections(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.max_redirections.load(Ordering::Relaxed))
+    }
+
+    pub fn set_max_redirections(&self, max_redirections: usize) {
+        self.max_redirections
+            .store(min(MAX_REDIRECTIONS, max_redirections), Ordering::Relaxed)
+    }
+
+    pub fn get_backend_request_timeout(&self) -> u64 {
+        self.backend_request_timeout.load(Ordering::Relaxed)
+    }
+
+    pub fn set_backend_request_timeout(&self, timeout: u64) {
+        self.backend_request_timeout
+            .store(timeout, Ordering::Relaxed)
+    }
+
+    pub fn get_session_rate_limit_ops_per_sec(&self) -> u64 {
+        self.session_rate_limit_ops_per_sec.load(Ordering::Relaxed)
+    }
+
+    pub fn set_session_rate_limit_ops_per_sec(&self, ops_per_sec: u64) {
+        self.session_rate_limit_ops_per_sec
+            .store(ops_per_sec, Ordering::Relaxed)
+    }
+
+    pub fn get_session_rate_limit_bytes_per_sec(&self) -> u64 {
+        self.session_rate_limit_bytes_per_sec
+            .load(Ordering::Relaxed)
+    }
+
+    pub fn set_session_rate_limit_bytes_per_sec(&self, bytes_per_sec: u64) {
+        self.session_rate_limit_bytes_per_sec
+            .store(bytes_per_sec, Ordering::Relaxed)
+    }
+
+    pub fn get_timeout(&self) -> Option<Duration> {
+        match self.timeout.load(Ordering::Relaxed) {
+            0 => None,
+            timeout => Some(Duration::from_secs(timeout)),
+        }
+    }
+
+    pub fn get_maxclients(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.maxclients.load(Ordering::Relaxed))
+    }
 }
 
 impl ServerProxyConfig {
@@ -59,25 +206,43 @@ impl ServerProxyConfig {
         match field.to_lowercase().as_ref() {
             "address" => Ok(self.address.clone()),
             "announce_address" => Ok(self.announce_address.clone()),
-            "auto_select_cluster" => Ok(self.auto_select_cluster.to_string()),
+            "auto_select_cluster" => Ok(self.get_auto_select_cluster().to_string()),
             "slowlog_len" => Ok(self.slowlog_len.to_string()),
             "thread_number" => Ok(self.thread_number.to_string()),
             "session_channel_size" => Ok(self.session_channel_size.to_string()),
             "backend_channel_size" => Ok(self.backend_channel_size.to_string()),
-            "backend_conn_num" => Ok(self.backend_conn_num.to_string()),
+            "backend_conn_num" => Ok(self.get_backend_conn_num().to_string()),
             "slowlog_log_slower_than" => Ok(self.get_slowlog_log_slower_than().to_string()),
             "slowlog_sample_rate" => Ok(self.get_slowlog_sample_rate().to_string()),
-            "backend_batch_min_time" => Ok(self.backend_batch_min_time.to_string()),
-            "backend_batch_max_time" => Ok(self.backend_batch_max_time.to_string()),
-            "backend_batch_buf" => Ok(self.backend_batch_buf.to_string()),
-            "session_batch_min_time" => Ok(self.session_batch_min_time.to_string()),
-            "session_batch_max_time" => Ok(self.session_batch_max_time.to_string()),
-            "session_batch_buf" => Ok(self.session_batch_buf.to_string()),
+            "key_stats_top_k" => Ok(self.key_stats_top_k.to_string()),
+            "backend_batch_min_time" => Ok(self.get_backend_batch_min_time().to_string()),
+            "backend_batch_max_time" => Ok(self.get_backend_batch_max_time().to_string()),
+            "backend_batch_buf" => Ok(self.get_backend_batch_buf().to_string()),
+            "session_batch_min_time" => Ok(self.get_session_batch_min_time().to_string()),
+            "session_batch_max_time" => Ok(self.get_session_batch_max_time().to_string()),
+            "session_batch_buf" => Ok(self.get_session_batch_buf().to_string()),
             "active_redirection" => Ok(self.active_redirection.to_string()),
             "max_redirections" => Ok(self
-                .max_redirections
+                .get_max_redirections()
                 .map(|n| n.get().to_string())
                 .unwrap_or_else(|| "none".to_string())),
+            "backend_request_timeout" => Ok(self.get_backend_request_timeout().to_string()),
+            "backend_circuit_breaker_threshold" => {
+                Ok(self.backend_circuit_breaker_threshold.to_string())
+            }
+            "backend_circuit_breaker_open_time" => {
+                Ok(self.backend_circuit_breaker_open_time.to_string())
+            }
+            "shutdown_timeout" => Ok(self.shutdown_timeout.to_string()),
+            "session_rate_limit_ops_per_sec" => {
+                Ok(self.get_session_rate_limit_ops_per_sec().to_string())
+            }
+            "session_rate_limit_bytes_per_sec" => {
+                Ok(self.get_session_rate_limit_bytes_per_sec().to_string())
+            }
+            "timeout" => Ok(self.timeout.load(Ordering::Relaxed).to_string()),
+            "maxclients" => Ok(self.maxclients.load(Ordering::Relaxed).to_string()),
+            "capture_dir" => Ok(self.capture_dir.clone().unwrap_or_else(|| "".to_string())),
             _ => Err(ConfigError::FieldNotFound),
         }
     }
@@ -86,12 +251,18 @@ impl ServerProxyConfig {
         match field.to_lowercase().as_ref() {
             "address" => Err(ConfigError::ReadonlyField),
             "announce_address" => Err(ConfigError::ReadonlyField),
-            "auto_select_cluster" => Err(ConfigError::ReadonlyField),
+            "auto_select_cluster" => {
+                let bool_value = value
+                    .parse::<bool>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.set_auto_select_cluster(bool_value);
+                Ok(())
+            }
             "slowlog_len" => Err(ConfigError::ReadonlyField),
             "thread_number" => Err(ConfigError::ReadonlyField),
             "session_channel_size" => Err(ConfigError::ReadonlyField),
             "backend_channel_size" => Err(ConfigError::ReadonlyField),
-            "backend_conn_num" => Err(ConfigError::ReadonlyField),
+            "backend_conn_num" => store_non_zero(&self.backend_conn_num, value),
             "slowlog_log_slower_than" => {
                 let int_value = value
                     .parse::<i64>()
@@ -106,17 +277,229 @@ impl ServerProxyConfig {
                 self.set_slowlog_sample_rate(int_value);
                 Ok(())
             }
-            "backend_batch_max_time" => Err(ConfigError::ReadonlyField),
-            "backend_batch_min_time" => Err(ConfigError::ReadonlyField),
-            "backend_batch_buf" => Err(ConfigError::ReadonlyField),
-            "session_batch_min_time" => Err(ConfigError::ReadonlyField),
-            "session_batch_max_time" => Err(ConfigError::ReadonlyField),
-            "session_batch_buf" => Err(ConfigError::ReadonlyField),
+            "key_stats_top_k" => Err(ConfigError::ReadonlyField),
+            "backend_batch_min_time" => store_usize(&self.backend_batch_min_time, value),
+            "backend_batch_max_time" => store_usize(&self.backend_batch_max_time, value),
+            "backend_batch_buf" => store_non_zero(&self.backend_batch_buf, value),
+            "session_batch_min_time" => store_usize(&self.session_batch_min_time, value),
+            "session_batch_max_time" => store_usize(&self.session_batch_max_time, value),
+            "session_batch_buf" => store_non_zero(&self.session_batch_buf, value),
             "active_redirection" => Err(ConfigError::ReadonlyField),
-            "max_redirections" => Err(ConfigError::ReadonlyField),
+            "max_redirections" => {
+                // Both "none" returned by `CONFIG GET` and zero disable it.
+                let int_value = if value.eq_ignore_ascii_case("none") {
+                    0
+                } else {
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| ConfigError::InvalidValue)?
+                };
+                self.set_max_redirections(int_value);
+                Ok(())
+            }
+            "backend_request_timeout" => {
+                let int_value = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.set_backend_request_timeout(int_value);
+                Ok(())
+            }
+            "backend_circuit_breaker_threshold" => Err(ConfigError::ReadonlyField),
+            "backend_circuit_breaker_open_time" => Err(ConfigError::ReadonlyField),
+            "shutdown_timeout" => Err(ConfigError::ReadonlyField),
+            "session_rate_limit_ops_per_sec" => {
+                let int_value = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.set_session_rate_limit_ops_per_sec(int_value);
+                Ok(())
+            }
+            "session_rate_limit_bytes_per_sec" => {
+                let int_value = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.set_session_rate_limit_bytes_per_sec(int_value);
+                Ok(())
+            }
+            "timeout" => {
+                let int_value = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.timeout.store(int_value, Ordering::Relaxed);
+                Ok(())
+            }
+            "maxclients" => store_usize(&self.maxclients, value),
+            "capture_dir" => Err(ConfigError::ReadonlyField),
             _ => Err(ConfigError::FieldNotFound),
         }
     }
+
+    // Applies the runtime fields of the config reloaded from the file.
+    // The other fields need a restart to take effect.
+    // All the fields are checked before storing any of them
+    // so that an invalid config file won't be partially applied.
+    pub fn update_from(&self, other: &ServerProxyConfig) -> Result<(), ConfigError> {
+        let mut fields = vec![];
+        for field in RUNTIME_FIELDS.iter() {
+            fields.push((*field, other.get_runtime_field(field)?));
+        }
+        check_runtime_fields(&fields)?;
+        for (field, value) in fields.iter() {
+            self.set_value(field, value)?;
+        }
+        Ok(())
+    }
+
+    // Like the `CONFIG REWRITE` of Redis, the runtime fields in the config file
+    // are replaced with the current values and the comments are kept.
+    pub fn rewrite(&self) -> Result<(), ConfigError> {
+        let config_file = self
+            .config_file
+            .as_ref()
+            .ok_or_else(|| ConfigError::NoConfigFile)?;
+        let content = fs::read_to_string(config_file).map_err(|err| {
+            error!("failed to read config file {}: {}", config_file, err);
+            ConfigError::FileError
+        })?;
+        let mut fields = vec![];
+        for field in RUNTIME_FIELDS.iter() {
+            fields.push((*field, self.get_runtime_field(field)?));
+        }
+        let content = rewrite_config_content(&content, &fields);
+        fs::write(config_file, content).map_err(|err| {
+            error!("failed to write config file {}: {}", config_file, err);
+            ConfigError::FileError
+        })
+    }
+
+    // Same as `get_field` but uses zero for the disabled `max_redirections`
+    // so that it could be written into the config file.
+    fn get_runtime_field(&self, field: &str) -> Result<String, ConfigError> {
+        if field == "max_redirections" {
+            return Ok(self.max_redirections.load(Ordering::Relaxed).to_string());
+        }
+        self.get_field(field)
+    }
+}
+
+fn load_non_zero(n: &AtomicUsize) -> NonZeroUsize {
+    // The setters never store zero.
+    NonZeroUsize::new(max(1, n.load(Ordering::Relaxed))).expect("load_non_zero")
+}
+
+fn store_usize(n: &AtomicUsize, value: &str) -> Result<(), ConfigError> {
+    let int_value = value
+        .parse::<usize>()
+        .map_err(|_| ConfigError::InvalidValue)?;
+    n.store(int_value, Ordering::Relaxed);
+    Ok(())
+}
+
+fn store_non_zero(n: &AtomicUsize, value: &str) -> Result<(), ConfigError> {
+    let int_value = value
+        .parse::<NonZeroUsize>()
+        .map_err(|_| ConfigError::InvalidValue)?;
+    n.store(int_value.get(), Ordering::Relaxed);
+    Ok(())
+}
+
+fn check_runtime_fields(fields: &[(&str, String)]) -> Result<(), ConfigError> {
+    for (field, value) in fields.iter() {
+        let valid = match *field {
+            "auto_select_cluster" => value.parse::<bool>().is_ok(),
+            "slowlog_log_slower_than" => value.parse::<i64>().is_ok(),
+            "backend_conn_num" | "backend_batch_buf" | "session_batch_buf" => {
+                value.parse::<NonZeroUsize>().is_ok()
+            }
+            _ => value.parse::<u64>().is_ok(),
+        };
+        if !valid {
+            error!("invalid config value {} = {}", field, value);
+            return Err(ConfigError::InvalidValue);
+        }
+    }
+
+    let get_u64 = |name: &str| {
+        fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .and_then(|(_, value)| value.parse::<u64>().ok())
+    };
+    let time_pairs = [
+        ("backend_batch_min_time", "backend_batch_max_time"),
+        ("session_batch_min_time", "session_batch_max_time"),
+    ];
+    for (min_field, max_field) in time_pairs.iter() {
+        if let (Some(min_time), Some(max_time)) = (get_u64(min_field), get_u64(max_field)) {
+            if min_time > max_time {
+                error!(
+                    "invalid config: {} {} is greater than {} {}",
+                    min_field, min_time, max_field, max_time
+                );
+                return Err(ConfigError::InvalidValue);
+            }
+        }
+    }
+    Ok(())
+}
+
+// All the runtime fields are numbers or booleans so they don't need quotes.
+// Only the fields in the root table are replaced,
+// and the missing ones are inserted before the first table header.
+fn rewrite_config_content(content: &str, fields: &[(&str, String)]) -> String {
+    let mut written = HashSet::new();
+    let mut lines = vec![];
+    let mut first_table_index = None;
+    for line in content.lines() {
+        if first_table_index.is_none() && line.trim_start().starts_with('[') {
+            first_table_index = Some(lines.len());
+        }
+        if first_table_index.is_some() {
+            lines.push(line.to_string());
+            continue;
+        }
+        let new_line = get_line_field(line).and_then(|field| {
+            fields
+                .iter()
+                .find(|(f, _)| *f == field)
+                .map(|(f, value)| (*f, format!("{} = {}", f, value)))
+        });
+        match new_line {
+            Some((field, new_line)) => {
+                written.insert(field);
+                lines.push(new_line);
+            }
+            None => lines.push(line.to_string()),
+        }
+    }
+    let mut missing_lines: Vec<String> = fields
+        .iter()
+        .filter(|(field, _)| !written.contains(field))
+        .map(|(field, value)| format!("{} = {}", field, value))
+        .collect();
+    match first_table_index {
+        Some(index) => {
+            if !missing_lines.is_empty() {
+                missing_lines.push("".to_string());
+            }
+            let table_lines = lines.split_off(index);
+            lines.extend(missing_lines);
+            lines.extend(table_lines);
+        }
+        None => lines.extend(missing_lines),
+    }
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content
+}
+
+fn get_line_field(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line.starts_with('#') {
+        return None;
+    }
+    let index = line.find('=')?;
+    Some(line[..index].trim())
 }
 
 #[derive(Clone)]
@@ -125,6 +508,9 @@ pub struct ServerProxyService<H: CmdCtxHandler + ThreadSafe + Clone> {
     cmd_ctx_handler: H,
     slow_request_logger: Arc<SlowRequestLogger>,
     future_registry: Arc<TrackedFutureRegistry>,
+    shutdown: Arc<ShutdownSignal>,
+    throttle_stats: Arc<ThrottleStats>,
+    capture: Arc<TrafficCapture>,
 }
 
 impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
@@ -133,12 +519,18 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
         cmd_ctx_handler: H,
         slow_request_logger: Arc<SlowRequestLogger>,
         future_registry: Arc<TrackedFutureRegistry>,
+        shutdown: Arc<ShutdownSignal>,
+        throttle_stats: Arc<ThrottleStats>,
+        capture: Arc<TrafficCapture>,
     ) -> Self {
         Self {
             config,
             cmd_ctx_handler,
             slow_request_logger,
             future_registry,
+            shutdown,
+            throttle_stats,
+            capture,
         }
     }
 
@@ -150,22 +542,34 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
             into_err(err_str)
         })?;
 
-        let mut listener = TcpListener::bind(&address).await.map_err(|err| {
+        let listener = TcpListener::bind(&address).await.map_err(|err| {
             error!("unable to bind address: {} {:?}", address, err);
             err
         })?;
+        self.serve(listener).await
+    }
 
+    async fn serve(&self, mut listener: TcpListener) -> Result<(), Box<dyn Error>> {
         let forward_handler = self.cmd_ctx_handler.clone();
         let slow_request_logger = self.slow_request_logger.clone();
 
         let session_id = AtomicUsize::new(0);
+        let clients = Arc::new(AtomicUsize::new(0));
         let config = self.config.clone();
 
         let future_registry = self.future_registry.clone();
 
         let mut s = listener.incoming();
-        while let Some(sock) = s.next().await {
-            let sock = sock?;
+        let mut draining = Box::pin(self.shutdown.wait_for_draining());
+        loop {
+            let sock = match future::select(s.next(), draining.as_mut()).await {
+                future::Either::Left((Some(sock), _)) => sock?,
+                future::Either::Left((None, _)) => break,
+                future::Either::Right(((), _)) => {
+                    info!("stop accepting new sessions");
+                    break;
+                }
+            };
 
             if let Err(err) = sock.set_nodelay(true) {
                 let err_str = format!("failed to set TCP_NODELAY: {:?}", err);
@@ -179,6 +583,16 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
             };
             info!("accept conn: {}", peer);
 
+            let client_num = clients.fetch_add(1, Ordering::SeqCst) + 1;
+            if let Some(maxclients) = config.get_maxclients() {
+                if client_num > maxclients.get() {
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                    warn!("max number of clients reached, reject conn: {}", peer);
+                    tokio::spawn(reject_session(sock));
+                    continue;
+                }
+            }
+
             let curr_session_id = session_id.fetch_add(1, Ordering::SeqCst);
 
             let handle_clone = forward_handler.clone();
@@ -188,22 +602,297 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
                     handle_clone,
                     slow_request_logger.clone(),
                     config.clone(),
+                    self.throttle_stats.clone(),
+                    self.capture.clone(),
                 )),
                 sock,
-                config.session_channel_size,
-                config.session_batch_min_time,
-                config.session_batch_max_time,
-                config.session_batch_buf,
+                SessionConfig {
+                    batch_min_time: config.get_session_batch_min_time(),
+                    batch_max_time: config.get_session_batch_max_time(),
+                    batch_buf: config.get_session_batch_buf(),
+                    idle_timeout: config.get_timeout(),
+                },
+                self.shutdown.wait_for_draining(),
             );
 
             let desc = format!("session: session_id={} peer={}", curr_session_id, peer);
-            let fut = session_handler.map(move |res| match res {
-                Ok(()) => info!("session IO closed {}", peer),
-                Err(err) => error!("session IO error {:?} {}", err, peer),
+            let session_clients = clients.clone();
+            let fut = session_handler.map(move |res| {
+                session_clients.fetch_sub(1, Ordering::SeqCst);
+                match res {
+                    Ok(()) => info!("session IO closed {}", peer),
+                    Err(err) => error!("session IO error {:?} {}", err, peer),
+                }
             });
             let fut = TrackedFutureRegistry::wrap(future_registry.clone(), fut, desc);
             tokio::spawn(fut);
         }
+
+        drop(s);
+        drop(listener);
+        if self.shutdown.is_draining() {
+            self.wait_for_sessions(&clients).await;
+        }
         Ok(())
     }
+
+    // `clients` is decreased when the session future ends.
+    async fn wait_for_sessions(&self, clients: &AtomicUsize) {
+        let timeout = Duration::from_millis(self.config.shutdown_timeout);
+        let start = Instant::now();
+        loop {
+            let session_num = clients.load(Ordering::SeqCst);
+            if session_num == 0 {
+                info!("all the sessions are closed");
+                return;
+            }
+            if start.elapsed() >= timeout {
+                warn!(
+                    "shutdown timeout, {} sessions are still not closed",
+                    session_num
+                );
+                return;
+            }
+            tokio::time::delay_for(DRAINING_CHECK_INTERVAL).await;
+        }
+    }
+}
+
+// Like Redis, the rejected client gets an error before the connection is closed.
+async fn reject_session(mut sock: TcpStream) {
+    let mut buf = vec![];
+    let resp = Resp::Error(ERR_MAX_CLIENTS.to_string().into_bytes());
+    if let Err(err) = resp_to_buf(&mut buf, &resp) {
+        error!("failed to encode max clients error: {}", err);
+        return;
+    }
+    if let Err(err) = sock.write_all(&buf).await {
+        warn!("failed to reply the rejected conn: {}", err);
+    }
+}
+
+#[cfg(test)]
+pub fn gen_testing_config() -> ServerProxyConfig {
+    ServerProxyConfig {
+        address: "127.0.0.1:5299".to_string(),
+        announce_address: "127.0.0.1:5299".to_string(),
+        auto_select_cluster: AtomicBool::new(true),
+        slowlog_len: NonZeroUsize::new(1024).unwrap(),
+        slowlog_log_slower_than: AtomicI64::new(0),
+        slowlog_sample_rate: AtomicU64::new(1),
+        key_stats_top_k: 20,
+        thread_number: NonZeroUsize::new(2).unwrap(),
+        backend_channel_size: 1024,
+        session_channel_size: 1024,
+        backend_conn_num: AtomicUsize::new(1),
+        backend_batch_min_time: AtomicUsize::new(10000),
+        backend_batch_max_time: AtomicUsize::new(10000),
+        backend_batch_buf: AtomicUsize::new(50),
+        session_batch_min_time: AtomicUsize::new(10000),
+        session_batch_max_time: AtomicUsize::new(10000),
+        session_batch_buf: AtomicUsize::new(50),
+        active_redirection: false,
+        max_redirections: AtomicUsize::new(0),
+        default_redirection_address: None,
+        backend_request_timeout: AtomicU64::new(0),
+        backend_circuit_breaker_threshold: 0,
+        backend_circuit_breaker_open_time: 1000,
+        shutdown_timeout: 10000,
+        session_rate_limit_ops_per_sec: AtomicU64::new(0),
+        session_rate_limit_bytes_per_sec: AtomicU64::new(0),
+        timeout: AtomicU64::new(0),
+        maxclients: AtomicUsize::new(10000),
+        capture_dir: None,
+        config_file: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::RespVec;
+    use crate::proxy::backend::CmdTask;
+    use crate::proxy::command::CmdReplyReceiver;
+    use crate::proxy::session::{CmdCtx, CmdReplyFuture, SessionState};
+    use std::sync::RwLock;
+    use tokio::io::AsyncReadExt;
+
+    const BLOCKING_CMD_TIME: Duration = Duration::from_millis(1500);
+
+    // Replies OK to all the commands.
+    // The blocking commands are replied after `BLOCKING_CMD_TIME`.
+    #[derive(Clone)]
+    struct DummyCmdCtxHandler;
+
+    impl CmdCtxHandler for DummyCmdCtxHandler {
+        fn handle_cmd_ctx(
+            &self,
+            cmd_ctx: CmdCtx,
+            result_receiver: CmdReplyReceiver,
+            _session_state: &RwLock<SessionState>,
+        ) -> CmdReplyFuture {
+            let ok: RespVec = Resp::Simple(b"OK".to_vec());
+            if !cmd_ctx.get_cmd().is_blocking() {
+                cmd_ctx.set_resp_result(Ok(ok));
+                return CmdReplyFuture::Left(result_receiver);
+            }
+            CmdReplyFuture::Right(Box::pin(async move {
+                tokio::time::delay_for(BLOCKING_CMD_TIME).await;
+                cmd_ctx.set_resp_result(Ok(ok));
+                result_receiver.await
+            }))
+        }
+    }
+
+    fn gen_service(config: ServerProxyConfig) -> ServerProxyService<DummyCmdCtxHandler> {
+        let config = Arc::new(config);
+        ServerProxyService::new(
+            config.clone(),
+            DummyCmdCtxHandler,
+            Arc::new(SlowRequestLogger::new(config)),
+            Arc::new(TrackedFutureRegistry::default()),
+            Arc::new(ShutdownSignal::default()),
+            Arc::new(ThrottleStats::default()),
+            Arc::new(TrafficCapture::default()),
+        )
+    }
+
+    // Runs the service until `test` is done.
+    async fn run_service<F: std::future::Future<Output = ()>>(
+        service: ServerProxyService<DummyCmdCtxHandler>,
+        listener: TcpListener,
+        test: F,
+    ) {
+        let serve = Box::pin(service.serve(listener));
+        match future::select(serve, Box::pin(test)).await {
+            future::Either::Left((res, _)) => panic!("service stopped: {:?}", res.err()),
+            future::Either::Right(((), _)) => (),
+        }
+    }
+
+    async fn send_cmd(sock: &mut TcpStream, cmd: &[u8]) -> Vec<u8> {
+        let mut request = format!("*1\r\n${}\r\n", cmd.len()).into_bytes();
+        request.extend_from_slice(cmd);
+        request.extend_from_slice(b"\r\n");
+        sock.write_all(&request).await.unwrap();
+        let mut buf = vec![0; 1024];
+        let n = sock.read(&mut buf).await.unwrap();
+        buf.truncate(n);
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_maxclients() {
+        let config = gen_testing_config();
+        config.maxclients.store(1, Ordering::Relaxed);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        run_service(gen_service(config), listener, async move {
+            let mut sock1 = TcpStream::connect(address).await.unwrap();
+            assert_eq!(send_cmd(&mut sock1, b"PING").await, b"+OK\r\n".to_vec());
+
+            let mut sock2 = TcpStream::connect(address).await.unwrap();
+            let mut buf = vec![];
+            sock2.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, format!("-{}\r\n", ERR_MAX_CLIENTS).into_bytes());
+
+            // The client number is decreased after the session is closed.
+            drop(sock1);
+            for _ in 0..50 {
+                let mut sock3 = TcpStream::connect(address).await.unwrap();
+                if send_cmd(&mut sock3, b"PING").await == b"+OK\r\n".to_vec() {
+                    return;
+                }
+                tokio::time::delay_for(Duration::from_millis(20)).await;
+            }
+            panic!("the client number is not decreased");
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let config = gen_testing_config();
+        config.timeout.store(1, Ordering::Relaxed);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        run_service(gen_service(config), listener, async move {
+            let mut idle_sock = TcpStream::connect(address).await.unwrap();
+            let mut blocking_sock = TcpStream::connect(address).await.unwrap();
+            let start = Instant::now();
+
+            // The session waiting for the reply of a blocking command longer than
+            // the timeout is not idle.
+            assert!(BLOCKING_CMD_TIME > Duration::from_secs(1));
+            assert_eq!(
+                send_cmd(&mut blocking_sock, b"BRPOPLPUSH").await,
+                b"+OK\r\n".to_vec()
+            );
+            assert!(start.elapsed() >= BLOCKING_CMD_TIME);
+
+            let mut buf = vec![];
+            let read_fut = idle_sock.read_to_end(&mut buf);
+            tokio::time::timeout(Duration::from_secs(3), read_fut)
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(buf.is_empty());
+        })
+        .await;
+    }
+
+    #[test]
+    fn test_rewrite_config_content() {
+        let content = "# comment\nbackend_conn_num = 2\n# session_batch_buf = 10\naddress = \"127.0.0.1:5299\"\n";
+        let fields = vec![
+            ("backend_conn_num", "4".to_string()),
+            ("session_batch_buf", "20".to_string()),
+        ];
+        let expected = "# comment\nbackend_conn_num = 4\n# session_batch_buf = 10\naddress = \"127.0.0.1:5299\"\nsession_batch_buf = 20\n";
+        assert_eq!(rewrite_config_content(content, &fields), expected);
+    }
+
+    #[test]
+    fn test_rewrite_config_content_with_tables() {
+        let content =
+            "backend_conn_num = 2\n\n[table]\nsession_batch_buf = 10\nbackend_conn_num = 2\n";
+        let fields = vec![
+            ("backend_conn_num", "4".to_string()),
+            ("session_batch_buf", "20".to_string()),
+        ];
+        let expected = "backend_conn_num = 4\n\nsession_batch_buf = 20\n\n[table]\nsession_batch_buf = 10\nbackend_conn_num = 2\n";
+        assert_eq!(rewrite_config_content(content, &fields), expected);
+    }
+
+    #[test]
+    fn test_update_from() {
+        let config = gen_testing_config();
+        let new_config = gen_testing_config();
+        new_config.backend_conn_num.store(4, Ordering::Relaxed);
+        new_config.timeout.store(60, Ordering::Relaxed);
+        config.update_from(&new_config).unwrap();
+        assert_eq!(config.get_backend_conn_num().get(), 4);
+        assert_eq!(config.get_timeout(), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_update_from_rollback() {
+        let config = gen_testing_config();
+        let new_config = gen_testing_config();
+        new_config.backend_conn_num.store(4, Ordering::Relaxed);
+        new_config.timeout.store(60, Ordering::Relaxed);
+        new_config
+            .session_batch_min_time
+            .store(20000, Ordering::Relaxed);
+        new_config
+            .session_batch_max_time
+            .store(10000, Ordering::Relaxed);
+        assert!(config.update_from(&new_config).is_err());
+        assert_eq!(config.get_backend_conn_num().get(), 1);
+        assert_eq!(config.get_timeout(), None);
+        assert_eq!(config.get_session_batch_min_time(), 10000);
+        assert_eq!(config.get_session_batch_max_time(), 10000);
+    }
 }
@@ -1,21 +1,25 @@
 use super::backend::{CmdTask, CmdTaskFactory, CmdTaskResult};
+use super::capture::{CaptureRecord, TrafficCapture};
 use super::cluster::{ClusterTag, DEFAULT_CLUSTER};
 use super::command::{
     new_command_pair, CmdReplyReceiver, CmdReplySender, CmdType, Command, CommandError,
     CommandResult, DataCmdType, TaskReply, TaskResult,
 };
+use super::key_stats::get_reply_size;
+use super::rate_limit::{RateLimiter, ThrottleStats};
 use super::service::ServerProxyConfig;
 use super::slowlog::{SlowRequestLogger, Slowlog, TaskEvent};
 use crate::common::batch::TryChunksTimeoutStreamExt;
 use crate::common::cluster::ClusterName;
+use crate::common::response;
 use crate::protocol::{
     new_simple_packet_codec, BinSafeStr, DecodeError, EncodeError, Resp, RespCodec, RespPacket,
     RespVec,
 };
-use futures::{future, stream, Future, TryFutureExt};
+use futures::{future, stream, Future, FutureExt, TryFutureExt};
 use futures::{SinkExt, StreamExt, TryStreamExt};
 use std::boxed::Box;
-use std::cmp::min;
+use std::cmp::{max, min};
 use std::collections::VecDeque;
 use std::convert::TryFrom;
 use std::error::Error;
@@ -23,6 +27,7 @@ use std::fmt;
 use std::io;
 use std::num::NonZeroUsize;
 use std::pin::Pin;
+use std::str;
 use std::sync;
 use std::sync::Arc;
 use std::time::Duration;
@@ -35,7 +40,7 @@ pub type CmdReplyFuture<'a> =
 
 pub trait CmdHandler {
     fn handle_cmd(&self, cmd: Command) -> CmdReplyFuture;
-    fn handle_slowlog(&self, request: Box<RespPacket>, slowlog: Slowlog);
+    fn handle_slowlog(&self, request: Box<RespPacket>, reply: &RespPacket, slowlog: Slowlog);
 }
 
 pub trait CmdCtxHandler {
@@ -43,10 +48,19 @@ pub trait CmdCtxHandler {
         &self,
         cmd_ctx: CmdCtx,
         result_receiver: CmdReplyReceiver,
-        session_cluster_name: &sync::RwLock<ClusterName>,
+        session_state: &sync::RwLock<SessionState>,
     ) -> CmdReplyFuture;
 }
 
+// Changed by `AUTH`.
+// The key prefix of the user is looked up for each command
+// so that the changes of `key_prefixes` also apply to the existing sessions.
+#[derive(Debug, Clone)]
+pub struct SessionState {
+    pub cluster_name: ClusterName,
+    pub user: Option<Arc<str>>,
+}
+
 #[derive(Debug)]
 pub struct CmdCtx {
     cmd: Command,
@@ -54,6 +68,7 @@ pub struct CmdCtx {
     slowlog: Slowlog,
     cluster_name: ClusterName,
     redirection_times: Option<usize>,
+    user: Option<Arc<str>>,
 }
 
 impl CmdCtx {
@@ -71,6 +86,7 @@ impl CmdCtx {
             slowlog,
             cluster_name,
             redirection_times: None,
+            user: None,
         }
     }
 
@@ -90,6 +106,14 @@ impl CmdCtx {
         self.cmd.change_element(index, data)
     }
 
+    pub fn rename_cmd(&mut self, cmd_name: Vec<u8>) -> bool {
+        self.cmd.rename(cmd_name)
+    }
+
+    pub fn replace_cmd_elements(&mut self, elements: Vec<BinSafeStr>) {
+        self.cmd.replace_elements(elements)
+    }
+
     // Returns remaining elements
     pub fn extract_inner_cmd(&mut self, removed_num: usize) -> Option<usize> {
         self.cmd.extract_inner_cmd(removed_num)
@@ -114,6 +138,18 @@ impl CmdCtx {
     pub fn get_redirection_times(&self) -> Option<usize> {
         self.redirection_times
     }
+
+    pub fn set_captured(&mut self, request: Vec<BinSafeStr>) {
+        self.slowlog.set_captured(request)
+    }
+
+    pub fn set_user(&mut self, user: Option<Arc<str>>) {
+        self.user = user;
+    }
+
+    pub fn get_user(&self) -> Option<Arc<str>> {
+        self.user.clone()
+    }
 }
 
 pub struct SessionContext {
@@ -176,6 +212,10 @@ impl CmdTask for CmdCtx {
     fn log_event(&mut self, event: TaskEvent) {
         self.slowlog.log_event(event);
     }
+
+    fn is_blocking_cmd(&self) -> bool {
+        self.cmd.is_blocking()
+    }
 }
 
 impl ClusterTag for CmdCtx {
@@ -223,10 +263,13 @@ impl CmdTaskFactory for CmdCtxFactory {
 
 pub struct Session<H: CmdCtxHandler> {
     session_id: usize,
-    cluster_name: sync::Arc<sync::RwLock<ClusterName>>,
+    state: sync::RwLock<SessionState>,
     cmd_ctx_handler: H,
     slow_request_logger: sync::Arc<SlowRequestLogger>,
     config: Arc<ServerProxyConfig>,
+    rate_limiter: sync::Mutex<RateLimiter>,
+    throttle_stats: Arc<ThrottleStats>,
+    capture: Arc<TrafficCapture>,
 }
 
 impl<H: CmdCtxHandler> Session<H> {
@@ -235,26 +278,101 @@ impl<H: CmdCtxHandler> Session<H> {
         cmd_ctx_handler: H,
         slow_request_logger: sync::Arc<SlowRequestLogger>,
         config: Arc<ServerProxyConfig>,
+        throttle_stats: Arc<ThrottleStats>,
+        capture: Arc<TrafficCapture>,
     ) -> Self {
         let cluster_name = ClusterName::try_from(DEFAULT_CLUSTER).expect("Session::new");
+        let rate_limiter = RateLimiter::new(
+            config.get_session_rate_limit_ops_per_sec(),
+            config.get_session_rate_limit_bytes_per_sec(),
+        );
         Session {
             session_id,
-            cluster_name: sync::Arc::new(sync::RwLock::new(cluster_name)),
+            state: sync::RwLock::new(SessionState {
+                cluster_name,
+                user: None,
+            }),
             cmd_ctx_handler,
             slow_request_logger,
             config,
+            rate_limiter: sync::Mutex::new(rate_limiter),
+            throttle_stats,
+            capture,
         }
     }
+
+    // Only the data commands are limited.
+    fn try_acquire_quota(&self, cmd: &Command) -> bool {
+        if cmd.get_type() != CmdType::Others {
+            return true;
+        }
+        let ops_per_sec = self.config.get_session_rate_limit_ops_per_sec();
+        let bytes_per_sec = self.config.get_session_rate_limit_bytes_per_sec();
+        if ops_per_sec == 0 && bytes_per_sec == 0 {
+            return true;
+        }
+        let bytes = cmd.get_command_bytes() as u64;
+        self.rate_limiter
+            .lock()
+            .expect("Session::try_acquire_quota")
+            .try_acquire(ops_per_sec, bytes_per_sec, bytes)
+    }
+
+    // AUTH is always captured regardless of the sample rate
+    // so that the replayed sessions select the same cluster.
+    fn should_capture(&self, cmd: &Command, cluster_name: &ClusterName) -> bool {
+        if cmd.get_type() == CmdType::Auth {
+            let auth_cluster_name = cmd
+                .get_command_last_element()
+                .and_then(|element| str::from_utf8(element).ok())
+                .and_then(|s| ClusterName::try_from(s).ok());
+            return self.capture.is_capturing(cluster_name)
+                || auth_cluster_name.map_or(false, |name| self.capture.is_capturing(&name));
+        }
+        self.capture.sample(cluster_name)
+    }
+
+    fn record_capture(&self, request: Vec<BinSafeStr>, reply: &RespPacket, slowlog: &Slowlog) {
+        let record = CaptureRecord::new(
+            max(0, slowlog.get_created_time()) as u64,
+            max(0, slowlog.get_latency()) as u64,
+            self.session_id as u64,
+            request,
+            reply,
+        );
+        match record {
+            Ok(record) => self.capture.record(record),
+            Err(err) => error!("failed to encode capture record: {}", err),
+        }
+    }
+
+    // Only the data commands are recorded.
+    fn record_key_stats(&self, request: Box<RespPacket>, reply: &RespPacket) -> Box<RespPacket> {
+        let cmd = Command::new(request);
+        if cmd.get_type() == CmdType::Others {
+            if let Some(key) = cmd.get_key() {
+                let cluster_name = self
+                    .state
+                    .read()
+                    .expect("Session::record_key_stats")
+                    .cluster_name
+                    .clone();
+                self.slow_request_logger.get_key_stats().record(
+                    &cluster_name,
+                    key,
+                    get_reply_size(reply),
+                );
+            }
+        }
+        cmd.into_packet()
+    }
 }
 
 impl<H: CmdCtxHandler> CmdHandler for Session<H> {
     fn handle_cmd(&self, cmd: Command) -> CmdReplyFuture {
         let (reply_sender, reply_receiver) = new_command_pair(&cmd);
-        let cluster_name = self
-            .cluster_name
-            .read()
-            .expect("Session::handle_cmd")
-            .clone();
+        let SessionState { cluster_name, user } =
+            self.state.read().expect("Session::handle_cmd").clone();
 
         let slowlog_enabled = self
             .slow_request_logger
@@ -266,27 +384,70 @@ impl<H: CmdCtxHandler> CmdHandler for Session<H> {
             self.session_id,
             slowlog_enabled,
         );
+        cmd_ctx.set_user(user);
+        if self.should_capture(cmd_ctx.get_cmd(), cmd_ctx.get_cluster_name()) {
+            // Copy the request before it's modified by the proxy, e.g. adding the key prefix.
+            let cmd = cmd_ctx.get_cmd();
+            let request = (0..cmd.get_command_len().unwrap_or(0))
+                .filter_map(|i| cmd.get_command_element(i))
+                .map(|element| element.to_vec())
+                .collect();
+            cmd_ctx.set_captured(request);
+        }
         cmd_ctx.log_event(TaskEvent::Created);
+
+        if !self.try_acquire_quota(cmd_ctx.get_cmd()) {
+            self.throttle_stats.record(cmd_ctx.get_cluster_name());
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_THROTTLED.to_string().into_bytes(),
+            )));
+            return CmdReplyFuture::Left(reply_receiver);
+        }
+
         self.cmd_ctx_handler
-            .handle_cmd_ctx(cmd_ctx, reply_receiver, &(*self.cluster_name))
+            .handle_cmd_ctx(cmd_ctx, reply_receiver, &self.state)
     }
 
-    fn handle_slowlog(&self, request: Box<RespPacket>, slowlog: Slowlog) {
+    fn handle_slowlog(&self, request: Box<RespPacket>, reply: &RespPacket, mut slowlog: Slowlog) {
+        if let Some(captured_request) = slowlog.take_captured_request() {
+            self.record_capture(captured_request, reply, &slowlog);
+        }
+        let request = if slowlog.is_enabled() {
+            self.record_key_stats(request, reply)
+        } else {
+            request
+        };
         self.slow_request_logger.add_slow_log(request, slowlog)
     }
 }
 
-pub async fn handle_session<H>(
+pub struct SessionConfig {
+    // In nanoseconds.
+    pub batch_min_time: usize,
+    pub batch_max_time: usize,
+    pub batch_buf: NonZeroUsize,
+    // Close the session if no request is received within it.
+    pub idle_timeout: Option<Duration>,
+}
+
+// The session will be closed once `draining` is resolved
+// and all the received requests are replied.
+pub async fn handle_session<H, D>(
     handler: sync::Arc<H>,
     sock: TcpStream,
-    _channel_size: usize,
-    session_batch_min_time: usize,
-    session_batch_max_time: usize,
-    session_batch_buf: NonZeroUsize,
+    session_config: SessionConfig,
+    draining: D,
 ) -> Result<(), SessionError>
 where
     H: CmdHandler + Send + Sync + 'static,
+    D: Future<Output = ()>,
 {
+    let SessionConfig {
+        batch_min_time: session_batch_min_time,
+        batch_max_time: session_batch_max_time,
+        batch_buf: session_batch_buf,
+        idle_timeout,
+    } = session_config;
     let (encoder, decoder) = new_simple_packet_codec::<Box<RespPacket>, Box<RespPacket>>();
     let (mut writer, reader) = RespCodec::new(encoder, decoder).framed(sock).split();
     let mut reader = reader
@@ -303,12 +464,34 @@ where
     let mut reply_receiver_list = Vec::with_capacity(session_batch_buf.get());
     let mut replies = Vec::with_capacity(session_batch_buf.get());
     let mut read_buf = VecDeque::with_capacity(session_batch_buf.get());
+    let mut draining = Box::pin(draining.fuse());
+    let mut is_draining = false;
 
     loop {
         let reqs = if read_buf.is_empty() {
-            match reader.next().await {
-                Some(reqs) => reqs,
-                None => return Ok(()),
+            if is_draining {
+                return Ok(());
+            }
+            // The sessions blocked by commands such as BLPOP are not idle
+            // since they are waiting for the replies below.
+            let next_reqs = future::select(reader.next(), draining.as_mut());
+            let res = match idle_timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, next_reqs).await {
+                    Ok(res) => res,
+                    Err(_) => {
+                        info!("close idle session after {:?}", timeout);
+                        return Ok(());
+                    }
+                },
+                None => next_reqs.await,
+            };
+            match res {
+                future::Either::Left((Some(reqs), _)) => reqs,
+                future::Either::Left((None, _)) => return Ok(()),
+                future::Either::Right(((), _)) => {
+                    info!("close idle session for draining");
+                    return Ok(());
+                }
             }
         } else {
             read_buf
@@ -363,7 +546,7 @@ where
                 Ok(task_reply) => {
                     let (request, packet, mut slowlog) = (*task_reply).into_inner();
                     slowlog.log_event(TaskEvent::WaitDone);
-                    handler.handle_slowlog(request, slowlog);
+                    handler.handle_slowlog(request, &packet, slowlog);
                     packet
                 }
                 Err(e) => {
@@ -386,6 +569,11 @@ where
             };
             return Err(err);
         }
+
+        // Stop reading new requests from the busy session.
+        if !is_draining && draining.as_mut().now_or_never().is_some() {
+            is_draining = true;
+        }
     }
 }
 
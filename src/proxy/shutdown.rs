@@ -0,0 +1,78 @@
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+// Shared by the service, the sessions and `UMCTL READY`.
+// Once draining starts, the server proxy stops accepting new sessions,
+// the idle sessions are closed and the busy ones are closed
+// after replying the requests already received.
+pub struct ShutdownSignal {
+    sender: watch::Sender<bool>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl Default for ShutdownSignal {
+    fn default() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Self { sender, receiver }
+    }
+}
+
+impl ShutdownSignal {
+    pub fn is_draining(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    pub fn start_draining(&self) {
+        if self.is_draining() {
+            return;
+        }
+        if self.sender.broadcast(true).is_err() {
+            error!("failed to notify draining");
+        }
+    }
+
+    // Resolves when draining starts. It's ready at once if it has started.
+    pub fn wait_for_draining(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut receiver = self.receiver.clone();
+        async move {
+            while let Some(draining) = receiver.recv().await {
+                if draining {
+                    return;
+                }
+            }
+        }
+    }
+
+    // Start draining on SIGTERM or SIGINT.
+    // A second SIGINT exits at once without waiting for the sessions.
+    pub async fn listen_to_signals(&self) -> Result<(), std::io::Error> {
+        let mut sigterm = signal(SignalKind::terminate())?;
+        let mut sigint = signal(SignalKind::interrupt())?;
+        futures::future::select(Box::pin(sigterm.recv()), Box::pin(sigint.recv())).await;
+        info!("received shutdown signal, start draining");
+        self.start_draining();
+
+        sigint.recv().await;
+        warn!("received SIGINT again, exit immediately");
+        std::process::exit(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[tokio::test]
+    async fn test_shutdown_signal() {
+        let shutdown = ShutdownSignal::default();
+        assert!(!shutdown.is_draining());
+        let mut fut = Box::pin(shutdown.wait_for_draining());
+        assert!(fut.as_mut().now_or_never().is_none());
+
+        shutdown.start_draining();
+        assert!(shutdown.is_draining());
+        fut.await;
+        shutdown.wait_for_draining().await;
+    }
+}
@@ -0,0 +1,117 @@
+use crate::common::cluster::SlotStats;
+use crate::common::utils::SLOT_NUM;
+use crate::protocol::{Array, BulkStr, Resp, RespVec};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Records the requests and bytes of the commands for every slot
+// so that the hot slots could be found and moved to other nodes.
+pub struct SlotStatsCounter {
+    requests: Vec<AtomicU64>,
+    bytes: Vec<AtomicU64>,
+}
+
+impl Default for SlotStatsCounter {
+    fn default() -> Self {
+        Self {
+            requests: (0..SLOT_NUM).map(|_| AtomicU64::new(0)).collect(),
+            bytes: (0..SLOT_NUM).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+}
+
+impl SlotStatsCounter {
+    pub fn record(&self, slot: usize, bytes: u64) {
+        if let (Some(requests_counter), Some(bytes_counter)) =
+            (self.requests.get(slot), self.bytes.get(slot))
+        {
+            requests_counter.fetch_add(1, Ordering::Relaxed);
+            bytes_counter.fetch_add(bytes, Ordering::Relaxed);
+        }
+    }
+
+    // Only returns the slots with traffic.
+    pub fn get(&self) -> Vec<SlotStats> {
+        self.collect(|counter| counter.load(Ordering::Relaxed))
+    }
+
+    // Returns the same result as `get` and resets the counters
+    // so that the caller could accumulate the increments.
+    pub fn take(&self) -> Vec<SlotStats> {
+        self.collect(|counter| counter.swap(0, Ordering::Relaxed))
+    }
+
+    pub fn reset(&self) {
+        for counter in self.requests.iter().chain(self.bytes.iter()) {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn collect<F>(&self, f: F) -> Vec<SlotStats>
+    where
+        F: Fn(&AtomicU64) -> u64,
+    {
+        self.requests
+            .iter()
+            .zip(self.bytes.iter())
+            .enumerate()
+            .filter_map(|(slot, (requests_counter, bytes_counter))| {
+                let requests = f(requests_counter);
+                let bytes = f(bytes_counter);
+                if requests == 0 && bytes == 0 {
+                    return None;
+                }
+                Some(SlotStats {
+                    slot,
+                    requests,
+                    bytes,
+                })
+            })
+            .collect()
+    }
+}
+
+pub fn slot_stats_to_resp(stats: Vec<SlotStats>) -> RespVec {
+    let elements = stats
+        .into_iter()
+        .map(|stats| Resp::Bulk(BulkStr::Str(stats.to_strings().join(" ").into_bytes())))
+        .collect();
+    Resp::Arr(Array::Arr(elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slot_stats_counter() {
+        let counter = SlotStatsCounter::default();
+        counter.record(1, 10);
+        counter.record(1, 20);
+        counter.record(SLOT_NUM - 1, 5);
+        counter.record(SLOT_NUM, 5);
+
+        let stats = counter.get();
+        assert_eq!(
+            stats,
+            vec![
+                SlotStats {
+                    slot: 1,
+                    requests: 2,
+                    bytes: 30
+                },
+                SlotStats {
+                    slot: SLOT_NUM - 1,
+                    requests: 1,
+                    bytes: 5
+                },
+            ]
+        );
+
+        assert_eq!(counter.take(), stats);
+        assert!(counter.get().is_empty());
+
+        counter.record(2, 1);
+        counter.reset();
+        assert!(counter.take().is_empty());
+    }
+}
@@ -1,5 +1,6 @@
+use super::key_stats::KeyStats;
 use super::service::ServerProxyConfig;
-use crate::protocol::{Array, BulkStr, Resp, RespPacket, RespVec};
+use crate::protocol::{Array, BinSafeStr, BulkStr, Resp, RespPacket, RespVec};
 use arc_swap::ArcSwapOption;
 use chrono::{naive, DateTime, Utc};
 use std::cmp::max;
@@ -70,6 +71,9 @@ pub struct Slowlog {
     event_map: RequestEventMap,
     session_id: usize,
     enabled: bool,
+    // The original request received from the client when it's captured.
+    // The events are also logged for the captured requests.
+    captured_request: Option<Vec<BinSafeStr>>,
 }
 
 #[derive(Debug)]
@@ -85,11 +89,12 @@ impl Slowlog {
             event_map: RequestEventMap::default(),
             session_id,
             enabled,
+            captured_request: None,
         }
     }
 
     pub fn log_event(&mut self, event: TaskEvent) {
-        if !self.enabled {
+        if !self.enabled && self.captured_request.is_none() {
             return;
         }
         self.event_map
@@ -103,6 +108,24 @@ impl Slowlog {
     pub fn is_enabled(&self) -> bool {
         self.enabled
     }
+
+    pub fn set_captured(&mut self, request: Vec<BinSafeStr>) {
+        self.captured_request = Some(request);
+    }
+
+    pub fn take_captured_request(&mut self) -> Option<Vec<BinSafeStr>> {
+        self.captured_request.take()
+    }
+
+    // In nanoseconds.
+    pub fn get_created_time(&self) -> i64 {
+        self.event_map.get_event_time(TaskEvent::Created)
+    }
+
+    // In nanoseconds.
+    pub fn get_latency(&self) -> i64 {
+        self.event_map.get_used_time(TaskEvent::WaitDone)
+    }
 }
 
 impl SlowlogRecord {
@@ -169,6 +192,7 @@ pub struct SlowRequestLogger {
     curr_index: atomic::AtomicUsize,
     rate_limiter: SlowLogRateLimiter,
     config: Arc<ServerProxyConfig>,
+    key_stats: KeyStats,
 }
 
 impl SlowRequestLogger {
@@ -181,11 +205,16 @@ impl SlowRequestLogger {
             slowlogs,
             curr_index: atomic::AtomicUsize::new(0),
             rate_limiter: SlowLogRateLimiter::default(),
+            key_stats: KeyStats::new(config.key_stats_top_k),
             config,
         }
     }
 
     pub fn add_slow_log(&self, request: Box<RespPacket>, log: Slowlog) {
+        // The captured requests are not sampled by `slowlog_sample_rate`.
+        if !log.is_enabled() {
+            return;
+        }
         let dt = log.event_map.get_used_time(TaskEvent::WaitDone);
         let threshold = self.config.get_slowlog_log_slower_than();
         // ms to ns
@@ -217,6 +246,11 @@ impl SlowRequestLogger {
         }
     }
 
+    // The sampled requests are also used to find the hot keys and big keys.
+    pub fn get_key_stats(&self) -> &KeyStats {
+        &self.key_stats
+    }
+
     // Returns whether this current log should be enabled.
     pub fn limit_rate(&self, slowlog_sample_rate: u64) -> bool {
         self.rate_limiter.check_current_enabled(slowlog_sample_rate)
@@ -1,15 +1,18 @@
 use super::redis_replicator::{RedisMasterReplicator, RedisReplicaReplicator};
 use super::replicator::{
-    MasterMeta, MasterReplicator, ReplicaMeta, ReplicaReplicator, ReplicatorMeta,
+    MasterMeta, MasterReplicator, ReplicaMeta, ReplicaReplicator, ReplicatorError, ReplicatorMeta,
 };
 use crate::common::cluster::ClusterName;
 use crate::common::future_group::{new_auto_drop_future, FutureAutoStopHandle};
 use crate::common::track::TrackedFutureRegistry;
-use crate::protocol::{Array, BulkStr, RedisClientFactory, Resp, RespVec};
+use crate::protocol::{Array, BulkStr, RedisClient, RedisClientFactory, Resp, RespVec};
 use crate::proxy::cluster::ClusterMetaError;
+use futures_timer::Delay;
 use itertools::Either;
 use std::collections::HashMap;
+use std::str;
 use std::sync::{atomic, Arc, RwLock};
+use std::time::{Duration, Instant};
 
 type ReplicatorRecord = Either<Arc<dyn MasterReplicator>, Arc<dyn ReplicaReplicator>>;
 type ReplicatorMap = HashMap<(ClusterName, String), (ReplicatorRecord, Arc<FutureAutoStopHandle>)>;
@@ -232,7 +235,96 @@ impl<F: RedisClientFactory> ReplicatorManager<F> {
         (master_metadata, replica_metadata)
     }
 
+    pub async fn get_repl_offset(&self, node_address: String) -> Option<u64> {
+        let mut client = match self
+            .client_factory
+            .create_client(node_address.clone())
+            .await
+        {
+            Ok(client) => client,
+            Err(err) => {
+                warn!("failed to connect to node {} {:?}", node_address, err);
+                return None;
+            }
+        };
+        let info_command = vec![b"INFO".to_vec(), b"replication".to_vec()];
+        match client.execute_single(info_command).await {
+            Ok(Resp::Bulk(BulkStr::Str(info))) => parse_repl_offset(&info),
+            Ok(reply) => {
+                warn!(
+                    "invalid INFO replication reply from {} {:?}",
+                    node_address, reply
+                );
+                None
+            }
+            Err(err) => {
+                warn!(
+                    "failed to send INFO replication to {} {:?}",
+                    node_address, err
+                );
+                None
+            }
+        }
+    }
+
+    // Should only be called after the writes to the master are paused.
+    pub async fn wait_for_replica(
+        &self,
+        master_node_address: String,
+        replica_node_address: String,
+        timeout: Duration,
+    ) -> Result<(), ReplicatorError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let master_offset = self.get_repl_offset(master_node_address.clone()).await;
+            let replica_offset = self.get_repl_offset(replica_node_address.clone()).await;
+            if let (Some(master_offset), Some(replica_offset)) = (master_offset, replica_offset) {
+                if replica_offset >= master_offset {
+                    return Ok(());
+                }
+            }
+            if Instant::now() >= deadline {
+                warn!(
+                    "replica {} can't catch up with {}: {:?} {:?}",
+                    replica_node_address, master_node_address, replica_offset, master_offset
+                );
+                return Err(ReplicatorError::Timeout);
+            }
+            Delay::new(Duration::from_millis(10)).await;
+        }
+    }
+
+    // The replicator of the new master will also promote it after the metadata is changed.
+    pub async fn promote_replica(
+        &self,
+        replica_node_address: String,
+    ) -> Result<(), ReplicatorError> {
+        let mut client = self
+            .client_factory
+            .create_client(replica_node_address.clone())
+            .await
+            .map_err(ReplicatorError::RedisError)?;
+        let cmd = vec![b"SLAVEOF".to_vec(), b"NO".to_vec(), b"ONE".to_vec()];
+        match client.execute_single(cmd).await {
+            Ok(Resp::Error(err)) => {
+                error!(
+                    "failed to promote replica {} {:?}",
+                    replica_node_address,
+                    str::from_utf8(&err)
+                );
+                Err(ReplicatorError::InvalidReply)
+            }
+            Ok(_) => Ok(()),
+            Err(err) => Err(ReplicatorError::RedisError(err)),
+        }
+    }
+
     pub fn get_metadata_report(&self) -> RespVec {
+        self.get_metadata_report_with_offsets(&HashMap::new())
+    }
+
+    // The `repl_offsets` are the replication offsets of the local nodes.
+    pub fn get_metadata_report_with_offsets(&self, repl_offsets: &HashMap<String, u64>) -> RespVec {
         let (master_metadata, replica_metadata) = self.get_metadata();
 
         let mut reports = vec![];
@@ -247,6 +339,9 @@ impl<F: RedisClientFactory> ReplicatorManager<F> {
             master_meta.push(format!("cluster:{}\n", cluster_name));
             master_meta.push("role:master\n".to_string());
             master_meta.push(format!("node_address:{}\n", master_node_address));
+            if let Some(offset) = repl_offsets.get(&master_node_address) {
+                master_meta.push(format!("repl_offset:{}\n", offset));
+            }
             for replica in replicas.into_iter() {
                 master_meta.push(format!(
                     "replica:{}@{}\n",
@@ -273,6 +368,9 @@ impl<F: RedisClientFactory> ReplicatorManager<F> {
             replica_meta.push(format!("cluster:{}\n", cluster_name));
             replica_meta.push("role:replica\n".to_string());
             replica_meta.push(format!("node_address:{}\n", replica_node_address));
+            if let Some(offset) = repl_offsets.get(&replica_node_address) {
+                replica_meta.push(format!("repl_offset:{}\n", offset));
+            }
             for master in masters.into_iter() {
                 replica_meta.push(format!(
                     "master:{}@{}\n",
@@ -291,3 +389,23 @@ impl<F: RedisClientFactory> ReplicatorManager<F> {
         Resp::Arr(Array::Arr(reports))
     }
 }
+
+// The replicas report the offset they have processed in `slave_repl_offset`.
+fn parse_repl_offset(info: &[u8]) -> Option<u64> {
+    let info = str::from_utf8(info).ok()?;
+    let mut master_repl_offset = None;
+    let mut slave_repl_offset = None;
+    for line in info.lines() {
+        let mut it = line.trim().splitn(2, ':');
+        match (it.next(), it.next()) {
+            (Some("master_repl_offset"), Some(value)) => {
+                master_repl_offset = value.parse::<u64>().ok()
+            }
+            (Some("slave_repl_offset"), Some(value)) => {
+                slave_repl_offset = value.parse::<u64>().ok()
+            }
+            _ => continue,
+        }
+    }
+    slave_repl_offset.or(master_repl_offset)
+}
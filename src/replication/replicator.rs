@@ -172,6 +172,8 @@ pub enum ReplicatorError {
     RedisError(RedisClientError),
     Io(io::Error),
     InvalidMeta,
+    InvalidReply,
+    Timeout,
 }
 
 impl fmt::Display for ReplicatorError {
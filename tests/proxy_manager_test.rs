@@ -14,7 +14,7 @@ mod tests {
     use std::convert::TryFrom;
     use std::num::NonZeroUsize;
     use std::str;
-    use std::sync::atomic::{AtomicI64, AtomicU64};
+    use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize};
     use std::sync::Arc;
     use std::time::Duration;
     use tokio;
@@ -46,25 +46,36 @@ mod tests {
         ServerProxyConfig {
             address: "localhost:5299".to_string(),
             announce_address: "localhost:5299".to_string(),
-            auto_select_cluster: true,
+            auto_select_cluster: AtomicBool::new(true),
             slowlog_len: NonZeroUsize::new(1024).unwrap(),
             slowlog_log_slower_than: AtomicI64::new(0),
             slowlog_sample_rate: AtomicU64::new(1),
+            key_stats_top_k: 20,
             thread_number: NonZeroUsize::new(2).unwrap(),
-            session_channel_size: 1024,
             backend_channel_size: 1024,
+            session_channel_size: 1024,
             // Should only be 1 so that when `wait_backend_ready` is done,
             // the whole backend is ready.
-            backend_conn_num: NonZeroUsize::new(1).unwrap(),
-            backend_batch_min_time: 10000,
-            backend_batch_max_time: 10000,
-            backend_batch_buf: NonZeroUsize::new(50).unwrap(),
-            session_batch_min_time: 10000,
-            session_batch_max_time: 10000,
-            session_batch_buf: NonZeroUsize::new(50).unwrap(),
+            backend_conn_num: AtomicUsize::new(1),
+            backend_batch_min_time: AtomicUsize::new(10000),
+            backend_batch_max_time: AtomicUsize::new(10000),
+            backend_batch_buf: AtomicUsize::new(50),
+            session_batch_min_time: AtomicUsize::new(10000),
+            session_batch_max_time: AtomicUsize::new(10000),
+            session_batch_buf: AtomicUsize::new(50),
             active_redirection: false,
-            max_redirections: None,
+            max_redirections: AtomicUsize::new(0),
             default_redirection_address: None,
+            backend_request_timeout: AtomicU64::new(0),
+            backend_circuit_breaker_threshold: 0,
+            backend_circuit_breaker_open_time: 1000,
+            shutdown_timeout: 10000,
+            session_rate_limit_ops_per_sec: AtomicU64::new(0),
+            session_rate_limit_bytes_per_sec: AtomicU64::new(0),
+            timeout: AtomicU64::new(0),
+            maxclients: AtomicUsize::new(10000),
+            capture_dir: None,
+            config_file: None,
         }
     }
 